@@ -0,0 +1,58 @@
+//! Playing and taking back moves on a `BoardState`, and generating the
+//! legal moves of a position.
+
+extern crate chessground;
+#[macro_use]
+extern crate criterion;
+extern crate shakmaty;
+
+use criterion::{black_box, Criterion};
+use shakmaty::{Move, Position};
+
+use chessground::BoardState;
+
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+fn kiwipete() -> BoardState {
+    let mut state = BoardState::new();
+    state.set_fen(KIWIPETE).expect("valid fen");
+    state
+}
+
+fn play_undo(c: &mut Criterion) {
+    let mut state = kiwipete();
+    let moves: Vec<Move> = state.legals().iter().cloned().collect();
+
+    c.bench_function("play_undo", |b| {
+        b.iter(|| {
+            for m in &moves {
+                state.play(m).expect("legal move");
+                state.undo();
+            }
+        });
+    });
+}
+
+fn set_fen(c: &mut Criterion) {
+    let mut state = BoardState::new();
+    c.bench_function("set_fen", |b| b.iter(|| state.set_fen(black_box(KIWIPETE)).expect("valid fen")));
+}
+
+fn legal_moves(c: &mut Criterion) {
+    let pos = kiwipete().position().expect("legal position");
+    c.bench_function("legal_moves", |b| b.iter(|| black_box(&pos).legal_moves()));
+}
+
+fn move_targets(c: &mut Criterion) {
+    let state = kiwipete();
+    c.bench_function("move_targets", |b| {
+        b.iter(|| {
+            for square in state.board().occupied() {
+                black_box(state.move_targets(square));
+            }
+        });
+    });
+}
+
+criterion_group!(benches, play_undo, set_fen, legal_moves, move_targets);
+criterion_main!(benches);
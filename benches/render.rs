@@ -0,0 +1,62 @@
+//! Drawing the whole board at several sizes, and redrawing a single
+//! square like the widget does after a move.
+
+extern crate cairo;
+extern crate chessground;
+#[macro_use]
+extern crate criterion;
+
+use cairo::{Context, Format, ImageSurface};
+use criterion::{BenchmarkId, Criterion};
+
+use chessground::{draw_board, BoardState};
+
+const MIDDLEGAME: &str = "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 0 8";
+
+fn middlegame() -> BoardState {
+    let mut state = BoardState::new();
+    state.set_fen(MIDDLEGAME).expect("valid fen");
+    state
+}
+
+fn full_board(c: &mut Criterion) {
+    let state = middlegame();
+    let mut group = c.benchmark_group("full_board");
+
+    for &size in &[128, 360, 720, 1440] {
+        let surface = ImageSurface::create(Format::ARgb32, size, size).expect("surface");
+        let cr = Context::new(&surface).expect("context");
+
+        // piece sets cache rasterized pieces, so warm up once per size
+        draw_board(&cr, &state, f64::from(size)).expect("draw");
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            b.iter(|| draw_board(&cr, &state, f64::from(size)).expect("draw"));
+        });
+    }
+
+    group.finish();
+}
+
+fn dirty_square(c: &mut Criterion) {
+    let state = middlegame();
+    let size = 720;
+    let square = f64::from(size) / 9.0;
+    let surface = ImageSurface::create(Format::ARgb32, size, size).expect("surface");
+    let cr = Context::new(&surface).expect("context");
+    draw_board(&cr, &state, f64::from(size)).expect("draw");
+
+    // the e4 square, inside the border of half a square
+    c.bench_function("dirty_square", |b| {
+        b.iter(|| {
+            cr.save().expect("save");
+            cr.rectangle(4.5 * square, 4.5 * square, square, square);
+            cr.clip();
+            draw_board(&cr, &state, f64::from(size)).expect("draw");
+            cr.restore().expect("restore");
+        });
+    });
+}
+
+criterion_group!(benches, full_board, dirty_square);
+criterion_main!(benches);
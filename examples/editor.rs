@@ -6,44 +6,63 @@ extern crate relm_derive;
 extern crate shakmaty;
 
 use gtk::prelude::*;
+use gtk::Orientation;
 use relm::Widget;
 use relm_derive::widget;
 
-use shakmaty::{Square, Board};
-use chessground::{Ground, UserMove, SetBoard};
+use shakmaty::Piece;
+use shakmaty::fen::Fen;
+use chessground::{Ground, Palette, SetEditMode, SetSparePiece, SparePiece, FenEdited};
 
 use self::Msg::*;
 
 #[derive(Msg)]
 pub enum Msg {
     Quit,
-    PieceMoved(Square, Square),
+    SparePicked(Option<Piece>),
+    Edited(String),
+}
+
+pub struct Model {
+    fen: String,
 }
 
 #[widget]
 impl Widget for Win {
-    fn model() -> Board {
-        Board::default()
+    fn model() -> Model {
+        Model {
+            fen: Fen::default().to_string(),
+        }
+    }
+
+    fn init_view(&mut self) {
+        self.components.ground.emit(SetEditMode(true));
     }
 
     fn update(&mut self, event: Msg) {
         match event {
             Quit => gtk::main_quit(),
-            PieceMoved(orig, dest) => {
-                if let Some(piece) = self.model.remove_piece_at(orig) {
-                    self.model.set_piece_at(dest, piece);
-                    self.components.ground.emit(SetBoard(self.model.clone()));
-                }
-            }
+            SparePicked(piece) => self.components.ground.emit(SetSparePiece(piece)),
+            Edited(fen) => self.model.fen = fen,
         }
     }
 
     view! {
         gtk::Window {
             title: "Chessground",
-            #[name="ground"]
-            Ground {
-                UserMove(orig, dest, _) => PieceMoved(orig, dest),
+            gtk::Box {
+                orientation: Orientation::Vertical,
+                Palette {
+                    SparePiece(piece) => SparePicked(piece),
+                },
+                #[name="ground"]
+                Ground {
+                    FenEdited(ref fen) => Edited(fen.clone()),
+                },
+                gtk::Label {
+                    selectable: true,
+                    text: &self.model.fen,
+                },
             },
             delete_event(_, _) => (Quit, Inhibit(false)),
         }
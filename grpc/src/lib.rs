@@ -0,0 +1,191 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! A gRPC server streaming the moves and clock updates of a chessground
+//! board, so that bots and dashboards can follow and play the game.
+//!
+//! The server runs on its own thread. The app publishes what happens on
+//! the board, e.g. from `GroundMsg::UserMove`, `RemoteMove` and
+//! `ClockChanged`, and polls for moves sent by clients, to play them with
+//! `GroundMsg::PlayMove`:
+//!
+//! ```no_run
+//! # use chessground_grpc::GrpcServer;
+//! let mut server = GrpcServer::spawn("127.0.0.1:50051".parse().unwrap()).expect("grpc server");
+//! while let Some(uci) = server.try_recv_move() {
+//!     println!("client wants to play {}", uci);
+//! }
+//! ```
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::thread;
+use std::time::Duration;
+
+use shakmaty::Color;
+use shakmaty::uci::Uci;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status, Streaming};
+
+/// Messages and service generated from `proto/game.proto`.
+pub mod proto {
+    tonic::include_proto!("chessground");
+}
+
+use proto::game_event::Event;
+use proto::game_service_server::{GameService, GameServiceServer};
+use proto::{ClockEvent, GameEvent, MoveEvent, MoveRequest};
+
+/// Events kept for clients that fall behind, before they miss some.
+const EVENT_BUFFER: usize = 256;
+
+struct Service {
+    events: broadcast::Sender<GameEvent>,
+    moves: mpsc::UnboundedSender<Uci>,
+}
+
+#[tonic::async_trait]
+impl GameService for Service {
+    type PlayStream = Pin<Box<dyn Stream<Item = Result<GameEvent, Status>> + Send>>;
+
+    async fn play(&self, request: Request<Streaming<MoveRequest>>) -> Result<Response<Self::PlayStream>, Status> {
+        let mut inbound = request.into_inner();
+        let moves = self.moves.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(request)) = inbound.next().await {
+                // the board checks legality, so only the notation is checked
+                if let Ok(uci) = request.uci.parse::<Uci>() {
+                    if moves.send(uci).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        // clients that lag behind skip the events they missed
+        let outbound = BroadcastStream::new(self.events.subscribe()).filter_map(|event| event.ok().map(Ok));
+        Ok(Response::new(Box::pin(outbound)))
+    }
+}
+
+/// Error when starting the server.
+#[derive(Debug)]
+pub enum GrpcError {
+    /// The runtime could not be started.
+    Io(io::Error),
+    /// The server could not listen on the address.
+    Transport(tonic::transport::Error),
+}
+
+impl fmt::Display for GrpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GrpcError::Io(ref err) => write!(f, "could not start grpc runtime: {}", err),
+            GrpcError::Transport(ref err) => write!(f, "grpc server failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for GrpcError {}
+
+/// A running `GameService`.
+pub struct GrpcServer {
+    addr: SocketAddr,
+    events: broadcast::Sender<GameEvent>,
+    moves: mpsc::UnboundedReceiver<Uci>,
+}
+
+impl fmt::Debug for GrpcServer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GrpcServer")
+            .field("addr", &self.addr)
+            .field("clients", &self.events.receiver_count())
+            .finish()
+    }
+}
+
+impl GrpcServer {
+    /// Start serving on a background thread. Errors of the server after
+    /// it started are printed to stderr.
+    pub fn spawn(addr: SocketAddr) -> Result<GrpcServer, GrpcError> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(GrpcError::Io)?;
+
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        let (moves_tx, moves) = mpsc::unbounded_channel();
+        let service = Service {
+            events: events.clone(),
+            moves: moves_tx,
+        };
+
+        // bind before returning, so that address errors are reported
+        let incoming = runtime.block_on(async {
+            tonic::transport::server::TcpIncoming::new(addr, true, Some(Duration::from_secs(60)))
+        }).map_err(|err| GrpcError::Io(io::Error::new(io::ErrorKind::AddrNotAvailable, err.to_string())))?;
+
+        thread::spawn(move || {
+            let server = tonic::transport::Server::builder()
+                .add_service(GameServiceServer::new(service))
+                .serve_with_incoming(incoming);
+            if let Err(err) = runtime.block_on(server) {
+                eprintln!("{}", GrpcError::Transport(err));
+            }
+        });
+
+        Ok(GrpcServer { addr, events, moves })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Tell the clients about a move, with the game ply it leads to and
+    /// the FEN of the new position.
+    pub fn publish_move(&self, ply: u32, uci: &Uci, fen: &str) {
+        // no receivers is fine, nobody is listening yet
+        let _ = self.events.send(GameEvent {
+            event: Some(Event::Move(MoveEvent {
+                ply,
+                uci: uci.to_string(),
+                fen: fen.to_owned(),
+            })),
+        });
+    }
+
+    /// Tell the clients the remaining times, like from
+    /// `GroundMsg::ClockChanged`.
+    pub fn publish_clock(&self, white: Duration, black: Duration, running: Option<Color>) {
+        let _ = self.events.send(GameEvent {
+            event: Some(Event::Clock(ClockEvent {
+                white_ms: white.as_millis() as u64,
+                black_ms: black.as_millis() as u64,
+                running: running.map_or("", |color| color.fold_wb("white", "black")).to_owned(),
+            })),
+        });
+    }
+
+    /// A move sent by a client, without blocking.
+    pub fn try_recv_move(&mut self) -> Option<Uci> {
+        self.moves.try_recv().ok()
+    }
+}
@@ -0,0 +1,322 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! An embeddable chessboard for GTK applications.
+//!
+//! `BoardView` is a plain GTK widget that can be put into any container,
+//! without using Relm in the rest of the application. It is driven
+//! through a `BoardController` and reports events to closures, either
+//! one per kind of event or all as `BoardEvent`s:
+//!
+//! ```no_run
+//! use gtk::prelude::*;
+//! use gtk_chessground::BoardView;
+//!
+//! gtk::init().expect("initialized gtk");
+//!
+//! let board = BoardView::new();
+//! let controller = board.controller();
+//! board.connect_user_move(|orig, dest, promotion| {
+//!     println!("user moved {} to {} ({:?})", orig, dest, promotion);
+//! });
+//! board.connect_game_end(|state| println!("game over: {:?}", state));
+//! controller.play_uci_moves("e2e4 e7e5");
+//!
+//! let window = gtk::Window::new(gtk::WindowType::Toplevel);
+//! window.add(board.widget());
+//! window.show_all();
+//! gtk::main();
+//! ```
+//!
+//! The API here is kept stable across releases of the `chessground`
+//! crate, which exposes more of the internals, like engines, online play
+//! and recording moves on chain.
+
+use std::fmt;
+use std::time::Duration;
+
+use relm::{Component, StreamHandle};
+use shakmaty::{Color, Role, Square};
+
+use chessground::{Ground, GroundMsg};
+
+pub use chessground::{Arrow, AutoOrient, Blindfold, Coordinates, DrawBrush, DrawShape, GameState,
+                      HighlightKind, MovableSide, MoveInput, PieceSet, Rgba, Theme, UciMovesError};
+
+/// Events of a `BoardView`.
+#[derive(Debug, Clone)]
+pub enum BoardEvent {
+    /// The user moved a piece from a square to another, with the role
+    /// picked for a promotion.
+    UserMove(Square, Square, Option<Role>),
+    /// The user dropped a piece from the pocket, e.g. in Crazyhouse.
+    UserDrop(Role, Square),
+    /// The user changed the position in edit mode, with its FEN.
+    Edited(String),
+    /// The user drew or erased arrows and circles.
+    ShapesChanged(Vec<DrawShape>),
+    /// The position changed, with the pieces captured by white and black,
+    /// and the material advantage of white.
+    MaterialChanged(Vec<Role>, Vec<Role>, i32),
+    /// The clocks changed, with the time left for white and black, and
+    /// the side whose clock is running.
+    ClockChanged(Duration, Duration, Option<Color>),
+    /// Moves given to `play_uci_moves()` could not be played.
+    UciMovesFailed(UciMovesError),
+    /// The game ended.
+    GameEnd(GameState),
+}
+
+impl BoardEvent {
+    fn from_msg(msg: &GroundMsg) -> Option<BoardEvent> {
+        Some(match *msg {
+            GroundMsg::UserMove(orig, dest, promotion) => BoardEvent::UserMove(orig, dest, promotion),
+            GroundMsg::UserDrop(role, square) => BoardEvent::UserDrop(role, square),
+            GroundMsg::FenEdited(ref fen) => BoardEvent::Edited(fen.clone()),
+            GroundMsg::ShapesChanged(ref shapes) => BoardEvent::ShapesChanged(shapes.clone()),
+            GroundMsg::MaterialChanged(ref white, ref black, advantage) => BoardEvent::MaterialChanged(white.clone(), black.clone(), advantage),
+            GroundMsg::ClockChanged(white, black, running) => BoardEvent::ClockChanged(white, black, running),
+            GroundMsg::UciMovesFailed(ref err) => BoardEvent::UciMovesFailed(err.clone()),
+            GroundMsg::GameEnd(state) => BoardEvent::GameEnd(state),
+            _ => return None,
+        })
+    }
+}
+
+/// A chessboard widget. The board lives as long as the view.
+pub struct BoardView {
+    ground: Component<Ground>,
+}
+
+impl fmt::Debug for BoardView {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoardView").finish()
+    }
+}
+
+impl BoardView {
+    /// Create a board in the starting position. GTK must be initialized.
+    pub fn new() -> BoardView {
+        BoardView {
+            ground: relm::create_component::<Ground>(()),
+        }
+    }
+
+    /// The GTK widget to add to a container.
+    pub fn widget(&self) -> &gtk::AspectFrame {
+        self.ground.widget()
+    }
+
+    /// A handle to drive the board. It can be cloned and kept anywhere,
+    /// and does nothing once the view is dropped.
+    pub fn controller(&self) -> BoardController {
+        BoardController {
+            stream: self.ground.stream().downgrade(),
+        }
+    }
+
+    /// Call `callback` with each event of the board.
+    pub fn connect_event<F: Fn(&BoardEvent) + 'static>(&self, callback: F) {
+        self.ground.stream().observe(move |msg: &GroundMsg| {
+            if let Some(event) = BoardEvent::from_msg(msg) {
+                callback(&event);
+            }
+        });
+    }
+
+    /// Call `callback` with the origin, destination and promotion role
+    /// of each move of the user.
+    pub fn connect_user_move<F: Fn(Square, Square, Option<Role>) + 'static>(&self, callback: F) {
+        self.ground.stream().observe(move |msg: &GroundMsg| {
+            if let GroundMsg::UserMove(orig, dest, promotion) = *msg {
+                callback(orig, dest, promotion);
+            }
+        });
+    }
+
+    /// Call `callback` with the role and square of each piece the user
+    /// drops from the pocket.
+    pub fn connect_user_drop<F: Fn(Role, Square) + 'static>(&self, callback: F) {
+        self.ground.stream().observe(move |msg: &GroundMsg| {
+            if let GroundMsg::UserDrop(role, square) = *msg {
+                callback(role, square);
+            }
+        });
+    }
+
+    /// Call `callback` with the FEN after each change in edit mode.
+    pub fn connect_edited<F: Fn(&str) + 'static>(&self, callback: F) {
+        self.ground.stream().observe(move |msg: &GroundMsg| {
+            if let GroundMsg::FenEdited(ref fen) = *msg {
+                callback(fen);
+            }
+        });
+    }
+
+    /// Call `callback` with the shapes of the user after each change.
+    pub fn connect_shapes_changed<F: Fn(&[DrawShape]) + 'static>(&self, callback: F) {
+        self.ground.stream().observe(move |msg: &GroundMsg| {
+            if let GroundMsg::ShapesChanged(ref shapes) = *msg {
+                callback(shapes);
+            }
+        });
+    }
+
+    /// Call `callback` with the pieces captured by white and black, and
+    /// the material advantage of white, when they change.
+    pub fn connect_material_changed<F: Fn(&[Role], &[Role], i32) + 'static>(&self, callback: F) {
+        self.ground.stream().observe(move |msg: &GroundMsg| {
+            if let GroundMsg::MaterialChanged(ref white, ref black, advantage) = *msg {
+                callback(white, black, advantage);
+            }
+        });
+    }
+
+    /// Call `callback` with the time left for white and black, and the
+    /// running side, when the clocks change.
+    pub fn connect_clock_changed<F: Fn(Duration, Duration, Option<Color>) + 'static>(&self, callback: F) {
+        self.ground.stream().observe(move |msg: &GroundMsg| {
+            if let GroundMsg::ClockChanged(white, black, running) = *msg {
+                callback(white, black, running);
+            }
+        });
+    }
+
+    /// Call `callback` when the game ended.
+    pub fn connect_game_end<F: Fn(GameState) + 'static>(&self, callback: F) {
+        self.ground.stream().observe(move |msg: &GroundMsg| {
+            if let GroundMsg::GameEnd(state) = *msg {
+                callback(state);
+            }
+        });
+    }
+}
+
+impl Default for BoardView {
+    fn default() -> BoardView {
+        BoardView::new()
+    }
+}
+
+/// Drives a `BoardView`.
+#[derive(Clone)]
+pub struct BoardController {
+    stream: StreamHandle<GroundMsg>,
+}
+
+impl fmt::Debug for BoardController {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoardController").finish()
+    }
+}
+
+impl BoardController {
+    /// Set up a position from FEN. Invalid FEN is ignored.
+    pub fn set_fen(&self, fen: &str) {
+        self.stream.emit(GroundMsg::SetFen(fen.to_owned()));
+    }
+
+    /// Play moves in UCI notation separated by spaces, e.g.
+    /// `"e2e4 e7e5"`. If one of them is not legal, none are played and
+    /// `BoardEvent::UciMovesFailed` is sent.
+    pub fn play_uci_moves(&self, moves: &str) {
+        self.stream.emit(GroundMsg::PlayUciMoves(moves.to_owned()));
+    }
+
+    /// Take back the last move.
+    pub fn undo(&self) {
+        self.stream.emit(GroundMsg::Undo);
+    }
+
+    /// Replay a move taken back.
+    pub fn redo(&self) {
+        self.stream.emit(GroundMsg::Redo);
+    }
+
+    pub fn flip(&self) {
+        self.stream.emit(GroundMsg::Flip);
+    }
+
+    pub fn set_orientation(&self, orientation: Color) {
+        self.stream.emit(GroundMsg::SetOrientation(orientation));
+    }
+
+    /// Turn the board by itself to the side to move or the local player.
+    pub fn set_auto_orient(&self, auto_orient: AutoOrient) {
+        self.stream.emit(GroundMsg::SetAutoOrient(auto_orient));
+    }
+
+    /// Restrict which side the user may move.
+    pub fn set_movable(&self, movable: MovableSide) {
+        self.stream.emit(GroundMsg::SetMovable(movable));
+    }
+
+    /// Watch without moving pieces.
+    pub fn set_view_only(&self, view_only: bool) {
+        self.stream.emit(GroundMsg::SetViewOnly(view_only));
+    }
+
+    /// Move pieces freely, ignoring the rules.
+    pub fn set_edit_mode(&self, editing: bool) {
+        self.stream.emit(GroundMsg::SetEditMode(editing));
+    }
+
+    pub fn set_move_input(&self, move_input: MoveInput) {
+        self.stream.emit(GroundMsg::SetMoveInput(move_input));
+    }
+
+    pub fn set_theme(&self, theme: Theme) {
+        self.stream.emit(GroundMsg::SetTheme(theme));
+    }
+
+    pub fn set_piece_set(&self, piece_set: PieceSet) {
+        self.stream.emit(GroundMsg::SetPieceSet(piece_set));
+    }
+
+    pub fn set_blindfold(&self, blindfold: Blindfold) {
+        self.stream.emit(GroundMsg::SetBlindfold(blindfold));
+    }
+
+    pub fn set_coordinates(&self, coordinates: Coordinates) {
+        self.stream.emit(GroundMsg::SetCoordinates(coordinates));
+    }
+
+    /// Set how long pieces glide to their squares.
+    pub fn set_animation_duration(&self, duration: Duration) {
+        self.stream.emit(GroundMsg::SetAnimationDuration(duration));
+    }
+
+    /// Replace the arrows and circles of the user.
+    pub fn set_shapes(&self, shapes: &[DrawShape]) {
+        self.stream.emit(GroundMsg::SetShapes(shapes.to_vec()));
+    }
+
+    /// Replace the arrows drawn beneath the shapes of the user.
+    pub fn set_arrows(&self, arrows: &[Arrow]) {
+        self.stream.emit(GroundMsg::SetArrows(arrows.to_vec()));
+    }
+
+    /// Replace the highlighted squares.
+    pub fn highlight_squares(&self, highlights: &[(Square, HighlightKind)]) {
+        self.stream.emit(GroundMsg::HighlightSquares(highlights.to_vec()));
+    }
+
+    /// Shade the squares attacked by the opponent of the side to move.
+    pub fn set_threats(&self, threats: bool) {
+        self.stream.emit(GroundMsg::SetThreats(threats));
+    }
+}
@@ -0,0 +1,199 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Playing with the arrow keys and a screen reader.
+//!
+//! A focus square is moved with the arrow keys, and Enter picks up the
+//! piece on it or plays the picked piece to it. The square in focus and
+//! its occupant are given to assistive technologies as the accessible
+//! description of the board, and shown as a tooltip.
+
+use gtk::prelude::*;
+use gtk::atk::prelude::*;
+use gtk::DrawingArea;
+use gdk::EventKey;
+use gdk::keys::constants as key;
+use cairo::Context;
+
+use shakmaty::{File, Rank, Role, Square};
+
+use util::{file_to_float, rank_to_float};
+use boardstate::BoardState;
+use ground::{GroundMsg, Stream};
+
+/// Square navigation for keyboard and screen reader users.
+pub struct Access {
+    enabled: bool,
+    cursor: Option<Square>,
+    picked: Option<Square>,
+}
+
+impl Access {
+    pub fn new() -> Access {
+        Access {
+            enabled: false,
+            cursor: None,
+            picked: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.cursor = None;
+            self.picked = None;
+        }
+    }
+
+    /// The square in focus, if navigation started.
+    pub fn cursor(&self) -> Option<Square> {
+        self.cursor
+    }
+
+    pub fn cancel(&mut self) {
+        self.picked = None;
+    }
+
+    pub(crate) fn key_press(&mut self, state: &BoardState, stream: &Stream, e: &EventKey) -> Inhibit {
+        if !self.enabled {
+            return Inhibit(false);
+        }
+
+        let keyval = e.keyval();
+
+        // up is away from the player, whichever side is at the bottom
+        let step = |dx: i32, dy: i32| state.orientation().fold_wb((dx, dy), (-dx, -dy));
+        let direction = if keyval == key::Left {
+            Some(step(-1, 0))
+        } else if keyval == key::Right {
+            Some(step(1, 0))
+        } else if keyval == key::Up {
+            Some(step(0, 1))
+        } else if keyval == key::Down {
+            Some(step(0, -1))
+        } else {
+            None
+        };
+
+        if let Some((dx, dy)) = direction {
+            self.cursor = Some(match self.cursor {
+                Some(cursor) => offset(cursor, dx, dy),
+                None => state.orientation().fold_wb(Square::A1, Square::H8),
+            });
+        } else if keyval == key::Return || keyval == key::KP_Enter || keyval == key::space {
+            if let Some(cursor) = self.cursor {
+                self.activate(state, stream, cursor);
+            }
+        } else if keyval == key::Escape && self.picked.is_some() {
+            self.picked = None;
+        } else {
+            return Inhibit(false);
+        }
+
+        Inhibit(true)
+    }
+
+    /// Pick up the piece on a square, or play the picked piece to it.
+    fn activate(&mut self, state: &BoardState, stream: &Stream, square: Square) {
+        if state.view_only() {
+            return;
+        }
+
+        match self.picked.take() {
+            Some(orig) if orig == square => (),
+            Some(orig) if state.valid_move(orig, square) => {
+                let dest = state.castling_rook(orig, square).unwrap_or(square);
+                stream.emit(GroundMsg::UserMove(orig, dest, None));
+            },
+            Some(_) => (),
+            None => {
+                if state.board().piece_at(square).map_or(false, |piece| state.is_movable(piece)) {
+                    self.picked = Some(square);
+                }
+            },
+        }
+    }
+
+    /// What a screen reader should say about the square in focus.
+    pub(crate) fn describe(&self, state: &BoardState) -> Option<String> {
+        let cursor = self.cursor?;
+        let mut text = describe_square(state, cursor);
+        if self.picked == Some(cursor) {
+            text.push_str(", picked up");
+        } else if let Some(picked) = self.picked {
+            if state.valid_move(picked, cursor) {
+                text.push_str(", legal move");
+            }
+        }
+        Some(text)
+    }
+
+    /// Give the description of the square in focus to assistive
+    /// technologies.
+    pub(crate) fn announce(&self, state: &BoardState, drawing_area: &DrawingArea) {
+        if let (Some(text), Some(accessible)) = (self.describe(state), drawing_area.accessible()) {
+            accessible.set_description(&text);
+        }
+    }
+
+    pub(crate) fn draw(&self, cr: &Context, state: &BoardState) -> Result<(), cairo::Error> {
+        if let Some(picked) = self.picked {
+            cr.rectangle(file_to_float(picked.file()), 7.0 - rank_to_float(picked.rank()), 1.0, 1.0);
+            state.theme().selected.set_source(cr);
+            cr.fill()?;
+        }
+
+        if let Some(cursor) = self.cursor {
+            cr.rectangle(file_to_float(cursor.file()) + 0.04, 7.04 - rank_to_float(cursor.rank()), 0.92, 0.92);
+            state.theme().move_hint.set_source(cr);
+            cr.set_line_width(0.08);
+            cr.stroke()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The square one step away in a direction, staying on the board.
+fn offset(square: Square, dx: i32, dy: i32) -> Square {
+    let file = (i32::from(i8::from(square.file())) + dx).max(0).min(7);
+    let rank = (i32::from(i8::from(square.rank())) + dy).max(0).min(7);
+    Square::from_coords(File::new(file as u32), Rank::new(rank as u32))
+}
+
+/// A square and its occupant in words, like "e4, white pawn".
+pub(crate) fn describe_square(state: &BoardState, square: Square) -> String {
+    match state.board().piece_at(square) {
+        Some(piece) => format!("{}, {} {}", square, piece.color.fold_wb("white", "black"), role_name(piece.role)),
+        None => format!("{}, empty", square),
+    }
+}
+
+fn role_name(role: Role) -> &'static str {
+    match role {
+        Role::Pawn => "pawn",
+        Role::Knight => "knight",
+        Role::Bishop => "bishop",
+        Role::Rook => "rook",
+        Role::Queen => "queen",
+        Role::King => "king",
+    }
+}
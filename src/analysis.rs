@@ -0,0 +1,249 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Evaluating the moves of a game with an engine, to point out
+//! inaccuracies, mistakes and blunders.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::sync::mpsc::TryRecvError;
+
+use shakmaty::{EnPassantMode, Position};
+use shakmaty::fen::Fen;
+
+use engine::{Engine, EngineEvent, Limit, Score};
+
+/// Evaluations are clamped to this many centipawns, so that a lost mate
+/// in a still winning position is not a blunder.
+const MAX_CP: i32 = 1000;
+
+/// How bad a move was.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    /// The Numeric Annotation Glyph, as in PGN.
+    pub fn nag(self) -> u8 {
+        match self {
+            Judgement::Inaccuracy => 6,
+            Judgement::Mistake => 2,
+            Judgement::Blunder => 4,
+        }
+    }
+
+    /// The symbol appended to the move, like `??` for a blunder.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "?!",
+            Judgement::Mistake => "?",
+            Judgement::Blunder => "??",
+        }
+    }
+}
+
+impl fmt::Display for Judgement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Judgement::Inaccuracy => "inaccuracy",
+            Judgement::Mistake => "mistake",
+            Judgement::Blunder => "blunder",
+        })
+    }
+}
+
+/// Centipawn losses from which a move is judged.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Thresholds {
+    pub inaccuracy: i32,
+    pub mistake: i32,
+    pub blunder: i32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Thresholds {
+        Thresholds {
+            inaccuracy: 50,
+            mistake: 100,
+            blunder: 300,
+        }
+    }
+}
+
+impl Thresholds {
+    /// Judge a move by how many centipawns it lost.
+    pub fn judge(&self, loss: i32) -> Option<Judgement> {
+        if loss >= self.blunder {
+            Some(Judgement::Blunder)
+        } else if loss >= self.mistake {
+            Some(Judgement::Mistake)
+        } else if loss >= self.inaccuracy {
+            Some(Judgement::Inaccuracy)
+        } else {
+            None
+        }
+    }
+}
+
+/// A score in centipawns from the point of view of the side to move,
+/// clamped to `±1000`. Mates count as the maximum.
+pub fn centipawns(score: Score) -> i32 {
+    match score {
+        Score::Cp(cp) => cp.max(-MAX_CP).min(MAX_CP),
+        Score::Mate(moves) if moves > 0 => MAX_CP,
+        Score::Mate(_) => -MAX_CP,
+    }
+}
+
+/// Results of the analysis.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AnalysisEvent {
+    /// The position at the ply was evaluated, from the point of view of
+    /// the side to move.
+    Evaluated { ply: u32, score: Score },
+    /// The move leading to the ply lost `loss` centipawns.
+    Judged { ply: u32, judgement: Judgement, loss: i32 },
+}
+
+#[derive(Debug)]
+struct Search {
+    ply: u32,
+    key: String,
+    parent: Option<String>,
+    score: Option<Score>,
+    stopped: bool,
+}
+
+/// Evaluates each position it is given with an engine, one at a time,
+/// and judges the move that led to it by comparing with the evaluation of
+/// the position before.
+#[derive(Debug)]
+pub struct Analysis {
+    engine: Engine,
+    limit: Limit,
+    thresholds: Thresholds,
+    searches: VecDeque<Search>,
+    evals: HashMap<String, Score>,
+}
+
+impl Analysis {
+    pub fn new(engine: Engine, limit: Limit) -> Analysis {
+        Analysis {
+            engine,
+            limit,
+            thresholds: Thresholds::default(),
+            searches: VecDeque::new(),
+            evals: HashMap::new(),
+        }
+    }
+
+    pub fn with_thresholds(mut self, thresholds: Thresholds) -> Analysis {
+        self.thresholds = thresholds;
+        self
+    }
+
+    pub fn thresholds(&self) -> Thresholds {
+        self.thresholds
+    }
+
+    pub fn set_thresholds(&mut self, thresholds: Thresholds) {
+        self.thresholds = thresholds;
+    }
+
+    /// Evaluate the position at the given ply, stopping the search of
+    /// the previous position. With the position before the last move, the
+    /// move is judged once both are evaluated.
+    pub fn analyse<P: Position + Clone>(&mut self, ply: u32, pos: &P, parent: Option<&P>) -> io::Result<()> {
+        if self.searches.iter().any(|search| !search.stopped) {
+            self.engine.stop()?;
+            for search in &mut self.searches {
+                search.stopped = true;
+            }
+        }
+
+        self.searches.push_back(Search {
+            ply,
+            key: key(pos),
+            parent: parent.map(key),
+            score: None,
+            stopped: false,
+        });
+        self.engine.go(pos, self.limit)
+    }
+
+    /// Results of finished searches, without blocking.
+    pub fn try_recv(&mut self) -> Result<Vec<AnalysisEvent>, TryRecvError> {
+        let mut events = Vec::new();
+
+        loop {
+            match self.engine.events().try_recv() {
+                Ok(EngineEvent::Info(info)) => {
+                    if let (Some(search), Some(score)) = (self.searches.front_mut(), info.score) {
+                        // only the main line counts
+                        if info.multipv.map_or(true, |n| n == 1) {
+                            search.score = Some(score);
+                        }
+                    }
+                },
+                Ok(EngineEvent::BestMove { .. }) => {
+                    if let Some(search) = self.searches.pop_front() {
+                        self.finish(search, &mut events);
+                    }
+                },
+                Ok(EngineEvent::Terminated) => return Err(TryRecvError::Disconnected),
+                Ok(_) => (),
+                Err(TryRecvError::Empty) => return Ok(events),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn finish(&mut self, search: Search, events: &mut Vec<AnalysisEvent>) {
+        let score = match search.score {
+            Some(score) => score,
+            None => return,
+        };
+
+        // a stopped search still gives a rough evaluation to judge the
+        // next move by
+        if search.stopped {
+            self.evals.entry(search.key).or_insert(score);
+            return;
+        }
+
+        self.evals.insert(search.key, score);
+        events.push(AnalysisEvent::Evaluated { ply: search.ply, score });
+
+        // the score after the move is from the point of view of the
+        // opponent
+        let before = search.parent.and_then(|parent| self.evals.get(&parent).cloned());
+        if let Some(before) = before {
+            let loss = centipawns(before) + centipawns(score);
+            if let Some(judgement) = self.thresholds.judge(loss) {
+                events.push(AnalysisEvent::Judged { ply: search.ply, judgement, loss });
+            }
+        }
+    }
+}
+
+fn key<P: Position + Clone>(pos: &P) -> String {
+    Fen::from_position(pos.clone(), EnPassantMode::Legal).to_string()
+}
@@ -0,0 +1,206 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! An editor pane for the comments and NAGs of a move.
+
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+
+use gtk::prelude::*;
+use gtk::{Label, Orientation, PolicyType, ScrolledWindow, TextBuffer, TextTagTable, TextView, ToggleButton, WrapMode};
+
+use relm::{Relm, Widget, Update, StreamHandle};
+
+use game::{GameMove, NAG_SYMBOLS};
+use gametree::NodeId;
+
+/// Annotation editor messages.
+#[derive(Debug, Msg)]
+pub enum AnnotationMsg {
+    /// Edit the annotations of a move, e.g. the one at the node of
+    /// `GroundMsg::NodeChanged`.
+    SetMove(NodeId, GameMove),
+    /// Stop editing, e.g. at the starting position.
+    Clear,
+    /// A NAG button was clicked.
+    NagClicked(u8),
+    /// The comment text changed.
+    CommentEdited(String),
+
+    /// Sent when a NAG was toggled or the comment edited, with all NAGs
+    /// and comments of the move. Forward to `Game::annotate()` and, for
+    /// moves of the main line, to `MoveListMsg::Annotate`.
+    Annotated(NodeId, Vec<u8>, Vec<String>),
+}
+
+/// Buttons for the NAGs and a text view for the comments of a move.
+/// Comments are separated by blank lines.
+pub struct AnnotationEditor {
+    vbox: gtk::Box,
+    title: Label,
+    buttons: Vec<(u8, ToggleButton)>,
+    buffer: TextBuffer,
+    stream: StreamHandle<AnnotationMsg>,
+    current: Option<(NodeId, GameMove)>,
+    /// Set while the widgets are filled in, to not report it as an edit.
+    updating: Rc<Cell<bool>>,
+}
+
+impl fmt::Debug for AnnotationEditor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AnnotationEditor")
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+impl AnnotationEditor {
+    fn show(&self) {
+        self.updating.set(true);
+        match self.current {
+            Some((_, ref m)) => {
+                self.title.set_text(m.san());
+                for &(nag, ref button) in &self.buttons {
+                    button.set_active(m.nags().contains(&nag));
+                }
+                self.buffer.set_text(&m.comments().join("\n\n"));
+            },
+            None => {
+                self.title.set_text("");
+                for &(_, ref button) in &self.buttons {
+                    button.set_active(false);
+                }
+                self.buffer.set_text("");
+            },
+        }
+        self.vbox.set_sensitive(self.current.is_some());
+        self.updating.set(false);
+    }
+
+    fn annotated(&self) {
+        if let Some((node, ref m)) = self.current {
+            self.stream.emit(AnnotationMsg::Annotated(node, m.nags().to_vec(), m.comments().to_vec()));
+        }
+    }
+}
+
+impl Update for AnnotationEditor {
+    type Model = ();
+    type ModelParam = ();
+    type Msg = AnnotationMsg;
+
+    fn model(_: &Relm<Self>, _: ()) {}
+
+    fn update(&mut self, event: AnnotationMsg) {
+        match event {
+            AnnotationMsg::SetMove(node, m) => {
+                self.current = Some((node, m));
+                self.show();
+            },
+            AnnotationMsg::Clear => {
+                self.current = None;
+                self.show();
+            },
+            AnnotationMsg::NagClicked(nag) => {
+                if let Some((_, ref mut m)) = self.current {
+                    m.toggle_nag(nag);
+                }
+                // the group of the NAG may have lost another one
+                self.show();
+                self.annotated();
+            },
+            AnnotationMsg::CommentEdited(text) => {
+                if let Some((_, ref mut m)) = self.current {
+                    m.set_comments(text.split("\n\n").map(str::trim).filter(|c| !c.is_empty()).map(str::to_owned).collect());
+                }
+                self.annotated();
+            },
+            AnnotationMsg::Annotated(..) => {},
+        }
+    }
+}
+
+impl Widget for AnnotationEditor {
+    type Root = gtk::Box;
+
+    fn root(&self) -> Self::Root {
+        self.vbox.clone()
+    }
+
+    fn view(relm: &Relm<Self>, _: ()) -> Self {
+        let updating = Rc::new(Cell::new(false));
+
+        let title = Label::new(None);
+        title.set_xalign(0.0);
+
+        // move assessments in the first row, positions in the second
+        let rows = [gtk::Box::new(Orientation::Horizontal, 2), gtk::Box::new(Orientation::Horizontal, 2)];
+        let mut buttons = Vec::new();
+        for &(nag, symbol) in NAG_SYMBOLS.iter() {
+            let button = ToggleButton::with_label(symbol);
+            let stream = relm.stream().clone();
+            let updating = Rc::clone(&updating);
+            button.connect_toggled(move |_| {
+                if !updating.get() {
+                    stream.emit(AnnotationMsg::NagClicked(nag));
+                }
+            });
+            rows[if nag <= 6 { 0 } else { 1 }].pack_start(&button, false, false, 0);
+            buttons.push((nag, button));
+        }
+
+        let buffer = TextBuffer::new(None::<&TextTagTable>);
+        {
+            let stream = relm.stream().clone();
+            let updating = Rc::clone(&updating);
+            buffer.connect_changed(move |buffer| {
+                if !updating.get() {
+                    let (start, end) = buffer.bounds();
+                    let text = buffer.text(&start, &end, false).map_or_else(String::new, |text| text.to_string());
+                    stream.emit(AnnotationMsg::CommentEdited(text));
+                }
+            });
+        }
+
+        let text_view = TextView::with_buffer(&buffer);
+        text_view.set_wrap_mode(WrapMode::Word);
+
+        let scrolled = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled.set_policy(PolicyType::Never, PolicyType::Automatic);
+        scrolled.add(&text_view);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 4);
+        vbox.pack_start(&title, false, false, 0);
+        for row in &rows {
+            vbox.pack_start(row, false, false, 0);
+        }
+        vbox.pack_start(&scrolled, true, true, 0);
+        vbox.set_sensitive(false);
+        vbox.show_all();
+
+        AnnotationEditor {
+            vbox,
+            title,
+            buttons,
+            buffer,
+            stream: relm.stream().clone(),
+            current: None,
+            updating,
+        }
+    }
+}
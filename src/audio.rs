@@ -0,0 +1,217 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Sound effects for moves and other game events.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use rodio::{Decoder, OutputStream, OutputStreamHandle, PlayError, Source, StreamError};
+use rodio::decoder::DecoderError;
+
+use shakmaty::Move;
+
+/// Events that can have a sound.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Sound {
+    Move,
+    Capture,
+    Check,
+    Castle,
+    Promotion,
+    GameEnd,
+    /// The clock of a side fell below the warning threshold.
+    LowTime,
+}
+
+impl Sound {
+    pub const ALL: [Sound; 7] = [
+        Sound::Move, Sound::Capture, Sound::Check, Sound::Castle,
+        Sound::Promotion, Sound::GameEnd, Sound::LowTime,
+    ];
+
+    /// The sound of a move that was just played. Check takes precedence,
+    /// then promotion, castling and capture.
+    pub fn of_move(m: &Move, check: bool) -> Sound {
+        if check {
+            Sound::Check
+        } else if m.is_promotion() {
+            Sound::Promotion
+        } else if m.is_castle() {
+            Sound::Castle
+        } else if m.is_capture() {
+            Sound::Capture
+        } else {
+            Sound::Move
+        }
+    }
+
+    /// File name without extension, as looked for by
+    /// `SoundSet::from_dir()`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Sound::Move => "move",
+            Sound::Capture => "capture",
+            Sound::Check => "check",
+            Sound::Castle => "castle",
+            Sound::Promotion => "promotion",
+            Sound::GameEnd => "game-end",
+            Sound::LowTime => "low-time",
+        }
+    }
+}
+
+/// Sound files for the events. Events without a file are silent.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SoundSet {
+    files: HashMap<Sound, PathBuf>,
+}
+
+impl SoundSet {
+    /// A set without any sounds.
+    pub fn new() -> SoundSet {
+        SoundSet::default()
+    }
+
+    /// Find sounds like `move.ogg` or `game-end.wav` in a directory.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> SoundSet {
+        let dir = dir.as_ref();
+        let mut sounds = SoundSet::new();
+
+        for &sound in Sound::ALL.iter() {
+            let found = ["ogg", "wav", "flac", "mp3"].iter()
+                .map(|ext| dir.join(format!("{}.{}", sound.name(), ext)))
+                .find(|path| path.is_file());
+
+            if let Some(path) = found {
+                sounds.set(sound, Some(path));
+            }
+        }
+
+        sounds
+    }
+
+    /// Set or remove the file played for an event.
+    pub fn set(&mut self, sound: Sound, path: Option<PathBuf>) {
+        match path {
+            Some(path) => self.files.insert(sound, path),
+            None => self.files.remove(&sound),
+        };
+    }
+
+    pub fn get(&self, sound: Sound) -> Option<&Path> {
+        self.files.get(&sound).map(PathBuf::as_path)
+    }
+}
+
+/// Error when playing a sound.
+#[derive(Debug)]
+pub enum AudioError {
+    /// The sound file could not be opened.
+    Io(io::Error),
+    /// The sound file could not be decoded.
+    Decoder(DecoderError),
+    /// There is no audio output device.
+    Stream(StreamError),
+    /// The output device refused the sound.
+    Play(PlayError),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AudioError::Io(ref err) => write!(f, "could not open sound: {}", err),
+            AudioError::Decoder(ref err) => write!(f, "could not decode sound: {}", err),
+            AudioError::Stream(ref err) => write!(f, "no audio output: {}", err),
+            AudioError::Play(ref err) => write!(f, "could not play sound: {}", err),
+        }
+    }
+}
+
+impl Error for AudioError {}
+
+/// Plays the sounds of a `SoundSet`. The audio output is opened when the
+/// first sound is played.
+pub struct Audio {
+    sounds: SoundSet,
+    muted: bool,
+    output: Option<(OutputStream, OutputStreamHandle)>,
+}
+
+impl fmt::Debug for Audio {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Audio")
+            .field("sounds", &self.sounds)
+            .field("muted", &self.muted)
+            .finish()
+    }
+}
+
+impl Audio {
+    pub fn new(sounds: SoundSet) -> Audio {
+        Audio {
+            sounds,
+            muted: false,
+            output: None,
+        }
+    }
+
+    pub fn sounds(&self) -> &SoundSet {
+        &self.sounds
+    }
+
+    pub fn set_sounds(&mut self, sounds: SoundSet) {
+        self.sounds = sounds;
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Play the sound of an event in the background, unless muted or the
+    /// event has no sound.
+    pub fn play(&mut self, sound: Sound) -> Result<(), AudioError> {
+        if self.muted {
+            return Ok(());
+        }
+
+        let path = match self.sounds.get(sound) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let source = Decoder::new(BufReader::new(File::open(path).map_err(AudioError::Io)?))
+            .map_err(AudioError::Decoder)?;
+
+        if self.output.is_none() {
+            self.output = Some(OutputStream::try_default().map_err(AudioError::Stream)?);
+        }
+
+        match self.output {
+            Some((_, ref handle)) => handle.play_raw(source.convert_samples()).map_err(AudioError::Play),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,373 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Command line interface to the chessground library.
+//!
+//! ```text
+//! chessground play
+//! chessground browse [--db DIR]
+//! chessground verify GAME_ID [--endpoint URL]
+//! chessground render GAME.pgn --out board.png [--ply N] [--size PX] [--flip]
+//! chessground export-gif GAME.pgn --out game.gif [--size PX] [--delay MS] [--tween FRAMES] [--flip]
+//! chessground engine-bench [--engine PATH] [--depth N]
+//! chessground daemon [--listen ADDR] [--record] [--origin URL]... [--token TOKEN]
+//! ```
+//!
+//! Defaults like the chain endpoint and the engine are taken from the
+//! settings file, or from another one given with `--config`.
+
+extern crate chessground;
+extern crate clap;
+extern crate gtk;
+extern crate relm;
+extern crate shakmaty;
+
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use gtk::prelude::*;
+use shakmaty::{CastlingMode, Chess, Color};
+use shakmaty::fen::Fen;
+
+use chessground::{export_gif, read_games, render_png, render_svg, verify_game,
+                  data_dir, BoardState, ChainClient, ChainConfig, Config, Engine, EngineEvent, Game,
+                  Daemon, GameBrowser, GameBrowserMsg, GameMove, GifOptions, Ground, GroundMsg, Limit,
+                  Settings, Storage};
+
+/// Positions searched by `engine-bench`, from the opening to the endgame.
+const BENCH_POSITIONS: [&str; 5] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+];
+
+fn cli() -> Command {
+    let pgn = Arg::new("pgn").value_name("PGN").required(true).help("PGN file");
+    let game = Arg::new("game").long("game").value_name("N").value_parser(value_parser!(usize)).default_value("1")
+        .help("Game in the PGN file, counting from 1");
+    let out = Arg::new("out").long("out").short('o').value_name("FILE").required(true);
+    let size = Arg::new("size").long("size").value_name("PX").value_parser(value_parser!(u16));
+    let flip = Arg::new("flip").long("flip").action(ArgAction::SetTrue).help("Show the board from the side of black");
+
+    Command::new("chessground")
+        .about("Play, verify and render chess games")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .arg(Arg::new("config").long("config").value_name("FILE").global(true).help("Settings file to use"))
+        .subcommand(Command::new("play")
+            .about("Open the board in a window"))
+        .subcommand(Command::new("browse")
+            .about("Browse the stored games and replay them")
+            .arg(Arg::new("db").long("db").value_name("DIR").help("Game database, by default the one in the data directory")))
+        .subcommand(Command::new("verify")
+            .about("Replay a game recorded on chain and check its moves and state hash")
+            .arg(Arg::new("game-id").value_name("GAME_ID").required(true))
+            .arg(Arg::new("endpoint").long("endpoint").value_name("URL").help("JSON-RPC endpoint of the chain")))
+        .subcommand(Command::new("render")
+            .about("Draw a position of a game as PNG, or SVG for .svg files")
+            .arg(pgn.clone())
+            .arg(game.clone())
+            .arg(out.clone())
+            .arg(Arg::new("ply").long("ply").value_name("N").value_parser(value_parser!(usize))
+                .help("Plies of the main line to play, by default all"))
+            .arg(size.clone().default_value("512"))
+            .arg(flip.clone()))
+        .subcommand(Command::new("export-gif")
+            .about("Animate the main line of a game as GIF")
+            .arg(pgn)
+            .arg(game)
+            .arg(out)
+            .arg(size.default_value("360"))
+            .arg(Arg::new("delay").long("delay").value_name("MS").value_parser(value_parser!(u64)).default_value("1000")
+                .help("How long each position is shown"))
+            .arg(Arg::new("tween").long("tween").value_name("FRAMES").value_parser(value_parser!(u32)).default_value("0")
+                .help("Frames sliding each moved piece"))
+            .arg(flip))
+        .subcommand(Command::new("engine-bench")
+            .about("Measure the speed of a UCI engine on a few positions")
+            .arg(Arg::new("engine").long("engine").value_name("PATH").help("Engine to run, by default the one in the settings"))
+            .arg(Arg::new("depth").long("depth").value_name("N").value_parser(value_parser!(u32)).default_value("12")))
+        .subcommand(Command::new("daemon")
+            .about("Serve games over JSON-RPC and server-sent events without a window")
+            .arg(Arg::new("listen").long("listen").value_name("ADDR").default_value("127.0.0.1:8080"))
+            .arg(Arg::new("record").long("record").action(ArgAction::SetTrue)
+                .help("Record the moves on the chain of the settings"))
+            .arg(Arg::new("origin").long("origin").value_name("URL").action(ArgAction::Append)
+                .help("Allow browser pages from this origin, like http://localhost:3000"))
+            .arg(Arg::new("token").long("token").value_name("TOKEN")
+                .help("Require this bearer token on JSON-RPC calls")))
+}
+
+fn load_game(args: &ArgMatches) -> Result<Game, String> {
+    let path = args.get_one::<String>("pgn").expect("required");
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let index = *args.get_one::<usize>("game").expect("defaulted");
+    let mut games = read_games(&text).map_err(|err| format!("{}: {}", path, err))?;
+    if index == 0 || index > games.len() {
+        return Err(format!("{}: no game {}, found {}", path, index, games.len()));
+    }
+    Ok(games.swap_remove(index - 1))
+}
+
+fn orientation(args: &ArgMatches) -> Color {
+    if args.get_flag("flip") { Color::Black } else { Color::White }
+}
+
+fn play(settings: &Settings) -> Result<(), String> {
+    gtk::init().map_err(|err| err.to_string())?;
+
+    let ground = relm::init::<Ground>(()).map_err(|_| "could not create the board".to_owned())?;
+    for message in settings.ground_messages() {
+        ground.emit(message);
+    }
+
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title("Chessground");
+    window.set_default_size(600, 600);
+    window.add(ground.widget());
+    window.connect_delete_event(|_, _| {
+        gtk::main_quit();
+        Inhibit(false)
+    });
+    window.show_all();
+
+    gtk::main();
+    Ok(())
+}
+
+fn browse(args: &ArgMatches, settings: &Settings) -> Result<(), String> {
+    let path = args.get_one::<String>("db").map_or_else(|| data_dir().join("games"), |path| path.into());
+    let storage = Storage::open(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let games = storage.games().map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    gtk::init().map_err(|err| err.to_string())?;
+
+    let ground = relm::init::<Ground>(()).map_err(|_| "could not create the board".to_owned())?;
+    for message in settings.ground_messages() {
+        ground.emit(message);
+    }
+    ground.emit(GroundMsg::SetViewOnly(true));
+
+    let browser = relm::init::<GameBrowser>(()).map_err(|_| "could not create the browser".to_owned())?;
+    {
+        let stream = ground.stream().clone();
+        browser.stream().observe(move |msg: &GameBrowserMsg| {
+            if let GameBrowserMsg::Open(ref stored) = *msg {
+                stream.emit(GroundMsg::LoadGame(stored.game.clone()));
+            }
+        });
+    }
+    browser.emit(GameBrowserMsg::SetGames(games));
+
+    let paned = gtk::Paned::new(gtk::Orientation::Horizontal);
+    paned.pack1(browser.widget(), true, false);
+    paned.pack2(ground.widget(), true, false);
+
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title("Chessground games");
+    window.set_default_size(1100, 600);
+    window.add(&paned);
+    window.connect_delete_event(|_, _| {
+        gtk::main_quit();
+        Inhibit(false)
+    });
+    window.show_all();
+
+    gtk::main();
+    Ok(())
+}
+
+fn verify(args: &ArgMatches, settings: &Settings) -> Result<(), String> {
+    let game_id = args.get_one::<String>("game-id").expect("required");
+    let config = match args.get_one::<String>("endpoint") {
+        Some(endpoint) => ChainConfig::new(endpoint),
+        None => settings.chain_config(),
+    };
+
+    let report = verify_game(&ChainClient::new(config), game_id).map_err(|err| err.to_string())?;
+    let hex = |hash: &[u8]| hash.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+
+    println!("game:       {}", report.game_id);
+    println!("moves:      {} on chain, {} replayed", report.records, report.replayed);
+    println!("position:   {}", report.fen);
+    println!("state hash: {}", hex(&report.state_root));
+    match report.expected_root {
+        Some(ref root) => println!("recorded:   {}", hex(root)),
+        None => println!("recorded:   none"),
+    }
+    for divergence in &report.divergences {
+        println!("divergence: {}", divergence);
+    }
+
+    if report.is_valid() {
+        Ok(())
+    } else {
+        Err(format!("{} does not verify", game_id))
+    }
+}
+
+fn render(args: &ArgMatches) -> Result<(), String> {
+    let game = load_game(args)?;
+    let ply = args.get_one::<usize>("ply").cloned().unwrap_or_else(|| game.len());
+    let size = *args.get_one::<u16>("size").expect("defaulted");
+    let out = args.get_one::<String>("out").expect("required");
+
+    let mut state = BoardState::new();
+    state.set_orientation(orientation(args));
+    state.set_position(&game.position_at(ply));
+    let last_move = game.tree().game_move(game.node_at(ply)).map(GameMove::mv);
+    state.set_last_move(last_move.and_then(|m| m.from().map(|from| (from, m.to()))));
+
+    let data = if Path::new(out).extension().map_or(false, |ext| ext == "svg") {
+        render_svg(&state, f64::from(size))
+    } else {
+        render_png(&state, i32::from(size))
+    };
+    fs::write(out, data.map_err(|err| err.to_string())?).map_err(|err| format!("{}: {}", out, err))
+}
+
+fn gif(args: &ArgMatches) -> Result<(), String> {
+    let game = load_game(args)?;
+    let out = args.get_one::<String>("out").expect("required");
+
+    let options = GifOptions {
+        size: *args.get_one::<u16>("size").expect("defaulted"),
+        delay: Duration::from_millis(*args.get_one::<u64>("delay").expect("defaulted")),
+        tween_frames: *args.get_one::<u32>("tween").expect("defaulted"),
+        orientation: orientation(args),
+        ..GifOptions::default()
+    };
+
+    let data = export_gif(&game, &options).map_err(|err| err.to_string())?;
+    fs::write(out, data).map_err(|err| format!("{}: {}", out, err))
+}
+
+/// Wait for an event of the engine, failing if it quit.
+fn wait_for<F: FnMut(EngineEvent) -> bool>(engine: &Engine, mut done: F) -> Result<(), String> {
+    loop {
+        match engine.events().recv() {
+            Ok(EngineEvent::Terminated) | Err(_) => return Err("engine terminated".to_owned()),
+            Ok(event) => if done(event) {
+                return Ok(());
+            },
+        }
+    }
+}
+
+fn engine_bench(args: &ArgMatches, settings: &Settings) -> Result<(), String> {
+    let program = match args.get_one::<String>("engine") {
+        Some(engine) => engine.into(),
+        None => settings.engine.clone().unwrap_or_else(|| "stockfish".into()),
+    };
+    let depth = *args.get_one::<u32>("depth").expect("defaulted");
+
+    let mut engine = Engine::spawn(&program).map_err(|err| format!("{}: {}", program.display(), err))?;
+    let mut name = None;
+    wait_for(&engine, |event| match event {
+        EngineEvent::Name(n) => { name = Some(n); false },
+        EngineEvent::UciOk => true,
+        _ => false,
+    })?;
+    println!("engine: {}", name.unwrap_or_else(|| program.display().to_string()));
+
+    let (mut total_nodes, mut total_time) = (0, Duration::from_secs(0));
+    for (index, fen) in BENCH_POSITIONS.iter().enumerate() {
+        let pos: Chess = fen.parse::<Fen>().expect("valid fen")
+            .into_position(CastlingMode::Standard).expect("legal position");
+
+        engine.new_game().and_then(|_| engine.is_ready()).map_err(|err| err.to_string())?;
+        wait_for(&engine, |event| match event {
+            EngineEvent::ReadyOk => true,
+            _ => false,
+        })?;
+
+        let start = Instant::now();
+        engine.go(&pos, Limit::Depth(depth)).map_err(|err| err.to_string())?;
+        let mut nodes = 0;
+        wait_for(&engine, |event| match event {
+            EngineEvent::Info(info) => {
+                nodes = info.nodes.unwrap_or(nodes);
+                false
+            },
+            EngineEvent::BestMove { .. } => true,
+            _ => false,
+        })?;
+        let time = start.elapsed();
+
+        println!("position {}: {} nodes in {:.2} s", index + 1, nodes, time.as_secs_f64());
+        total_nodes += nodes;
+        total_time += time;
+    }
+
+    println!("total: {} nodes in {:.2} s, {:.0} nodes/s",
+             total_nodes, total_time.as_secs_f64(), total_nodes as f64 / total_time.as_secs_f64().max(0.001));
+    Ok(())
+}
+
+fn daemon(args: &ArgMatches, settings: &Settings) -> Result<(), String> {
+    let addr = args.get_one::<String>("listen").expect("defaulted");
+    let mut daemon = Daemon::new().with_error_handler(|err| eprintln!("connection closed: {}", err));
+    for origin in args.get_many::<String>("origin").into_iter().flatten() {
+        daemon = daemon.with_origin(origin);
+    }
+    if let Some(token) = args.get_one::<String>("token") {
+        daemon = daemon.with_token(token);
+    }
+    if args.get_flag("record") {
+        daemon = daemon.with_chain(ChainClient::new(settings.chain_config()));
+    }
+
+    println!("daemon listening on {}", addr);
+    daemon.serve(addr.as_str()).map_err(|err| format!("{}: {}", addr, err))
+}
+
+fn main() {
+    let matches = cli().get_matches();
+
+    let config = match matches.get_one::<String>("config") {
+        Some(path) => Config::open(path),
+        None => Config::load(),
+    };
+    let settings = match config {
+        Ok(config) => config.settings().clone(),
+        Err(err) => {
+            eprintln!("chessground: {}", err);
+            process::exit(2);
+        }
+    };
+
+    let result = match matches.subcommand() {
+        Some(("play", _)) => play(&settings),
+        Some(("browse", args)) => browse(args, &settings),
+        Some(("verify", args)) => verify(args, &settings),
+        Some(("render", args)) => render(args),
+        Some(("export-gif", args)) => gif(args),
+        Some(("engine-bench", args)) => engine_bench(args, &settings),
+        Some(("daemon", args)) => daemon(args, &settings),
+        _ => unreachable!("subcommand required"),
+    };
+
+    if let Err(err) = result {
+        eprintln!("chessground: {}", err);
+        process::exit(1);
+    }
+}
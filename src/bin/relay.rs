@@ -0,0 +1,571 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! A relay server for hosting games over WebSocket.
+//!
+//! Clients speak the `NetMessage` protocol, one JSON message per text
+//! frame, and start with `Hello`. A `Hello` with an empty game id asks to
+//! be paired with the next player waiting, and is answered with a `Hello`
+//! for the new game. The first player of a game plays white. Clients
+//! joining a game with both seats taken watch as spectators.
+//!
+//! Seats belong to the key a client proved with `Identify` before its
+//! `Hello`, and stay reserved for that key when the client disconnects, so
+//! that only the same player can come back to them. Clients that did not
+//! identify only watch. Moves must be signed with the key of their seat.
+//!
+//! Lobby and tournament messages are sent to all clients, including the sender, so that
+//! the first acceptance of a challenge wins. The first identified client
+//! to announce pairings of a tournament organizes it, and only its key may
+//! announce further rounds. Results are taken from the organizer or the
+//! players seated in the game. Clients get the open
+//! challenges when they connect, and challenges are cancelled when their
+//! creator disconnects.
+//!
+//! Moves are checked against the rules before they are passed on. Every
+//! minute the state roots of games with new moves are recorded on chain,
+//! if an endpoint is given:
+//!
+//! ```text
+//! relay [ADDR] [CHAIN_ENDPOINT]
+//! ```
+
+extern crate chessground;
+extern crate serde_json;
+extern crate shakmaty;
+extern crate tungstenite;
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use shakmaty::{Color, Position};
+use tungstenite::{Message, WebSocket};
+
+use chessground::{verify_record, BoardState, ChainClient, ChainConfig, Challenge, MoveRecord, NetMessage, Pairing};
+
+/// How long a connection waits for a frame before sending queued messages.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Time between state root checkpoints on chain.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How far the time of an `Identify` may be off, in milliseconds.
+const IDENTIFY_MAX_AGE: u64 = 5 * 60 * 1000;
+
+type ClientId = usize;
+
+type PublicKey = [u8; 32];
+
+/// A seat of a game, reserved for the key of a player.
+struct Seat {
+    key: PublicKey,
+    /// The connection of the player, if connected.
+    client: Option<ClientId>,
+}
+
+struct Game {
+    state: BoardState,
+    moves: Vec<MoveRecord>,
+    /// White and black.
+    players: [Option<Seat>; 2],
+    spectators: Vec<ClientId>,
+    /// Number of plies covered by the last checkpoint.
+    checkpointed: u32,
+}
+
+impl Game {
+    fn new() -> Game {
+        Game {
+            state: BoardState::new(),
+            moves: Vec::new(),
+            players: [None, None],
+            spectators: Vec::new(),
+            checkpointed: 0,
+        }
+    }
+
+    fn ply(&self) -> u32 {
+        self.moves.len() as u32
+    }
+
+    fn hello(&self, game_id: &str) -> NetMessage {
+        NetMessage::Hello {
+            game_id: game_id.to_owned(),
+            ply: self.ply(),
+            root: Some(self.state.state_root()),
+        }
+    }
+
+    fn color_of(&self, client: ClientId) -> Option<Color> {
+        self.players.iter()
+            .position(|seat| seat.as_ref().map_or(false, |seat| seat.client == Some(client)))
+            .map(|index| Color::from_white(index == 0))
+    }
+
+    fn key_of(&self, color: Color) -> Option<&PublicKey> {
+        self.players[color.fold_wb(0, 1)].as_ref().map(|seat| &seat.key)
+    }
+
+    fn members(&self) -> Vec<ClientId> {
+        self.players.iter()
+            .filter_map(|seat| seat.as_ref().and_then(|seat| seat.client))
+            .chain(self.spectators.iter().cloned())
+            .collect()
+    }
+
+    /// Take the seat reserved for the key, or a free seat, or watch.
+    fn join(&mut self, client: ClientId, key: Option<&PublicKey>) {
+        self.leave(client);
+
+        let seat = key.and_then(|key| {
+            self.players.iter().position(|seat| seat.as_ref().map_or(false, |seat| seat.key == *key))
+                .or_else(|| self.players.iter().position(Option::is_none))
+                .map(|index| (index, *key))
+        });
+
+        match seat {
+            Some((index, key)) => self.players[index] = Some(Seat { key, client: Some(client) }),
+            None => self.spectators.push(client),
+        }
+    }
+
+    /// Keep the seat of the client reserved, but stop sending to it.
+    fn leave(&mut self, client: ClientId) {
+        for seat in self.players.iter_mut().flatten() {
+            if seat.client == Some(client) {
+                seat.client = None;
+            }
+        }
+        self.spectators.retain(|c| *c != client);
+    }
+
+    /// Play a move of a player, if it is their turn and the move is legal.
+    /// Moves that are already known are accepted without playing them
+    /// again.
+    fn play(&mut self, color: Color, record: &MoveRecord) -> Result<bool, String> {
+        if !self.key_of(color).map_or(false, |key| verify_record(key, record)) {
+            return Err(format!("move at ply {} not signed by {:?}", record.ply(), color));
+        }
+        if record.ply() < self.ply() {
+            let known = &self.moves[record.ply() as usize];
+            return if known.orig() == record.orig() && known.dest() == record.dest() && known.promotion() == record.promotion() {
+                Ok(false)
+            } else {
+                Err(format!("conflicting move at ply {}", record.ply()))
+            };
+        }
+        if record.ply() > self.ply() {
+            return Err(format!("expected ply {}, found {}", self.ply(), record.ply()));
+        }
+        if self.state.game_state().is_over() {
+            return Err("game is over".to_owned());
+        }
+        if self.state.position().map(|pos| pos.turn()) != Some(color) {
+            return Err(format!("not the turn of {:?}", color));
+        }
+
+        let m = self.state.legals().iter().find(|m| {
+            m.from() == Some(record.orig()) && m.to() == record.dest() && m.promotion() == record.promotion()
+        }).cloned();
+
+        match m {
+            Some(ref m) if self.state.play(m).is_ok() => {
+                self.moves.push(record.clone());
+                Ok(true)
+            },
+            _ => Err(format!("illegal move {}{} at ply {}", record.orig(), record.dest(), record.ply())),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Relay {
+    clients: HashMap<ClientId, Sender<String>>,
+    /// Keys proved by the clients with `Identify`.
+    keys: HashMap<ClientId, PublicKey>,
+    /// The game each client is in.
+    joined: HashMap<ClientId, String>,
+    games: HashMap<String, Game>,
+    /// A client waiting to be paired.
+    waiting: Option<ClientId>,
+    /// Organizers of the tournaments, and the games of their pairings.
+    tournaments: HashMap<String, (PublicKey, HashSet<String>)>,
+    /// Open challenges in the lobby, with the client that created them.
+    challenges: Vec<(ClientId, Challenge)>,
+    next_client: ClientId,
+    next_game: u64,
+}
+
+impl Relay {
+    fn connect(&mut self, tx: Sender<String>) -> ClientId {
+        let client = self.next_client;
+        self.next_client += 1;
+        self.clients.insert(client, tx);
+        for &(_, ref challenge) in &self.challenges {
+            self.send(client, &NetMessage::Challenge(challenge.clone()));
+        }
+        client
+    }
+
+    fn disconnect(&mut self, client: ClientId) {
+        self.clients.remove(&client);
+        self.keys.remove(&client);
+        if self.waiting == Some(client) {
+            self.waiting = None;
+        }
+        let (abandoned, open): (Vec<_>, Vec<_>) = self.challenges.drain(..).partition(|&(owner, _)| owner == client);
+        self.challenges = open;
+        for (_, challenge) in abandoned {
+            self.send_all(&NetMessage::CancelChallenge(challenge.id));
+        }
+        if let Some(game_id) = self.joined.remove(&client) {
+            if let Some(game) = self.games.get_mut(&game_id) {
+                game.leave(client);
+            }
+        }
+    }
+
+    fn send(&self, client: ClientId, msg: &NetMessage) {
+        if let Some(tx) = self.clients.get(&client) {
+            let _ = tx.send(msg.to_json().to_string());
+        }
+    }
+
+    fn send_all(&self, msg: &NetMessage) {
+        for &client in self.clients.keys() {
+            self.send(client, msg);
+        }
+    }
+
+    /// Send to everyone in the game except the sender.
+    fn broadcast(&self, game_id: &str, sender: ClientId, msg: &NetMessage) {
+        if let Some(game) = self.games.get(game_id) {
+            for client in game.members() {
+                if client != sender {
+                    self.send(client, msg);
+                }
+            }
+        }
+    }
+
+    fn join(&mut self, client: ClientId, game_id: String) {
+        if let Some(previous) = self.joined.insert(client, game_id.clone()) {
+            if previous != game_id {
+                if let Some(game) = self.games.get_mut(&previous) {
+                    game.leave(client);
+                }
+            }
+        }
+        let key = self.keys.get(&client);
+        self.games.entry(game_id).or_insert_with(Game::new).join(client, key);
+    }
+
+    fn pair(&mut self, client: ClientId) {
+        // players without a key could not take their seats
+        let key = match self.keys.get(&client) {
+            Some(key) => *key,
+            None => return,
+        };
+
+        match self.waiting.take() {
+            Some(waiting) if self.clients.contains_key(&waiting) && self.keys.get(&waiting).map_or(false, |k| *k != key) => {
+                let game_id = format!("relay-{}", self.next_game);
+                self.next_game += 1;
+                self.join(waiting, game_id.clone());
+                self.join(client, game_id.clone());
+
+                let hello = self.games[&game_id].hello(&game_id);
+                self.send(waiting, &hello);
+                self.send(client, &hello);
+            },
+            _ => self.waiting = Some(client),
+        }
+    }
+
+    fn receive(&mut self, client: ClientId, msg: NetMessage) {
+        if let NetMessage::Identify(ref identity) = msg {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+            if identity.verify(now, IDENTIFY_MAX_AGE) {
+                self.keys.insert(client, *identity.public_key());
+            } else {
+                eprintln!("client {}: invalid identity ignored", client);
+            }
+            return;
+        }
+
+        if let NetMessage::Hello { ref game_id, ply, .. } = msg {
+            if game_id.is_empty() {
+                self.pair(client);
+            } else {
+                self.join(client, game_id.clone());
+                let game = &self.games[game_id];
+                let missing: Vec<_> = game.moves.iter().filter(|r| r.ply() >= ply).cloned().collect();
+                self.send(client, &game.hello(game_id));
+                if !missing.is_empty() {
+                    self.send(client, &NetMessage::Resync(missing));
+                }
+            }
+            return;
+        }
+
+        match msg {
+            NetMessage::Challenge(ref challenge) => {
+                if !self.challenges.iter().any(|&(_, ref c)| c.id == challenge.id) {
+                    self.challenges.push((client, challenge.clone()));
+                    self.send_all(&msg);
+                }
+                return;
+            },
+            // only the creator can cancel, and only others can accept
+            NetMessage::CancelChallenge(ref id) => {
+                self.close_challenge(id, |owner| owner == client, &msg);
+                return;
+            },
+            NetMessage::AcceptChallenge { ref id, .. } => {
+                self.close_challenge(id, |owner| owner != client, &msg);
+                return;
+            },
+            // for tournament organizers and players
+            NetMessage::Pairings { ref tournament, ref pairings, .. } => {
+                if self.announce(client, tournament, pairings) {
+                    self.send_all(&msg);
+                } else {
+                    eprintln!("{}: pairings from client {} ignored", tournament, client);
+                }
+                return;
+            },
+            NetMessage::GameOver { ref game_id, .. } => {
+                if self.may_report(client, game_id) {
+                    self.send_all(&msg);
+                } else {
+                    eprintln!("{}: result from client {} ignored", game_id, client);
+                }
+                return;
+            },
+            _ => (),
+        }
+
+        let game_id = match self.joined.get(&client) {
+            Some(game_id) => game_id.clone(),
+            None => return,
+        };
+        let color = self.games.get(&game_id).and_then(|game| game.color_of(client));
+
+        match msg {
+            NetMessage::Hello { .. } | NetMessage::Identify(_) | NetMessage::Challenge(_) |
+            NetMessage::CancelChallenge(_) | NetMessage::AcceptChallenge { .. } |
+            NetMessage::Pairings { .. } | NetMessage::GameOver { .. } => (),
+            NetMessage::Chat(ref chat) if chat.room == game_id => self.broadcast(&game_id, client, &msg),
+            NetMessage::Chat(_) => (),
+            NetMessage::Move(ref record) => {
+                if let Some(color) = color {
+                    self.relay_moves(&game_id, client, color, vec![record.clone()]);
+                }
+            },
+            NetMessage::Resync(records) => {
+                if let Some(color) = color {
+                    self.relay_moves(&game_id, client, color, records);
+                }
+            },
+            NetMessage::OfferDraw | NetMessage::AcceptDraw | NetMessage::DeclineDraw |
+            NetMessage::Resign | NetMessage::Clock { .. } => {
+                let color = match color {
+                    Some(color) => color,
+                    None => return,
+                };
+                if let Some(game) = self.games.get_mut(&game_id) {
+                    match msg {
+                        NetMessage::OfferDraw => { game.state.offer_draw(color); },
+                        NetMessage::AcceptDraw => { game.state.accept_draw(color); },
+                        NetMessage::Resign => { game.state.resign(color); },
+                        _ => (),
+                    }
+                }
+                self.broadcast(&game_id, client, &msg);
+            },
+        }
+    }
+
+    /// Accept pairings only from the organizer of the tournament, which is
+    /// the first identified client to announce pairings for it.
+    fn announce(&mut self, client: ClientId, tournament: &str, pairings: &[Pairing]) -> bool {
+        let key = match self.keys.get(&client) {
+            Some(key) => *key,
+            None => return false,
+        };
+        let &mut (ref organizer, ref mut games) = self.tournaments.entry(tournament.to_owned())
+            .or_insert_with(|| (key, HashSet::new()));
+        if *organizer != key {
+            return false;
+        }
+        games.extend(pairings.iter().map(|pairing| pairing.game_id.clone()));
+        true
+    }
+
+    /// Results come from a player of the game, or from the organizer of a
+    /// tournament it belongs to.
+    fn may_report(&self, client: ClientId, game_id: &str) -> bool {
+        if self.games.get(game_id).map_or(false, |game| game.color_of(client).is_some()) {
+            return true;
+        }
+        self.keys.get(&client).map_or(false, |key| {
+            self.tournaments.values().any(|&(ref organizer, ref games)| organizer == key && games.contains(game_id))
+        })
+    }
+
+    fn close_challenge<F: Fn(ClientId) -> bool>(&mut self, id: &str, allowed: F, msg: &NetMessage) {
+        let index = self.challenges.iter().position(|&(owner, ref c)| c.id == id && allowed(owner));
+        if let Some(index) = index {
+            self.challenges.remove(index);
+            self.send_all(msg);
+        }
+    }
+
+    /// Check the moves of a player and pass the new ones on.
+    fn relay_moves(&mut self, game_id: &str, client: ClientId, color: Color, records: Vec<MoveRecord>) {
+        let mut new = Vec::new();
+        if let Some(game) = self.games.get_mut(game_id) {
+            for record in records {
+                if record.game_id() != game_id {
+                    eprintln!("{}: move of game {} ignored", game_id, record.game_id());
+                    continue;
+                }
+                match game.play(color, &record) {
+                    Ok(true) => new.push(record),
+                    Ok(false) => (),
+                    Err(err) => {
+                        eprintln!("{}: {}", game_id, err);
+                        break;
+                    }
+                }
+            }
+        }
+
+        for record in new {
+            self.broadcast(game_id, client, &NetMessage::Move(record));
+        }
+    }
+
+    /// Games with moves since their last checkpoint, with their state
+    /// roots.
+    fn pending_checkpoints(&self) -> Vec<(String, u32, chessground::Hash)> {
+        self.games.iter()
+            .filter(|&(_, game)| game.ply() > game.checkpointed)
+            .map(|(game_id, game)| (game_id.clone(), game.ply(), game.state.state_root()))
+            .collect()
+    }
+}
+
+fn serve(relay: &Mutex<Relay>, stream: TcpStream) -> tungstenite::Result<()> {
+    let mut ws = tungstenite::accept(stream).map_err(|err| match err {
+        tungstenite::HandshakeError::Failure(err) => err,
+        tungstenite::HandshakeError::Interrupted(_) => tungstenite::Error::Io(io::ErrorKind::WouldBlock.into()),
+    })?;
+    ws.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+
+    let (tx, outgoing) = mpsc::channel();
+    let client = relay.lock().expect("relay lock").connect(tx);
+    let result = run_client(relay, client, &mut ws, &outgoing);
+    relay.lock().expect("relay lock").disconnect(client);
+    result
+}
+
+fn run_client(relay: &Mutex<Relay>, client: ClientId, ws: &mut WebSocket<TcpStream>, outgoing: &Receiver<String>) -> tungstenite::Result<()> {
+    loop {
+        loop {
+            match outgoing.try_recv() {
+                Ok(text) => ws.write_message(Message::Text(text))?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+
+        match ws.read_message() {
+            Ok(Message::Text(text)) => {
+                let msg = serde_json::from_str(&text).ok().and_then(|value| NetMessage::from_json(&value));
+                if let Some(msg) = msg {
+                    relay.lock().expect("relay lock").receive(client, msg);
+                }
+            },
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => (),
+            Err(tungstenite::Error::Io(ref err)) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => (),
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn checkpoint(relay: &Mutex<Relay>, client: &ChainClient) {
+    loop {
+        thread::sleep(CHECKPOINT_INTERVAL);
+
+        // talk to the chain without holding the lock
+        let pending = relay.lock().expect("relay lock").pending_checkpoints();
+        for (game_id, ply, root) in pending {
+            match client.submit_state_root(&game_id, ply, &root) {
+                Ok(tx) => {
+                    println!("{}: checkpoint at ply {} in {}", game_id, ply, tx);
+                    if let Some(game) = relay.lock().expect("relay lock").games.get_mut(&game_id) {
+                        game.checkpointed = game.checkpointed.max(ply);
+                    }
+                },
+                Err(err) => eprintln!("{}: checkpoint failed: {}", game_id, err),
+            }
+        }
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:9001".to_owned());
+    let endpoint = args.next();
+
+    let listener = TcpListener::bind(&addr).expect("bind relay address");
+    println!("relay listening on {}", addr);
+
+    let relay = Arc::new(Mutex::new(Relay::default()));
+
+    if let Some(endpoint) = endpoint {
+        let relay = relay.clone();
+        let client = ChainClient::new(ChainConfig::new(&endpoint));
+        thread::spawn(move || checkpoint(&relay, &client));
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("accept failed: {}", err);
+                continue;
+            }
+        };
+
+        let relay = relay.clone();
+        thread::spawn(move || {
+            if let Err(err) = serve(&relay, stream) {
+                eprintln!("connection closed: {}", err);
+            }
+        });
+    }
+}
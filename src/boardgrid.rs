@@ -0,0 +1,160 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+use std::fmt;
+
+use gtk::prelude::*;
+use gtk::Grid;
+
+use relm::{Component, Relm, StreamHandle, Update, Widget};
+
+use ground::{Ground, GroundMsg};
+
+/// Board grid messages.
+#[derive(Debug, Msg)]
+pub enum BoardGridMsg {
+    /// Pass a message to the board with the given index.
+    ToBoard(usize, GroundMsg),
+    /// Append another board.
+    AddBoard,
+    /// Remove the last board.
+    RemoveBoard,
+    /// Change the number of boards per row. `0` picks a roughly square
+    /// layout.
+    SetColumns(usize),
+
+    /// Sent when the board with the given index emits an event, like
+    /// `UserMove` or `GameEnd`.
+    BoardEvent(usize, GroundMsg),
+}
+
+/// Several independent boards laid out in rows, e.g. for a simul or to
+/// analyse next to a running game. Each board has its own position,
+/// pieces and settings.
+pub struct BoardGrid {
+    grid: Grid,
+    stream: StreamHandle<BoardGridMsg>,
+    boards: Vec<Component<Ground>>,
+    columns: usize,
+}
+
+impl fmt::Debug for BoardGrid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BoardGrid")
+            .field("boards", &self.boards.len())
+            .field("columns", &self.columns)
+            .finish()
+    }
+}
+
+impl BoardGrid {
+    fn add_board(&mut self) {
+        let board = relm::create_component::<Ground>(());
+
+        let index = self.boards.len();
+        let stream = self.stream.clone();
+        board.stream().observe(move |msg: &GroundMsg| {
+            if let Some(event) = msg.clone_event() {
+                stream.emit(BoardGridMsg::BoardEvent(index, event));
+            }
+        });
+
+        board.widget().show();
+        self.boards.push(board);
+        self.layout();
+    }
+
+    fn remove_board(&mut self) {
+        if let Some(board) = self.boards.pop() {
+            self.grid.remove(board.widget());
+            self.layout();
+        }
+    }
+
+    fn layout(&self) {
+        let columns = match self.columns {
+            0 => (1..).find(|c| c * c >= self.boards.len()).unwrap_or(1),
+            columns => columns,
+        };
+
+        for child in self.grid.children() {
+            self.grid.remove(&child);
+        }
+
+        for (index, board) in self.boards.iter().enumerate() {
+            let (row, column) = (index / columns, index % columns);
+            self.grid.attach(board.widget(), column as i32, row as i32, 1, 1);
+        }
+    }
+}
+
+impl Update for BoardGrid {
+    type Model = usize;
+    type ModelParam = usize;
+    type Msg = BoardGridMsg;
+
+    fn model(_: &Relm<Self>, boards: usize) -> usize {
+        boards
+    }
+
+    fn update(&mut self, event: BoardGridMsg) {
+        match event {
+            BoardGridMsg::ToBoard(index, msg) => {
+                if let Some(board) = self.boards.get(index) {
+                    board.emit(msg);
+                }
+            },
+            BoardGridMsg::AddBoard => self.add_board(),
+            BoardGridMsg::RemoveBoard => self.remove_board(),
+            BoardGridMsg::SetColumns(columns) => {
+                self.columns = columns;
+                self.layout();
+            },
+            BoardGridMsg::BoardEvent(..) => {},
+        }
+    }
+}
+
+impl Widget for BoardGrid {
+    type Root = Grid;
+
+    fn root(&self) -> Self::Root {
+        self.grid.clone()
+    }
+
+    fn view(relm: &Relm<Self>, boards: usize) -> Self {
+        let grid = Grid::new();
+        grid.set_row_spacing(8);
+        grid.set_column_spacing(8);
+        grid.set_row_homogeneous(true);
+        grid.set_column_homogeneous(true);
+        grid.show();
+
+        let mut board_grid = BoardGrid {
+            grid,
+            stream: relm.stream().clone(),
+            boards: Vec::new(),
+            columns: 0,
+        };
+
+        for _ in 0..boards {
+            board_grid.add_board();
+        }
+
+        board_grid
+    }
+}
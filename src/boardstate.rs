@@ -14,22 +14,304 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::error::Error;
 use std::f64::consts::PI;
+use std::fmt;
+use std::time::Duration;
 
-use cairo::{Context, RadialGradient};
+use cairo::Context;
+use pango::FontDescription;
 
-use shakmaty::{Color, Square, Role, Bitboard, Chess, Position, Move, MoveList};
+use shakmaty::{Color, Square, File, Rank, Role, Piece, Board, Bitboard, Chess, Position, FromSetup,
+               Setup, Material, CastlingMode, EnPassantMode, Move, MoveList, Outcome};
+use shakmaty::variant::{Variant, VariantPosition};
+use shakmaty::fen::{Fen, ParseFenError};
+use shakmaty::san::{San, SanError as ShakmatySanError, SanPlus};
+use shakmaty::uci::Uci;
+
+#[cfg(feature = "blockchain")]
+use commitment::{Commitment, Hash};
+use zobrist::{move_delta, zobrist_hash};
+use game::{Game, IllegalMoveError};
 
 use pieceset::PieceSet;
+use renderer::Renderer;
+use theme::Theme;
+use shapes::{Arrow, DrawShape};
 use util::{file_to_float, rank_to_float};
 
+const FILE_GLYPHS: [&str; 8] = ["a", "b", "c", "d", "e", "f", "g", "h"];
+const RANK_GLYPHS: [&str; 8] = ["1", "2", "3", "4", "5", "6", "7", "8"];
+
+/// How pieces are moved with the mouse.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum MoveInput {
+    /// Either way.
+    Both,
+    /// Click a piece to select it, then click the destination.
+    Click,
+    /// Drag a piece to the destination.
+    Drag,
+}
+
+/// Which side the local user may move.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum MovableSide {
+    /// Pieces of both colors, e.g. for analysis.
+    Both,
+    /// Only the white pieces.
+    White,
+    /// Only the black pieces.
+    Black,
+    /// No pieces at all.
+    None,
+}
+
+impl MovableSide {
+    pub fn allows(self, color: Color) -> bool {
+        match self {
+            MovableSide::Both => true,
+            MovableSide::White => color == Color::White,
+            MovableSide::Black => color == Color::Black,
+            MovableSide::None => false,
+        }
+    }
+}
+
+/// How a square given to `set_highlights()` is highlighted, each with a
+/// color of the theme.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum HighlightKind {
+    /// Filled like the squares of the last move.
+    LastMove,
+    /// Glowing like the square of a king in check.
+    Check,
+    /// Filled with the threat color, e.g. for attacked pieces.
+    Threat,
+    /// Filled with the hint color, e.g. for a suggested move.
+    Hint,
+}
+
+/// Whether the board turns by itself to face a side.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum AutoOrient {
+    /// The board keeps the orientation it was given.
+    Off,
+    /// The side to move is at the bottom, e.g. for two players sharing
+    /// a screen.
+    SideToMove,
+    /// The side the local player may move is at the bottom, e.g. in
+    /// online games. Nothing turns while both or no sides are movable.
+    Player,
+}
+
+/// A position before or after a move, for `undo()` and `redo()`.
+#[derive(Debug, Clone)]
+struct Ply {
+    setup: Setup,
+    last_move: Option<(Square, Square)>,
+    zobrist: u64,
+    /// The move played from this position, or that led to it in the redo
+    /// stack.
+    m: Move,
+}
+
+/// Whether a game is running, and if not, how it ended.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum GameState {
+    Ongoing,
+    /// Checkmate, won by the given side.
+    Checkmate(Color),
+    Stalemate,
+    /// Drawn by repetition, the fifty-move rule or insufficient material.
+    Drawn(DrawReason),
+    /// Ended by a variant specific rule, like a third check or a king on
+    /// the hill.
+    VariantEnd(Outcome),
+    /// The given side resigned.
+    Resigned(Color),
+    /// The time of the given side ran out.
+    Flagged(Color),
+    DrawAgreed,
+    /// Stopped without a result.
+    Aborted,
+}
+
+impl GameState {
+    pub fn is_over(self) -> bool {
+        self != GameState::Ongoing
+    }
+
+    /// The result, or `None` if the game is still running or was aborted.
+    pub fn outcome(self) -> Option<Outcome> {
+        match self {
+            GameState::Ongoing | GameState::Aborted => None,
+            GameState::Checkmate(winner) => Some(Outcome::Decisive { winner }),
+            GameState::Stalemate | GameState::Drawn(_) | GameState::DrawAgreed => Some(Outcome::Draw),
+            GameState::VariantEnd(outcome) => Some(outcome),
+            GameState::Resigned(loser) | GameState::Flagged(loser) => Some(Outcome::Decisive { winner: !loser }),
+        }
+    }
+}
+
+impl fmt::Display for GameState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GameState::Ongoing => f.write_str("ongoing"),
+            GameState::Checkmate(winner) => write!(f, "{} wins by checkmate", winner.fold_wb("white", "black")),
+            GameState::Stalemate => f.write_str("draw by stalemate"),
+            GameState::Drawn(reason) => write!(f, "draw by {}", reason),
+            GameState::VariantEnd(Outcome::Decisive { winner }) => write!(f, "{} wins by variant rules", winner.fold_wb("white", "black")),
+            GameState::VariantEnd(Outcome::Draw) => f.write_str("draw by variant rules"),
+            GameState::Resigned(loser) => write!(f, "{} resigned", loser.fold_wb("white", "black")),
+            GameState::Flagged(loser) => write!(f, "{} lost on time", loser.fold_wb("white", "black")),
+            GameState::DrawAgreed => f.write_str("draw by agreement"),
+            GameState::Aborted => f.write_str("aborted"),
+        }
+    }
+}
+
+/// Why a game ended in a draw by the rules.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum DrawReason {
+    /// The side to move has no legal moves, but is not in check.
+    Stalemate,
+    /// The same position occurred three times.
+    Repetition,
+    /// Fifty moves by each side without a capture or pawn move.
+    FiftyMoves,
+    /// Neither side can possibly checkmate.
+    InsufficientMaterial,
+}
+
+impl fmt::Display for DrawReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            DrawReason::Stalemate => "stalemate",
+            DrawReason::Repetition => "threefold repetition",
+            DrawReason::FiftyMoves => "fifty-move rule",
+            DrawReason::InsufficientMaterial => "insufficient material",
+        })
+    }
+}
+
+/// Error when applying moves with `BoardState::apply_uci_moves()`. The
+/// index counts the moves of the list from 0. No move is applied if any
+/// of them fails.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum UciMovesError {
+    /// The board is not set up with a legal position.
+    IllegalPosition,
+    /// A move is not in UCI notation, like `e2e4` or `e7e8q`.
+    InvalidUci { index: usize, uci: String },
+    /// A move is not legal after the moves before it.
+    IllegalMove { index: usize, uci: String },
+}
+
+impl fmt::Display for UciMovesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UciMovesError::IllegalPosition => f.write_str("illegal position"),
+            UciMovesError::InvalidUci { index, ref uci } => write!(f, "invalid uci {} at move {}", uci, index + 1),
+            UciMovesError::IllegalMove { index, ref uci } => write!(f, "illegal move {} at move {}", uci, index + 1),
+        }
+    }
+}
+
+impl Error for UciMovesError {}
+
+/// Error when reading a move with `BoardState::parse_san()`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SanError {
+    /// The text is not in standard algebraic notation.
+    Invalid(String),
+    /// The move is not legal on the board.
+    Illegal(String),
+    /// The move could be one of several legal moves, like `Nd2` with
+    /// knights on `b1` and `f3`.
+    Ambiguous(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SanError::Invalid(ref san) => write!(f, "invalid san: {}", san),
+            SanError::Illegal(ref san) => write!(f, "illegal move: {}", san),
+            SanError::Ambiguous(ref san) => write!(f, "ambiguous move: {}", san),
+        }
+    }
+}
+
+impl Error for SanError {}
+
+/// Where file and rank labels are drawn.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Coordinates {
+    /// In the border around the board.
+    Outside,
+    /// In the corners of the squares along the bottom and left edge.
+    Inside,
+}
+
+/// The position shown on the board, together with highlights and other
+/// hints.
 pub struct BoardState {
+    setup: Setup,
+    variant: Variant,
+    castling_mode: CastlingMode,
+    editing: bool,
+    move_hints: bool,
+    threats: bool,
+    hover_hints: Option<Duration>,
+    snap_radius: f64,
+    move_input: MoveInput,
+    auto_queen: bool,
+    view_only: bool,
+    movable: MovableSide,
+    show_coordinates: bool,
+    coordinates: Coordinates,
+    spare_piece: Option<Piece>,
     orientation: Color,
+    auto_orient: AutoOrient,
     check: Option<Square>,
     last_move: Option<(Square, Square)>,
+    premove: Option<(Square, Square)>,
     turn: Option<Color>,
     piece_set: PieceSet,
+    theme: Theme,
     legals: MoveList,
+    shapes: Vec<DrawShape>,
+    engine_shapes: Vec<DrawShape>,
+    /// Arrows set by the caller, beneath the shapes of the user.
+    arrows: Vec<Arrow>,
+    /// Squares highlighted by the caller, over the last move.
+    highlights: Vec<(Square, HighlightKind)>,
+    tablebase_shapes: Vec<DrawShape>,
+    #[cfg(feature = "blockchain")]
+    commitment: Commitment,
+    /// Positions and last moves before each `play()`, for takebacks, and
+    /// the hashes of the positions to detect repetitions.
+    history: Vec<Ply>,
+    /// Plies taken back with `undo()`, to be replayed with `redo()`.
+    redo: Vec<Ply>,
+    /// Most plies kept for `undo()`, or `None` for all.
+    history_limit: Option<usize>,
+    draw_offer: Option<Color>,
+    takeback_offer: Option<Color>,
+    /// How the game ended, if not by the position on the board.
+    ended: Option<GameState>,
+    /// Zobrist hash of `setup`, updated with each move.
+    zobrist: u64,
+}
+
+impl fmt::Debug for BoardState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BoardState")
+            .field("variant", &self.variant)
+            .field("fen", &self.fen())
+            .field("orientation", &self.orientation)
+            .finish()
+    }
 }
 
 impl BoardState {
@@ -37,30 +319,804 @@ impl BoardState {
         BoardState::from_position(&Chess::default())
     }
 
-    pub fn from_position<P: Position>(pos: &P) -> Self {
+    pub fn from_position<P: Position + Clone>(pos: &P) -> Self {
         let mut state = BoardState {
+            setup: Setup::empty(),
+            variant: Variant::Chess,
+            castling_mode: CastlingMode::Standard,
+            editing: false,
+            move_hints: true,
+            threats: false,
+            hover_hints: None,
+            snap_radius: 0.25,
+            move_input: MoveInput::Both,
+            auto_queen: false,
+            view_only: false,
+            movable: MovableSide::Both,
+            show_coordinates: true,
+            coordinates: Coordinates::Outside,
+            spare_piece: None,
             orientation: pos.turn(),
+            auto_orient: AutoOrient::Off,
             check: None,
             last_move: None,
+            premove: None,
             turn: None,
             piece_set: PieceSet::merida(),
+            theme: Theme::default(),
             legals: MoveList::new(),
+            shapes: Vec::new(),
+            engine_shapes: Vec::new(),
+            arrows: Vec::new(),
+            highlights: Vec::new(),
+            tablebase_shapes: Vec::new(),
+            #[cfg(feature = "blockchain")]
+            commitment: Commitment::new(""),
+            history: Vec::new(),
+            redo: Vec::new(),
+            history_limit: None,
+            draw_offer: None,
+            takeback_offer: None,
+            ended: None,
+            zobrist: 0,
         };
 
         state.set_position(pos);
+        #[cfg(feature = "blockchain")]
+        state.commitment = Commitment::new(&state.fen());
         state
     }
 
-    pub fn set_position<P: Position>(&mut self, pos: &P) {
+    /// Set up a position of any variant, using its rules from now on.
+    pub fn from_variant_position(pos: &VariantPosition) -> Self {
+        let mut state = BoardState::from_position(pos);
+        state.variant = pos.variant();
+        state
+    }
+
+    /// Set up one of the 960 starting positions of Chess960, numbered
+    /// as proposed by Reinhard Scharnagl. Number 518 is the standard
+    /// starting position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `scharnagl_number` is not below 960.
+    pub fn new_chess960(scharnagl_number: u32) -> Self {
+        let mut state = BoardState::new();
+        state.set_chess960(scharnagl_number);
+        state
+    }
+
+    /// Like `new_chess960()`, but keeps the orientation and other
+    /// settings.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `scharnagl_number` is not below 960.
+    pub fn set_chess960(&mut self, scharnagl_number: u32) {
+        let back_rank = chess960_back_rank(scharnagl_number);
+
+        let mut setup = Setup::empty();
+        for (file, &role) in back_rank.iter().enumerate() {
+            let file = File::new(file as u32);
+            setup.board.set_piece_at(Square::from_coords(file, Rank::First), role.of(Color::White));
+            setup.board.set_piece_at(Square::from_coords(file, Rank::Second), Role::Pawn.of(Color::White));
+            setup.board.set_piece_at(Square::from_coords(file, Rank::Seventh), Role::Pawn.of(Color::Black));
+            setup.board.set_piece_at(Square::from_coords(file, Rank::Eighth), role.of(Color::Black));
+        }
+        setup.castling_rights = setup.board.rooks() & (Bitboard::rank(Rank::First) | Bitboard::rank(Rank::Eighth));
+
+        let pos = Chess::from_setup(setup, CastlingMode::Chess960).expect("valid chess960 position");
+        self.variant = Variant::Chess;
+        self.castling_mode = CastlingMode::Chess960;
+        self.set_position(&pos);
+        self.last_move = None;
+        self.premove = None;
+        #[cfg(feature = "blockchain")]
+        self.commitment = Commitment::new(&self.fen());
+        self.reset_game();
+    }
+
+    pub fn set_position<P: Position + Clone>(&mut self, pos: &P) {
+        self.load_position(pos);
+        self.zobrist = zobrist_hash(&self.setup);
+    }
+
+    fn load_position<P: Position + Clone>(&mut self, pos: &P) {
         self.check = if pos.checkers().any() { pos.board().king_of(pos.turn()) } else { None };
         self.legals = pos.legal_moves();
         self.turn = Some(pos.turn());
+        self.setup = pos.clone().into_setup(EnPassantMode::Legal);
+    }
+
+    /// The rules used for legal move hints and `play()`.
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Switch to another variant, keeping the pieces on the board if the
+    /// position is legal in the new variant. Otherwise the starting
+    /// position of the variant is set up.
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+
+        let mut setup = self.setup.clone();
+        if variant == Variant::Crazyhouse {
+            setup.pockets = setup.pockets.or_else(|| Some(Material::new()));
+        } else {
+            setup.pockets = None;
+        }
+
+        let pos = VariantPosition::from_setup(variant, setup, self.castling_mode)
+            .unwrap_or_else(|_| VariantPosition::new(variant));
+        self.set_position(&pos);
+        self.last_move = None;
+        self.premove = None;
+        #[cfg(feature = "blockchain")]
+        self.commitment = Commitment::new(&self.fen());
+        self.reset_game();
+    }
+
+    /// Chess960 castling rights allow castling with rooks on any file.
+    pub fn castling_mode(&self) -> CastlingMode {
+        self.castling_mode
+    }
+
+    pub fn set_castling_mode(&mut self, mode: CastlingMode) {
+        self.castling_mode = mode;
+    }
+
+    pub fn setup(&self) -> &Setup {
+        &self.setup
+    }
+
+    pub fn set_setup(&mut self, setup: Setup) {
+        self.setup = setup;
+        self.zobrist = zobrist_hash(&self.setup);
+        #[cfg(feature = "blockchain")]
+        self.commitment = Commitment::new(&self.fen());
+        self.reset_game();
+    }
+
+    /// The moves played since the position was set up, as far as the
+    /// history limit keeps them, e.g. for saving the game. `None` in
+    /// variants other than standard chess.
+    pub fn to_game(&self) -> Option<Game> {
+        if self.variant != Variant::Chess {
+            return None;
+        }
+
+        let initial = self.history.first().map_or(&self.setup, |ply| &ply.setup).clone();
+        let mut game = Game::from_position(Chess::from_setup(initial, self.castling_mode).ok()?);
+        for ply in &self.history {
+            game.push(ply.m.clone()).ok()?;
+        }
+        Some(game)
+    }
+
+    /// Forget the move history, offers and how the game ended, when a new
+    /// position is set up.
+    fn reset_game(&mut self) {
+        self.history.clear();
+        self.redo.clear();
+        self.draw_offer = None;
+        self.takeback_offer = None;
+        self.ended = None;
+    }
+
+    /// Number of plies played since the start of the game, derived from
+    /// the move number and side to move.
+    pub fn ply(&self) -> u32 {
+        2 * (u32::from(self.setup.fullmoves) - 1) + self.setup.turn.fold_wb(0, 1)
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.setup.board
+    }
+
+    pub fn set_board(&mut self, board: Board) {
+        self.setup.board = board;
+        self.zobrist = zobrist_hash(&self.setup);
+    }
+
+    /// The position in Forsyth-Edwards Notation.
+    pub fn fen(&self) -> String {
+        Fen::from_setup(self.setup.clone()).to_string()
+    }
+
+    /// Set up a position from Forsyth-Edwards Notation. Illegal positions
+    /// are accepted, but have no legal move hints.
+    pub fn set_fen(&mut self, fen: &str) -> Result<(), ParseFenError> {
+        let setup = fen.parse::<Fen>()?.into_setup();
+
+        match VariantPosition::from_setup(self.variant, setup.clone(), self.castling_mode) {
+            Ok(pos) => self.set_position(&pos),
+            Err(_) => {
+                self.check = None;
+                self.legals.clear();
+                self.turn = Some(setup.turn);
+            }
+        }
+
+        self.setup = setup;
+        self.zobrist = zobrist_hash(&self.setup);
+        self.last_move = None;
+        self.premove = None;
+        #[cfg(feature = "blockchain")]
+        self.commitment = Commitment::new(&self.fen());
+        self.reset_game();
+        Ok(())
+    }
+
+    /// The position, if it is legal in the variant.
+    pub fn position(&self) -> Option<VariantPosition> {
+        VariantPosition::from_setup(self.variant, self.setup.clone(), self.castling_mode).ok()
+    }
+
+    /// Whether the game is still running, and if not, how it ended.
+    pub fn game_state(&self) -> GameState {
+        if let Some(ended) = self.ended {
+            return ended;
+        }
+
+        let pos = match self.position() {
+            Some(pos) => pos,
+            None => return GameState::Ongoing,
+        };
+
+        if let Some(outcome) = pos.variant_outcome() {
+            GameState::VariantEnd(outcome)
+        } else if pos.is_checkmate() {
+            GameState::Checkmate(!pos.turn())
+        } else {
+            match self.draw_reason() {
+                Some(DrawReason::Stalemate) => GameState::Stalemate,
+                Some(reason) => GameState::Drawn(reason),
+                None => GameState::Ongoing,
+            }
+        }
+    }
+
+    /// The result of the game, if it is over.
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.game_state().outcome()
+    }
+
+    /// Why the game is drawn, if it is drawn by the rules rather than by
+    /// agreement. Repetitions and the fifty-move rule draw automatically.
+    pub fn draw_reason(&self) -> Option<DrawReason> {
+        if self.ended.is_some() {
+            return None;
+        }
+
+        let pos = self.position()?;
+        if pos.variant_outcome().is_some() || pos.is_checkmate() {
+            None
+        } else if pos.is_insufficient_material() {
+            Some(DrawReason::InsufficientMaterial)
+        } else if pos.is_stalemate() {
+            Some(DrawReason::Stalemate)
+        } else if self.setup.halfmoves >= 100 {
+            Some(DrawReason::FiftyMoves)
+        } else if self.repetitions() >= 3 {
+            Some(DrawReason::Repetition)
+        } else {
+            None
+        }
+    }
+
+    /// End the game because the time of `color` ran out.
+    pub fn flag(&mut self, color: Color) -> bool {
+        self.end(GameState::Flagged(color))
+    }
+
+    /// End the game without a result.
+    pub fn abort(&mut self) -> bool {
+        self.end(GameState::Aborted)
+    }
+
+    fn end(&mut self, state: GameState) -> bool {
+        if self.game_state().is_over() {
+            return false;
+        }
+        self.ended = Some(state);
+        self.draw_offer = None;
+        self.takeback_offer = None;
+        self.premove = None;
+        true
+    }
+
+    /// How often the current position occurred since it was set up,
+    /// counting itself.
+    pub fn repetitions(&self) -> usize {
+        1 + self.history.iter().filter(|ply| ply.zobrist == self.zobrist).count()
+    }
+
+    /// Zobrist hash of the pieces, side to move, castling rights and en
+    /// passant square, but not the move counters. It is updated
+    /// incrementally by `play()`.
+    pub fn zobrist(&self) -> u64 {
+        self.zobrist
+    }
+
+    /// The side that offered a draw, if the offer is still open.
+    pub fn draw_offer(&self) -> Option<Color> {
+        self.draw_offer
+    }
+
+    /// Offer a draw on behalf of `color`. Returns `false` if the game is
+    /// already over.
+    pub fn offer_draw(&mut self, color: Color) -> bool {
+        if self.game_state().is_over() {
+            return false;
+        }
+        self.draw_offer = Some(color);
+        true
+    }
+
+    /// Accept the draw offered by the opponent of `color`, ending the
+    /// game.
+    pub fn accept_draw(&mut self, color: Color) -> bool {
+        self.draw_offer == Some(!color) && self.end(GameState::DrawAgreed)
+    }
+
+    /// End the game with a win for the opponent of `color`.
+    pub fn resign(&mut self, color: Color) -> bool {
+        self.end(GameState::Resigned(color))
+    }
+
+    /// The side that asked to take back a move, if the request is still
+    /// open.
+    pub fn takeback_offer(&self) -> Option<Color> {
+        self.takeback_offer
+    }
+
+    /// Ask to take back the last move of `color`. Returns `false` if
+    /// there is no such move.
+    pub fn propose_takeback(&mut self, color: Color) -> bool {
+        if self.game_state().is_over() || self.takeback_plies(color) == 0 {
+            return false;
+        }
+        self.takeback_offer = Some(color);
+        true
+    }
+
+    /// Accept the takeback asked for by the opponent of `color`, undoing
+    /// their last move and the reply to it, if any.
+    pub fn accept_takeback(&mut self, color: Color) -> bool {
+        if self.takeback_offer != Some(!color) {
+            return false;
+        }
+        self.takeback_offer = None;
+        self.draw_offer = None;
+
+        let plies = self.takeback_plies(!color);
+        let undone = (0..plies).all(|_| self.undo());
+        self.redo.clear();
+        undone
+    }
+
+    /// Plies to undo so that `color` can replay its last move.
+    fn takeback_plies(&self, color: Color) -> usize {
+        let plies = if self.turn == Some(color) { 2 } else { 1 };
+        if self.history.len() >= plies { plies } else { 0 }
+    }
+
+    /// The position before the last `play()`, if any.
+    pub fn previous_position(&self) -> Option<VariantPosition> {
+        self.history.last().and_then(|ply| {
+            VariantPosition::from_setup(self.variant, ply.setup.clone(), self.castling_mode).ok()
+        })
+    }
+
+    /// Go back to the position before the last `play()`, with its castling
+    /// rights, en passant square, move counters and highlights. The move
+    /// can be replayed with `redo()`.
+    pub fn undo(&mut self) -> bool {
+        let ply = match self.history.pop() {
+            Some(ply) => ply,
+            None => return false,
+        };
+
+        let after = self.snapshot(ply.m.clone());
+        self.restore(&ply);
+        #[cfg(feature = "blockchain")]
+        self.commitment.pop();
+        self.redo.push(after);
+        true
+    }
+
+    /// Replay the last move taken back with `undo()`.
+    pub fn redo(&mut self) -> bool {
+        let ply = match self.redo.pop() {
+            Some(ply) => ply,
+            None => return false,
+        };
+
+        let before = self.snapshot(ply.m.clone());
+        self.restore(&ply);
+        self.push_history(before);
+        #[cfg(feature = "blockchain")]
+        self.commit(&ply.m);
+        true
+    }
+
+    /// Whether there is a move to `undo()`.
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    /// Whether there is a move to `redo()`.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Keep at most `limit` plies for `undo()`, or all with `None`.
+    /// Repetitions are only detected within the kept plies.
+    pub fn set_history_limit(&mut self, limit: Option<usize>) {
+        self.history_limit = limit;
+        self.trim_history();
+    }
+
+    pub fn history_limit(&self) -> Option<usize> {
+        self.history_limit
+    }
+
+    fn push_history(&mut self, ply: Ply) {
+        self.history.push(ply);
+        self.trim_history();
+    }
+
+    fn trim_history(&mut self) {
+        if let Some(limit) = self.history_limit {
+            if self.history.len() > limit {
+                let excess = self.history.len() - limit;
+                self.history.drain(..excess);
+            }
+        }
+    }
+
+    fn snapshot(&self, m: Move) -> Ply {
+        Ply {
+            setup: self.setup.clone(),
+            last_move: self.last_move,
+            zobrist: self.zobrist,
+            m,
+        }
+    }
+
+    fn restore(&mut self, ply: &Ply) {
+        match VariantPosition::from_setup(self.variant, ply.setup.clone(), self.castling_mode) {
+            Ok(pos) => self.load_position(&pos),
+            Err(_) => self.setup = ply.setup.clone(),
+        }
+        self.zobrist = ply.zobrist;
+        self.last_move = ply.last_move;
+        self.premove = None;
+    }
+
+    /// Pieces of a side missing from the board compared to the starting
+    /// position, i.e. captured by the opponent, from pawns to queens. A
+    /// piece beyond the starting count was promoted, so it stands for a
+    /// pawn that was not captured.
+    pub fn captured(&self, color: Color) -> Vec<Role> {
+        let count = |role: Role| self.setup.board.by_piece(role.of(color)).count();
+
+        let mut promoted = 0;
+        let mut captured = Vec::new();
+        for &(role, start) in &[(Role::Knight, 2), (Role::Bishop, 2), (Role::Rook, 2), (Role::Queen, 1)] {
+            let n = count(role);
+            promoted += n.saturating_sub(start);
+            captured.extend((n..start).map(|_| role));
+        }
+
+        let pawns = 8usize.saturating_sub(count(Role::Pawn) + promoted);
+        let mut roles = vec![Role::Pawn; pawns];
+        roles.extend(captured);
+        roles
+    }
+
+    /// Material of a side on the board minus the material of the
+    /// opponent, counting pawns 1, knights and bishops 3, rooks 5 and
+    /// queens 9.
+    pub fn material_advantage(&self, color: Color) -> i32 {
+        let material = |color: Color| -> i32 {
+            [(Role::Pawn, 1), (Role::Knight, 3), (Role::Bishop, 3), (Role::Rook, 5), (Role::Queen, 9)].iter()
+                .map(|&(role, value)| value * self.setup.board.by_piece(role.of(color)).count() as i32)
+                .sum()
+        };
+        material(color) - material(!color)
+    }
+
+    /// Pieces in hand that can be dropped in Crazyhouse.
+    pub fn pockets(&self) -> Option<&Material> {
+        self.setup.pockets.as_ref()
+    }
+
+    /// Squares where a piece from the pocket can be dropped.
+    pub fn drop_targets(&self, role: Role) -> Bitboard {
+        self.legals.iter().filter(|m| m.from().is_none() && m.role() == role).map(Move::to).collect()
+    }
+
+    pub fn valid_drop(&self, role: Role, square: Square) -> bool {
+        self.drop_targets(role).contains(square)
+    }
+
+    /// Play a legal move, updating the hints and the commitment.
+    pub fn play(&mut self, m: &Move) -> Result<(), IllegalMoveError> {
+        let mut pos = self.position()
+            .filter(|pos| pos.is_legal(m))
+            .ok_or_else(|| IllegalMoveError::new(m.clone()))?;
+
+        let before = self.setup.clone();
+        let ply = self.snapshot(m.clone());
+        self.push_history(ply);
+        self.redo.clear();
+        self.draw_offer = None;
+        self.takeback_offer = None;
+
+        pos.play_unchecked(m);
+        self.load_position(&pos);
+        self.zobrist ^= move_delta(&before, &self.setup, m);
+        debug_assert_eq!(self.zobrist, zobrist_hash(&self.setup));
+        self.last_move = Some((m.from().unwrap_or_else(|| m.to()), m.to()));
+        self.premove = None;
+        #[cfg(feature = "blockchain")]
+        self.commit(m);
+        Ok(())
+    }
+
+    /// Play a list of moves separated by spaces, like the `moves` of a UCI
+    /// `position` command. Returns the number of moves played. All moves
+    /// are checked first, so that either all or none are played.
+    pub fn apply_uci_moves(&mut self, moves: &str) -> Result<usize, UciMovesError> {
+        let mut pos = self.position().ok_or(UciMovesError::IllegalPosition)?;
+
+        let mut checked = Vec::new();
+        for (index, text) in moves.split_whitespace().enumerate() {
+            let uci = text.parse::<Uci>().map_err(|_| UciMovesError::InvalidUci { index, uci: text.to_owned() })?;
+            let m = uci.to_move(&pos).map_err(|_| UciMovesError::IllegalMove { index, uci: text.to_owned() })?;
+            pos.play_unchecked(&m);
+            checked.push(m);
+        }
+
+        for m in &checked {
+            self.play(m).expect("checked move is legal");
+        }
+        Ok(checked.len())
+    }
+
+    /// A legal move in standard algebraic notation, disambiguated as
+    /// needed and with a `+` or `#` suffix, like `Nbd7` or `exd8=Q#`.
+    /// `None` if the move is not legal on the board.
+    pub fn san(&self, m: &Move) -> Option<String> {
+        let pos = self.position().filter(|pos| pos.is_legal(m))?;
+        Some(SanPlus::from_move(pos, m).to_string())
+    }
+
+    /// Read a move in standard algebraic notation. Castling may be
+    /// written with zeros, like `0-0`, and check and mate suffixes and
+    /// annotations like `!?` are ignored. Superfluous disambiguation, like
+    /// `Ngf3` when only one knight can go there, is accepted.
+    pub fn parse_san(&self, text: &str) -> Result<Move, SanError> {
+        let normalized = text.trim()
+            .trim_end_matches(|c| c == '!' || c == '?' || c == '+' || c == '#')
+            .replace('0', "O")
+            .replace('o', "O");
+        let normalized = normalized.trim_end_matches(" e.p.").trim_end_matches("e.p.");
+
+        let san = normalized.parse::<San>().map_err(|_| SanError::Invalid(text.to_owned()))?;
+        let pos = self.position().ok_or_else(|| SanError::Illegal(text.to_owned()))?;
+        san.to_move(&pos).map_err(|err| match err {
+            ShakmatySanError::AmbiguousSan => SanError::Ambiguous(text.to_owned()),
+            ShakmatySanError::IllegalSan => SanError::Illegal(text.to_owned()),
+        })
+    }
+
+    /// Root of the hash chain over all moves played with `play()` since the
+    /// position was set up.
+    #[cfg(feature = "blockchain")]
+    pub fn state_root(&self) -> Hash {
+        self.commitment.root()
+    }
+
+    #[cfg(feature = "blockchain")]
+    pub fn commitment(&self) -> &Commitment {
+        &self.commitment
+    }
+
+    /// Extend the hash chain with a move that was just played.
+    #[cfg(feature = "blockchain")]
+    fn commit(&mut self, m: &Move) {
+        let uci = Uci::from_move(m, self.castling_mode).to_string();
+        let fen = self.fen();
+        self.commitment.push(&uci, &fen);
+    }
+
+    /// In edit mode pieces can be moved and placed freely.
+    pub fn editing(&self) -> bool {
+        self.editing
+    }
+
+    pub fn set_editing(&mut self, editing: bool) {
+        self.editing = editing;
+        self.premove = None;
+    }
+
+    /// Show the legal destinations of the selected piece.
+    pub fn move_hints(&self) -> bool {
+        self.move_hints
+    }
+
+    pub fn set_move_hints(&mut self, move_hints: bool) {
+        self.move_hints = move_hints;
+    }
+
+    /// How long the pointer rests on a piece of the side to move before
+    /// its legal squares are shown, or `None` to show them only when the
+    /// piece is selected.
+    pub fn hover_hints(&self) -> Option<Duration> {
+        self.hover_hints
+    }
+
+    pub fn set_hover_hints(&mut self, delay: Option<Duration>) {
+        self.hover_hints = delay;
+    }
+
+    /// Shade the squares attacked by the opponent of the side to move.
+    pub fn threats(&self) -> bool {
+        self.threats
+    }
+
+    pub fn set_threats(&mut self, threats: bool) {
+        self.threats = threats;
+    }
+
+    /// Number of pieces of `attacker` attacking each square, indexed by
+    /// square.
+    pub fn attack_counts(&self, attacker: Color) -> [u8; 64] {
+        let board = &self.setup.board;
+        let mut counts = [0; 64];
+        for square in Square::ALL {
+            counts[usize::from(square)] = board.attacks_to(square, attacker, board.occupied()).count() as u8;
+        }
+        counts
+    }
+
+    /// How far outside of a legal square, in squares, a dragged piece can
+    /// be dropped and still land on it. Also applies to drops just off the
+    /// board.
+    pub fn snap_radius(&self) -> f64 {
+        self.snap_radius
+    }
+
+    pub fn set_snap_radius(&mut self, radius: f64) {
+        self.snap_radius = radius.max(0.0);
+    }
+
+    pub fn move_input(&self) -> MoveInput {
+        self.move_input
+    }
+
+    pub fn set_move_input(&mut self, move_input: MoveInput) {
+        self.move_input = move_input;
+    }
+
+    /// Promote to a queen without asking, unless a modifier key is held.
+    pub fn auto_queen(&self) -> bool {
+        self.auto_queen
+    }
+
+    pub fn set_auto_queen(&mut self, auto_queen: bool) {
+        self.auto_queen = auto_queen;
+    }
+
+    /// Pieces can not be moved, but shapes can still be drawn.
+    pub fn view_only(&self) -> bool {
+        self.view_only
+    }
+
+    pub fn set_view_only(&mut self, view_only: bool) {
+        self.view_only = view_only;
+        if view_only {
+            self.premove = None;
+        }
+    }
+
+    pub fn movable(&self) -> MovableSide {
+        self.movable
+    }
+
+    pub fn set_movable(&mut self, movable: MovableSide) {
+        self.movable = movable;
+
+        let premove_allowed = self.premove
+            .and_then(|(orig, _)| self.setup.board.piece_at(orig))
+            .map_or(true, |piece| movable.allows(piece.color));
+        if !premove_allowed {
+            self.premove = None;
+        }
+    }
+
+    /// Whether the local user may pick up the piece. Edit mode ignores
+    /// the movable side.
+    pub fn is_movable(&self, piece: Piece) -> bool {
+        self.editing || self.can_move(piece.color)
+    }
+
+    /// Whether the local user may move for `color`. Nobody can once the
+    /// game is over.
+    pub fn can_move(&self, color: Color) -> bool {
+        self.movable.allows(color) && !self.game_state().is_over()
+    }
+
+    /// The piece placed by clicks in edit mode.
+    pub fn spare_piece(&self) -> Option<Piece> {
+        self.spare_piece
+    }
+
+    pub fn set_spare_piece(&mut self, piece: Option<Piece>) {
+        self.spare_piece = piece;
+    }
+
+    /// Move a piece ignoring the rules, or remove it if `dest` is `None`.
+    pub fn edit_move(&mut self, orig: Square, dest: Option<Square>) {
+        if let Some(piece) = self.setup.board.remove_piece_at(orig) {
+            if let Some(dest) = dest {
+                self.setup.board.set_piece_at(dest, piece);
+            }
+        }
+
+        self.edited();
+    }
+
+    /// Place a piece, or remove it if the same piece is already there.
+    pub fn edit_put(&mut self, square: Square, piece: Piece) {
+        if self.setup.board.piece_at(square) == Some(piece) {
+            self.setup.board.remove_piece_at(square);
+        } else {
+            self.setup.board.set_piece_at(square, piece);
+        }
+
+        self.edited();
+    }
+
+    fn edited(&mut self) {
+        // drop castling rights of rooks that are gone
+        let rooks = self.setup.board.rooks();
+        self.setup.castling_rights &= rooks;
+        self.setup.ep_square = None;
+        self.zobrist = zobrist_hash(&self.setup);
+
+        self.check = None;
+        self.last_move = None;
+        self.legals.clear();
+        #[cfg(feature = "blockchain")]
+        self.commitment = Commitment::new(&self.fen());
+        self.reset_game();
+    }
+
+    pub fn last_move(&self) -> Option<(Square, Square)> {
+        self.last_move
     }
 
     pub fn set_last_move(&mut self, m: Option<(Square, Square)>) {
         self.last_move = m;
     }
 
+    pub fn set_premove(&mut self, m: Option<(Square, Square)>) {
+        self.premove = m;
+    }
+
+    pub fn premove(&self) -> Option<(Square, Square)> {
+        self.premove
+    }
+
+    /// The king in check, if any.
+    pub fn check(&self) -> Option<Square> {
+        self.check
+    }
+
     pub fn set_check(&mut self, king: Option<Square>) {
         self.check = king;
     }
@@ -73,8 +1129,49 @@ impl BoardState {
         self.turn
     }
 
+    /// Squares with a last move, premove or check highlight.
+    pub(crate) fn highlighted(&self) -> Bitboard {
+        self.last_move.iter()
+            .chain(self.premove.iter())
+            .flat_map(|&(orig, dest)| vec![orig, dest])
+            .chain(self.check)
+            .collect()
+    }
+
+    /// Squares the piece on `orig` can move to. Castling moves target the
+    /// rook, and also the square the king ends up on, unless that would
+    /// be ambiguous.
     pub fn move_targets(&self, orig: Square) -> Bitboard {
-        self.legals.iter().filter(|m| m.from() == Some(orig)).map(Move::to).collect()
+        let targets: Bitboard = self.legals.iter().filter(|m| m.from() == Some(orig)).map(Move::to).collect();
+        let kings: Bitboard = self.castles(orig).into_iter().map(|(king, _)| king).collect();
+        targets | (kings & !Bitboard::from_square(orig))
+    }
+
+    /// Castling moves of the king on `orig`, as pairs of the square the
+    /// king ends up on and the square of the rook.
+    pub fn castles(&self, orig: Square) -> Vec<(Square, Square)> {
+        let color = match self.setup.board.color_at(orig) {
+            Some(color) => color,
+            None => return Vec::new(),
+        };
+
+        self.legals.iter().filter_map(|m| match *m {
+            Move::Castle { king, rook } if king == orig => {
+                m.castling_side().map(|side| (side.king_to(color), rook))
+            },
+            _ => None,
+        }).collect()
+    }
+
+    /// If moving the king from `orig` to `dest` is castling, returns the
+    /// rook square, so that the move can be expressed by the king taking
+    /// its own rook. A normal king move to `dest` takes precedence.
+    pub fn castling_rook(&self, orig: Square, dest: Square) -> Option<Square> {
+        if self.legals.iter().any(|m| m.from() == Some(orig) && m.to() == dest) {
+            return None;
+        }
+
+        self.castles(orig).into_iter().find(|&(king, _)| king == dest).map(|(_, rook)| rook)
     }
 
     pub fn valid_move(&self, orig: Square, dest: Square) -> bool {
@@ -103,40 +1200,172 @@ impl BoardState {
         self.orientation
     }
 
+    pub fn set_auto_orient(&mut self, auto_orient: AutoOrient) {
+        self.auto_orient = auto_orient;
+    }
+
+    pub fn auto_orient(&self) -> AutoOrient {
+        self.auto_orient
+    }
+
+    /// The orientation the board should turn to, if it follows a side.
+    pub fn followed_orientation(&self) -> Option<Color> {
+        match self.auto_orient {
+            AutoOrient::Off => None,
+            AutoOrient::SideToMove => self.turn,
+            AutoOrient::Player => match self.movable {
+                MovableSide::White => Some(Color::White),
+                MovableSide::Black => Some(Color::Black),
+                MovableSide::Both | MovableSide::None => None,
+            },
+        }
+    }
+
+    pub fn shapes(&self) -> &[DrawShape] {
+        &self.shapes
+    }
+
+    pub fn shapes_mut(&mut self) -> &mut Vec<DrawShape> {
+        &mut self.shapes
+    }
+
+    pub fn set_shapes(&mut self, shapes: Vec<DrawShape>) {
+        self.shapes = shapes;
+    }
+
+    pub fn engine_shapes(&self) -> &[DrawShape] {
+        &self.engine_shapes
+    }
+
+    pub fn set_engine_shapes(&mut self, shapes: Vec<DrawShape>) {
+        self.engine_shapes = shapes;
+    }
+
+    pub fn arrows(&self) -> &[Arrow] {
+        &self.arrows
+    }
+
+    /// Replace the arrows set by the caller.
+    pub fn set_arrows(&mut self, arrows: Vec<Arrow>) {
+        self.arrows = arrows;
+    }
+
+    pub fn highlights(&self) -> &[(Square, HighlightKind)] {
+        &self.highlights
+    }
+
+    /// Replace the highlighted squares.
+    pub fn set_highlights(&mut self, highlights: Vec<(Square, HighlightKind)>) {
+        self.highlights = highlights;
+    }
+
+    pub fn tablebase_shapes(&self) -> &[DrawShape] {
+        &self.tablebase_shapes
+    }
+
+    pub fn set_tablebase_shapes(&mut self, shapes: Vec<DrawShape>) {
+        self.tablebase_shapes = shapes;
+    }
+
     pub fn piece_set(&self) -> &PieceSet {
         &self.piece_set
     }
 
+    pub fn set_piece_set(&mut self, piece_set: PieceSet) {
+        self.piece_set = piece_set;
+    }
+
+    pub fn show_coordinates(&self) -> bool {
+        self.show_coordinates
+    }
+
+    pub fn set_show_coordinates(&mut self, show_coordinates: bool) {
+        self.show_coordinates = show_coordinates;
+    }
+
+    pub fn coordinates(&self) -> Coordinates {
+        self.coordinates
+    }
+
+    pub fn set_coordinates(&mut self, coordinates: Coordinates) {
+        self.coordinates = coordinates;
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
     pub(crate) fn draw(&self, cr: &Context) -> Result<(), cairo::Error> {
         self.draw_border(cr)?;
         self.draw_turn(cr)?;
         self.draw_board(cr)?;
-        self.draw_last_move(cr)?;
-        self.draw_check(cr)?;
-        Ok(())
+        self.draw_inner_coordinates(cr)?;
+        self.draw_marks(cr)
     }
 
     fn draw_border(&self, cr: &Context) -> Result<(), cairo::Error> {
-        cr.set_source_rgb(0.2, 0.2, 0.5);
+        self.theme.border.set_source(cr);
         cr.rectangle(-0.5, -0.5, 9.0, 9.0);
         cr.fill()?;
 
-        cr.set_font_size(0.20);
-        cr.set_source_rgb(0.8, 0.8, 0.8);
+        if self.show_coordinates && self.coordinates == Coordinates::Outside {
+            self.theme.coordinates.set_source(cr);
+
+            for (rank, glyph) in RANK_GLYPHS.iter().enumerate() {
+                self.draw_text(cr, (-0.25, 7.5 - rank as f64), 0.20, glyph)?;
+                self.draw_text(cr, (8.25, 7.5 - rank as f64), 0.20, glyph)?;
+            }
 
-        for (rank, glyph) in ["1", "2", "3", "4", "5", "6", "7", "8"].iter().enumerate() {
-            self.draw_text(cr, (-0.25, 7.5 - rank as f64), glyph)?;
-            self.draw_text(cr, (8.25, 7.5 - rank as f64), glyph)?;
+            for (file, glyph) in FILE_GLYPHS.iter().enumerate() {
+                self.draw_text(cr, (0.5 + file as f64, -0.25), 0.20, glyph)?;
+                self.draw_text(cr, (0.5 + file as f64, 8.25), 0.20, glyph)?;
+            }
         }
 
-        for (file, glyph) in ["a", "b", "c", "d", "e", "f", "g", "h"].iter().enumerate() {
-            self.draw_text(cr, (0.5 + file as f64, -0.25), glyph)?;
-            self.draw_text(cr, (0.5 + file as f64, 8.25), glyph)?;
+        Ok(())
+    }
+
+    fn draw_inner_coordinates(&self, cr: &Context) -> Result<(), cairo::Error> {
+        if !self.show_coordinates || self.coordinates != Coordinates::Inside {
+            return Ok(());
+        }
+
+        // The board is rotated as a whole, so the edge next to the player
+        // is rank 1 or rank 8 depending on the orientation. Labels go into
+        // the bottom right (files) and top left (ranks) corner as seen by
+        // the player.
+        let (bottom, left) = self.orientation.fold_wb((Rank::First, File::A), (Rank::Eighth, File::H));
+        let inset = self.orientation.fold_wb(0.82, 0.18);
+
+        for (file, glyph) in FILE_GLYPHS.iter().enumerate() {
+            let square = Square::from_coords(File::new(file as u32), bottom);
+            self.set_label_source(cr, square);
+            let y = 7.0 - rank_to_float(bottom) + inset;
+            self.draw_text(cr, (file as f64 + inset, y), 0.16, glyph)?;
+        }
+
+        for (rank, glyph) in RANK_GLYPHS.iter().enumerate() {
+            let square = Square::from_coords(left, Rank::new(rank as u32));
+            self.set_label_source(cr, square);
+            let x = file_to_float(left) + 1.0 - inset;
+            self.draw_text(cr, (x, 7.0 - rank as f64 + 1.0 - inset), 0.16, glyph)?;
         }
 
         Ok(())
     }
 
+    fn set_label_source(&self, cr: &Context, square: Square) {
+        if square.is_light() {
+            self.theme.dark.set_source(cr);
+        } else {
+            self.theme.light.set_source(cr);
+        }
+    }
+
     fn draw_turn(&self, cr: &Context) -> Result<(), cairo::Error> {
         match self.turn {
             Some(Color::White) => {
@@ -155,64 +1384,169 @@ impl BoardState {
         Ok(())
     }
 
-    fn draw_text(&self, cr: &Context, (x, y): (f64, f64), text: &str) -> Result<(), cairo::Error> {
-        let font = cr.font_extents()?;
-        let e = cr.text_extents(text)?;
+    fn draw_text(&self, cr: &Context, (x, y): (f64, f64), size: f64, text: &str) -> Result<(), cairo::Error> {
+        // Pango works in whole device units, so lay out the text at 100
+        // times the size and scale it down.
+        let layout = match pangocairo::create_layout(cr) {
+            Some(layout) => layout,
+            None => return Ok(()),
+        };
+
+        let mut font = FontDescription::from_string("Sans Bold");
+        font.set_absolute_size(size * 100.0 * f64::from(pango::SCALE));
+        layout.set_font_description(Some(&font));
+        layout.set_text(text);
+        let (width, height) = layout.pixel_size();
 
         cr.save()?;
         cr.translate(x, y);
         cr.rotate(self.orientation.fold_wb(0.0, PI));
-        cr.move_to(-0.5 * e.width, 0.5 * font.height - font.descent);
-        cr.show_text(text)?;
+        cr.scale(0.01, 0.01);
+        cr.move_to(-0.5 * f64::from(width), -0.5 * f64::from(height));
+        pangocairo::show_layout(cr, &layout);
         cr.restore()?;
 
         Ok(())
     }
 
-    fn draw_board(&self, cr: &Context) -> Result<(), cairo::Error> {
-        cr.rectangle(0.0, 0.0, 8.0, 8.0);
-        cr.set_source_rgb(0.55, 0.64, 0.68); // dark
-        cr.fill()?;
-
-        cr.set_source_rgb(0.87, 0.89, 0.90); // light
+    /// The squares, without coordinates.
+    pub(crate) fn draw_board(&self, r: &dyn Renderer) -> Result<(), cairo::Error> {
+        r.fill_rect(0.0, 0.0, 8.0, 8.0, self.theme.dark)?;
 
         for square in Square::ALL {
             if square.is_light() {
-                cr.rectangle(file_to_float(square.file()), 7.0 - rank_to_float(square.rank()), 1.0, 1.0);
-                cr.fill()?;
+                r.fill_square(square, self.theme.light)?;
             }
         }
 
         Ok(())
     }
 
-    fn draw_last_move(&self, cr: &Context) -> Result<(), cairo::Error> {
+    /// Square highlights on top of the board: threats, the last move,
+    /// highlighted squares, the premove and check.
+    pub(crate) fn draw_marks(&self, r: &dyn Renderer) -> Result<(), cairo::Error> {
+        self.draw_threats(r)?;
+        self.draw_last_move(r)?;
+        self.draw_highlights(r)?;
+        self.draw_premove(r)?;
+        self.draw_check(r)?;
+        Ok(())
+    }
+
+    fn draw_last_move(&self, r: &dyn Renderer) -> Result<(), cairo::Error> {
         if let Some((orig, dest)) = self.last_move {
-            cr.set_source_rgba(0.61, 0.78, 0.0, 0.41);
-            cr.rectangle(file_to_float(orig.file()), 7.0 - rank_to_float(orig.rank()), 1.0, 1.0);
-            cr.fill()?;
+            r.fill_square(orig, self.theme.last_move)?;
 
             if dest != orig {
-                cr.rectangle(file_to_float(dest.file()), 7.0 - rank_to_float(dest.rank()), 1.0, 1.0);
-                cr.fill()?;
+                r.fill_square(dest, self.theme.last_move)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_premove(&self, r: &dyn Renderer) -> Result<(), cairo::Error> {
+        if let Some((orig, dest)) = self.premove {
+            r.fill_square(orig, self.theme.premove)?;
+            r.fill_square(dest, self.theme.premove)?;
+        }
+
+        Ok(())
+    }
+
+    /// Heat map of the squares attacked by the opponent, darker with
+    /// more attackers.
+    fn draw_threats(&self, r: &dyn Renderer) -> Result<(), cairo::Error> {
+        let turn = match self.turn {
+            Some(turn) if self.threats && !self.editing => turn,
+            _ => return Ok(()),
+        };
+
+        let counts = self.attack_counts(!turn);
+        for square in Square::ALL {
+            let count = counts[usize::from(square)];
+            if count > 0 {
+                let intensity = f64::from(count.min(3)) / 3.0;
+                let threat = self.theme.threat;
+                r.fill_square(square, threat.with_alpha(threat.alpha * (0.3 + 0.7 * intensity)))?;
             }
         }
 
         Ok(())
     }
 
-    fn draw_check(&self, cr: &Context) -> Result<(), cairo::Error> {
+    fn draw_highlights(&self, r: &dyn Renderer) -> Result<(), cairo::Error> {
+        for &(square, kind) in &self.highlights {
+            let color = match kind {
+                HighlightKind::LastMove => self.theme.last_move,
+                HighlightKind::Threat => self.theme.threat,
+                HighlightKind::Hint => self.theme.hint,
+                HighlightKind::Check => {
+                    self.draw_glow(r, square)?;
+                    continue;
+                },
+            };
+
+            r.fill_square(square, color)?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_check(&self, r: &dyn Renderer) -> Result<(), cairo::Error> {
         if let Some(check) = self.check {
-            let cx = 0.5 + file_to_float(check.file());
-            let cy = 7.5 - rank_to_float(check.rank());
-            let gradient = RadialGradient::new(cx, cy, 0.0, cx, cy, 0.5f64.hypot(0.5));
-            gradient.add_color_stop_rgba(0.0, 1.0, 0.0, 0.0, 1.0);
-            gradient.add_color_stop_rgba(0.25, 0.91, 0.0, 0.0, 1.0);
-            gradient.add_color_stop_rgba(0.89, 0.66, 0.0, 0.0, 0.0);
-            cr.set_source(&gradient)?;
-            cr.paint()?;
+            self.draw_glow(r, check)?;
         }
 
         Ok(())
     }
+
+    /// Radial gradient in the check color around the center of a square.
+    fn draw_glow(&self, r: &dyn Renderer, square: Square) -> Result<(), cairo::Error> {
+        let center = (0.5 + file_to_float(square.file()), 7.5 - rank_to_float(square.rank()));
+        r.fill_glow(center, 0.5f64.hypot(0.5), self.theme.check)
+    }
+}
+
+/// Put a piece on the `nth` empty file of a back rank.
+fn place(rank: &mut [Option<Role>; 8], nth: usize, role: Role) {
+    let file = (0..8).filter(|&file| rank[file].is_none()).nth(nth).expect("empty file");
+    rank[file] = Some(role);
+}
+
+/// Back rank of a Chess960 starting position.
+fn chess960_back_rank(scharnagl_number: u32) -> [Role; 8] {
+    assert!(scharnagl_number < 960, "scharnagl number out of range");
+
+    const KNIGHTS: [(usize, usize); 10] = [
+        (0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4),
+    ];
+
+    let mut rank = [None; 8];
+    let mut n = scharnagl_number as usize;
+
+    rank[2 * (n % 4) + 1] = Some(Role::Bishop);
+    n /= 4;
+    rank[2 * (n % 4)] = Some(Role::Bishop);
+    n /= 4;
+
+    place(&mut rank, n % 6, Role::Queen);
+    n /= 6;
+
+    // place the second knight first, so that the index of the first one
+    // is unaffected
+    let (first, second) = KNIGHTS[n];
+    place(&mut rank, second, Role::Knight);
+    place(&mut rank, first, Role::Knight);
+
+    // rook, king, rook in the remaining files
+    place(&mut rank, 0, Role::Rook);
+    place(&mut rank, 0, Role::King);
+    place(&mut rank, 0, Role::Rook);
+
+    let mut roles = [Role::Pawn; 8];
+    for (role, r) in roles.iter_mut().zip(rank.iter()) {
+        *role = r.expect("all files filled");
+    }
+    roles
 }
@@ -0,0 +1,326 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! A browser of the stored games, with filters, thumbnails of the final
+//! positions, and a button to open a game for replay.
+//!
+//! The browser does not own the storage. It shows the games matching a
+//! `GameFilter`, which is set from the filter bar. List the games of
+//! `Storage::games()` and forward opened games to a board, like
+//! `GroundMsg::LoadGame`.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::iter;
+use std::rc::Rc;
+
+use gtk::prelude::*;
+use gtk::{Adjustment, Button, ComboBoxText, DrawingArea, Entry, Label, ListBox, Orientation, PolicyType, ScrolledWindow, SelectionMode};
+
+use relm::{Relm, Widget, Update, StreamHandle};
+
+use shakmaty::{Chess, Color};
+
+use boardstate::BoardState;
+use openings::{Opening, Openings};
+use render::draw_board;
+use storage::{variant_name, GameFilter, StoredGame};
+
+/// Size of the thumbnails in the list.
+const THUMBNAIL: i32 = 64;
+
+/// Results offered by the filter bar, as in the `Result` header.
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// Game browser messages.
+#[derive(Debug, Msg)]
+pub enum GameBrowserMsg {
+    /// List these games. Forward `Storage::games()` here after saving or
+    /// removing games.
+    SetGames(Vec<StoredGame>),
+    /// Show only the games matching this filter. The filter bar sends it
+    /// whenever one of its fields changes.
+    SetFilter(GameFilter),
+
+    /// Sent when the user opened a game. Forward its `game` to a replay
+    /// board with `GroundMsg::LoadGame`.
+    Open(StoredGame),
+}
+
+/// A stored game with what the list shows of it.
+struct Listed {
+    stored: StoredGame,
+    opening: Option<Opening>,
+    position: Chess,
+}
+
+/// The fields of the filter bar.
+#[derive(Clone)]
+struct FilterBar {
+    player: Entry,
+    date: Entry,
+    opening: Entry,
+    result: ComboBoxText,
+    variant: ComboBoxText,
+    on_chain: ComboBoxText,
+}
+
+impl FilterBar {
+    fn filter(&self) -> GameFilter {
+        let id = |combo: &ComboBoxText| combo.active_id().map(|id| id.to_string()).filter(|id| !id.is_empty());
+
+        GameFilter {
+            player: self.player.text().to_string(),
+            date: self.date.text().to_string(),
+            opening: self.opening.text().to_string(),
+            result: id(&self.result),
+            variant: id(&self.variant),
+            on_chain: id(&self.on_chain).map(|id| id == "on"),
+        }
+    }
+
+    /// Offer the variants of these games, keeping the selected one if
+    /// it is still among them.
+    fn set_variants<'a, I: Iterator<Item = &'a str>>(&self, variants: I) {
+        let selected = self.variant.active_id();
+        self.variant.remove_all();
+        self.variant.append(Some(""), "Any variant");
+        for variant in variants {
+            self.variant.append(Some(variant), variant);
+        }
+        if selected.map_or(true, |id| !self.variant.set_active_id(Some(id.as_str()))) {
+            self.variant.set_active_id(Some(""));
+        }
+    }
+}
+
+/// A widget listing stored games with a filter bar and thumbnails of
+/// their final positions.
+pub struct GameBrowser {
+    vbox: gtk::Box,
+    list: ListBox,
+    count: Label,
+    bar: FilterBar,
+    openings: Openings,
+    games: Vec<Listed>,
+    filter: GameFilter,
+    /// Shared by all thumbnails, so that the pieces are loaded only once.
+    thumbnail: Rc<RefCell<BoardState>>,
+    stream: StreamHandle<GameBrowserMsg>,
+}
+
+impl fmt::Debug for GameBrowser {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GameBrowser")
+            .field("games", &self.games.len())
+            .field("filter", &self.filter)
+            .finish()
+    }
+}
+
+impl GameBrowser {
+    fn render(&self) {
+        for child in self.list.children() {
+            self.list.remove(&child);
+        }
+
+        let mut shown = 0;
+        for listed in self.games.iter().filter(|listed| self.filter.matches(&listed.stored, listed.opening.as_ref())) {
+            shown += 1;
+            let game = &listed.stored.game;
+            let hbox = gtk::Box::new(Orientation::Horizontal, 6);
+
+            let preview = DrawingArea::new();
+            preview.set_size_request(THUMBNAIL, THUMBNAIL);
+            {
+                let thumbnail = Rc::clone(&self.thumbnail);
+                let position = listed.position.clone();
+                preview.connect_draw(move |widget, cr| {
+                    let size = f64::from(widget.allocated_width().min(widget.allocated_height()));
+                    let mut thumbnail = thumbnail.borrow_mut();
+                    thumbnail.set_position(&position);
+                    draw_board(cr, &thumbnail, size).unwrap();
+                    Inhibit(false)
+                });
+            }
+            hbox.pack_start(&preview, false, false, 0);
+
+            let players = format!("{} – {}  {}",
+                                  game.header("White").unwrap_or("?"),
+                                  game.header("Black").unwrap_or("?"),
+                                  game.header("Result").unwrap_or("*"));
+            let mut details = vec![game.header("Date").unwrap_or("????.??.??").to_owned()];
+            details.push(variant_name(game).to_owned());
+            if let Some(ref opening) = listed.opening {
+                details.push(opening.to_string());
+            }
+            details.push(format!("{} plies", game.len()));
+            if !listed.stored.transactions.is_empty() {
+                details.push("on chain".to_owned());
+            }
+
+            let labels = gtk::Box::new(Orientation::Vertical, 2);
+            let title = Label::new(Some(&players));
+            title.set_xalign(0.0);
+            labels.pack_start(&title, false, false, 0);
+            let subtitle = Label::new(Some(&details.join(" · ")));
+            subtitle.set_xalign(0.0);
+            labels.pack_start(&subtitle, false, false, 0);
+            hbox.pack_start(&labels, true, true, 0);
+
+            let stored = listed.stored.clone();
+            let stream = self.stream.clone();
+            let button = Button::with_label("Open");
+            button.connect_clicked(move |_| stream.emit(GameBrowserMsg::Open(stored.clone())));
+            hbox.pack_end(&button, false, false, 0);
+
+            self.list.add(&hbox);
+        }
+
+        self.count.set_text(&format!("{} of {} games", shown, self.games.len()));
+        self.list.show_all();
+    }
+}
+
+impl Update for GameBrowser {
+    type Model = ();
+    type ModelParam = ();
+    type Msg = GameBrowserMsg;
+
+    fn model(_: &Relm<Self>, _: ()) {}
+
+    fn update(&mut self, event: GameBrowserMsg) {
+        match event {
+            GameBrowserMsg::SetGames(games) => {
+                let openings = &self.openings;
+                self.games = games.into_iter().map(|stored| Listed {
+                    opening: openings.classify(&stored.game).cloned(),
+                    position: stored.game.position(),
+                    stored,
+                }).collect();
+
+                let variants: BTreeSet<&str> = self.games.iter().map(|listed| variant_name(&listed.stored.game)).collect();
+                self.bar.set_variants(variants.into_iter());
+                self.filter = self.bar.filter();
+                self.render();
+            },
+            GameBrowserMsg::SetFilter(filter) => {
+                if filter != self.filter {
+                    self.filter = filter;
+                    self.render();
+                }
+            },
+            GameBrowserMsg::Open(_) => {},
+        }
+    }
+}
+
+impl Widget for GameBrowser {
+    type Root = gtk::Box;
+
+    fn root(&self) -> Self::Root {
+        self.vbox.clone()
+    }
+
+    fn view(relm: &Relm<Self>, _: ()) -> Self {
+        let list = ListBox::new();
+        list.set_selection_mode(SelectionMode::None);
+
+        let scrolled = ScrolledWindow::new(None::<&Adjustment>, None::<&Adjustment>);
+        scrolled.set_policy(PolicyType::Never, PolicyType::Automatic);
+        scrolled.add(&list);
+
+        let entry = |placeholder: &str| {
+            let entry = Entry::new();
+            entry.set_placeholder_text(Some(placeholder));
+            entry
+        };
+
+        let result = ComboBoxText::new();
+        result.append(Some(""), "Any result");
+        for r in &RESULTS {
+            result.append(Some(*r), r);
+        }
+        result.set_active_id(Some(""));
+
+        let on_chain = ComboBoxText::new();
+        on_chain.append(Some(""), "On and off chain");
+        on_chain.append(Some("on"), "On chain");
+        on_chain.append(Some("off"), "Off chain");
+        on_chain.set_active_id(Some(""));
+
+        let bar = FilterBar {
+            player: entry("Player"),
+            date: entry("Date, like 2023.05"),
+            opening: entry("Opening or ECO"),
+            result,
+            variant: ComboBoxText::new(),
+            on_chain,
+        };
+        bar.set_variants(iter::empty());
+
+        {
+            let emit = {
+                let stream = relm.stream().clone();
+                let bar = bar.clone();
+                Rc::new(move || stream.emit(GameBrowserMsg::SetFilter(bar.filter())))
+            };
+            for entry in &[&bar.player, &bar.date, &bar.opening] {
+                let emit = Rc::clone(&emit);
+                entry.connect_changed(move |_| emit());
+            }
+            for combo in &[&bar.result, &bar.variant, &bar.on_chain] {
+                let emit = Rc::clone(&emit);
+                combo.connect_changed(move |_| emit());
+            }
+        }
+
+        let form = gtk::Box::new(Orientation::Horizontal, 6);
+        form.pack_start(&bar.player, true, true, 0);
+        form.pack_start(&bar.date, false, false, 0);
+        form.pack_start(&bar.opening, true, true, 0);
+        form.pack_start(&bar.result, false, false, 0);
+        form.pack_start(&bar.variant, false, false, 0);
+        form.pack_start(&bar.on_chain, false, false, 0);
+
+        let count = Label::new(None);
+        count.set_xalign(0.0);
+
+        let mut thumbnail = BoardState::new();
+        thumbnail.set_orientation(Color::White);
+        thumbnail.set_show_coordinates(false);
+        thumbnail.set_view_only(true);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 6);
+        vbox.pack_start(&form, false, false, 0);
+        vbox.pack_start(&scrolled, true, true, 0);
+        vbox.pack_start(&count, false, false, 0);
+        vbox.show_all();
+
+        GameBrowser {
+            vbox,
+            list,
+            count,
+            bar,
+            openings: Openings::new(),
+            games: Vec::new(),
+            filter: GameFilter::default(),
+            thumbnail: Rc::new(RefCell::new(thumbnail)),
+            stream: relm.stream().clone(),
+        }
+    }
+}
@@ -0,0 +1,496 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+
+use shakmaty::{Square, Role};
+
+use boardstate::BoardState;
+use commitment::Hash;
+use util::{from_hex, to_hex};
+
+/// Settings for the blockchain RPC endpoint.
+#[derive(Debug, Clone)]
+pub struct ChainConfig {
+    /// JSON-RPC endpoint, e.g. `http://localhost:8545`.
+    pub endpoint: String,
+    /// How often a request is retried when the endpoint cannot be
+    /// reached. Requests the endpoint answered, even with an HTTP error,
+    /// are not sent again.
+    pub retries: u32,
+    pub retry_delay: Duration,
+    /// How long to wait for a connection to the endpoint.
+    pub connect_timeout: Duration,
+    /// How long to wait for a reply.
+    pub read_timeout: Duration,
+    /// Number of confirmations after which a move counts as confirmed.
+    pub confirmations: u64,
+    /// How often pending transactions are polled.
+    pub poll_interval: Duration,
+}
+
+impl ChainConfig {
+    pub fn new(endpoint: &str) -> ChainConfig {
+        ChainConfig {
+            endpoint: endpoint.to_owned(),
+            retries: 3,
+            retry_delay: Duration::from_secs(2),
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+            confirmations: 1,
+            poll_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Error when talking to the chain.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ChainError {
+    /// The endpoint could not be reached, or replied with an HTTP error.
+    Http(String),
+    /// The endpoint replied with a JSON-RPC error.
+    Rpc { code: i64, message: String },
+    /// The reply could not be understood.
+    InvalidResponse,
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChainError::Http(ref err) => write!(f, "http error: {}", err),
+            ChainError::Rpc { code, ref message } => write!(f, "rpc error {}: {}", code, message),
+            ChainError::InvalidResponse => f.write_str("invalid rpc response"),
+        }
+    }
+}
+
+impl Error for ChainError {}
+
+/// A confirmed move, as stored in a transaction.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MoveRecord {
+    game_id: String,
+    ply: u32,
+    orig: Square,
+    dest: Square,
+    promotion: Option<Role>,
+    timestamp: u64,
+    signature: Option<Vec<u8>>,
+}
+
+impl MoveRecord {
+    /// Create an unsigned record of the move with the current time.
+    pub fn new(game_id: &str, ply: u32, orig: Square, dest: Square, promotion: Option<Role>) -> MoveRecord {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+
+        MoveRecord {
+            game_id: game_id.to_owned(),
+            ply,
+            orig,
+            dest,
+            promotion,
+            timestamp,
+            signature: None,
+        }
+    }
+
+    pub fn game_id(&self) -> &str {
+        &self.game_id
+    }
+
+    /// Number of plies played before this move.
+    pub fn ply(&self) -> u32 {
+        self.ply
+    }
+
+    pub fn orig(&self) -> Square {
+        self.orig
+    }
+
+    pub fn dest(&self) -> Square {
+        self.dest
+    }
+
+    pub fn promotion(&self) -> Option<Role> {
+        self.promotion
+    }
+
+    /// Milliseconds since the Unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn signature(&self) -> Option<&[u8]> {
+        self.signature.as_ref().map(Vec::as_slice)
+    }
+
+    pub fn set_signature(&mut self, signature: Option<Vec<u8>>) {
+        self.signature = signature;
+    }
+
+    /// The bytes covered by the signature: all fields except the signature
+    /// itself, each prefixed with its length, so that no two records share
+    /// them whatever their game ids contain.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let fields = [
+            self.game_id.clone(),
+            self.ply.to_string(),
+            self.orig.to_string(),
+            self.dest.to_string(),
+            self.promotion.map_or(String::new(), |r| r.char().to_string()),
+            self.timestamp.to_string(),
+        ];
+        fields.iter().map(|field| format!("{}:{}", field.len(), field)).collect::<String>().into_bytes()
+    }
+
+    /// The transaction payload.
+    pub fn to_payload(&self) -> Value {
+        json!({
+            "game": self.game_id,
+            "ply": self.ply,
+            "orig": self.orig.to_string(),
+            "dest": self.dest.to_string(),
+            "promotion": self.promotion.map(|r| r.char().to_string()),
+            "timestamp": self.timestamp,
+            "signature": self.signature.as_ref().map(|s| to_hex(s)),
+        })
+    }
+
+    /// Parse a transaction payload.
+    pub fn from_payload(payload: &Value) -> Option<MoveRecord> {
+        Some(MoveRecord {
+            game_id: payload["game"].as_str()?.to_owned(),
+            ply: u32::try_from(payload["ply"].as_u64()?).ok()?,
+            orig: payload["orig"].as_str()?.parse().ok()?,
+            dest: payload["dest"].as_str()?.parse().ok()?,
+            promotion: match payload["promotion"] {
+                Value::Null => None,
+                ref role => Some(role.as_str()?.chars().next().and_then(Role::from_char)?),
+            },
+            timestamp: payload["timestamp"].as_u64()?,
+            signature: match payload["signature"] {
+                Value::Null => None,
+                ref signature => Some(from_hex(signature.as_str()?)?),
+            },
+        })
+    }
+}
+
+/// A blocking JSON-RPC client for the chess contract.
+///
+/// Moves are submitted with `chess_submitMove`, which returns a transaction
+/// id, and tracked with `chess_getConfirmations`.
+#[derive(Debug, Clone)]
+pub struct ChainClient {
+    config: ChainConfig,
+    agent: ureq::Agent,
+}
+
+impl ChainClient {
+    pub fn new(config: ChainConfig) -> ChainClient {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(config.connect_timeout)
+            .timeout_read(config.read_timeout)
+            .build();
+        ChainClient { config, agent }
+    }
+
+    pub fn config(&self) -> &ChainConfig {
+        &self.config
+    }
+
+    /// Call a JSON-RPC method, retrying if the endpoint cannot be reached.
+    /// HTTP error replies are not retried, because the endpoint may have
+    /// acted on the request, like a submitted move.
+    pub fn call(&self, method: &str, params: Value) -> Result<Value, ChainError> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+
+        let mut attempt = 0;
+        let response: Value = loop {
+            match self.agent.post(&self.config.endpoint).send_json(request.clone()) {
+                Ok(response) => break response.into_json().map_err(|_| ChainError::InvalidResponse)?,
+                Err(err @ ureq::Error::Status(..)) => return Err(ChainError::Http(err.to_string())),
+                Err(err @ ureq::Error::Transport(_)) => {
+                    if attempt >= self.config.retries {
+                        return Err(ChainError::Http(err.to_string()));
+                    }
+                    attempt += 1;
+                    thread::sleep(self.config.retry_delay);
+                }
+            }
+        };
+
+        if let Some(error) = response.get("error").filter(|e| !e.is_null()) {
+            return Err(ChainError::Rpc {
+                code: error["code"].as_i64().unwrap_or(0),
+                message: error["message"].as_str().unwrap_or("").to_owned(),
+            });
+        }
+
+        response.get("result").cloned().ok_or(ChainError::InvalidResponse)
+    }
+
+    /// Submit a move and return the transaction id.
+    pub fn submit(&self, record: &MoveRecord) -> Result<String, ChainError> {
+        self.call("chess_submitMove", json!([record.to_payload()]))?
+            .as_str().map(str::to_owned).ok_or(ChainError::InvalidResponse)
+    }
+
+    /// All moves recorded for a game, in the order of their transactions.
+    pub fn moves(&self, game_id: &str) -> Result<Vec<MoveRecord>, ChainError> {
+        self.call("chess_getMoves", json!([game_id]))?
+            .as_array().ok_or(ChainError::InvalidResponse)?
+            .iter()
+            .map(|payload| MoveRecord::from_payload(payload).ok_or(ChainError::InvalidResponse))
+            .collect()
+    }
+
+    /// The final state hash recorded for a game, if any.
+    pub fn state_root(&self, game_id: &str) -> Result<Option<Hash>, ChainError> {
+        match self.call("chess_getStateRoot", json!([game_id]))? {
+            Value::Null => Ok(None),
+            value => {
+                let bytes = value.as_str().and_then(from_hex).ok_or(ChainError::InvalidResponse)?;
+                let mut hash = [0; 32];
+                if bytes.len() != hash.len() {
+                    return Err(ChainError::InvalidResponse);
+                }
+                hash.copy_from_slice(&bytes);
+                Ok(Some(hash))
+            }
+        }
+    }
+
+    /// Record the state hash of a game after a ply, e.g. as a checkpoint
+    /// of a game in progress, and return the transaction id.
+    pub fn submit_state_root(&self, game_id: &str, ply: u32, root: &Hash) -> Result<String, ChainError> {
+        self.call("chess_submitStateRoot", json!([game_id, ply, to_hex(root)]))?
+            .as_str().map(str::to_owned).ok_or(ChainError::InvalidResponse)
+    }
+
+    /// Number of confirmations of a transaction. Pending transactions have
+    /// none.
+    pub fn confirmations(&self, tx: &str) -> Result<u64, ChainError> {
+        match self.call("chess_getConfirmations", json!([tx]))? {
+            Value::Null => Ok(0),
+            value => value.as_u64().ok_or(ChainError::InvalidResponse),
+        }
+    }
+}
+
+/// Progress of moves handed to a `ChainRecorder`.
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    /// The move was accepted by the endpoint.
+    Submitted { game_id: String, ply: u32, tx: String },
+    /// The transaction has enough confirmations.
+    Confirmed { game_id: String, ply: u32, tx: String },
+    /// Submitting the move or tracking its transaction failed.
+    Failed { game_id: String, ply: u32, error: ChainError },
+}
+
+/// Submits moves on a background thread and tracks their confirmations.
+#[derive(Debug)]
+pub struct ChainRecorder {
+    records: Sender<MoveRecord>,
+}
+
+impl ChainRecorder {
+    /// Start the recorder thread. The thread stops when the recorder is
+    /// dropped and all pending transactions are resolved.
+    pub fn spawn(client: ChainClient) -> (ChainRecorder, Receiver<ChainEvent>) {
+        let (records, rx) = mpsc::channel();
+        let (tx, events) = mpsc::channel();
+        thread::spawn(move || record_moves(&client, &rx, &tx));
+        (ChainRecorder { records }, events)
+    }
+
+    /// Queue a move for submission.
+    pub fn record(&self, record: MoveRecord) {
+        let _ = self.records.send(record);
+    }
+}
+
+fn record_moves(client: &ChainClient, records: &Receiver<MoveRecord>, events: &Sender<ChainEvent>) {
+    let mut pending: Vec<(MoveRecord, String)> = Vec::new();
+    let mut closed = false;
+
+    while !closed || !pending.is_empty() {
+        let received = if closed {
+            thread::sleep(client.config().poll_interval);
+            Err(RecvTimeoutError::Timeout)
+        } else {
+            records.recv_timeout(client.config().poll_interval)
+        };
+
+        match received {
+            Ok(record) => {
+                let event = match client.submit(&record) {
+                    Ok(tx) => {
+                        pending.push((record.clone(), tx.clone()));
+                        ChainEvent::Submitted { game_id: record.game_id, ply: record.ply, tx }
+                    }
+                    Err(error) => ChainEvent::Failed { game_id: record.game_id, ply: record.ply, error },
+                };
+                if events.send(event).is_err() {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => closed = true,
+            Err(RecvTimeoutError::Timeout) => {
+                let mut still_pending = Vec::new();
+
+                for (record, tx) in pending.drain(..) {
+                    let event = match client.confirmations(&tx) {
+                        Ok(n) if n >= client.config().confirmations => {
+                            ChainEvent::Confirmed { game_id: record.game_id, ply: record.ply, tx }
+                        }
+                        Ok(_) => {
+                            still_pending.push((record, tx));
+                            continue;
+                        }
+                        Err(error) => ChainEvent::Failed { game_id: record.game_id, ply: record.ply, error },
+                    };
+                    if events.send(event).is_err() {
+                        return;
+                    }
+                }
+
+                pending = still_pending;
+            }
+        }
+    }
+}
+
+/// A way in which the moves on chain differ from a valid game.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Divergence {
+    /// A record belongs to another game.
+    WrongGame { index: usize, game_id: String },
+    /// A record has another ply than its position in the game.
+    WrongPly { expected: u32, found: u32 },
+    /// A move is not legal in the position reached by the previous moves.
+    /// Replaying stops here.
+    IllegalMove(MoveRecord),
+    /// The hash chain over the replayed moves does not end in the recorded
+    /// state hash.
+    StateRootMismatch { expected: Hash, actual: Hash },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Divergence::WrongGame { index, ref game_id } => write!(f, "record {} belongs to game {}", index, game_id),
+            Divergence::WrongPly { expected, found } => write!(f, "expected ply {}, found {}", expected, found),
+            Divergence::IllegalMove(ref record) => write!(f, "illegal move {}{} at ply {}", record.orig, record.dest, record.ply),
+            Divergence::StateRootMismatch { ref expected, ref actual } => {
+                write!(f, "state hash {} does not match recorded {}", to_hex(actual), to_hex(expected))
+            }
+        }
+    }
+}
+
+/// Result of replaying a game from chain data with `verify_game()`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VerificationReport {
+    pub game_id: String,
+    /// Number of moves found on chain.
+    pub records: usize,
+    /// Number of moves that were replayed legally.
+    pub replayed: usize,
+    /// FEN of the position after the replayed moves.
+    pub fen: String,
+    /// Hash chain root of the replayed moves.
+    pub state_root: Hash,
+    /// The state hash recorded on chain, if any.
+    pub expected_root: Option<Hash>,
+    pub divergences: Vec<Divergence>,
+}
+
+impl VerificationReport {
+    /// All moves are legal, in order and match the recorded state hash.
+    pub fn is_valid(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+/// Replay moves from the standard starting position, checking game ids,
+/// plies, legality and the final state hash.
+pub fn verify_records(game_id: &str, records: &[MoveRecord], expected_root: Option<Hash>) -> VerificationReport {
+    let mut state = BoardState::new();
+    let mut divergences = Vec::new();
+    let mut replayed = 0;
+
+    for (index, record) in records.iter().enumerate() {
+        if record.game_id != game_id {
+            divergences.push(Divergence::WrongGame { index, game_id: record.game_id.clone() });
+        }
+
+        if record.ply != replayed as u32 {
+            divergences.push(Divergence::WrongPly { expected: replayed as u32, found: record.ply });
+        }
+
+        let m = state.legals().iter().find(|m| {
+            m.from() == Some(record.orig) && m.to() == record.dest && m.promotion() == record.promotion
+        }).cloned();
+
+        match m {
+            Some(ref m) if state.play(m).is_ok() => replayed += 1,
+            _ => {
+                divergences.push(Divergence::IllegalMove(record.clone()));
+                break;
+            }
+        }
+    }
+
+    let state_root = state.state_root();
+    if let Some(expected) = expected_root {
+        if expected != state_root {
+            divergences.push(Divergence::StateRootMismatch { expected, actual: state_root });
+        }
+    }
+
+    VerificationReport {
+        game_id: game_id.to_owned(),
+        records: records.len(),
+        replayed,
+        fen: state.fen(),
+        state_root,
+        expected_root,
+        divergences,
+    }
+}
+
+/// Download the moves of a game and its state hash from the chain and
+/// replay them. Errors are only returned if the chain cannot be queried;
+/// invalid games are reported in the `VerificationReport`.
+pub fn verify_game(client: &ChainClient, game_id: &str) -> Result<VerificationReport, ChainError> {
+    let records = client.moves(game_id)?;
+    let expected_root = client.state_root(game_id)?;
+    Ok(verify_records(game_id, &records, expected_root))
+}
@@ -0,0 +1,132 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+use std::fmt;
+
+use gtk::prelude::*;
+use gtk::{glib, Adjustment, Align, Entry, Label, ListBox, Orientation, PolicyType, ScrolledWindow, SelectionMode};
+
+use relm::{Relm, Widget, Update};
+
+use net::ChatMessage;
+
+/// Chat pane messages.
+#[derive(Debug, Msg)]
+pub enum ChatMsg {
+    /// Show a received or sent message. Filter it with `ChatRooms` first.
+    Show(ChatMessage),
+    /// Remove all messages, e.g. when switching rooms.
+    Clear,
+    /// Hide the entry, e.g. for spectators who may only read.
+    SetReadOnly(bool),
+
+    /// Sent when the user entered a line. Forward to `Peer::chat()` and
+    /// back to `ChatMsg::Show`.
+    Submitted(String),
+}
+
+/// A list of chat messages with an entry below, to be placed next to the
+/// board.
+pub struct ChatPane {
+    vbox: gtk::Box,
+    scrolled: ScrolledWindow,
+    list: ListBox,
+    entry: Entry,
+}
+
+impl fmt::Debug for ChatPane {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChatPane")
+            .field("messages", &self.list.children().len())
+            .finish()
+    }
+}
+
+impl ChatPane {
+    fn show(&self, msg: &ChatMessage) {
+        let label = Label::new(None);
+        label.set_markup(&format!("<b>{}</b> {}",
+                                  glib::markup_escape_text(&msg.author),
+                                  glib::markup_escape_text(&msg.text)));
+        label.set_xalign(0.0);
+        label.set_line_wrap(true);
+        label.set_selectable(true);
+        self.list.add(&label);
+        self.list.show_all();
+
+        // follow the conversation
+        let adjustment = self.scrolled.vadjustment();
+        adjustment.set_value(adjustment.upper() - adjustment.page_size());
+    }
+}
+
+impl Update for ChatPane {
+    type Model = ();
+    type ModelParam = ();
+    type Msg = ChatMsg;
+
+    fn model(_: &Relm<Self>, _: ()) {}
+
+    fn update(&mut self, event: ChatMsg) {
+        match event {
+            ChatMsg::Show(msg) => self.show(&msg),
+            ChatMsg::Clear => {
+                for child in self.list.children() {
+                    self.list.remove(&child);
+                }
+            },
+            ChatMsg::SetReadOnly(read_only) => self.entry.set_visible(!read_only),
+            ChatMsg::Submitted(_) => {},
+        }
+    }
+}
+
+impl Widget for ChatPane {
+    type Root = gtk::Box;
+
+    fn root(&self) -> Self::Root {
+        self.vbox.clone()
+    }
+
+    fn view(relm: &Relm<Self>, _: ()) -> Self {
+        let list = ListBox::new();
+        list.set_selection_mode(SelectionMode::None);
+        list.set_valign(Align::End);
+
+        let scrolled = ScrolledWindow::new(None::<&Adjustment>, None::<&Adjustment>);
+        scrolled.set_policy(PolicyType::Never, PolicyType::Automatic);
+        scrolled.add(&list);
+
+        let entry = Entry::new();
+        entry.set_placeholder_text(Some("Chat"));
+        let stream = relm.stream().clone();
+        entry.connect_activate(move |entry| {
+            let text = entry.text().trim().to_owned();
+            if !text.is_empty() {
+                entry.set_text("");
+                stream.emit(ChatMsg::Submitted(text));
+            }
+        });
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 4);
+        vbox.pack_start(&scrolled, true, true, 0);
+        vbox.pack_start(&entry, false, false, 0);
+        vbox.show_all();
+
+        ChatPane { vbox, scrolled, list, entry }
+    }
+}
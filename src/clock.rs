@@ -0,0 +1,436 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "gtk3")]
+use gtk::prelude::*;
+#[cfg(feature = "gtk3")]
+use gtk::{Label, Orientation};
+
+#[cfg(feature = "gtk3")]
+use relm::{Relm, Widget, Update};
+
+use shakmaty::Color;
+
+use timing::{real_time, TimeSource};
+
+/// A period of a time control, for example 40 moves in 90 minutes with
+/// a 30 second increment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Period {
+    moves: Option<u32>,
+    base: Duration,
+    increment: Duration,
+    delay: Duration,
+}
+
+impl Period {
+    /// A period without increment or delay that lasts for the rest of the
+    /// game.
+    pub fn new(base: Duration) -> Period {
+        Period {
+            moves: None,
+            base,
+            increment: Duration::from_secs(0),
+            delay: Duration::from_secs(0),
+        }
+    }
+
+    /// End the period after the given number of moves.
+    pub fn with_moves(mut self, moves: u32) -> Period {
+        self.moves = Some(moves);
+        self
+    }
+
+    /// Add time after each move (Fischer increment).
+    pub fn with_increment(mut self, increment: Duration) -> Period {
+        self.increment = increment;
+        self
+    }
+
+    /// Only start counting down after a delay on each move
+    /// (Bronstein/simple delay).
+    pub fn with_delay(mut self, delay: Duration) -> Period {
+        self.delay = delay;
+        self
+    }
+
+    pub fn moves(&self) -> Option<u32> {
+        self.moves
+    }
+
+    pub fn base(&self) -> Duration {
+        self.base
+    }
+
+    pub fn increment(&self) -> Duration {
+        self.increment
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+}
+
+/// A time control consisting of one or more periods. The last period
+/// repeats if it is limited to a number of moves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeControl {
+    periods: Vec<Period>,
+}
+
+impl TimeControl {
+    /// A single period time control, like 5+3.
+    pub fn new(base: Duration, increment: Duration) -> TimeControl {
+        TimeControl {
+            periods: vec![Period::new(base).with_increment(increment)],
+        }
+    }
+
+    /// A time control with multiple periods.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `periods` is empty.
+    pub fn from_periods(periods: Vec<Period>) -> TimeControl {
+        assert!(!periods.is_empty(), "time control needs at least one period");
+        TimeControl { periods }
+    }
+
+    pub fn periods(&self) -> &[Period] {
+        &self.periods
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SideClock {
+    remaining: Duration,
+    period: usize,
+    moves: u32,
+}
+
+impl SideClock {
+    fn new(control: &TimeControl) -> SideClock {
+        SideClock {
+            remaining: control.periods[0].base,
+            period: 0,
+            moves: 0,
+        }
+    }
+}
+
+/// The clocks of both players.
+#[derive(Clone)]
+pub struct Clock {
+    control: TimeControl,
+    white: SideClock,
+    black: SideClock,
+    running: Option<(Color, Instant)>,
+    flagged: Option<Color>,
+    untimed: Option<Color>,
+    time_source: Arc<dyn TimeSource>,
+}
+
+impl fmt::Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Clock")
+            .field("white", &self.remaining(Color::White))
+            .field("black", &self.remaining(Color::Black))
+            .field("running", &self.running())
+            .field("flagged", &self.flagged)
+            .finish()
+    }
+}
+
+impl Clock {
+    /// Set up both clocks. The clocks are stopped until `start()`.
+    pub fn new(control: TimeControl) -> Clock {
+        Clock {
+            white: SideClock::new(&control),
+            black: SideClock::new(&control),
+            control,
+            running: None,
+            flagged: None,
+            untimed: None,
+            time_source: real_time(),
+        }
+    }
+
+    /// Take the time from `time_source`, e.g. a `ManualTime` in tests.
+    pub fn with_time_source(mut self, time_source: Arc<dyn TimeSource>) -> Clock {
+        self.stop();
+        self.time_source = time_source;
+        self
+    }
+
+    pub fn time_control(&self) -> &TimeControl {
+        &self.control
+    }
+
+    fn side(&self, color: Color) -> &SideClock {
+        color.fold_wb(&self.white, &self.black)
+    }
+
+    fn side_mut(&mut self, color: Color) -> &mut SideClock {
+        match color {
+            Color::White => &mut self.white,
+            Color::Black => &mut self.black,
+        }
+    }
+
+    /// Time used by the running side in its current move, after the
+    /// delay.
+    fn spent(&self) -> Duration {
+        match self.running {
+            Some((color, _)) if self.untimed == Some(color) => Duration::from_secs(0),
+            Some((color, since)) => {
+                let elapsed = self.time_source.now().duration_since(since);
+                let delay = self.control.periods[self.side(color).period].delay;
+                elapsed.checked_sub(delay).unwrap_or_default()
+            },
+            None => Duration::from_secs(0),
+        }
+    }
+
+    /// The remaining time of a player.
+    pub fn remaining(&self, color: Color) -> Duration {
+        let remaining = self.side(color).remaining;
+        if self.running() == Some(color) {
+            remaining.checked_sub(self.spent()).unwrap_or_default()
+        } else {
+            remaining
+        }
+    }
+
+    /// The side whose clock is ticking.
+    pub fn running(&self) -> Option<Color> {
+        self.running.map(|(color, _)| color)
+    }
+
+    /// Let the time of a side stand still while it is to move, e.g. for
+    /// the opponents in a simul, where only the clock of the simul giver
+    /// runs.
+    pub fn set_untimed(&mut self, color: Option<Color>) {
+        let running = self.running();
+        self.stop();
+        self.untimed = color;
+        if let Some(running) = running {
+            self.running = Some((running, self.time_source.now()));
+        }
+    }
+
+    /// The side whose time stands still, if any.
+    pub fn untimed(&self) -> Option<Color> {
+        self.untimed
+    }
+
+    /// The side that ran out of time, if any.
+    pub fn flagged(&self) -> Option<Color> {
+        self.flagged
+    }
+
+    /// Start the clock of a player. Does nothing after a flag.
+    pub fn start(&mut self, color: Color) {
+        self.stop();
+        if self.flagged.is_none() {
+            self.running = Some((color, self.time_source.now()));
+        }
+    }
+
+    /// Stop the running clock, without completing the move.
+    pub fn stop(&mut self) {
+        if let Some(color) = self.running() {
+            let remaining = self.remaining(color);
+            self.side_mut(color).remaining = remaining;
+            self.running = None;
+        }
+    }
+
+    /// Complete the move of the running side: add the increment, advance
+    /// to the next period if due, and start the clock of the opponent.
+    /// Returns how long the move took, including the delay.
+    pub fn press(&mut self) -> Option<Duration> {
+        let (color, since) = self.running?;
+
+        if self.flag().is_some() {
+            return None;
+        }
+
+        let elapsed = self.time_source.now().duration_since(since);
+        self.stop();
+
+        {
+            let periods = &self.control.periods;
+            let side = match color {
+                Color::White => &mut self.white,
+                Color::Black => &mut self.black,
+            };
+            side.remaining += periods[side.period].increment;
+            side.moves += 1;
+
+            if periods[side.period].moves.map_or(false, |moves| side.moves >= moves) {
+                // the last period repeats
+                side.period = (side.period + 1).min(periods.len() - 1);
+                side.moves = 0;
+                side.remaining += periods[side.period].base;
+            }
+        }
+
+        self.running = Some((!color, self.time_source.now()));
+        Some(elapsed)
+    }
+
+    /// Check if the running side ran out of time. Returns the color that
+    /// just flagged, stopping the clock.
+    pub fn flag(&mut self) -> Option<Color> {
+        match self.running() {
+            Some(color) if self.remaining(color) == Duration::from_secs(0) => {
+                self.stop();
+                self.flagged = Some(color);
+                Some(color)
+            },
+            _ => None,
+        }
+    }
+
+    /// Overwrite the remaining time of both players, for example when
+    /// synchronizing with a server or peer. The running clock keeps
+    /// running.
+    pub fn set_remaining(&mut self, white: Duration, black: Duration) {
+        let running = self.running();
+        self.running = None;
+        self.white.remaining = white;
+        self.black.remaining = black;
+        if let Some(color) = running {
+            self.running = Some((color, self.time_source.now()));
+        }
+    }
+}
+
+/// Format a remaining time as `h:mm:ss`, `m:ss`, or with tenths of
+/// seconds when below 10 seconds.
+#[cfg(feature = "gtk3")]
+pub(crate) fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else if secs >= 10 {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    } else {
+        format!("0:{:02}.{}", secs, time.subsec_millis() / 100)
+    }
+}
+
+/// Chess clock messages.
+#[cfg(feature = "gtk3")]
+#[derive(Debug, Msg)]
+pub enum ClockMsg {
+    /// Show the remaining times of white and black, and the running side.
+    /// Forward `GroundMsg::ClockChanged` here.
+    SetTimes(Duration, Duration, Option<Color>),
+    /// Show the clock of the given color at the bottom.
+    SetOrientation(Color),
+}
+
+/// A widget that shows the clocks of both players, with the running clock
+/// highlighted.
+#[cfg(feature = "gtk3")]
+pub struct ChessClock {
+    vbox: gtk::Box,
+    top: Label,
+    bottom: Label,
+    orientation: Color,
+    times: (Duration, Duration, Option<Color>),
+}
+
+#[cfg(feature = "gtk3")]
+impl fmt::Debug for ChessClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChessClock")
+            .field("orientation", &self.orientation)
+            .field("times", &self.times)
+            .finish()
+    }
+}
+
+#[cfg(feature = "gtk3")]
+impl ChessClock {
+    fn render(&self) {
+        let (white, black, running) = self.times;
+
+        for &(label, color) in &[(&self.top, !self.orientation), (&self.bottom, self.orientation)] {
+            let text = format_time(color.fold_wb(white, black));
+            if running == Some(color) {
+                label.set_markup(&format!("<b>{}</b>", text));
+            } else {
+                label.set_markup(&text);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "gtk3")]
+impl Update for ChessClock {
+    type Model = ();
+    type ModelParam = ();
+    type Msg = ClockMsg;
+
+    fn model(_: &Relm<Self>, _: ()) {}
+
+    fn update(&mut self, event: ClockMsg) {
+        match event {
+            ClockMsg::SetTimes(white, black, running) => {
+                self.times = (white, black, running);
+            },
+            ClockMsg::SetOrientation(orientation) => {
+                self.orientation = orientation;
+            },
+        }
+
+        self.render();
+    }
+}
+
+#[cfg(feature = "gtk3")]
+impl Widget for ChessClock {
+    type Root = gtk::Box;
+
+    fn root(&self) -> Self::Root {
+        self.vbox.clone()
+    }
+
+    fn view(_: &Relm<Self>, _: ()) -> Self {
+        let vbox = gtk::Box::new(Orientation::Vertical, 0);
+
+        let top = Label::new(None);
+        let bottom = Label::new(None);
+        vbox.pack_start(&top, false, false, 0);
+        vbox.pack_end(&bottom, false, false, 0);
+        vbox.show_all();
+
+        ChessClock {
+            vbox,
+            top,
+            bottom,
+            orientation: Color::White,
+            times: (Duration::from_secs(0), Duration::from_secs(0), None),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Hash chain over the history of a game.
+//!
+//! The root of a game with no moves is `SHA-256(fen)` of the starting
+//! position. Each move extends the chain with
+//! `SHA-256(previous root || SHA-256(fen after the move) || uci)`, so two
+//! parties agree on the root exactly if they agree on the whole history.
+
+use sha2::{Digest, Sha256};
+
+/// A SHA-256 hash.
+pub type Hash = [u8; 32];
+
+/// Hash of a position, given as FEN.
+pub fn position_hash(fen: &str) -> Hash {
+    Sha256::digest(fen.as_bytes()).into()
+}
+
+/// Extend the chain by one move.
+pub fn chain_hash(previous: &Hash, position: &Hash, uci: &str) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(previous);
+    hasher.update(position);
+    hasher.update(uci.as_bytes());
+    hasher.finalize().into()
+}
+
+/// The roots after each move of a game.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Commitment {
+    roots: Vec<Hash>,
+}
+
+impl Commitment {
+    /// Start a chain at the given position.
+    pub fn new(initial_fen: &str) -> Commitment {
+        Commitment {
+            roots: vec![position_hash(initial_fen)],
+        }
+    }
+
+    /// Record a move in UCI notation and the FEN of the resulting position.
+    pub fn push(&mut self, uci: &str, fen: &str) -> Hash {
+        let root = chain_hash(&self.root(), &position_hash(fen), uci);
+        self.roots.push(root);
+        root
+    }
+
+    /// Forget the last move. The root of the starting position is kept.
+    pub fn pop(&mut self) -> Option<Hash> {
+        if self.roots.len() > 1 {
+            self.roots.pop()
+        } else {
+            None
+        }
+    }
+
+    /// The current root.
+    pub fn root(&self) -> Hash {
+        *self.roots.last().expect("initial root")
+    }
+
+    /// Roots of all plies, starting with the initial position.
+    pub fn roots(&self) -> &[Hash] {
+        &self.roots
+    }
+
+    /// Number of moves covered.
+    pub fn len(&self) -> usize {
+        self.roots.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,382 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! User settings.
+//!
+//! Settings are kept in `$XDG_CONFIG_HOME/chessground/settings.toml`,
+//! usually `~/.config/chessground/settings.toml`. Missing keys keep their
+//! defaults:
+//!
+//! ```toml
+//! theme = "brown"
+//! piece_set = "/usr/share/chessground/pieces/alpha"
+//! animation = 300
+//! sounds = "/usr/share/chessground/sounds"
+//! muted = false
+//! engine = "/usr/bin/stockfish"
+//! chain_endpoint = "http://localhost:8545"
+//! relay = "127.0.0.1:9001"
+//! key_file = "/home/user/.local/share/chessground/player.key"
+//! clock_base = 300
+//! clock_increment = 3
+//! ```
+//!
+//! The theme is the name of a preset or the path of a theme file. The
+//! animation is in milliseconds, the default clock in seconds.
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use toml::Value;
+use toml::value::Table;
+
+use audio::SoundSet;
+#[cfg(feature = "blockchain")]
+use chain::ChainConfig;
+use clock::TimeControl;
+#[cfg(feature = "blockchain")]
+use crypto::PlayerKey;
+#[cfg(feature = "gtk3")]
+use ground::GroundMsg;
+use pieceset::PieceSet;
+use theme::{Theme, ThemeError};
+
+/// Error when loading or saving the settings.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read or written.
+    Io(io::Error),
+    /// The file is not valid TOML.
+    Toml(toml::de::Error),
+    /// A setting has the wrong type, like a number for a path.
+    InvalidSetting(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref err) => write!(f, "could not access settings: {}", err),
+            ConfigError::Toml(ref err) => write!(f, "invalid settings: {}", err),
+            ConfigError::InvalidSetting(ref key) => write!(f, "invalid setting for {}", key),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> ConfigError {
+        ConfigError::Io(err)
+    }
+}
+
+/// A base directory from an XDG environment variable, or the fallback
+/// below the home directory. Relative paths in the variable are ignored,
+/// as the specification asks.
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    match env::var_os(var).map(PathBuf::from) {
+        Some(ref dir) if dir.is_absolute() => dir.clone(),
+        _ => env::var_os("HOME").map_or_else(PathBuf::new, PathBuf::from).join(fallback),
+    }
+}
+
+/// Directory of the settings and other configuration, like piece sets.
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config").join("chessground")
+}
+
+/// Directory of user data, like the player key and stored games.
+pub fn data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share").join("chessground")
+}
+
+/// Typed user settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Name of a preset, see `Theme::PRESETS`, or path of a theme file.
+    pub theme: String,
+    /// Directory of piece images, or `None` for the built-in merida set.
+    pub piece_set: Option<PathBuf>,
+    /// How long pieces glide to their squares.
+    pub animation: Duration,
+    /// Directory of sound files, or `None` for silence.
+    pub sounds: Option<PathBuf>,
+    pub muted: bool,
+    /// UCI engine for analysis and bots.
+    pub engine: Option<PathBuf>,
+    /// JSON-RPC endpoint of the chain.
+    pub chain_endpoint: String,
+    /// Address of the relay server for `Peer::connect()`.
+    pub relay: String,
+    /// Where the key of the player is kept.
+    pub key_file: PathBuf,
+    /// Time per player of new games.
+    pub clock_base: Duration,
+    /// Time added after every move of new games.
+    pub clock_increment: Duration,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            theme: "blue".to_owned(),
+            piece_set: None,
+            animation: Duration::from_millis(300),
+            sounds: None,
+            muted: false,
+            engine: None,
+            chain_endpoint: "http://localhost:8545".to_owned(),
+            relay: "127.0.0.1:9001".to_owned(),
+            key_file: data_dir().join("player.key"),
+            clock_base: Duration::from_secs(5 * 60),
+            clock_increment: Duration::from_secs(3),
+        }
+    }
+}
+
+impl Settings {
+    /// Parse settings from TOML. Unknown keys are ignored.
+    pub fn from_toml(text: &str) -> Result<Settings, ConfigError> {
+        let value = text.parse::<Value>().map_err(ConfigError::Toml)?;
+        let mut settings = Settings::default();
+
+        let string = |key: &str| -> Result<Option<String>, ConfigError> {
+            match value.get(key) {
+                Some(v) => v.as_str().map(|s| Some(s.to_owned())).ok_or_else(|| ConfigError::InvalidSetting(key.to_owned())),
+                None => Ok(None),
+            }
+        };
+
+        if let Some(theme) = string("theme")? {
+            settings.theme = theme;
+        }
+        if let Some(piece_set) = string("piece_set")? {
+            settings.piece_set = Some(PathBuf::from(piece_set));
+        }
+        let duration = |key: &str, unit: fn(u64) -> Duration| -> Result<Option<Duration>, ConfigError> {
+            match value.get(key) {
+                Some(v) => match v.as_integer() {
+                    Some(n) if n >= 0 => Ok(Some(unit(n as u64))),
+                    _ => Err(ConfigError::InvalidSetting(key.to_owned())),
+                },
+                None => Ok(None),
+            }
+        };
+
+        if let Some(animation) = duration("animation", Duration::from_millis)? {
+            settings.animation = animation;
+        }
+        if let Some(sounds) = string("sounds")? {
+            settings.sounds = Some(PathBuf::from(sounds));
+        }
+        if let Some(v) = value.get("muted") {
+            settings.muted = v.as_bool().ok_or_else(|| ConfigError::InvalidSetting("muted".to_owned()))?;
+        }
+        if let Some(engine) = string("engine")? {
+            settings.engine = Some(PathBuf::from(engine));
+        }
+        if let Some(endpoint) = string("chain_endpoint")? {
+            settings.chain_endpoint = endpoint;
+        }
+        if let Some(relay) = string("relay")? {
+            settings.relay = relay;
+        }
+        if let Some(key_file) = string("key_file")? {
+            settings.key_file = PathBuf::from(key_file);
+        }
+        if let Some(base) = duration("clock_base", Duration::from_secs)? {
+            settings.clock_base = base;
+        }
+        if let Some(increment) = duration("clock_increment", Duration::from_secs)? {
+            settings.clock_increment = increment;
+        }
+
+        Ok(settings)
+    }
+
+    /// Format the settings as TOML, leaving out unset paths.
+    pub fn to_toml(&self) -> String {
+        let path = |p: &Path| Value::String(p.to_string_lossy().into_owned());
+
+        let mut table = Table::new();
+        table.insert("theme".to_owned(), Value::String(self.theme.clone()));
+        if let Some(ref piece_set) = self.piece_set {
+            table.insert("piece_set".to_owned(), path(piece_set));
+        }
+        table.insert("animation".to_owned(), Value::Integer(self.animation.as_millis() as i64));
+        if let Some(ref sounds) = self.sounds {
+            table.insert("sounds".to_owned(), path(sounds));
+        }
+        table.insert("muted".to_owned(), Value::Boolean(self.muted));
+        if let Some(ref engine) = self.engine {
+            table.insert("engine".to_owned(), path(engine));
+        }
+        table.insert("chain_endpoint".to_owned(), Value::String(self.chain_endpoint.clone()));
+        table.insert("relay".to_owned(), Value::String(self.relay.clone()));
+        table.insert("key_file".to_owned(), path(&self.key_file));
+        table.insert("clock_base".to_owned(), Value::Integer(self.clock_base.as_secs() as i64));
+        table.insert("clock_increment".to_owned(), Value::Integer(self.clock_increment.as_secs() as i64));
+        Value::Table(table).to_string()
+    }
+
+    /// Load the settings from a file. A missing file gives the defaults.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Settings, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Settings::from_toml(&text),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Save the settings to a file, creating its directory if needed.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_toml())?;
+        Ok(())
+    }
+
+    /// The theme preset or file.
+    pub fn theme(&self) -> Result<Theme, ThemeError> {
+        match Theme::preset(&self.theme) {
+            Some(theme) => Ok(theme),
+            None => Theme::load(&self.theme),
+        }
+    }
+
+    pub fn piece_set(&self) -> PieceSet {
+        self.piece_set.as_ref().map_or_else(PieceSet::merida, PieceSet::from_dir)
+    }
+
+    pub fn sounds(&self) -> SoundSet {
+        self.sounds.as_ref().map_or_else(SoundSet::new, SoundSet::from_dir)
+    }
+
+    #[cfg(feature = "blockchain")]
+    pub fn chain_config(&self) -> ChainConfig {
+        ChainConfig::new(&self.chain_endpoint)
+    }
+
+    /// The default time control of new games.
+    pub fn time_control(&self) -> TimeControl {
+        TimeControl::new(self.clock_base, self.clock_increment)
+    }
+
+    /// The key of the player, generated on first use.
+    #[cfg(feature = "blockchain")]
+    pub fn player_key(&self) -> io::Result<PlayerKey> {
+        if let Some(dir) = self.key_file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        PlayerKey::load_or_generate(&self.key_file)
+    }
+
+    /// Messages applying the board settings to a `Ground`. A theme that
+    /// cannot be loaded is left out.
+    #[cfg(feature = "gtk3")]
+    pub fn ground_messages(&self) -> Vec<GroundMsg> {
+        let mut messages = Vec::new();
+        if let Ok(theme) = self.theme() {
+            messages.push(GroundMsg::SetTheme(theme));
+        }
+        messages.push(GroundMsg::SetPieceSet(self.piece_set()));
+        messages.push(GroundMsg::SetAnimationDuration(self.animation));
+        messages.push(GroundMsg::SetSounds(self.sounds()));
+        messages.push(GroundMsg::SetMuted(self.muted));
+        messages
+    }
+}
+
+/// The settings file with listeners, which are told about every change,
+/// e.g. to forward `Settings::ground_messages()` to the board.
+pub struct Config {
+    path: PathBuf,
+    settings: Settings,
+    listeners: Vec<Box<dyn Fn(&Settings)>>,
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("path", &self.path)
+            .field("settings", &self.settings)
+            .finish()
+    }
+}
+
+impl Config {
+    /// Load the settings from the default location, see `config_dir()`.
+    pub fn load() -> Result<Config, ConfigError> {
+        Config::open(config_dir().join("settings.toml"))
+    }
+
+    /// Load the settings from a file, which is created when saving.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let path = path.as_ref().to_owned();
+        Ok(Config {
+            settings: Settings::load(&path)?,
+            path,
+            listeners: Vec::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Call a function with the new settings after each change.
+    pub fn connect_changed<F: Fn(&Settings) + 'static>(&mut self, f: F) {
+        self.listeners.push(Box::new(f));
+    }
+
+    /// Change the settings, save them, and tell the listeners if anything
+    /// changed.
+    pub fn update<F: FnOnce(&mut Settings)>(&mut self, f: F) -> Result<(), ConfigError> {
+        let mut settings = self.settings.clone();
+        f(&mut settings);
+        self.replace(settings);
+        self.settings.save(&self.path)
+    }
+
+    /// Read the file again, e.g. after it was edited by hand, and tell the
+    /// listeners if anything changed.
+    pub fn reload(&mut self) -> Result<(), ConfigError> {
+        let settings = Settings::load(&self.path)?;
+        self.replace(settings);
+        Ok(())
+    }
+
+    fn replace(&mut self, settings: Settings) {
+        if settings != self.settings {
+            self.settings = settings;
+            for listener in &self.listeners {
+                listener(&self.settings);
+            }
+        }
+    }
+}
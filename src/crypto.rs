@@ -0,0 +1,242 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier};
+use rand_core::OsRng;
+
+use serde_json::Value;
+
+use shakmaty::{Square, Role};
+
+use chain::MoveRecord;
+use util::{from_hex, to_hex};
+
+/// An ed25519 key pair identifying a player.
+pub struct PlayerKey {
+    keypair: Keypair,
+}
+
+impl fmt::Debug for PlayerKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PlayerKey")
+            .field("public_key", &to_hex(&self.public_key()))
+            .finish()
+    }
+}
+
+impl Clone for PlayerKey {
+    fn clone(&self) -> PlayerKey {
+        PlayerKey {
+            keypair: Keypair::from_bytes(&self.keypair.to_bytes()).expect("valid keypair"),
+        }
+    }
+}
+
+impl PlayerKey {
+    /// Generate a new random key.
+    pub fn generate() -> PlayerKey {
+        PlayerKey {
+            keypair: Keypair::generate(&mut OsRng),
+        }
+    }
+
+    /// Load a key saved with `PlayerKey::save()`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<PlayerKey> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid key file");
+        let bytes = from_hex(fs::read_to_string(path)?.trim()).ok_or_else(invalid)?;
+
+        Ok(PlayerKey {
+            keypair: Keypair::from_bytes(&bytes).map_err(|_| invalid())?,
+        })
+    }
+
+    /// Load the key at `path`, or generate and save a new one if the file
+    /// does not exist yet.
+    pub fn load_or_generate<P: AsRef<Path>>(path: P) -> io::Result<PlayerKey> {
+        if path.as_ref().exists() {
+            PlayerKey::load(path)
+        } else {
+            let key = PlayerKey::generate();
+            key.save(path)?;
+            Ok(key)
+        }
+    }
+
+    /// Save the key as hex. The file is only readable by the owner.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(path)?;
+
+        // the mode only applies to new files, so restrict an existing one
+        // before the secret is written to it
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+
+        writeln!(file, "{}", to_hex(&self.keypair.to_bytes()))
+    }
+
+    /// The 32 byte public key to share with other players.
+    pub fn public_key(&self) -> [u8; 32] {
+        self.keypair.public.to_bytes()
+    }
+
+    /// Sign a message, returning a 64 byte signature.
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        self.keypair.sign(message).to_bytes().to_vec()
+    }
+
+    /// Sign a move record in place.
+    pub fn sign_record(&self, record: &mut MoveRecord) {
+        let signature = self.sign(&record.signing_bytes());
+        record.set_signature(Some(signature));
+    }
+}
+
+/// Check a signature made with `PlayerKey::sign()`.
+pub fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    let public_key = match PublicKey::from_bytes(public_key) {
+        Ok(public_key) => public_key,
+        Err(_) => return false,
+    };
+
+    match Signature::try_from(signature) {
+        Ok(signature) => public_key.verify(message, &signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Check that a move record was signed by the owner of `public_key`.
+/// Unsigned records are rejected.
+pub fn verify_record(public_key: &[u8], record: &MoveRecord) -> bool {
+    record.signature().map_or(false, |signature| {
+        verify(public_key, &record.signing_bytes(), signature)
+    })
+}
+
+/// Proof that a client holds the key of a player, for a relay to bind
+/// seats and tournaments to the key instead of the connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    public_key: [u8; 32],
+    timestamp: u64,
+    signature: Vec<u8>,
+}
+
+impl Identity {
+    /// Sign the current time with `key`.
+    pub fn new(key: &PlayerKey) -> Identity {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        let public_key = key.public_key();
+        Identity {
+            signature: key.sign(&Identity::signing_bytes(&public_key, timestamp)),
+            public_key,
+            timestamp,
+        }
+    }
+
+    pub fn public_key(&self) -> &[u8; 32] {
+        &self.public_key
+    }
+
+    /// Milliseconds since the Unix epoch when the proof was made.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// The signature is valid and was made at most `max_age` before or
+    /// after `now`, in milliseconds since the Unix epoch.
+    pub fn verify(&self, now: u64, max_age: u64) -> bool {
+        now.max(self.timestamp) - now.min(self.timestamp) <= max_age &&
+            verify(&self.public_key, &Identity::signing_bytes(&self.public_key, self.timestamp), &self.signature)
+    }
+
+    /// Prefixed so that the bytes can not be mistaken for a move record.
+    fn signing_bytes(public_key: &[u8; 32], timestamp: u64) -> Vec<u8> {
+        format!("identify:{}:{}", to_hex(public_key), timestamp).into_bytes()
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "key": to_hex(&self.public_key),
+            "timestamp": self.timestamp,
+            "signature": to_hex(&self.signature),
+        })
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Option<Identity> {
+        let key = from_hex(value["key"].as_str()?)?;
+        if key.len() != 32 {
+            return None;
+        }
+        let mut public_key = [0; 32];
+        public_key.copy_from_slice(&key);
+
+        Some(Identity {
+            public_key,
+            timestamp: value["timestamp"].as_u64()?,
+            signature: from_hex(value["signature"].as_str()?)?,
+        })
+    }
+}
+
+/// Signs the moves of the local player in a game.
+#[derive(Debug, Clone)]
+pub struct MoveSigner {
+    game_id: String,
+    key: PlayerKey,
+}
+
+impl MoveSigner {
+    pub fn new(game_id: &str, key: PlayerKey) -> MoveSigner {
+        MoveSigner {
+            game_id: game_id.to_owned(),
+            key,
+        }
+    }
+
+    pub fn game_id(&self) -> &str {
+        &self.game_id
+    }
+
+    pub fn key(&self) -> &PlayerKey {
+        &self.key
+    }
+
+    /// Create a signed record of a move.
+    pub fn sign_move(&self, ply: u32, orig: Square, dest: Square, promotion: Option<Role>) -> MoveRecord {
+        let mut record = MoveRecord::new(&self.game_id, ply, orig, dest, promotion);
+        self.key.sign_record(&mut record);
+        record
+    }
+}
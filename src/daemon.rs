@@ -0,0 +1,429 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! A headless daemon managing games over JSON-RPC and HTTP, for web
+//! frontends.
+//!
+//! JSON-RPC 2.0 requests are posted to `/rpc`:
+//!
+//! * `create_game` with an optional `id` and `fen`, returning the `id`
+//!   and `fen` of the new game.
+//! * `make_move` with the `id` of a game and a move in `uci` notation,
+//!   returning the new `ply`, `fen` and `state_root`.
+//! * `get_fen` and `get_pgn` with the `id` of a game.
+//!
+//! The moves of a game are streamed as server-sent events from
+//! `GET /games/ID/events`, starting with the moves already played:
+//!
+//! ```text
+//! event: move
+//! data: {"ply":0,"uci":"e2e4","fen":"..."}
+//! ```
+//!
+//! When recording on chain, `chain` events report the transactions of the
+//! moves.
+//!
+//! Browsers may only reach the daemon from the origins allowed with
+//! `Daemon::with_origin()`, and calls must be sent as `application/json`,
+//! so that other pages can not post to `/rpc` without a preflight. With
+//! `Daemon::with_token()`, calls also need an `Authorization: Bearer`
+//! header with the token.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use shakmaty::uci::Uci;
+
+use boardstate::BoardState;
+use chain::{ChainClient, ChainEvent, ChainRecorder, MoveRecord};
+use util::to_hex;
+
+/// Largest request body accepted.
+const MAX_BODY: usize = 1 << 20;
+
+/// Time between comments that keep idle event streams open.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const UNKNOWN_GAME: i64 = -32000;
+const ILLEGAL_MOVE: i64 = -32001;
+
+type RpcError = (i64, String);
+
+struct DaemonGame {
+    state: BoardState,
+    /// Events of the moves played so far, for new subscribers.
+    history: Vec<String>,
+    subscribers: Vec<Sender<String>>,
+}
+
+impl DaemonGame {
+    fn broadcast(&mut self, event: String) {
+        self.subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+}
+
+#[derive(Default)]
+struct Games {
+    games: HashMap<String, DaemonGame>,
+    next_id: u64,
+    recorder: Option<ChainRecorder>,
+}
+
+fn sse(event: &str, data: &Value) -> String {
+    format!("event: {}\ndata: {}\n\n", event, data)
+}
+
+fn invalid_params(message: &str) -> RpcError {
+    (INVALID_PARAMS, message.to_owned())
+}
+
+impl Games {
+    fn game<'a>(&'a mut self, params: &'a Value) -> Result<(&'a str, &'a mut DaemonGame), RpcError> {
+        let id = params["id"].as_str().ok_or_else(|| invalid_params("missing game id"))?;
+        match self.games.get_mut(id) {
+            Some(game) => Ok((id, game)),
+            None => Err((UNKNOWN_GAME, format!("unknown game {}", id))),
+        }
+    }
+
+    fn call(&mut self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "create_game" => {
+                let id = match params["id"].as_str() {
+                    Some(id) if self.games.contains_key(id) => return Err(invalid_params("game id is taken")),
+                    Some(id) => id.to_owned(),
+                    None => {
+                        self.next_id += 1;
+                        format!("game-{}", self.next_id)
+                    },
+                };
+
+                let mut state = BoardState::new();
+                if let Some(fen) = params["fen"].as_str() {
+                    if state.set_fen(fen).is_err() || state.position().is_none() {
+                        return Err(invalid_params("invalid fen"));
+                    }
+                }
+
+                let fen = state.fen();
+                self.games.insert(id.clone(), DaemonGame {
+                    state,
+                    history: Vec::new(),
+                    subscribers: Vec::new(),
+                });
+                Ok(json!({ "id": id, "fen": fen }))
+            },
+            "make_move" => {
+                let uci = params["uci"].as_str().and_then(|uci| uci.parse::<Uci>().ok()).ok_or_else(|| invalid_params("invalid uci"))?;
+                let record = {
+                    let (id, game) = self.game(params)?;
+                    if game.state.game_state().is_over() {
+                        return Err((ILLEGAL_MOVE, "game is over".to_owned()));
+                    }
+
+                    let mode = game.state.castling_mode();
+                    let m = game.state.legals().iter().find(|m| Uci::from_move(m, mode) == uci).cloned();
+                    let (m, orig) = match m.as_ref().and_then(|m| m.from().map(|orig| (m, orig))) {
+                        Some((m, orig)) if game.state.play(m).is_ok() => (m.clone(), orig),
+                        _ => return Err((ILLEGAL_MOVE, format!("illegal move {}", uci))),
+                    };
+
+                    let ply = game.history.len() as u32;
+                    let fen = game.state.fen();
+                    let event = sse("move", &json!({ "ply": ply, "uci": uci.to_string(), "fen": fen }));
+                    game.history.push(event.clone());
+                    game.broadcast(event);
+                    MoveRecord::new(id, ply, orig, m.to(), m.promotion())
+                };
+
+                if let Some(ref recorder) = self.recorder {
+                    recorder.record(record.clone());
+                }
+
+                let (_, game) = self.game(params)?;
+                Ok(json!({
+                    "ply": record.ply(),
+                    "fen": game.state.fen(),
+                    "state_root": to_hex(&game.state.state_root()),
+                }))
+            },
+            "get_fen" => {
+                let (_, game) = self.game(params)?;
+                Ok(Value::String(game.state.fen()))
+            },
+            "get_pgn" => {
+                let (_, game) = self.game(params)?;
+                let pgn = game.state.to_game().map_or_else(String::new, |game| game.to_pgn());
+                Ok(Value::String(pgn))
+            },
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method {}", method))),
+        }
+    }
+
+    /// Tell the subscribers of a game about the transaction of a move.
+    fn chain_event(&mut self, event: ChainEvent) {
+        let (game_id, data) = match event {
+            ChainEvent::Submitted { game_id, ply, tx } => (game_id, json!({ "ply": ply, "status": "submitted", "tx": tx })),
+            ChainEvent::Confirmed { game_id, ply, tx } => (game_id, json!({ "ply": ply, "status": "confirmed", "tx": tx })),
+            ChainEvent::Failed { game_id, ply, error } => (game_id, json!({ "ply": ply, "status": "failed", "error": error.to_string() })),
+        };
+        if let Some(game) = self.games.get_mut(&game_id) {
+            game.broadcast(sse("chain", &data));
+        }
+    }
+}
+
+type ErrorHandler = Arc<dyn Fn(&io::Error) + Send + Sync>;
+
+/// Who may talk to the daemon.
+#[derive(Default, Clone)]
+struct Access {
+    origins: Vec<String>,
+    token: Option<String>,
+}
+
+impl Access {
+    /// The origin of a browser request, if it is allowed.
+    fn allowed_origin<'a>(&self, origin: &'a str) -> Option<&'a str> {
+        Some(origin).filter(|origin| self.origins.iter().any(|allowed| allowed == origin))
+    }
+}
+
+/// Games served over HTTP, optionally recording their moves on chain.
+pub struct Daemon {
+    games: Arc<Mutex<Games>>,
+    access: Access,
+    on_error: Option<ErrorHandler>,
+}
+
+impl fmt::Debug for Daemon {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Daemon")
+            .field("games", &self.games.lock().map(|games| games.games.len()).unwrap_or(0))
+            .field("origins", &self.access.origins)
+            .finish()
+    }
+}
+
+impl Daemon {
+    pub fn new() -> Daemon {
+        Daemon {
+            games: Arc::new(Mutex::new(Games::default())),
+            access: Access::default(),
+            on_error: None,
+        }
+    }
+
+    /// Allow browser pages from `origin`, like `http://localhost:3000`.
+    /// Requests from other origins are refused.
+    pub fn with_origin(mut self, origin: &str) -> Daemon {
+        self.access.origins.push(origin.to_owned());
+        self
+    }
+
+    /// Require `Authorization: Bearer TOKEN` on JSON-RPC calls.
+    pub fn with_token(mut self, token: &str) -> Daemon {
+        self.access.token = Some(token.to_owned());
+        self
+    }
+
+    /// Call `f` when a connection fails, e.g. to log it.
+    pub fn with_error_handler<F: Fn(&io::Error) + Send + Sync + 'static>(mut self, f: F) -> Daemon {
+        self.on_error = Some(Arc::new(f));
+        self
+    }
+
+    /// Submit every move to the chain, reporting the transactions as
+    /// `chain` events.
+    pub fn with_chain(self, client: ChainClient) -> Daemon {
+        let (recorder, events) = ChainRecorder::spawn(client);
+        self.games.lock().expect("games lock").recorder = Some(recorder);
+
+        let games = Arc::clone(&self.games);
+        thread::spawn(move || {
+            for event in events {
+                games.lock().expect("games lock").chain_event(event);
+            }
+        });
+        self
+    }
+
+    /// Handle a JSON-RPC call without going through HTTP.
+    pub fn call(&self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        self.games.lock().expect("games lock").call(method, params)
+    }
+
+    /// Accept connections until accepting fails, handling each on its own
+    /// thread. Errors of single connections go to the error handler.
+    pub fn serve<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let games = Arc::clone(&self.games);
+            let access = self.access.clone();
+            let on_error = self.on_error.clone();
+            thread::spawn(move || {
+                if let (Err(err), Some(on_error)) = (handle(&games, &access, stream), on_error) {
+                    on_error(&err);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for Daemon {
+    fn default() -> Daemon {
+        Daemon::new()
+    }
+}
+
+/// CORS headers for an allowed origin.
+fn cors(origin: Option<&str>) -> String {
+    origin.map_or_else(String::new, |origin| {
+        format!("Access-Control-Allow-Origin: {}\r\nAccess-Control-Allow-Headers: Content-Type, Authorization\r\nVary: Origin\r\n", origin)
+    })
+}
+
+fn respond(stream: &mut TcpStream, origin: Option<&str>, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    write!(stream,
+           "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+           status, content_type, body.len(), cors(origin), body)?;
+    stream.flush()
+}
+
+fn rpc_reply(games: &Mutex<Games>, body: &[u8]) -> Value {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(_) => return json!({ "jsonrpc": "2.0", "id": null, "error": { "code": PARSE_ERROR, "message": "parse error" } }),
+    };
+
+    let id = request["id"].clone();
+    let result = match request["method"].as_str() {
+        Some(method) => games.lock().expect("games lock").call(method, &request["params"]),
+        None => Err((INVALID_REQUEST, "missing method".to_owned())),
+    };
+
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+    }
+}
+
+/// Stream the moves of a game until the client goes away.
+fn subscribe(games: &Mutex<Games>, stream: &mut TcpStream, origin: Option<&str>, game_id: &str) -> io::Result<()> {
+    let (tx, events) = mpsc::channel();
+    let history = match games.lock().expect("games lock").games.get_mut(game_id) {
+        Some(game) => {
+            game.subscribers.push(tx);
+            game.history.clone()
+        },
+        None => return respond(stream, origin, "404 Not Found", "text/plain", "unknown game"),
+    };
+
+    write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n{}\r\n", cors(origin))?;
+    for event in history {
+        stream.write_all(event.as_bytes())?;
+    }
+    stream.flush()?;
+
+    loop {
+        match events.recv_timeout(KEEPALIVE_INTERVAL) {
+            Ok(event) => stream.write_all(event.as_bytes())?,
+            Err(RecvTimeoutError::Timeout) => stream.write_all(b": keepalive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        stream.flush()?;
+    }
+}
+
+fn handle(games: &Mutex<Games>, access: &Access, mut stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_owned();
+    let path = parts.next().unwrap_or("").to_owned();
+
+    let mut length = 0;
+    let mut content_type = String::new();
+    let mut origin = None;
+    let mut authorization = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        let mut header = line.splitn(2, ':');
+        let name = header.next().unwrap_or("").trim().to_ascii_lowercase();
+        let value = header.next().unwrap_or("").trim().to_owned();
+        match name.as_str() {
+            "content-length" => length = value.parse().unwrap_or(0),
+            "content-type" => content_type = value,
+            "origin" => origin = Some(value),
+            "authorization" => authorization = Some(value),
+            _ => (),
+        }
+    }
+
+    // browsers send the origin of the page, other clients usually none
+    let allowed = match origin {
+        Some(ref origin) => match access.allowed_origin(origin) {
+            Some(origin) => Some(origin),
+            None => return respond(&mut stream, None, "403 Forbidden", "text/plain", "origin not allowed"),
+        },
+        None => None,
+    };
+
+    if length > MAX_BODY {
+        return respond(&mut stream, allowed, "413 Payload Too Large", "text/plain", "request too large");
+    }
+
+    let events = path.strip_prefix("/games/").and_then(|rest| rest.strip_suffix("/events"));
+    match (method.as_str(), path.as_str(), events) {
+        ("POST", "/rpc", _) => {
+            if content_type.split(';').next().map_or(true, |mime| !mime.trim().eq_ignore_ascii_case("application/json")) {
+                return respond(&mut stream, allowed, "415 Unsupported Media Type", "text/plain", "expected application/json");
+            }
+            if let Some(ref token) = access.token {
+                if authorization.as_ref().and_then(|value| value.strip_prefix("Bearer ")) != Some(token.as_str()) {
+                    return respond(&mut stream, allowed, "401 Unauthorized", "text/plain", "missing or wrong token");
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            let reply = rpc_reply(games, &body);
+            respond(&mut stream, allowed, "200 OK", "application/json", &reply.to_string())
+        },
+        ("GET", _, Some(game_id)) => subscribe(games, &mut stream, allowed, game_id),
+        ("OPTIONS", _, _) => respond(&mut stream, allowed, "204 No Content", "text/plain", ""),
+        _ => respond(&mut stream, allowed, "404 Not Found", "text/plain", "not found"),
+    }
+}
@@ -0,0 +1,321 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::ffi::OsStr;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use shakmaty::{Position, EnPassantMode};
+use shakmaty::fen::Fen;
+use shakmaty::uci::Uci;
+
+use shapes::{DrawBrush, DrawShape};
+use analysis::centipawns;
+
+/// An evaluation from the point of view of the side to move.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Score {
+    /// Centipawns.
+    Cp(i32),
+    /// Mate in the given number of moves. Negative if the side to move is
+    /// getting mated.
+    Mate(i32),
+}
+
+/// Search limit.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Limit {
+    Depth(u32),
+    Nodes(u64),
+    MoveTime(Duration),
+    /// Search until `Engine::stop()` is called.
+    Infinite,
+}
+
+/// Search progress reported by the engine.
+#[derive(Debug, Clone, Default)]
+pub struct Info {
+    pub depth: Option<u32>,
+    pub seldepth: Option<u32>,
+    pub multipv: Option<u32>,
+    pub score: Option<Score>,
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
+    pub pv: Vec<Uci>,
+}
+
+/// The latest line of each rank while searching with MultiPV.
+#[derive(Debug, Clone, Default)]
+pub struct MultiPv {
+    lines: Vec<Info>,
+}
+
+impl MultiPv {
+    pub fn new() -> MultiPv {
+        MultiPv::default()
+    }
+
+    /// Replace the line of the rank reported by the info. Returns `false`
+    /// if the info has no line.
+    pub fn update(&mut self, info: Info) -> bool {
+        if info.pv.is_empty() || info.score.is_none() {
+            return false;
+        }
+
+        let rank = info.multipv.unwrap_or(1);
+        // lines of a shallower depth are outdated once the best line is
+        // deeper
+        if rank == 1 {
+            let depth = info.depth;
+            self.lines.retain(|line| line.depth >= depth);
+        }
+
+        let existing = self.lines.iter().position(|line| line.multipv.unwrap_or(1) == rank);
+        match existing {
+            Some(index) => self.lines[index] = info,
+            None => {
+                self.lines.push(info);
+                self.lines.sort_by_key(|line| line.multipv.unwrap_or(1));
+            },
+        }
+        true
+    }
+
+    /// Forget the lines, e.g. when searching a new position.
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    /// The lines, best first.
+    pub fn lines(&self) -> &[Info] {
+        &self.lines
+    }
+}
+
+/// Messages received from the engine.
+#[derive(Debug, Clone)]
+pub enum EngineEvent {
+    /// The engine identified itself.
+    Name(String),
+    /// The engine finished the `uci` handshake.
+    UciOk,
+    /// Reply to `Engine::is_ready()`.
+    ReadyOk,
+    /// Search progress.
+    Info(Info),
+    /// The search is finished. `best` is `None` if there are no legal moves.
+    BestMove { best: Option<Uci>, ponder: Option<Uci> },
+    /// The engine process closed its output.
+    Terminated,
+}
+
+/// A UCI engine running in a child process.
+///
+/// Output of the engine is parsed on a background thread and delivered
+/// through `Engine::events()`.
+#[derive(Debug)]
+pub struct Engine {
+    child: Child,
+    stdin: ChildStdin,
+    events: Receiver<EngineEvent>,
+}
+
+impl Engine {
+    /// Spawn an engine, for example `Engine::spawn("stockfish")`, and start
+    /// the `uci` handshake.
+    pub fn spawn<S: AsRef<OsStr>>(program: S) -> io::Result<Engine> {
+        let mut child = Command::new(program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take().expect("piped stdin");
+        let stdout = child.stdout.take().expect("piped stdout");
+
+        let (tx, events) = mpsc::channel();
+        thread::spawn(move || read_events(stdout, &tx));
+
+        let mut engine = Engine { child, stdin, events };
+        engine.send("uci")?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    /// Events sent by the engine.
+    pub fn events(&self) -> &Receiver<EngineEvent> {
+        &self.events
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.send(&format!("setoption name {} value {}", name, value))
+    }
+
+    /// Search for the given number of best lines, reported with
+    /// `Info::multipv`.
+    pub fn set_multipv(&mut self, lines: u32) -> io::Result<()> {
+        self.set_option("MultiPV", &lines.max(1).to_string())
+    }
+
+    /// Ask the engine to reply with `EngineEvent::ReadyOk`.
+    pub fn is_ready(&mut self) -> io::Result<()> {
+        self.send("isready")
+    }
+
+    pub fn new_game(&mut self) -> io::Result<()> {
+        self.send("ucinewgame")
+    }
+
+    /// Start searching the given position. Progress is reported with
+    /// `EngineEvent::Info` and the result with `EngineEvent::BestMove`.
+    pub fn go<P: Position + Clone>(&mut self, pos: &P, limit: Limit) -> io::Result<()> {
+        let fen = Fen::from_position(pos.clone(), EnPassantMode::Legal);
+        self.send(&format!("position fen {}", fen))?;
+
+        self.send(&match limit {
+            Limit::Depth(depth) => format!("go depth {}", depth),
+            Limit::Nodes(nodes) => format!("go nodes {}", nodes),
+            Limit::MoveTime(time) => format!("go movetime {}", time.as_millis()),
+            Limit::Infinite => "go infinite".to_owned(),
+        })
+    }
+
+    /// Stop the current search. The engine will still send its best move.
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.send("stop")
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn read_events(stdout: ChildStdout, tx: &Sender<EngineEvent>) {
+    for line in BufReader::new(stdout).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        if let Some(event) = parse_line(&line) {
+            if tx.send(event).is_err() {
+                return;
+            }
+        }
+    }
+
+    let _ = tx.send(EngineEvent::Terminated);
+}
+
+fn parse_line(line: &str) -> Option<EngineEvent> {
+    let mut tokens = line.split_whitespace();
+
+    match tokens.next()? {
+        "id" => match tokens.next()? {
+            "name" => Some(EngineEvent::Name(tokens.collect::<Vec<_>>().join(" "))),
+            _ => None,
+        },
+        "uciok" => Some(EngineEvent::UciOk),
+        "readyok" => Some(EngineEvent::ReadyOk),
+        "bestmove" => {
+            let best = tokens.next().and_then(|t| t.parse().ok()).filter(|m| *m != Uci::Null);
+            let ponder = match tokens.next() {
+                Some("ponder") => tokens.next().and_then(|t| t.parse().ok()),
+                _ => None,
+            };
+            Some(EngineEvent::BestMove { best, ponder })
+        }
+        "info" => parse_info(tokens).map(EngineEvent::Info),
+        _ => None,
+    }
+}
+
+fn parse_info<'a, I: Iterator<Item = &'a str>>(mut tokens: I) -> Option<Info> {
+    let mut info = Info::default();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "depth" => info.depth = tokens.next().and_then(|t| t.parse().ok()),
+            "seldepth" => info.seldepth = tokens.next().and_then(|t| t.parse().ok()),
+            "multipv" => info.multipv = tokens.next().and_then(|t| t.parse().ok()),
+            "nodes" => info.nodes = tokens.next().and_then(|t| t.parse().ok()),
+            "time" => info.time = tokens.next().and_then(|t| t.parse().ok()).map(Duration::from_millis),
+            "score" => {
+                info.score = match (tokens.next(), tokens.next().and_then(|t| t.parse().ok())) {
+                    (Some("cp"), Some(cp)) => Some(Score::Cp(cp)),
+                    (Some("mate"), Some(mate)) => Some(Score::Mate(mate)),
+                    _ => None,
+                };
+            }
+            "pv" => {
+                info.pv = tokens.by_ref().filter_map(|t| t.parse().ok()).collect();
+            }
+            "string" => break,
+            _ => (),
+        }
+    }
+
+    // ignore currmove and other reports without a line
+    if info.pv.is_empty() && info.score.is_none() {
+        None
+    } else {
+        Some(info)
+    }
+}
+
+/// Arrows for the first moves of a principal variation. Moves of the side to
+/// move are blue, replies are red.
+pub(crate) fn pv_shapes(pv: &[Uci]) -> Vec<DrawShape> {
+    pv.iter().take(4).enumerate().filter_map(|(ply, m)| match *m {
+        Uci::Normal { from, to, .. } => Some((from, to, ply)),
+        Uci::Put { to, .. } => Some((to, to, ply)),
+        Uci::Null => None,
+    }).map(|(orig, dest, ply)| {
+        DrawShape::new(orig, dest, if ply % 2 == 0 { DrawBrush::Blue } else { DrawBrush::Red })
+    }).collect()
+}
+
+/// Arrows for the first moves of the best lines when searching with
+/// MultiPV. The best move is drawn fully, worse moves thinner and fainter
+/// by how many centipawns they lose.
+pub(crate) fn multipv_shapes(lines: &[Info]) -> Vec<DrawShape> {
+    let best = match lines.first().and_then(|line| line.score) {
+        Some(score) => centipawns(score),
+        None => return Vec::new(),
+    };
+
+    lines.iter().filter_map(|line| {
+        let (orig, dest) = match line.pv.first() {
+            Some(&Uci::Normal { from, to, .. }) => (from, to),
+            Some(&Uci::Put { to, .. }) => (to, to),
+            _ => return None,
+        };
+        let loss = (best - centipawns(line.score?)).max(0);
+        let weight = 100 - (loss * 85 / 300).min(85);
+        Some(DrawShape::new(orig, dest, DrawBrush::Blue).with_weight(weight as u8))
+    }).collect()
+}
@@ -0,0 +1,266 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Extended Position Description, as used by engine test suites like
+//! _Win at Chess_. A record is a position followed by operations, e.g.
+//! `bm Qg6; id "WAC.003";`. Show a record on the board with
+//! `GroundMsg::SetPos(Pos::new(record.position()))`.
+
+use std::error::Error;
+use std::fmt;
+
+use shakmaty::{CastlingMode, Chess, EnPassantMode, FromSetup, Move, Position};
+use shakmaty::fen::{Epd, Fen};
+use shakmaty::san::SanPlus;
+use shakmaty::uci::Uci;
+
+/// Error when parsing EPD.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EpdError {
+    /// The first four fields do not describe a legal position.
+    InvalidPosition(String),
+    /// An operation is not terminated by `;`, or a string is not closed.
+    InvalidOperation(String),
+    /// A move of a `bm` or `am` operation is illegal in the position.
+    InvalidMove(String),
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EpdError::InvalidPosition(ref epd) => write!(f, "invalid epd position: {}", epd),
+            EpdError::InvalidOperation(ref op) => write!(f, "invalid epd operation: {}", op),
+            EpdError::InvalidMove(ref san) => write!(f, "invalid epd move: {}", san),
+        }
+    }
+}
+
+impl Error for EpdError {}
+
+/// A position with its operations.
+#[derive(Debug, Clone)]
+pub struct EpdRecord {
+    position: Chess,
+    /// Opcodes with their operands, in order. String operands are kept
+    /// without quotes.
+    operations: Vec<(String, Vec<String>)>,
+}
+
+impl EpdRecord {
+    pub fn new(position: Chess) -> EpdRecord {
+        EpdRecord {
+            position,
+            operations: Vec::new(),
+        }
+    }
+
+    /// Parse a line of EPD.
+    pub fn from_line(line: &str) -> Result<EpdRecord, EpdError> {
+        let line = line.trim();
+        let fields: Vec<&str> = line.splitn(5, ' ').collect();
+        if fields.len() < 4 {
+            return Err(EpdError::InvalidPosition(line.to_owned()));
+        }
+
+        let epd = fields[..4].join(" ");
+        let position = epd.parse::<Fen>()
+            .ok()
+            .and_then(|fen| Chess::from_setup(fen.into_setup(), CastlingMode::Standard).ok())
+            .ok_or_else(|| EpdError::InvalidPosition(epd.clone()))?;
+
+        let mut record = EpdRecord::new(position);
+        record.operations = parse_operations(fields.get(4).cloned().unwrap_or(""))?;
+
+        // check that the moves can be read
+        record.moves("bm")?;
+        record.moves("am")?;
+        Ok(record)
+    }
+
+    pub fn position(&self) -> &Chess {
+        &self.position
+    }
+
+    pub fn operations(&self) -> &[(String, Vec<String>)] {
+        &self.operations
+    }
+
+    /// The operands of an opcode, if the record has it.
+    pub fn operation(&self, opcode: &str) -> Option<&[String]> {
+        self.operations.iter().find(|&&(ref op, _)| op == opcode).map(|&(_, ref operands)| &operands[..])
+    }
+
+    /// Add an operation, replacing one with the same opcode.
+    pub fn set_operation(&mut self, opcode: &str, operands: Vec<String>) {
+        match self.operations.iter_mut().find(|&&mut (ref op, _)| op == opcode) {
+            Some(&mut (_, ref mut existing)) => *existing = operands,
+            None => self.operations.push((opcode.to_owned(), operands)),
+        }
+    }
+
+    /// The `id` of the record, like `WAC.001`.
+    pub fn id(&self) -> Option<&str> {
+        self.operation("id").and_then(|operands| operands.first()).map(String::as_str)
+    }
+
+    /// The best moves, from the `bm` operation.
+    pub fn best_moves(&self) -> Vec<Move> {
+        self.moves("bm").unwrap_or_default()
+    }
+
+    /// Set the `bm` operation.
+    pub fn set_best_moves(&mut self, moves: &[Move]) {
+        let operands = self.san(moves);
+        self.set_operation("bm", operands);
+    }
+
+    /// The moves to avoid, from the `am` operation.
+    pub fn avoid_moves(&self) -> Vec<Move> {
+        self.moves("am").unwrap_or_default()
+    }
+
+    /// Set the `am` operation.
+    pub fn set_avoid_moves(&mut self, moves: &[Move]) {
+        let operands = self.san(moves);
+        self.set_operation("am", operands);
+    }
+
+    fn san(&self, moves: &[Move]) -> Vec<String> {
+        moves.iter().map(|m| SanPlus::from_move(self.position.clone(), m).to_string()).collect()
+    }
+
+    /// The evaluation in centipawns from the point of view of the side to
+    /// move, from the `ce` operation.
+    pub fn centipawn_eval(&self) -> Option<i32> {
+        self.operation("ce").and_then(|operands| operands.first()).and_then(|ce| ce.parse().ok())
+    }
+
+    fn moves(&self, opcode: &str) -> Result<Vec<Move>, EpdError> {
+        self.operation(opcode).unwrap_or(&[]).iter().map(|san| {
+            san.parse::<SanPlus>()
+                .ok()
+                .and_then(|parsed| parsed.san.to_move(&self.position).ok())
+                .ok_or_else(|| EpdError::InvalidMove(san.clone()))
+        }).collect()
+    }
+
+    /// Whether an answer of an engine passes the test: it must be one of
+    /// the best moves and none of the moves to avoid. `None` if the
+    /// record has neither.
+    pub fn check_answer(&self, answer: &Uci) -> Option<bool> {
+        let (best, avoid) = (self.best_moves(), self.avoid_moves());
+        if best.is_empty() && avoid.is_empty() {
+            return None;
+        }
+
+        let m = match answer.to_move(&self.position) {
+            Ok(m) => m,
+            Err(_) => return Some(false),
+        };
+
+        Some((best.is_empty() || best.contains(&m)) && !avoid.contains(&m))
+    }
+}
+
+impl fmt::Display for EpdRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Epd::from_position(self.position.clone(), EnPassantMode::Legal))?;
+
+        for &(ref opcode, ref operands) in &self.operations {
+            write!(f, " {}", opcode)?;
+            for operand in operands {
+                if opcode == "id" || operand.is_empty() || operand.contains(char::is_whitespace) || operand.contains(';') {
+                    write!(f, " \"{}\"", operand)?;
+                } else {
+                    write!(f, " {}", operand)?;
+                }
+            }
+            f.write_str(";")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse `opcode operand...;` operations.
+fn parse_operations(text: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+    let mut operations = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    loop {
+        while chars.peek().map_or(false, |c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            return Ok(operations);
+        }
+
+        let mut tokens = Vec::new();
+        let mut token = String::new();
+        let mut terminated = false;
+
+        while let Some(c) = chars.next() {
+            match c {
+                ';' => {
+                    terminated = true;
+                    break;
+                },
+                '"' => {
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '"' {
+                            closed = true;
+                            break;
+                        }
+                        token.push(c);
+                    }
+                    if !closed {
+                        return Err(EpdError::InvalidOperation(text.to_owned()));
+                    }
+                    tokens.push(token.split_off(0));
+                },
+                c if c.is_whitespace() => {
+                    if !token.is_empty() {
+                        tokens.push(token.split_off(0));
+                    }
+                },
+                c => token.push(c),
+            }
+        }
+
+        if !token.is_empty() {
+            tokens.push(token);
+        }
+
+        if !terminated || tokens.is_empty() {
+            return Err(EpdError::InvalidOperation(text.to_owned()));
+        }
+
+        let opcode = tokens.remove(0);
+        operations.push((opcode, tokens));
+    }
+}
+
+/// Parse all records of an EPD file, one per line, skipping empty lines
+/// and comments starting with `#`.
+pub fn read_epd(text: &str) -> Result<Vec<EpdRecord>, EpdError> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(EpdRecord::from_line)
+        .collect()
+}
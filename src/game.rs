@@ -0,0 +1,330 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use shakmaty::{Move, Chess, Color, Position};
+use shakmaty::san::SanPlus;
+
+use gametree::{GameTree, NodeId};
+
+/// A move of a game, together with its annotations.
+#[derive(Debug, Clone)]
+pub struct GameMove {
+    m: Move,
+    san: String,
+    comments: Vec<String>,
+    nags: Vec<u8>,
+    clock: Option<Duration>,
+    elapsed: Option<Duration>,
+}
+
+impl GameMove {
+    /// Annotate a move played in a position.
+    pub(crate) fn new(pos: &Chess, m: Move) -> Result<GameMove, IllegalMoveError> {
+        if !pos.is_legal(&m) {
+            return Err(IllegalMoveError { m });
+        }
+
+        Ok(GameMove {
+            san: SanPlus::from_move(pos.clone(), &m).to_string(),
+            m,
+            comments: Vec::new(),
+            nags: Vec::new(),
+            clock: None,
+            elapsed: None,
+        })
+    }
+
+    /// The move.
+    pub fn mv(&self) -> &Move {
+        &self.m
+    }
+
+    /// The move in Standard Algebraic Notation, including check and
+    /// checkmate suffixes.
+    pub fn san(&self) -> &str {
+        &self.san
+    }
+
+    /// Comments following the move.
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    pub fn push_comment(&mut self, comment: String) {
+        self.comments.push(comment);
+    }
+
+    /// Replace all comments, e.g. after editing them.
+    pub fn set_comments(&mut self, comments: Vec<String>) {
+        self.comments = comments;
+    }
+
+    /// Numeric Annotation Glyphs, e.g. `1` for `!` or `2` for `?`.
+    pub fn nags(&self) -> &[u8] {
+        &self.nags
+    }
+
+    pub fn push_nag(&mut self, nag: u8) {
+        if !self.nags.contains(&nag) {
+            self.nags.push(nag);
+        }
+    }
+
+    pub fn set_nags(&mut self, nags: Vec<u8>) {
+        self.nags = nags;
+    }
+
+    pub fn remove_nag(&mut self, nag: u8) -> bool {
+        let len = self.nags.len();
+        self.nags.retain(|&n| n != nag);
+        self.nags.len() != len
+    }
+
+    /// Add a NAG, replacing one of the same group, like `?` for `!`, or
+    /// remove it if already given.
+    pub fn toggle_nag(&mut self, nag: u8) {
+        if !self.remove_nag(nag) {
+            if let Some(group) = nag_group(nag) {
+                self.nags.retain(|&n| nag_group(n) != Some(group));
+            }
+            self.nags.push(nag);
+        }
+    }
+
+    /// Remaining time on the clock of the moving side after the move.
+    pub fn clock(&self) -> Option<Duration> {
+        self.clock
+    }
+
+    pub fn set_clock(&mut self, clock: Option<Duration>) {
+        self.clock = clock;
+    }
+
+    /// Time the moving side spent thinking on the move.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.elapsed
+    }
+
+    pub fn set_elapsed(&mut self, elapsed: Option<Duration>) {
+        self.elapsed = elapsed;
+    }
+}
+
+/// Groups of NAGs that exclude each other: the assessment of the move
+/// and of the position.
+fn nag_group(nag: u8) -> Option<u8> {
+    match nag {
+        1..=6 => Some(0),
+        10..=19 => Some(1),
+        _ => None,
+    }
+}
+
+/// Common NAGs with their symbols, for annotation buttons.
+pub const NAG_SYMBOLS: [(u8, &str); 14] = [
+    (1, "!"), (2, "?"), (3, "!!"), (4, "??"), (5, "!?"), (6, "?!"),
+    (10, "="), (13, "\u{221e}"), (14, "\u{2a72}"), (15, "\u{2a71}"),
+    (16, "\u{b1}"), (17, "\u{2213}"), (18, "+\u{2212}"), (19, "\u{2212}+"),
+];
+
+/// The symbol of a NAG, like `!?` for 5, if it has one.
+pub fn nag_symbol(nag: u8) -> Option<&'static str> {
+    NAG_SYMBOLS.iter().find(|&&(n, _)| n == nag).map(|&(_, symbol)| symbol)
+}
+
+/// Error when trying to add an illegal move to a game.
+#[derive(Debug, Clone)]
+pub struct IllegalMoveError {
+    m: Move,
+}
+
+impl IllegalMoveError {
+    pub(crate) fn new(m: Move) -> IllegalMoveError {
+        IllegalMoveError { m }
+    }
+
+    /// The rejected move.
+    pub fn mv(&self) -> &Move {
+        &self.m
+    }
+}
+
+impl fmt::Display for IllegalMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "illegal move: {}", self.m)
+    }
+}
+
+impl Error for IllegalMoveError {}
+
+/// A game: headers, a start position and the moves played from there,
+/// including variations.
+#[derive(Debug, Clone)]
+pub struct Game {
+    headers: Vec<(String, String)>,
+    comment: Option<String>,
+    tree: GameTree,
+}
+
+impl Game {
+    /// Create a game from the standard starting position.
+    pub fn new() -> Game {
+        Game::from_position(Chess::default())
+    }
+
+    /// Create a game from a custom starting position.
+    pub fn from_position(initial: Chess) -> Game {
+        Game {
+            headers: Vec::new(),
+            comment: None,
+            tree: GameTree::new(initial),
+        }
+    }
+
+    /// Get the value of a header, e.g. `game.header("White")`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|h| h.0 == name).map(|h| h.1.as_str())
+    }
+
+    /// Set a header, replacing an existing header of the same name.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        if let Some(header) = self.headers.iter_mut().find(|h| h.0 == name) {
+            header.1 = value.to_owned();
+            return;
+        }
+
+        self.headers.push((name.to_owned(), value.to_owned()));
+    }
+
+    /// All headers in order.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The starting position.
+    pub fn initial_position(&self) -> &Chess {
+        self.tree.initial_position()
+    }
+
+    /// Comment before the first move.
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_ref().map(String::as_str)
+    }
+
+    pub fn set_comment(&mut self, comment: Option<String>) {
+        self.comment = comment;
+    }
+
+    /// All moves, including variations.
+    pub fn tree(&self) -> &GameTree {
+        &self.tree
+    }
+
+    pub fn tree_mut(&mut self) -> &mut GameTree {
+        &mut self.tree
+    }
+
+    /// The moves of the main line.
+    pub fn moves(&self) -> Vec<&GameMove> {
+        self.tree.mainline().into_iter().filter_map(|node| self.tree.game_move(node)).collect()
+    }
+
+    /// Number of plies of the main line.
+    pub fn len(&self) -> usize {
+        self.tree.mainline().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.next(self.tree.root()).is_none()
+    }
+
+    /// The main line node after the given number of plies. A `ply` beyond
+    /// the end of the game gives the last node.
+    pub fn node_at(&self, ply: usize) -> NodeId {
+        match ply.checked_sub(1) {
+            Some(index) => {
+                let mainline = self.tree.mainline();
+                mainline.get(index).or_else(|| mainline.last()).cloned().unwrap_or_else(|| self.tree.root())
+            },
+            None => self.tree.root(),
+        }
+    }
+
+    /// The position after the given number of plies of the main line. A
+    /// `ply` beyond the end of the game gives the final position.
+    pub fn position_at(&self, ply: usize) -> Chess {
+        self.tree.position_at(self.node_at(ply))
+    }
+
+    /// The final position.
+    pub fn position(&self) -> Chess {
+        self.tree.position_at(self.tree.mainline_end())
+    }
+
+    /// The remaining times of white and black at a node, from the clock
+    /// of the last move of each side leading to it.
+    pub fn clocks_at(&self, node: NodeId) -> (Option<Duration>, Option<Duration>) {
+        let mut turn = self.tree.initial_position().turn();
+        let mut clocks = (None, None);
+        for id in self.tree.path(node) {
+            if let Some(clock) = self.tree.game_move(id).and_then(GameMove::clock) {
+                match turn {
+                    Color::White => clocks.0 = Some(clock),
+                    Color::Black => clocks.1 = Some(clock),
+                }
+            }
+            turn = !turn;
+        }
+        clocks
+    }
+
+    /// Replace the NAGs and comments of the move at a node, e.g. from
+    /// `AnnotationMsg::Annotated`. Returns whether the node has a move.
+    pub fn annotate(&mut self, node: NodeId, nags: Vec<u8>, comments: Vec<String>) -> bool {
+        match self.tree.game_move_mut(node) {
+            Some(m) => {
+                m.set_nags(nags);
+                m.set_comments(comments);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Play a move at the end of the main line.
+    pub fn push(&mut self, m: Move) -> Result<(), IllegalMoveError> {
+        let end = self.tree.mainline_end();
+        self.tree.push(end, m).map(|_| ())
+    }
+
+    /// Remove all moves of the main line after the given ply, together with
+    /// their variations.
+    pub fn truncate(&mut self, ply: usize) {
+        if let Some(&node) = self.tree.mainline().get(ply) {
+            self.tree.remove(node);
+        }
+    }
+}
+
+impl Default for Game {
+    fn default() -> Game {
+        Game::new()
+    }
+}
@@ -0,0 +1,259 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+use shakmaty::{Move, Chess, Position};
+
+use game::{GameMove, IllegalMoveError};
+
+/// Refers to a node of a `GameTree`. The root node stands for the
+/// starting position, every other node for the move leading to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone)]
+struct Node {
+    parent: Option<NodeId>,
+    m: Option<GameMove>,
+    /// The position after the move, so that it is not replayed from the
+    /// root on every lookup.
+    position: Chess,
+    children: Vec<NodeId>,
+}
+
+/// Moves of a game with variations. The first child of each node
+/// continues the main line, the others are variations.
+#[derive(Debug, Clone)]
+pub struct GameTree {
+    nodes: Vec<Option<Node>>,
+    /// The last node of the main line.
+    mainline_end: NodeId,
+}
+
+impl GameTree {
+    /// Create a tree with only the root node.
+    pub fn new(initial: Chess) -> GameTree {
+        GameTree {
+            nodes: vec![Some(Node {
+                parent: None,
+                m: None,
+                position: initial,
+                children: Vec::new(),
+            })],
+            mainline_end: NodeId(0),
+        }
+    }
+
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// The position at the root.
+    pub fn initial_position(&self) -> &Chess {
+        &self.node(self.root()).expect("root is never removed").position
+    }
+
+    fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id.0).and_then(Option::as_ref)
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes.get_mut(id.0).and_then(Option::as_mut)
+    }
+
+    /// Check if a node exists, i.e. it was not removed.
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.node(id).is_some()
+    }
+
+    /// The move leading to a node, or `None` for the root.
+    pub fn game_move(&self, id: NodeId) -> Option<&GameMove> {
+        self.node(id).and_then(|n| n.m.as_ref())
+    }
+
+    pub fn game_move_mut(&mut self, id: NodeId) -> Option<&mut GameMove> {
+        self.node_mut(id).and_then(|n| n.m.as_mut())
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).and_then(|n| n.parent)
+    }
+
+    /// Continuations of a node, starting with the main continuation.
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        self.node(id).map_or(&[], |n| &n.children)
+    }
+
+    /// The main continuation of a node.
+    pub fn next(&self, id: NodeId) -> Option<NodeId> {
+        self.children(id).first().cloned()
+    }
+
+    /// Alternatives to the move leading to a node.
+    pub fn variations(&self, id: NodeId) -> Vec<NodeId> {
+        match self.parent(id) {
+            Some(parent) => self.children(parent).iter().cloned().filter(|&c| c != id).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Nodes from the first move to the given node.
+    pub fn path(&self, id: NodeId) -> Vec<NodeId> {
+        let mut path = Vec::new();
+        let mut node = id;
+        while let Some(parent) = self.parent(node) {
+            path.push(node);
+            node = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Number of moves from the root to the given node.
+    pub fn ply(&self, id: NodeId) -> usize {
+        self.path(id).len()
+    }
+
+    /// Nodes following the given node along main continuations.
+    pub fn line(&self, id: NodeId) -> Vec<NodeId> {
+        let mut line = Vec::new();
+        let mut node = id;
+        while let Some(next) = self.next(node) {
+            line.push(next);
+            node = next;
+        }
+        line
+    }
+
+    /// The last node of the line through the given node.
+    pub fn end(&self, id: NodeId) -> NodeId {
+        self.line(id).last().cloned().unwrap_or(id)
+    }
+
+    /// The last node of the main line, the same as `end(root())`.
+    pub fn mainline_end(&self) -> NodeId {
+        self.mainline_end
+    }
+
+    /// Nodes of the main line.
+    pub fn mainline(&self) -> Vec<NodeId> {
+        self.line(self.root())
+    }
+
+    /// Check if a node is part of the main line.
+    pub fn is_mainline(&self, id: NodeId) -> bool {
+        let mut node = id;
+        while let Some(parent) = self.parent(node) {
+            if self.next(parent) != Some(node) {
+                return false;
+            }
+            node = parent;
+        }
+        self.contains(id)
+    }
+
+    /// The position at a node.
+    pub fn position_at(&self, id: NodeId) -> Chess {
+        match self.node(id) {
+            Some(node) => node.position.clone(),
+            None => self.initial_position().clone(),
+        }
+    }
+
+    /// Add a move after a node, as the main continuation if it is the
+    /// first, or as a variation. If the move already exists, its node is
+    /// returned.
+    pub fn push(&mut self, parent: NodeId, m: Move) -> Result<NodeId, IllegalMoveError> {
+        if let Some(&existing) = self.children(parent).iter().find(|&&c| self.game_move(c).map(GameMove::mv) == Some(&m)) {
+            return Ok(existing);
+        }
+
+        let (game_move, position) = match self.node(parent) {
+            Some(node) => {
+                let game_move = GameMove::new(&node.position, m)?;
+                let mut position = node.position.clone();
+                position.play_unchecked(game_move.mv());
+                (game_move, position)
+            },
+            None => return Err(IllegalMoveError::new(m)),
+        };
+
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Some(Node {
+            parent: Some(parent),
+            m: Some(game_move),
+            position,
+            children: Vec::new(),
+        }));
+        if let Some(parent) = self.node_mut(parent) {
+            parent.children.push(id);
+        }
+        if parent == self.mainline_end {
+            self.mainline_end = id;
+        }
+        Ok(id)
+    }
+
+    /// Move a variation one place up among its siblings. Promoting the
+    /// first variation makes it the main continuation.
+    pub fn promote(&mut self, id: NodeId) {
+        if let Some(parent) = self.parent(id).and_then(|p| self.node_mut(p)) {
+            if let Some(index) = parent.children.iter().position(|&c| c == id) {
+                if index > 0 {
+                    parent.children.swap(index - 1, index);
+                }
+            }
+        }
+        self.mainline_end = self.end(self.root());
+    }
+
+    /// Make the line through a node the main line.
+    pub fn promote_to_mainline(&mut self, id: NodeId) {
+        let mut node = id;
+        while let Some(parent) = self.parent(node) {
+            if let Some(parent) = self.node_mut(parent) {
+                if let Some(index) = parent.children.iter().position(|&c| c == node) {
+                    let child = parent.children.remove(index);
+                    parent.children.insert(0, child);
+                }
+            }
+            node = parent;
+        }
+        self.mainline_end = self.end(self.root());
+    }
+
+    /// Delete a node and all moves after it. The root can not be removed.
+    pub fn remove(&mut self, id: NodeId) -> bool {
+        let parent = match self.parent(id) {
+            Some(parent) => parent,
+            None => return false,
+        };
+
+        if let Some(parent) = self.node_mut(parent) {
+            parent.children.retain(|&c| c != id);
+        }
+
+        let mut stack = vec![id];
+        while let Some(node) = stack.pop() {
+            if let Some(node) = self.nodes.get_mut(node.0).and_then(Option::take) {
+                stack.extend(node.children);
+            }
+        }
+        self.mainline_end = self.end(self.root());
+
+        true
+    }
+}
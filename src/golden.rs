@@ -0,0 +1,247 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Golden image tests of the drawing code.
+//!
+//! A `Scene` holds a `BoardState` with its `Pieces` and `Promotable`,
+//! driven by a `ManualTime`, so that animations can be frozen at exact
+//! moments. Frames are compared with the PNGs in `tests/golden`. Only
+//! `CHESSGROUND_BLESS=1` writes images: it records all frames as the new
+//! goldens, after an intended change or for a new test. Review and commit
+//! them with the change.
+//!
+//! Frames without a golden yet are skipped with a note, so that a checkout
+//! without the images still passes. Set `CHESSGROUND_REQUIRE_GOLDEN=1`,
+//! e.g. in CI once the goldens are committed, to fail on them instead.
+
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cairo::{Context, Format, ImageSurface};
+
+use shakmaty::{Color, Move, Role, Square};
+
+use boardstate::{BoardState, Coordinates, HighlightKind};
+use pieces::{Blindfold, Pieces};
+use promotable::Promotable;
+use render::board_matrix;
+use timing::ManualTime;
+
+/// Width and height of the frames in pixels.
+const SIZE: i32 = 288;
+
+/// Largest difference of a color channel that still counts as equal,
+/// to allow for rounding in other versions of cairo and librsvg.
+const CHANNEL_TOLERANCE: u8 = 8;
+
+/// Share of pixels that may differ by more than the tolerance.
+const PIXEL_TOLERANCE: f64 = 0.002;
+
+struct Scene {
+    state: BoardState,
+    pieces: Pieces,
+    promotable: Promotable,
+    time: ManualTime,
+}
+
+impl Scene {
+    fn new(fen: &str) -> Scene {
+        let mut state = BoardState::new();
+        state.set_fen(fen).expect("valid fen");
+
+        let time = ManualTime::new();
+        let pieces = Pieces::new_from_board(state.board()).with_time_source(Arc::new(time.clone()));
+        let promotable = Promotable::new().with_time_source(Arc::new(time.clone()));
+
+        Scene { state, pieces, promotable, time }
+    }
+
+    /// Play a move and let the pieces start gliding with the next step.
+    fn play(&mut self, m: &Move) {
+        self.state.play(m).expect("legal move");
+        self.pieces.set_board(self.state.board());
+        self.step();
+    }
+
+    /// Let time pass and advance the animations, like the frame clock.
+    fn advance(&mut self, millis: u64) {
+        self.time.advance(Duration::from_millis(millis));
+        self.step();
+    }
+
+    fn step(&mut self) {
+        self.pieces.step(&mut |_, _, _, _| ());
+        self.promotable.step(&mut |_, _, _, _| ());
+    }
+
+    /// Draw the layers in the same order as the widget.
+    fn render(&self) -> ImageSurface {
+        let surface = ImageSurface::create(Format::ARgb32, SIZE, SIZE).expect("surface");
+
+        {
+            let cr = Context::new(&surface).expect("context");
+            cr.transform(board_matrix(&self.state, f64::from(SIZE)));
+            let clip = (-0.5, -0.5, 8.5, 8.5);
+
+            self.state.draw(&cr).expect("draw board");
+            self.pieces.draw(&cr, &self.state, &self.promotable, Blindfold::Off, clip).expect("draw pieces");
+            self.pieces.draw_drag(&cr, &self.state, Blindfold::Off).expect("draw drag");
+            self.promotable.draw(&cr, &self.state).expect("draw promotion");
+        }
+
+        surface
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.png", name))
+}
+
+fn write_png(surface: &ImageSurface, path: &PathBuf) {
+    fs::create_dir_all(path.parent().expect("golden directory")).expect("create golden directory");
+    let mut file = File::create(path).expect("create png");
+    surface.write_to_png(&mut file).expect("write png");
+}
+
+/// Pixels of a surface as rows of native endian ARGB bytes, without the
+/// padding at the end of each row.
+fn pixels(surface: &mut ImageSurface) -> Vec<u8> {
+    surface.flush();
+    let (width, height, stride) = (surface.width() as usize, surface.height() as usize, surface.stride() as usize);
+    let data = surface.data().expect("surface data");
+    data.chunks(stride).take(height).flat_map(|row| row[..width * 4].iter().cloned()).collect()
+}
+
+fn assert_golden(name: &str, scene: &Scene) {
+    let mut actual = scene.render();
+    let path = golden_path(name);
+
+    if env::var_os("CHESSGROUND_BLESS").is_some() {
+        write_png(&actual, &path);
+        return;
+    }
+
+    let mut file = match File::open(&path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound && env::var_os("CHESSGROUND_REQUIRE_GOLDEN").is_none() => {
+            eprintln!("{}: skipped, no golden at {}, bless it with CHESSGROUND_BLESS=1", name, path.display());
+            return;
+        },
+        Err(err) => panic!("{}: no golden at {} ({}), bless it with CHESSGROUND_BLESS=1", name, path.display(), err),
+    };
+    let mut expected = ImageSurface::create_from_png(&mut file).expect("read golden");
+    assert_eq!((expected.width(), expected.height()), (SIZE, SIZE), "{}: golden has another size", name);
+
+    let differing = pixels(&mut actual).chunks(4)
+        .zip(pixels(&mut expected).chunks(4))
+        .filter(|&(a, e)| a.iter().zip(e).any(|(&a, &e)| (a as i16 - e as i16).abs() > i16::from(CHANNEL_TOLERANCE)))
+        .count();
+
+    let allowed = (PIXEL_TOLERANCE * f64::from(SIZE * SIZE)) as usize;
+    assert!(differing <= allowed,
+            "{}: {} pixels differ from {}, bless the frame with CHESSGROUND_BLESS=1 if the change is intended",
+            name, differing, path.display());
+}
+
+fn e2e4() -> Move {
+    Move::Normal { role: Role::Pawn, from: Square::E2, capture: None, to: Square::E4, promotion: None }
+}
+
+#[test]
+fn starting_position() {
+    assert_golden("starting_position", &Scene::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"));
+}
+
+#[test]
+fn highlights() {
+    // the queen gives check, with the last move and marked squares
+    let mut scene = Scene::new("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+    scene.state.set_last_move(Some((Square::D8, Square::H4)));
+    scene.state.set_highlights(vec![
+        (Square::E1, HighlightKind::Threat),
+        (Square::G2, HighlightKind::Hint),
+        (Square::F2, HighlightKind::LastMove),
+    ]);
+    assert_golden("highlights", &scene);
+}
+
+#[test]
+fn threats() {
+    let mut scene = Scene::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+    scene.state.set_threats(true);
+    assert_golden("threats", &scene);
+}
+
+#[test]
+fn coordinates_outside() {
+    let mut scene = Scene::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    scene.state.set_show_coordinates(true);
+    scene.state.set_coordinates(Coordinates::Outside);
+    assert_golden("coordinates_outside", &scene);
+}
+
+#[test]
+fn coordinates_inside_flipped() {
+    let mut scene = Scene::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    scene.state.set_show_coordinates(true);
+    scene.state.set_coordinates(Coordinates::Inside);
+    scene.state.set_orientation(Color::Black);
+    assert_golden("coordinates_inside_flipped", &scene);
+}
+
+#[test]
+fn move_animation() {
+    let mut scene = Scene::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    scene.play(&e2e4());
+    assert_golden("move_animation_start", &scene);
+
+    scene.advance(150);
+    assert_golden("move_animation_half", &scene);
+
+    scene.advance(150);
+    assert_golden("move_animation_end", &scene);
+}
+
+#[test]
+fn promotion_overlay() {
+    let mut scene = Scene::new("8/4P3/8/8/8/2k5/8/4K3 w - - 0 1");
+    scene.promotable.start(&scene.state, Color::White, Square::E7, Square::E8);
+    scene.step();
+    assert_golden("promotion_overlay_start", &scene);
+
+    // move the pointer from the queen to the second choice
+    scene.advance(1000);
+    scene.promotable.hover(Some(Square::E7), &mut |_| ());
+    scene.advance(1000);
+    assert_golden("promotion_overlay_hovered", &scene);
+}
+
+#[test]
+fn promotion_overlay_flipped() {
+    let mut scene = Scene::new("4k3/8/8/2K5/8/8/4p3/8 b - - 0 1");
+    scene.state.set_orientation(Color::Black);
+    scene.promotable.start(&scene.state, Color::Black, Square::E2, Square::E1);
+    scene.advance(1000);
+    assert_golden("promotion_overlay_flipped", &scene);
+}
@@ -0,0 +1,672 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Board rendered with OpenGL.
+//!
+//! The pieces are rasterized once into a texture atlas on the GPU, and
+//! each frame the board, highlights, pieces and arrows are drawn as
+//! textured quads in a single draw call. Animating a move then costs
+//! almost no CPU time, which adds up when many boards are shown at once.
+//! The board only displays positions: moves come from the application.
+//!
+//! OpenGL is reached through libepoxy, which GTK already loaded into the
+//! process.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::os::raw::c_void;
+use std::ptr;
+use std::rc::Rc;
+use std::time::Duration;
+
+use gtk::prelude::*;
+use gtk::gdk::GLError;
+use gtk::glib::{self, Continue};
+use gtk::GLArea;
+use cairo::{Context, Format, ImageSurface};
+
+use glium::{Blend, BlendingFunction, DrawParameters, LinearBlendingFactor, Program, Surface, VertexBuffer};
+use glium::backend::{Backend, Context as GlContext, Facade};
+use glium::debug::DebugCallbackBehavior;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::texture::{MipmapsOption, RawImage2d, Texture2d};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
+use glium::SwapBuffersError;
+
+use libloading::os::unix::Library;
+
+use shakmaty::{Board, Color, Move, Piece, Role, Square};
+use shakmaty::fen::ParseFenError;
+
+use boardstate::BoardState;
+use game::IllegalMoveError;
+use pieceset::PieceSet;
+use renderer::Renderer;
+use shapes::Arrow;
+use theme::{Rgba, Theme};
+use util::{ease, square_to_pos};
+
+/// Size of a cell of the texture atlas in pixels. Mipmaps take care of
+/// smaller boards.
+const CELL: i32 = 128;
+
+/// Cells of the atlas: the twelve pieces, then a white one for solid
+/// colors, the glow of a king in check and the ring of a circled square.
+const CELLS: i32 = 15;
+const WHITE_CELL: i32 = 12;
+const GLOW_CELL: i32 = 13;
+const RING_CELL: i32 = 14;
+
+const VERTEX_SHADER: &str = "
+    #version 140
+
+    uniform mat4 matrix;
+
+    in vec2 position;
+    in vec2 tex_coords;
+    in vec4 color;
+
+    out vec2 v_tex_coords;
+    out vec4 v_color;
+
+    void main() {
+        v_tex_coords = tex_coords;
+        v_color = color;
+        gl_Position = matrix * vec4(position, 0.0, 1.0);
+    }
+";
+
+const FRAGMENT_SHADER: &str = "
+    #version 140
+
+    uniform sampler2D atlas;
+
+    in vec2 v_tex_coords;
+    in vec4 v_color;
+
+    out vec4 f_color;
+
+    void main() {
+        // the atlas has premultiplied alpha, like cairo
+        f_color = vec4(v_color.rgb * v_color.a, v_color.a) * texture(atlas, v_tex_coords);
+    }
+";
+
+#[derive(Copy, Clone)]
+struct Vertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+implement_vertex!(Vertex, position, tex_coords, color);
+
+/// Triangles of a frame, in board coordinates.
+struct Mesh {
+    vertices: Vec<Vertex>,
+}
+
+impl Mesh {
+    fn vertex(&mut self, (x, y): (f64, f64), (u, v): (f64, f64), color: Rgba) {
+        self.vertices.push(Vertex {
+            position: [x as f32, y as f32],
+            tex_coords: [u as f32, v as f32],
+            color: [color.red as f32, color.green as f32, color.blue as f32, color.alpha as f32],
+        });
+    }
+
+    fn triangle(&mut self, a: (f64, f64), b: (f64, f64), c: (f64, f64), color: Rgba) {
+        let white = cell_center(WHITE_CELL);
+        self.vertex(a, white, color);
+        self.vertex(b, white, color);
+        self.vertex(c, white, color);
+    }
+
+    /// Quad with the corners given clockwise from the top left, textured
+    /// with an atlas cell. `flip` turns the texture upside down.
+    fn quad(&mut self, corners: [(f64, f64); 4], cell: i32, flip: bool, color: Rgba) {
+        let (u0, u1) = (f64::from(cell) / f64::from(CELLS), f64::from(cell + 1) / f64::from(CELLS));
+        let mut uv = [(u0, 0.0), (u1, 0.0), (u1, 1.0), (u0, 1.0)];
+        if flip {
+            uv.rotate_left(2);
+        }
+
+        for &i in &[0, 1, 2, 0, 2, 3] {
+            self.vertex(corners[i], uv[i], color);
+        }
+    }
+
+    fn rect(&mut self, (x, y): (f64, f64), size: f64, cell: i32, flip: bool, color: Rgba) {
+        let h = size / 2.0;
+        self.quad([(x - h, y - h), (x + h, y - h), (x + h, y + h), (x - h, y + h)], cell, flip, color);
+    }
+
+    fn square(&mut self, square: Square, color: Rgba) {
+        self.rect(square_to_pos(square), 1.0, WHITE_CELL, false, color);
+    }
+
+    fn arrow(&mut self, arrow: &Arrow) {
+        let (orig, dest) = (square_to_pos(arrow.from), square_to_pos(arrow.to));
+        if arrow.from == arrow.to {
+            self.rect(dest, 1.0, RING_CELL, false, arrow.color);
+            return;
+        }
+
+        // same proportions as the arrows drawn with cairo
+        let marker = 3.75 * arrow.width;
+        let margin = 0.1;
+        let (dx, dy) = (dest.0 - orig.0, dest.1 - orig.1);
+        let hypot = dx.hypot(dy);
+        let (ux, uy) = (dx / hypot, dy / hypot);
+        let shaft = (dest.0 - ux * (marker + margin), dest.1 - uy * (marker + margin));
+        let head = (dest.0 - ux * margin, dest.1 - uy * margin);
+
+        let (nx, ny) = (-uy * arrow.width / 2.0, ux * arrow.width / 2.0);
+        let corners = [(orig.0 + nx, orig.1 + ny), (shaft.0 + nx, shaft.1 + ny), (shaft.0 - nx, shaft.1 - ny), (orig.0 - nx, orig.1 - ny)];
+        self.quad(corners, WHITE_CELL, false, arrow.color);
+
+        let (mx, my) = (-uy * marker / 2.0, ux * marker / 2.0);
+        self.triangle(head, (shaft.0 + mx, shaft.1 + my), (shaft.0 - mx, shaft.1 - my), arrow.color);
+    }
+}
+
+/// Texture coordinates of the center of an atlas cell.
+fn cell_center(cell: i32) -> (f64, f64) {
+    ((f64::from(cell) + 0.5) / f64::from(CELLS), 0.5)
+}
+
+fn piece_cell(piece: Piece) -> i32 {
+    piece.color.fold_wb(0, 6) + piece.role as i32 - 1
+}
+
+/// A piece sliding to its square after a move.
+struct Slide {
+    piece: Piece,
+    from: (f64, f64),
+    to: Square,
+}
+
+/// Pieces that moved between two boards. Pieces that appeared without a
+/// matching one gone elsewhere, like a promoted queen, do not slide.
+fn slides(before: &Board, after: &Board) -> Vec<Slide> {
+    let mut vacated: Vec<(Square, Piece)> = before.occupied().into_iter()
+        .filter_map(|sq| before.piece_at(sq).filter(|&p| after.piece_at(sq) != Some(p)).map(|p| (sq, p)))
+        .collect();
+
+    let mut slides = Vec::new();
+    for to in after.occupied() {
+        let piece = match after.piece_at(to) {
+            Some(piece) if before.piece_at(to) != Some(piece) => piece,
+            _ => continue,
+        };
+        if let Some(i) = vacated.iter().position(|&(_, p)| p == piece) {
+            let (from, _) = vacated.remove(i);
+            slides.push(Slide { piece, from: square_to_pos(from), to });
+        }
+    }
+    slides
+}
+
+struct Model {
+    state: BoardState,
+    animation: Duration,
+    slides: Vec<Slide>,
+    /// Frame time in microseconds when the slides started.
+    start: Option<i64>,
+    /// Progress of the slides from 0 to 1.
+    elapsed: f64,
+    ticking: bool,
+}
+
+impl Model {
+    fn mesh(&self) -> Mesh {
+        let state = &self.state;
+        let theme = state.theme();
+        let mut mesh = Mesh { vertices: Vec::new() };
+
+        for square in Square::ALL {
+            mesh.square(square, if square.is_light() { theme.light } else { theme.dark });
+        }
+
+        if let Some((orig, dest)) = state.last_move() {
+            mesh.square(orig, theme.last_move);
+            if dest != orig {
+                mesh.square(dest, theme.last_move);
+            }
+        }
+
+        if let Some(check) = state.check() {
+            mesh.rect(square_to_pos(check), 2.0 * 0.5f64.hypot(0.5), GLOW_CELL, false, theme.check);
+        }
+
+        let flip = state.orientation() == Color::Black;
+        let white = Rgba::new(1.0, 1.0, 1.0, 1.0);
+        let t = ease(0.0, 1.0, self.elapsed);
+
+        for square in state.board().occupied() {
+            let piece = match state.board().piece_at(square) {
+                Some(piece) => piece,
+                None => continue,
+            };
+
+            let (x, y) = square_to_pos(square);
+            let pos = match self.slides.iter().find(|s| s.to == square && s.piece == piece) {
+                Some(slide) if self.elapsed < 1.0 => (slide.from.0 + (x - slide.from.0) * t, slide.from.1 + (y - slide.from.1) * t),
+                _ => (x, y),
+            };
+
+            mesh.rect(pos, 1.0, piece_cell(piece), flip, white);
+        }
+
+        for arrow in state.arrows() {
+            mesh.arrow(arrow);
+        }
+
+        mesh
+    }
+
+    /// Column major transformation from board coordinates to clip space,
+    /// fitting the board into the viewport.
+    fn matrix(&self, (width, height): (u32, u32)) -> [[f32; 4]; 4] {
+        let (width, height) = (f64::from(width.max(1)), f64::from(height.max(1)));
+        let unit = width.min(height) / 8.0;
+        let sign = self.state.orientation().fold_wb(1.0, -1.0);
+        let sx = (sign * unit * 2.0 / width) as f32;
+        let sy = (-sign * unit * 2.0 / height) as f32;
+
+        [
+            [sx, 0.0, 0.0, 0.0],
+            [0.0, sy, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [-4.0 * sx, -4.0 * sy, 0.0, 1.0],
+        ]
+    }
+}
+
+/// Lets glium draw into the context of a `GLArea`.
+struct AreaBackend {
+    area: GLArea,
+}
+
+unsafe impl Backend for AreaBackend {
+    fn swap_buffers(&self) -> Result<(), SwapBuffersError> {
+        // GTK presents the frame after the render signal
+        Ok(())
+    }
+
+    unsafe fn get_proc_address(&self, symbol: &str) -> *const c_void {
+        epoxy::get_proc_addr(symbol)
+    }
+
+    fn get_framebuffer_dimensions(&self) -> (u32, u32) {
+        let scale = self.area.scale_factor();
+        let alloc = self.area.allocation();
+        ((alloc.width() * scale).max(1) as u32, (alloc.height() * scale).max(1) as u32)
+    }
+
+    fn is_current(&self) -> bool {
+        unsafe { self.make_current() };
+        true
+    }
+
+    unsafe fn make_current(&self) {
+        if self.area.is_realized() {
+            self.area.make_current();
+        }
+    }
+}
+
+/// What lives on the GPU while the area is realized.
+struct Gpu {
+    context: Rc<GlContext>,
+    program: Program,
+    atlas: Option<Texture2d>,
+}
+
+impl Gpu {
+    fn new(area: &GLArea) -> Option<Gpu> {
+        let context = unsafe {
+            GlContext::new(AreaBackend { area: area.clone() }, true, DebugCallbackBehavior::Ignore).ok()?
+        };
+        let program = Program::from_source(&context, VERTEX_SHADER, FRAGMENT_SHADER, None).ok()?;
+        Some(Gpu { context, program, atlas: None })
+    }
+
+    fn draw(&mut self, model: &Model) -> Result<(), DrawError> {
+        if self.atlas.is_none() {
+            self.atlas = Some(upload_atlas(&self.context, model.state.piece_set()).map_err(DrawError::Atlas)?);
+        }
+
+        let mesh = model.mesh();
+        let vertices = VertexBuffer::new(&self.context, &mesh.vertices).map_err(|err| DrawError::Buffer(format!("{:?}", err)))?;
+
+        let atlas = self.atlas.as_ref().expect("atlas uploaded");
+        let uniforms = uniform! {
+            matrix: model.matrix(self.context.get_framebuffer_dimensions()),
+            atlas: atlas.sampled()
+                .minify_filter(MinifySamplerFilter::LinearMipmapLinear)
+                .magnify_filter(MagnifySamplerFilter::Linear),
+        };
+
+        let params = DrawParameters {
+            blend: Blend {
+                color: BlendingFunction::Addition {
+                    source: LinearBlendingFactor::One,
+                    destination: LinearBlendingFactor::OneMinusSourceAlpha,
+                },
+                alpha: BlendingFunction::Addition {
+                    source: LinearBlendingFactor::One,
+                    destination: LinearBlendingFactor::OneMinusSourceAlpha,
+                },
+                constant_value: (0.0, 0.0, 0.0, 0.0),
+            },
+            ..Default::default()
+        };
+
+        let mut frame = glium::Frame::new(Rc::clone(&self.context), self.context.get_framebuffer_dimensions());
+        frame.clear_color(0.0, 0.0, 0.0, 0.0);
+        let drawn = frame.draw(&vertices, NoIndices(PrimitiveType::TrianglesList), &self.program, &uniforms, &params);
+        let finished = frame.finish();
+        drawn.map_err(|err| DrawError::Draw(err.to_string()))?;
+        finished.map_err(|err| DrawError::Draw(format!("{:?}", err)))
+    }
+}
+
+/// Why a frame could not be drawn, shown in place of the board.
+#[derive(Debug)]
+enum DrawError {
+    /// The piece set could not be rasterized, e.g. because of a broken
+    /// image.
+    Atlas(cairo::Error),
+    Buffer(String),
+    Draw(String),
+}
+
+impl fmt::Display for DrawError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DrawError::Atlas(ref err) => write!(f, "could not rasterize the pieces: {}", err),
+            DrawError::Buffer(ref err) => write!(f, "could not upload the vertices: {}", err),
+            DrawError::Draw(ref err) => write!(f, "could not draw the board: {}", err),
+        }
+    }
+}
+
+/// Rasterize the pieces and the other cells with cairo and upload them.
+fn upload_atlas<F: Facade>(facade: &F, piece_set: &PieceSet) -> Result<Texture2d, cairo::Error> {
+    let mut surface = ImageSurface::create(Format::ARgb32, CELLS * CELL, CELL)?;
+    {
+        let cr = Context::new(&surface)?;
+        cr.scale(f64::from(CELL), f64::from(CELL));
+
+        for (i, &piece) in PIECES.iter().enumerate() {
+            cr.save()?;
+            cr.translate(i as f64, 0.0);
+            piece_set.render(&cr, piece)?;
+            cr.restore()?;
+        }
+
+        let white = Rgba::new(1.0, 1.0, 1.0, 1.0);
+        let center = |cell: i32| (f64::from(cell) + 0.5, 0.5);
+        cr.fill_rect(f64::from(WHITE_CELL), 0.0, 1.0, 1.0, white)?;
+        cr.push_clip(f64::from(GLOW_CELL), 0.0, 1.0, 1.0)?;
+        cr.fill_glow(center(GLOW_CELL), 0.5, white)?;
+        cr.pop_clip()?;
+        cr.stroke_circle(center(RING_CELL), 0.475, 0.05, white)?;
+    }
+
+    surface.flush();
+    let stride = surface.stride() as usize;
+    let data = surface.data().map_err(|_| cairo::Error::SurfaceFinished)?;
+
+    // cairo stores native endian ARGB words, GL wants RGBA bytes
+    let (width, height) = ((CELLS * CELL) as usize, CELL as usize);
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in data.chunks(stride).take(height) {
+        for px in row[..width * 4].chunks(4) {
+            let argb = u32::from_ne_bytes([px[0], px[1], px[2], px[3]]);
+            rgba.extend_from_slice(&[(argb >> 16) as u8, (argb >> 8) as u8, argb as u8, (argb >> 24) as u8]);
+        }
+    }
+
+    let image = RawImage2d::from_raw_rgba(rgba, (width as u32, height as u32));
+    Texture2d::with_mipmaps(facade, image, MipmapsOption::AutoGeneratedMipmaps)
+        .map_err(|_| cairo::Error::NoMemory)
+}
+
+/// Pieces in the order of their atlas cells.
+const PIECES: [Piece; 12] = [
+    Piece { color: Color::White, role: Role::Pawn },
+    Piece { color: Color::White, role: Role::Knight },
+    Piece { color: Color::White, role: Role::Bishop },
+    Piece { color: Color::White, role: Role::Rook },
+    Piece { color: Color::White, role: Role::Queen },
+    Piece { color: Color::White, role: Role::King },
+    Piece { color: Color::Black, role: Role::Pawn },
+    Piece { color: Color::Black, role: Role::Knight },
+    Piece { color: Color::Black, role: Role::Bishop },
+    Piece { color: Color::Black, role: Role::Rook },
+    Piece { color: Color::Black, role: Role::Queen },
+    Piece { color: Color::Black, role: Role::King },
+];
+
+/// Load the OpenGL entry points from libepoxy once per process.
+fn load_gl() {
+    thread_local!(static LOADED: std::cell::Cell<bool> = std::cell::Cell::new(false));
+
+    LOADED.with(|loaded| {
+        if !loaded.replace(true) {
+            let library = Library::this();
+            epoxy::load_with(|symbol| unsafe {
+                library.get::<*const c_void>(symbol.as_bytes()).map_or(ptr::null(), |s| *s)
+            });
+        }
+    });
+}
+
+/// Board widget drawn with OpenGL, for displaying positions while
+/// keeping the CPU free. See the module documentation.
+pub struct GpuBoard {
+    area: GLArea,
+    model: Rc<RefCell<Model>>,
+    gpu: Rc<RefCell<Option<Gpu>>>,
+}
+
+impl fmt::Debug for GpuBoard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GpuBoard")
+            .field("fen", &self.model.borrow().state.fen())
+            .field("realized", &self.gpu.borrow().is_some())
+            .finish()
+    }
+}
+
+impl Default for GpuBoard {
+    fn default() -> GpuBoard {
+        GpuBoard::new()
+    }
+}
+
+impl GpuBoard {
+    /// A board with the starting position.
+    pub fn new() -> GpuBoard {
+        let area = GLArea::new();
+        area.set_has_alpha(true);
+        area.set_size_request(160, 160);
+        area.set_hexpand(true);
+        area.set_vexpand(true);
+
+        let model = Rc::new(RefCell::new(Model {
+            state: BoardState::new(),
+            animation: Duration::from_millis(300),
+            slides: Vec::new(),
+            start: None,
+            elapsed: 1.0,
+            ticking: false,
+        }));
+        let gpu = Rc::new(RefCell::new(None));
+
+        {
+            let gpu = Rc::downgrade(&gpu);
+            area.connect_realize(move |area| {
+                area.make_current();
+                if area.error().is_some() {
+                    return;
+                }
+                load_gl();
+                if let Some(gpu) = gpu.upgrade() {
+                    *gpu.borrow_mut() = Gpu::new(area);
+                    if gpu.borrow().is_none() {
+                        area.set_error(Some(&glib::Error::new(GLError::NotAvailable, "could not set up OpenGL for the board")));
+                    }
+                }
+            });
+        }
+
+        {
+            let gpu = Rc::downgrade(&gpu);
+            area.connect_unrealize(move |area| {
+                // textures and buffers go away with the context
+                area.make_current();
+                if let Some(gpu) = gpu.upgrade() {
+                    gpu.borrow_mut().take();
+                }
+            });
+        }
+
+        {
+            let gpu = Rc::downgrade(&gpu);
+            let model = Rc::downgrade(&model);
+            area.connect_render(move |area, _| {
+                if let (Some(gpu), Some(model)) = (gpu.upgrade(), model.upgrade()) {
+                    if let Some(ref mut gpu) = *gpu.borrow_mut() {
+                        if let Err(err) = gpu.draw(&model.borrow()) {
+                            area.set_error(Some(&glib::Error::new(GLError::NotAvailable, &err.to_string())));
+                        }
+                    }
+                }
+                Inhibit(true)
+            });
+        }
+
+        GpuBoard { area, model, gpu }
+    }
+
+    pub fn widget(&self) -> &GLArea {
+        &self.area
+    }
+
+    /// Play a legal move, sliding the pieces that moved.
+    pub fn play(&self, m: &Move) -> Result<(), IllegalMoveError> {
+        let mut model = self.model.borrow_mut();
+        let before = model.state.board().clone();
+        model.state.play(m)?;
+        model.slides = slides(&before, model.state.board());
+        model.start = None;
+        model.elapsed = 0.0;
+        drop(model);
+
+        self.animate();
+        Ok(())
+    }
+
+    pub fn set_fen(&self, fen: &str) -> Result<(), ParseFenError> {
+        {
+            let mut model = self.model.borrow_mut();
+            model.state.set_fen(fen)?;
+            model.slides.clear();
+            model.elapsed = 1.0;
+        }
+        self.area.queue_render();
+        Ok(())
+    }
+
+    pub fn fen(&self) -> String {
+        self.model.borrow().state.fen()
+    }
+
+    pub fn orientation(&self) -> Color {
+        self.model.borrow().state.orientation()
+    }
+
+    pub fn set_orientation(&self, orientation: Color) {
+        self.model.borrow_mut().state.set_orientation(orientation);
+        self.area.queue_render();
+    }
+
+    pub fn flip(&self) {
+        self.set_orientation(!self.orientation());
+    }
+
+    pub fn set_theme(&self, theme: Theme) {
+        self.model.borrow_mut().state.set_theme(theme);
+        self.area.queue_render();
+    }
+
+    /// Use another piece set. Its atlas is uploaded with the next frame.
+    pub fn set_piece_set(&self, piece_set: PieceSet) {
+        self.model.borrow_mut().state.set_piece_set(piece_set);
+        if let Some(ref mut gpu) = *self.gpu.borrow_mut() {
+            gpu.atlas = None;
+        }
+        self.area.queue_render();
+    }
+
+    pub fn set_arrows(&self, arrows: Vec<Arrow>) {
+        self.model.borrow_mut().state.set_arrows(arrows);
+        self.area.queue_render();
+    }
+
+    /// How long pieces slide after `play()`. Zero disables animations.
+    pub fn set_animation_duration(&self, duration: Duration) {
+        self.model.borrow_mut().animation = duration;
+    }
+
+    fn animate(&self) {
+        {
+            let mut model = self.model.borrow_mut();
+            if model.animation == Duration::from_secs(0) {
+                model.elapsed = 1.0;
+            }
+            if model.elapsed >= 1.0 || model.ticking {
+                drop(model);
+                self.area.queue_render();
+                return;
+            }
+            model.ticking = true;
+        }
+
+        let model = Rc::downgrade(&self.model);
+        self.area.add_tick_callback(move |area, clock| {
+            area.queue_render();
+
+            if let Some(model) = model.upgrade() {
+                let mut model = model.borrow_mut();
+                let now = clock.frame_time();
+                let start = *model.start.get_or_insert(now);
+                let duration = model.animation.as_micros().max(1) as f64;
+                model.elapsed = (((now - start) as f64) / duration).min(1.0);
+                if model.elapsed < 1.0 {
+                    return Continue(true);
+                }
+                model.slides.clear();
+                model.ticking = false;
+            }
+            Continue(false)
+        });
+    }
+}
@@ -18,27 +18,65 @@ use std::rc::{Rc, Weak};
 use std::cell::RefCell;
 use std::f64::consts::PI;
 use std::cmp::{min, max};
+#[cfg(feature = "blockchain")]
+use std::collections::VecDeque;
 use std::fmt;
+#[cfg(feature = "blockchain")]
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::path::PathBuf;
 
 use gtk::prelude::*;
-use gtk::DrawingArea;
-use gdk::{EventButton, EventMotion, EventMask};
+use gtk::atk::prelude::*;
+use gtk::{glib, AspectFrame, DrawingArea, ShadowType};
+use gdk::keys::constants as key;
+use gdk::{EventButton, EventKey, EventMotion, EventMask, EventScroll, EventTouch, EventSequence, EventType, ModifierType, ScrollDirection};
 use cairo::{Context, Matrix};
 
 use relm::{Relm, Widget, Update, StreamHandle};
 
-use shakmaty::{Square, Rank, Color, Role, Board, Move, MoveList, Chess, Position};
+use shakmaty::{Square, Rank, Color, Role, Piece, Board, Bitboard, Material, Move, MoveList, Chess, Position, Setup, EnPassantMode};
+use shakmaty::uci::Uci;
+use shakmaty::variant::{Variant, VariantPosition};
 
-use util::{file_to_float, pos_to_square, rank_to_float};
-use pieces::Pieces;
-use drawable::{Drawable, DrawShape};
+use util::{file_to_float, pos_to_square, rank_to_float, square_to_pos};
+use pieces::{Blindfold, Pieces};
+use shapes::{Arrow, Drawable, DrawBrush, DrawShape};
 use promotable::Promotable;
-use boardstate::BoardState;
-
-type Stream = StreamHandle<GroundMsg>;
+use pockets::Pockets;
+use keyboard::Keyboard;
+use access::{describe_square, Access};
+use boardstate::{AutoOrient, BoardState, HighlightKind, Coordinates, GameState, MovableSide, MoveInput, UciMovesError};
+use theme::Theme;
+use pieceset::PieceSet;
+use timing::{real_time, TimeSource};
+use engine::{multipv_shapes, pv_shapes, Info, Score};
+use analysis::{Analysis, AnalysisEvent, Judgement};
+use game::{Game, GameMove};
+use gametree::NodeId;
+#[cfg(feature = "blockchain")]
+use chain::{ChainError, ChainEvent, MoveRecord};
+#[cfg(feature = "blockchain")]
+use crypto::{verify_record, MoveSigner};
+use clock::{Clock, TimeControl};
+use audio::{Audio, Sound, SoundSet};
+use speech::{Speech, Vocabulary};
+use notation::Notation;
+use openings::{Opening, Openings};
+use tablebase::{tablebase_shapes, MoveProbe, Probe, Tablebase};
+use trainer::{Drill, DrillRun, Recall};
+use lichess::{replay, LichessEvent, LichessGame, LichessState};
+use puzzle::{Puzzle, PuzzleRun, PuzzleStats, Verdict};
+use opponent::Bot;
+use recovery::Recovery;
+use storage::StoredGame;
+
+pub(crate) type Stream = StreamHandle<GroundMsg>;
 
 pub struct Model {
     state: Rc<RefCell<State>>,
+    stream: Stream,
 }
 
 impl fmt::Debug for Model {
@@ -50,19 +88,340 @@ impl fmt::Debug for Model {
 /// Chessground events and messages.
 #[derive(Debug, Msg)]
 pub enum GroundMsg {
-    /// Flip the board.
+    /// Flip the board. The pieces glide to their new places.
     Flip,
     /// Set the board orientation.
     SetOrientation(Color),
+    /// Turn the board by itself to the side to move, or to the color of
+    /// the local player, with the pieces gliding like for `Flip`.
+    SetAutoOrient(AutoOrient),
     /// Set up a position configuration.
     SetPos(Pos),
     /// Set up a board.
     SetBoard(Board),
+    /// Set up a Chess960 starting position by its Scharnagl number
+    /// (0 to 959) and enable Chess960 castling.
+    SetChess960(u32),
+    /// Use the rules of a variant for legal moves, `PlayMove` and
+    /// `GameEnd`. In Crazyhouse the pockets are shown above and below the
+    /// board.
+    SetVariant(Variant),
+    /// Play a legal move in the current position, animating the pieces.
+    PlayMove(Move),
+    /// Play a move received from the other player, for example through a
+    /// `Peer`. Illegal moves are ignored.
+    #[cfg(feature = "blockchain")]
+    RemoteMove(MoveRecord),
+    /// Play moves missed while disconnected, e.g. from a
+    /// `NetMessage::Resync`, in quick succession. If one of them is not
+    /// legal the rest are dropped and `ResyncFailed` is sent.
+    #[cfg(feature = "blockchain")]
+    CatchUp(Vec<MoveRecord>),
+    /// Replace the arrows and circles on the board.
+    SetShapes(Vec<DrawShape>),
+    /// Replace the arrows set by the caller, e.g. for analysis, puzzles
+    /// or tutorials. They stay beneath the shapes drawn by the user until
+    /// replaced, and an empty list clears them.
+    SetArrows(Vec<Arrow>),
+    /// Replace the squares highlighted by the caller, e.g. to show
+    /// threats or hints from an engine. An empty list clears them.
+    HighlightSquares(Vec<(Square, HighlightKind)>),
+    /// Change the board colors, e.g. to `Theme::preset("brown")`.
+    SetTheme(Theme),
+    /// Switch the piece images, e.g. to one of `PieceSet::scan("pieces")`.
+    SetPieceSet(PieceSet),
+    /// Show or hide the legal destinations of the selected piece.
+    SetMoveHints(bool),
+    /// Let dragged pieces dropped within this distance of a legal square,
+    /// in squares, land on it. `0.0` disables snapping.
+    SetSnapRadius(f64),
+    /// Set how long pieces glide to their squares, `300ms` by default.
+    /// Zero disables the animations.
+    SetAnimationDuration(Duration),
+    /// Move a focus square with the arrow keys and pick up or play pieces
+    /// with Enter, describing the square in focus to screen readers and
+    /// in a tooltip. The arrow keys no longer step through the game.
+    SetSquareNavigation(bool),
+    /// Move pieces by clicking, by dragging or both.
+    SetMoveInput(MoveInput),
+    /// Promote to a queen right away. Holding Shift, Ctrl or Alt while
+    /// moving still brings up the choice of pieces.
+    SetAutoQueen(bool),
+    /// Watch without moving pieces, e.g. when spectating. Moves from
+    /// messages are still animated and shapes can still be drawn.
+    SetViewOnly(bool),
+    /// Restrict which side the local user may move.
+    SetMovable(MovableSide),
+    /// Hide the pieces, or show them as uniform tokens. Moves can still
+    /// be made by clicking, dragging or typing them.
+    SetBlindfold(Blindfold),
+    /// Change which pieces the promotion dialog offers and in which order,
+    /// starting at the promotion square.
+    SetPromotionOrder(Vec<Role>),
+    /// Show the legal squares of a piece of the side to move when the
+    /// pointer rests on it for the delay, without clicking. `None` turns
+    /// the hints off.
+    SetHoverHints(Option<Duration>),
+    /// Shade the squares attacked by the opponent of the side to move,
+    /// darker with more attackers, e.g. for beginners or training.
+    SetThreats(bool),
+    /// Show or hide the file and rank labels.
+    SetShowCoordinates(bool),
+    /// Draw the file and rank labels in the border or on the board.
+    SetCoordinates(Coordinates),
+    /// Discard the pending premove, if any.
+    CancelPremove,
+    /// Show an engine line as arrows. An empty line removes the arrows.
+    SetPv(Vec<Uci>),
+    /// Show the first moves of the best engine lines as arrows, thicker
+    /// for better moves. Forward `MultiPv::lines()` after each update.
+    SetMultiPv(Vec<Info>),
+    /// Load a game for replay and go to its final position.
+    LoadGame(Game),
+    /// Go to a ply of the line shown, which is the main line unless a
+    /// variation was entered with `GoToNode`.
+    GoToPly(usize),
+    /// Go to a move of the loaded game, possibly in a variation.
+    GoToNode(NodeId),
+    /// Go one ply forward in the line shown.
+    StepForward,
+    /// Go one ply backward in the loaded game.
+    StepBackward,
+    /// Enable or disable edit mode, where pieces can be moved freely,
+    /// ignoring the rules.
+    SetEditMode(bool),
+    /// Select the piece placed by clicking a square in edit mode, or `None`
+    /// to only move pieces.
+    SetSparePiece(Option<Piece>),
+    /// Set up a position from FEN. Invalid FEN is ignored.
+    SetFen(String),
+    /// Play moves in UCI notation separated by spaces, like the `moves`
+    /// of a UCI `position` command, e.g. to catch up with an engine or a
+    /// server. The pieces jump to the final position. If a move fails,
+    /// none are played and `UciMovesFailed` is sent.
+    PlayUciMoves(String),
+    /// Report progress of a `ChainRecorder` as `MoveSubmitted`,
+    /// `MoveConfirmed` and `MoveRejected` events.
+    #[cfg(feature = "blockchain")]
+    WatchChain(Receiver<ChainEvent>),
+    /// Show a Lichess game as it is played, and send the moves of the
+    /// user to Lichess. The board follows the game stream, so user moves
+    /// appear once Lichess accepted them.
+    WatchLichess(LichessGame),
+    /// Sign moves of the local player. Each legal `UserMove` is followed
+    /// by a `SignedMove` event.
+    #[cfg(feature = "blockchain")]
+    SetSigner(Option<MoveSigner>),
+    /// Check the signature of a move received from another player against
+    /// their public key, resulting in `MoveVerified` or `SignatureFailed`.
+    #[cfg(feature = "blockchain")]
+    VerifyMove(MoveRecord, Vec<u8>),
+    /// Start a chess clock, or remove it with `None`. The clock of the
+    /// side to move starts after the first move, and the clocks are
+    /// pressed whenever a move is played on the board.
+    SetTimeControl(Option<TimeControl>),
+    /// Synchronize the remaining times of white and black.
+    SetClockTimes(Duration, Duration),
+    /// Let the time of a side stand still, e.g. for the opponents of a
+    /// simul giver, or let both clocks run with `None`.
+    SetUntimedSide(Option<Color>),
+    /// Play sounds for moves and game events. Moves are only heard when
+    /// played with `PlayMove` or `RemoteMove`, not with `SetPos`.
+    SetSounds(SoundSet),
+    /// Mute or unmute the sounds.
+    SetMuted(bool),
+    /// Announce moves, the end of the game and optionally clock warnings
+    /// with speech, or stop with `None`. Like sounds, moves are only
+    /// announced when played with `PlayMove` or `RemoteMove`.
+    SetSpeech(Option<Speech>),
+    /// Type moves with the piece letters of a language, and announce them
+    /// in that language if the speech set with `SetSpeech` knows it.
+    /// Forward to `MoveListMsg::SetNotation`, too.
+    SetNotation(Notation),
+    /// Save the game on the board to a recovery file after every move and
+    /// periodically while it runs, or stop with `None`, which also removes
+    /// the file, e.g. when quitting normally. The file is removed when the
+    /// game ends.
+    SetAutosave(Option<Recovery>),
+    /// Continue an interrupted game, e.g. from `Recovery::interrupted()`,
+    /// replaying its main line and setting the clock times. Forward it to
+    /// `MoveListMsg` and the other widgets, too.
+    Resume(StoredGame),
+    /// Probe the Syzygy tables in a directory in endgames, or stop probing
+    /// with `None`.
+    SetTablebase(Option<PathBuf>),
+    /// Offer a draw on behalf of a side.
+    OfferDraw(Color),
+    /// Accept the draw offered by the opponent of a side, which ends the
+    /// game with `GameEnd`.
+    AcceptDraw(Color),
+    /// A side gives up, which ends the game with `GameEnd`.
+    Resign(Color),
+    /// Stop the game without a result, which ends it with `GameEnd`.
+    Abort,
+    /// Ask to take back the last move of a side.
+    ProposeTakeback(Color),
+    /// Accept the takeback asked for by the opponent of a side. The
+    /// pieces glide back to where they were.
+    AcceptTakeback(Color),
+    /// Go back to the position before the last move played on the board.
+    /// The pieces glide back.
+    Undo,
+    /// Replay the last move taken back with `Undo`.
+    Redo,
+    /// Keep at most this many plies for `Undo`, or all with `None`.
+    SetHistoryLimit(Option<usize>),
+    /// Set up a puzzle, or stop solving with `None`. Only the solver's
+    /// pieces can be moved. Wrong moves glide back, right moves are
+    /// played and answered by the opponent, so `UserMove` must not be
+    /// answered with `PlayMove` while solving.
+    SetPuzzle(Option<Puzzle>),
+    /// Evaluate each position shown on the board with an engine and judge
+    /// the moves played, or stop analysing with `None`.
+    SetAnalysis(Option<Analysis>),
+    /// Play against a computer opponent, or stop with `None`. Only the
+    /// other side can be moved by the user. The moves of the bot are sent
+    /// as `UserMove` or `UserDrop`.
+    SetBot(Option<Bot>),
+    /// Drill a line of an opening repertoire, or stop with `None`. The
+    /// opponent side is played automatically and wrong moves glide back,
+    /// so `UserMove` must not be answered with `PlayMove` while drilling.
+    SetDrill(Option<Drill>),
 
     /// Sent when the completed a piece drag or move.
     UserMove(Square, Square, Option<Role>),
+    /// Sent when the user dropped a piece from the pocket onto a square.
+    UserDrop(Role, Square),
     /// Sent when shapes are added, removed or cleared.
     ShapesChanged(Vec<DrawShape>),
+    /// Sent when another ply of the loaded game is shown.
+    PlyChanged(usize),
+    /// Sent with `PlyChanged`, identifying the move in the game tree.
+    NodeChanged(NodeId),
+    /// Sent when the position was changed in edit mode, with the new FEN.
+    FenEdited(String),
+    /// Sent when the move at a ply was submitted to the chain, with the
+    /// transaction id.
+    #[cfg(feature = "blockchain")]
+    MoveSubmitted(u32, String),
+    /// Sent when the move at a ply is confirmed on chain.
+    #[cfg(feature = "blockchain")]
+    MoveConfirmed(u32, String),
+    /// Sent when recording the move at a ply failed.
+    #[cfg(feature = "blockchain")]
+    MoveRejected(u32, ChainError),
+    /// Sent after a legal `UserMove` if a signer is set.
+    #[cfg(feature = "blockchain")]
+    SignedMove(MoveRecord),
+    /// Sent when a move passed `VerifyMove`.
+    #[cfg(feature = "blockchain")]
+    MoveVerified(MoveRecord),
+    /// Sent when a move failed `VerifyMove`.
+    #[cfg(feature = "blockchain")]
+    SignatureFailed(MoveRecord),
+    /// Sent when a move given to `CatchUp` is not legal on the board.
+    #[cfg(feature = "blockchain")]
+    ResyncFailed(MoveRecord),
+    /// Sent when the moves given to `PlayUciMoves` could not be played.
+    UciMovesFailed(UciMovesError),
+    /// Sent periodically while a clock is set, with the remaining times of
+    /// white and black and the running side. Forward to
+    /// `ClockMsg::SetTimes`.
+    ClockChanged(Duration, Duration, Option<Color>),
+    /// Sent when a move was made while a clock is set, with the game ply
+    /// it leads to, the time spent on it and the remaining time of the
+    /// moving side. Forward to `MoveListMsg::TimeMove` and
+    /// `GameMove::set_elapsed`.
+    MoveTimed(u32, Duration, Duration),
+    /// Sent when a player ran out of time. No more moves can be made.
+    Flag(Color),
+    /// Sent when the recovery file set with `SetAutosave` could not be
+    /// written or removed, with the reason.
+    AutosaveFailed(String),
+    /// Sent when the game ended, by checkmate, agreement, resignation, a
+    /// flag, an abort, a variant specific rule like a third check, a king
+    /// on the hill, or an exploded king, or a draw by the rules:
+    /// stalemate, threefold repetition, the fifty-move rule or
+    /// insufficient material. The pieces can no longer be moved.
+    GameEnd(GameState),
+    /// Sent when a move reached a named opening, or with `None` when back
+    /// at the starting position or in another variant.
+    OpeningChanged(Option<Opening>),
+    /// Sent when a piece was captured or the position changed otherwise,
+    /// with the pieces captured by white, the pieces captured by black,
+    /// and the material advantage of white. Forward to
+    /// `CapturedTrayMsg::SetMaterial`.
+    MaterialChanged(Vec<Role>, Vec<Role>, i32),
+    /// Sent after each move in positions covered by the tablebase, with
+    /// the result of the position and of each legal move.
+    TablebaseProbed(Probe, Vec<MoveProbe>),
+    /// Sent when the puzzle given to `SetPuzzle` was solved, with its id.
+    PuzzleSolved(String, PuzzleStats),
+    /// Sent when too many wrong moves were tried on the puzzle given to
+    /// `SetPuzzle`, with its id. Solving stops.
+    PuzzleFailed(String, PuzzleStats),
+    /// Sent when the position at a game ply was evaluated by the engine
+    /// given to `SetAnalysis`, from the point of view of the side to move.
+    Evaluated(u32, Score),
+    /// Sent when the move leading to a game ply lost enough centipawns to
+    /// be judged, with the loss. The judgement is also added to the move of
+    /// the loaded game. Forward to `MoveListMsg::JudgeMove`.
+    MoveJudged(u32, Judgement, i32),
+    /// Sent when the move of the drilled line was found in a position,
+    /// with its FEN and the quality of the recall from 0 to 5. Forward to
+    /// `Repertoire::review`.
+    DrillRecalled(String, u8),
+    /// Sent when the line given to `SetDrill` was played to the end, with
+    /// the number of wrong moves.
+    DrillFinished(u32),
+    /// Sent for each update of the game given to `WatchLichess`.
+    Lichess(LichessEvent),
+}
+
+impl GroundMsg {
+    /// A copy of an outgoing event, like `UserMove` or `GameEnd`, to pass
+    /// it on to a parent widget. Incoming messages give `None`.
+    pub(crate) fn clone_event(&self) -> Option<GroundMsg> {
+        Some(match *self {
+            GroundMsg::UserMove(orig, dest, promotion) => GroundMsg::UserMove(orig, dest, promotion),
+            GroundMsg::UserDrop(role, square) => GroundMsg::UserDrop(role, square),
+            GroundMsg::ShapesChanged(ref shapes) => GroundMsg::ShapesChanged(shapes.clone()),
+            GroundMsg::PlyChanged(ply) => GroundMsg::PlyChanged(ply),
+            GroundMsg::NodeChanged(node) => GroundMsg::NodeChanged(node),
+            GroundMsg::FenEdited(ref fen) => GroundMsg::FenEdited(fen.clone()),
+            #[cfg(feature = "blockchain")]
+            GroundMsg::MoveSubmitted(ply, ref tx) => GroundMsg::MoveSubmitted(ply, tx.clone()),
+            #[cfg(feature = "blockchain")]
+            GroundMsg::MoveConfirmed(ply, ref tx) => GroundMsg::MoveConfirmed(ply, tx.clone()),
+            #[cfg(feature = "blockchain")]
+            GroundMsg::MoveRejected(ply, ref err) => GroundMsg::MoveRejected(ply, err.clone()),
+            #[cfg(feature = "blockchain")]
+            GroundMsg::SignedMove(ref record) => GroundMsg::SignedMove(record.clone()),
+            #[cfg(feature = "blockchain")]
+            GroundMsg::MoveVerified(ref record) => GroundMsg::MoveVerified(record.clone()),
+            #[cfg(feature = "blockchain")]
+            GroundMsg::SignatureFailed(ref record) => GroundMsg::SignatureFailed(record.clone()),
+            #[cfg(feature = "blockchain")]
+            GroundMsg::ResyncFailed(ref record) => GroundMsg::ResyncFailed(record.clone()),
+            GroundMsg::UciMovesFailed(ref err) => GroundMsg::UciMovesFailed(err.clone()),
+            GroundMsg::ClockChanged(white, black, running) => GroundMsg::ClockChanged(white, black, running),
+            GroundMsg::MoveTimed(ply, elapsed, remaining) => GroundMsg::MoveTimed(ply, elapsed, remaining),
+            GroundMsg::Flag(color) => GroundMsg::Flag(color),
+            GroundMsg::AutosaveFailed(ref reason) => GroundMsg::AutosaveFailed(reason.clone()),
+            GroundMsg::GameEnd(game_state) => GroundMsg::GameEnd(game_state),
+            GroundMsg::OpeningChanged(ref opening) => GroundMsg::OpeningChanged(opening.clone()),
+            GroundMsg::MaterialChanged(ref white, ref black, advantage) => GroundMsg::MaterialChanged(white.clone(), black.clone(), advantage),
+            GroundMsg::TablebaseProbed(probe, ref moves) => GroundMsg::TablebaseProbed(probe, moves.clone()),
+            GroundMsg::PuzzleSolved(ref id, stats) => GroundMsg::PuzzleSolved(id.clone(), stats),
+            GroundMsg::PuzzleFailed(ref id, stats) => GroundMsg::PuzzleFailed(id.clone(), stats),
+            GroundMsg::Evaluated(ply, score) => GroundMsg::Evaluated(ply, score),
+            GroundMsg::MoveJudged(ply, judgement, loss) => GroundMsg::MoveJudged(ply, judgement, loss),
+            GroundMsg::DrillRecalled(ref fen, quality) => GroundMsg::DrillRecalled(fen.clone(), quality),
+            GroundMsg::DrillFinished(mistakes) => GroundMsg::DrillFinished(mistakes),
+            GroundMsg::Lichess(ref event) => GroundMsg::Lichess(event.clone()),
+            _ => return None,
+        })
+    }
 }
 
 /// A position configuration.
@@ -74,7 +433,7 @@ pub enum GroundMsg {
 /// * Side to move
 #[derive(Debug, Clone)]
 pub struct Pos {
-    board: Board,
+    setup: Setup,
     legals: Box<MoveList>,
     check: Option<Square>,
     last_move: Option<(Square, Square)>,
@@ -83,9 +442,9 @@ pub struct Pos {
 
 impl Pos {
     /// Create a new position configuration.
-    pub fn new<P: Position>(p: &P) -> Pos {
+    pub fn new<P: Position + Clone>(p: &P) -> Pos {
         Pos {
-            board: p.board().clone(),
+            setup: p.clone().into_setup(EnPassantMode::Legal),
             legals: Box::new(p.legal_moves()),
             check: if p.checkers().any() { p.board().king_of(p.turn()) } else { None },
             last_move: None,
@@ -95,8 +454,11 @@ impl Pos {
 
     /// Create a position configuration from a board, without any other hints.
     pub fn from_board(board: Board) -> Pos {
+        let mut setup = Setup::empty();
+        setup.board = board;
+
         Pos {
-            board,
+            setup,
             legals: Box::new(MoveList::new()),
             check: None,
             last_move: None,
@@ -155,6 +517,7 @@ impl Default for Pos {
 /// Chessground, a chess board widget.
 #[derive(Debug)]
 pub struct Ground {
+    frame: AspectFrame,
     drawing_area: DrawingArea,
     model: Model,
 }
@@ -164,9 +527,10 @@ impl Update for Ground {
     type ModelParam = ();
     type Msg = GroundMsg;
 
-    fn model(_: &Relm<Self>, _: ()) -> Model {
+    fn model(relm: &Relm<Self>, _: ()) -> Model {
         Model {
             state: Rc::new(RefCell::new(State::new())),
+            stream: relm.stream().clone(),
         }
     }
 
@@ -176,50 +540,1077 @@ impl Update for Ground {
         match event {
             GroundMsg::Flip => {
                 let orientation = state.board_state.orientation();
-                state.board_state.set_orientation(!orientation);
+                state.set_orientation(!orientation);
                 self.drawing_area.queue_draw();
             },
+            GroundMsg::SetAutoOrient(auto_orient) => {
+                state.board_state.set_auto_orient(auto_orient);
+                self.follow_orientation(&mut state);
+            },
             GroundMsg::SetOrientation(orientation) => {
-                state.board_state.set_orientation(orientation);
+                state.set_orientation(orientation);
                 self.drawing_area.queue_draw();
             },
             GroundMsg::SetPos(pos) => {
-                state.pieces.set_board(&pos.board);
-                state.promotable.update(&pos.legals);
-                state.board_state.set_check(pos.check);
-                state.board_state.set_last_move(pos.last_move);
-                state.board_state.set_turn(pos.turn);
-                *state.board_state.legals_mut() = *pos.legals;
-                self.drawing_area.queue_draw();
+                let before = state.snapshot();
+                state.set_pos(pos);
+                state.press_clock(&self.model.stream);
+                self.play_premove(&mut state);
+                self.position_changed(&mut state);
+                state.queue_damage(&self.drawing_area, &before);
+            },
+            GroundMsg::PlayMove(m) => {
+                let before = state.snapshot();
+                if state.play(&m) {
+                    state.press_clock(&self.model.stream);
+                    self.play_premove(&mut state);
+                    self.position_changed(&mut state);
+                    state.queue_damage(&self.drawing_area, &before);
+                }
+            },
+            #[cfg(feature = "blockchain")]
+            GroundMsg::RemoteMove(record) => {
+                let m = state.board_state.legals().iter().find(|m| {
+                    m.from() == Some(record.orig()) && m.to() == record.dest() && m.promotion() == record.promotion()
+                }).cloned();
+
+                if let Some(m) = m {
+                    let before = state.snapshot();
+                    if state.play(&m) {
+                        state.press_clock(&self.model.stream);
+                        self.play_premove(&mut state);
+                        self.position_changed(&mut state);
+                        state.queue_damage(&self.drawing_area, &before);
+                    }
+                }
+            },
+            #[cfg(feature = "blockchain")]
+            GroundMsg::CatchUp(records) => {
+                state.catch_up.extend(records);
+                if !state.catching_up {
+                    state.catching_up = true;
+                    self.fast_forward();
+                }
             },
             GroundMsg::SetBoard(board) => {
                 state.pieces.set_board(&board);
+                state.board_state.set_board(board);
                 state.board_state.set_check(None);
                 state.board_state.set_last_move(None);
                 state.board_state.set_turn(None);
+                state.board_state.set_premove(None);
                 state.board_state.legals_mut().clear();
                 state.promotable.cancel();
                 self.drawing_area.queue_draw();
             },
+            GroundMsg::SetChess960(number) if number < 960 => {
+                state.set_chess960(number);
+                self.position_changed(&mut state);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetVariant(variant) => {
+                state.set_variant(variant);
+                self.position_changed(&mut state);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetShapes(shapes) => {
+                state.board_state.set_shapes(shapes);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetArrows(arrows) => {
+                state.board_state.set_arrows(arrows);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::HighlightSquares(highlights) => {
+                state.board_state.set_highlights(highlights);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetTheme(theme) => {
+                state.board_state.set_theme(theme);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetPieceSet(piece_set) => {
+                state.board_state.set_piece_set(piece_set);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetMoveHints(move_hints) => {
+                state.board_state.set_move_hints(move_hints);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetHoverHints(delay) => {
+                state.board_state.set_hover_hints(delay);
+                if delay.is_none() {
+                    let ctx = WidgetContext::new(&state.board_state, &self.drawing_area);
+                    state.pieces.clear_hover(&ctx);
+                }
+            },
+            GroundMsg::SetThreats(threats) => {
+                state.board_state.set_threats(threats);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetSnapRadius(radius) => {
+                state.board_state.set_snap_radius(radius);
+            },
+            GroundMsg::SetAnimationDuration(duration) => {
+                state.pieces.set_animation(duration);
+            },
+            GroundMsg::SetSquareNavigation(enabled) => {
+                state.access.set_enabled(enabled);
+                self.drawing_area.set_has_tooltip(enabled);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetMoveInput(move_input) => {
+                state.board_state.set_move_input(move_input);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetAutoQueen(auto_queen) => {
+                state.board_state.set_auto_queen(auto_queen);
+            },
+            GroundMsg::SetViewOnly(view_only) => {
+                state.set_view_only(view_only);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetBlindfold(blindfold) => {
+                state.blindfold = blindfold;
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetMovable(movable) => {
+                state.board_state.set_movable(movable);
+                if state.pieces.selected().and_then(|sq| state.board_state.board().piece_at(sq)).map_or(false, |p| !movable.allows(p.color)) {
+                    state.pieces.cancel();
+                }
+                self.follow_orientation(&mut state);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetPromotionOrder(order) => {
+                state.promotable.set_order(order);
+            },
+            GroundMsg::SetShowCoordinates(show_coordinates) => {
+                state.board_state.set_show_coordinates(show_coordinates);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetCoordinates(coordinates) => {
+                state.board_state.set_coordinates(coordinates);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::CancelPremove => {
+                state.board_state.set_premove(None);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetPv(pv) => {
+                state.board_state.set_engine_shapes(pv_shapes(&pv));
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetMultiPv(lines) => {
+                state.board_state.set_engine_shapes(multipv_shapes(&lines));
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::LoadGame(game) => {
+                let end = game.tree().end(game.tree().root());
+                state.game = Some(game);
+                self.go_to_node(&mut state, end);
+            },
+            GroundMsg::GoToPly(ply) => {
+                if let Some(node) = state.node_at(ply) {
+                    self.go_to_node(&mut state, node);
+                }
+            },
+            GroundMsg::GoToNode(node) => {
+                self.go_to_node(&mut state, node);
+            },
+            GroundMsg::StepForward => {
+                let next = state.game.as_ref().and_then(|g| state.node.and_then(|n| g.tree().next(n)));
+                if let Some(next) = next {
+                    self.go_to_node(&mut state, next);
+                }
+            },
+            GroundMsg::StepBackward => {
+                let parent = state.game.as_ref().and_then(|g| state.node.and_then(|n| g.tree().parent(n)));
+                if let Some(parent) = parent {
+                    self.go_to_node(&mut state, parent);
+                }
+            },
+            GroundMsg::SetEditMode(editing) => {
+                state.board_state.set_editing(editing);
+                state.promotable.cancel();
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetSparePiece(piece) => {
+                state.board_state.set_spare_piece(piece);
+            },
+            #[cfg(feature = "blockchain")]
+            GroundMsg::WatchChain(events) => {
+                let stream = self.model.stream.clone();
+                glib::timeout_add_local(Duration::from_millis(200), move || {
+                    loop {
+                        match events.try_recv() {
+                            Ok(ChainEvent::Submitted { ply, tx, .. }) => stream.emit(GroundMsg::MoveSubmitted(ply, tx)),
+                            Ok(ChainEvent::Confirmed { ply, tx, .. }) => stream.emit(GroundMsg::MoveConfirmed(ply, tx)),
+                            Ok(ChainEvent::Failed { ply, error, .. }) => stream.emit(GroundMsg::MoveRejected(ply, error)),
+                            Err(TryRecvError::Empty) => return Continue(true),
+                            Err(TryRecvError::Disconnected) => return Continue(false),
+                        }
+                    }
+                });
+            },
+            GroundMsg::SetAnalysis(analysis) => {
+                state.analysis = analysis;
+                self.report_analysis(&mut state);
+                if state.analysis.is_some() && !state.analysis_polling {
+                    state.analysis_polling = true;
+                    self.poll_analysis();
+                }
+            },
+            GroundMsg::SetBot(bot) => {
+                if let Some(ref bot) = bot {
+                    state.board_state.set_movable(bot.color().fold_wb(MovableSide::Black, MovableSide::White));
+                }
+                state.bot = bot;
+                if state.bot.is_some() && !state.bot_polling {
+                    state.bot_polling = true;
+                    self.poll_bot();
+                }
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::MoveJudged(ply, judgement, _) => {
+                state.record_judgement(ply, judgement);
+            },
+            GroundMsg::WatchLichess(game) => {
+                state.lichess = Some(game);
+                state.lichess_fen = "startpos".to_owned();
+                state.lichess_plies = None;
+                if !state.lichess_polling {
+                    state.lichess_polling = true;
+                    self.poll_lichess();
+                }
+            },
+            GroundMsg::Lichess(LichessEvent::GameFull { ref initial_fen, state: ref game, .. }) => {
+                state.lichess_fen = initial_fen.clone();
+                state.lichess_plies = None;
+                self.sync_lichess(&mut state, game);
+            },
+            GroundMsg::Lichess(LichessEvent::GameState(ref game)) => {
+                self.sync_lichess(&mut state, game);
+            },
+            GroundMsg::SetFen(fen) => {
+                if state.set_fen(&fen) {
+                    self.position_changed(&mut state);
+                    self.drawing_area.queue_draw();
+                }
+            },
+            GroundMsg::PlayUciMoves(moves) => {
+                match state.board_state.apply_uci_moves(&moves) {
+                    Ok(_) => {
+                        state.jump_pieces();
+                        state.press_clock(&self.model.stream);
+                        self.position_changed(&mut state);
+                        self.drawing_area.queue_draw();
+                    },
+                    Err(err) => self.model.stream.emit(GroundMsg::UciMovesFailed(err)),
+                }
+            },
             GroundMsg::UserMove(orig, dest, None) if state.board_state.valid_move(orig, dest) => {
                 if state.board_state.legals().iter().any(|m| m.from() == Some(orig) && m.to() == dest && m.promotion().is_some()) {
                     let color = state.pieces.figurine_at(orig).map_or_else(|| {
                         Color::from_white(dest.rank() > Rank::Fourth)
                     }, |figurine| figurine.piece().color);
-                    state.promotable.start(color, orig, dest);
+                    state.promotable.start(&state.board_state, color, orig, dest);
                     self.drawing_area.queue_draw();
+                } else if state.puzzle.is_some() {
+                    self.puzzle_move(&mut state, orig, dest, None);
+                } else if state.drill.is_some() {
+                    self.drill_move(&mut state, orig, dest, None);
+                } else {
+                    #[cfg(feature = "blockchain")]
+                    self.sign_move(&state, orig, dest, None);
+                    self.send_lichess(&state, orig, dest, None);
+                }
+            },
+            GroundMsg::UserMove(orig, dest, promotion) if state.board_state.legal_move(orig, dest, promotion) => {
+                if state.puzzle.is_some() {
+                    self.puzzle_move(&mut state, orig, dest, promotion);
+                } else if state.drill.is_some() {
+                    self.drill_move(&mut state, orig, dest, promotion);
+                } else {
+                    #[cfg(feature = "blockchain")]
+                    self.sign_move(&state, orig, dest, promotion);
+                    self.send_lichess(&state, orig, dest, promotion);
                 }
             },
+            GroundMsg::SetPuzzle(puzzle) => {
+                state.auto_reply = None;
+                state.drill = None;
+                state.puzzle = None;
+                if let Some(puzzle) = puzzle {
+                    let mut pos = Pos::new(puzzle.position());
+                    pos.set_last_move(puzzle.last_move());
+                    if state.board_state.variant() != Variant::Chess {
+                        state.set_variant(Variant::Chess);
+                    }
+                    state.set_pos(pos);
+                    state.board_state.set_movable(puzzle.solver().fold_wb(MovableSide::White, MovableSide::Black));
+                    state.puzzle = Some(PuzzleRun::new(puzzle));
+                }
+                self.position_changed(&mut state);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::SetDrill(drill) => {
+                state.auto_reply = None;
+                state.puzzle = None;
+                state.drill = None;
+                if let Some(drill) = drill {
+                    if state.board_state.variant() != Variant::Chess {
+                        state.set_variant(Variant::Chess);
+                    }
+                    state.set_pos(Pos::new(&Chess::default()));
+                    state.board_state.set_movable(drill.color().fold_wb(MovableSide::White, MovableSide::Black));
+                    let mut run = DrillRun::new(drill);
+                    let reply = run.opening_reply();
+                    state.drill = Some(run);
+                    if let Some(reply) = reply {
+                        self.schedule_reply(&mut state, reply);
+                    }
+                }
+                self.position_changed(&mut state);
+                self.drawing_area.queue_draw();
+            },
+            #[cfg(feature = "blockchain")]
+            GroundMsg::SetSigner(signer) => {
+                state.signer = signer;
+            },
+            #[cfg(feature = "blockchain")]
+            GroundMsg::VerifyMove(record, public_key) => {
+                if verify_record(&public_key, &record) {
+                    self.model.stream.emit(GroundMsg::MoveVerified(record));
+                } else {
+                    self.model.stream.emit(GroundMsg::SignatureFailed(record));
+                }
+            },
+            GroundMsg::SetTimeControl(control) => {
+                let time_source = Arc::clone(&state.time_source);
+                state.clock = control.map(|control| Clock::new(control).with_time_source(time_source));
+                if let Some(ref mut clock) = state.clock {
+                    clock.set_untimed(state.untimed);
+                }
+                state.low_time = (false, false);
+                state.clock_turn = state.board_state.turn();
+                state.clock_ply = state.board_state.ply();
+                if state.clock.is_some() && !state.ticking {
+                    state.ticking = true;
+                    self.tick_clock();
+                }
+            },
+            GroundMsg::SetUntimedSide(color) => {
+                state.untimed = color;
+                if let Some(ref mut clock) = state.clock {
+                    clock.set_untimed(color);
+                }
+            },
+            GroundMsg::SetClockTimes(white, black) => {
+                if let Some(ref mut clock) = state.clock {
+                    clock.set_remaining(white, black);
+                }
+                state.low_time = (false, false);
+            },
+            GroundMsg::SetSounds(sounds) => {
+                state.audio.set_sounds(sounds);
+            },
+            GroundMsg::SetMuted(muted) => {
+                state.audio.set_muted(muted);
+            },
+            GroundMsg::SetSpeech(speech) => {
+                state.speech = speech;
+            },
+            GroundMsg::SetAutosave(recovery) => {
+                if recovery.is_none() {
+                    if let Some(Err(err)) = state.recovery.as_ref().map(Recovery::discard) {
+                        self.model.stream.emit(GroundMsg::AutosaveFailed(err.to_string()));
+                    }
+                }
+                if let Some(ref recovery) = recovery {
+                    if !state.autosave_polling {
+                        state.autosave_polling = true;
+                        self.poll_autosave(recovery.interval());
+                    }
+                }
+                state.recovery = recovery;
+            },
+            GroundMsg::Resume(stored) => {
+                let before = state.snapshot();
+                state.set_pos(Pos::new(stored.game.initial_position()));
+                for game_move in stored.game.moves() {
+                    if state.board_state.play(game_move.mv()).is_err() {
+                        break;
+                    }
+                }
+                state.sync_pieces();
+
+                // continue the clocks without counting the replayed moves
+                state.clock_turn = state.board_state.turn();
+                state.clock_ply = state.board_state.ply();
+                if let Some((white, black)) = stored.clock {
+                    if let Some(ref mut clock) = state.clock {
+                        clock.set_remaining(white, black);
+                    }
+                    state.low_time = (false, false);
+                    self.model.stream.emit(GroundMsg::ClockChanged(white, black, None));
+                }
+
+                self.position_changed(&mut state);
+                state.queue_damage(&self.drawing_area, &before);
+            },
+            GroundMsg::SetNotation(notation) => {
+                if let (Some(speech), Some(vocabulary)) = (state.speech.as_mut(), Vocabulary::for_language(notation.language())) {
+                    speech.set_vocabulary(vocabulary);
+                }
+                state.keyboard.set_notation(notation);
+                self.drawing_area.queue_draw();
+            },
+            GroundMsg::OfferDraw(color) => {
+                state.board_state.offer_draw(color);
+            },
+            GroundMsg::AcceptDraw(color) => {
+                if state.board_state.accept_draw(color) {
+                    self.report_outcome(&mut state);
+                }
+            },
+            GroundMsg::Resign(color) => {
+                if state.board_state.resign(color) {
+                    self.report_outcome(&mut state);
+                }
+            },
+            GroundMsg::Abort => {
+                if state.board_state.abort() {
+                    self.report_outcome(&mut state);
+                }
+            },
+            GroundMsg::ProposeTakeback(color) => {
+                state.board_state.propose_takeback(color);
+            },
+            GroundMsg::AcceptTakeback(color) => {
+                let before = state.snapshot();
+                if state.board_state.accept_takeback(color) {
+                    state.sync_pieces();
+                    state.press_clock(&self.model.stream);
+                    self.position_changed(&mut state);
+                    state.queue_damage(&self.drawing_area, &before);
+                }
+            },
+            GroundMsg::Undo => {
+                self.step_history(&mut state, BoardState::undo);
+            },
+            GroundMsg::Redo => {
+                self.step_history(&mut state, BoardState::redo);
+            },
+            GroundMsg::SetHistoryLimit(limit) => {
+                state.board_state.set_history_limit(limit);
+            },
+            GroundMsg::SetTablebase(path) => {
+                state.tablebase = path.and_then(|path| {
+                    let mut tablebase = Tablebase::new();
+                    match tablebase.add_directory(path) {
+                        Ok(count) if count > 0 => Some(tablebase),
+                        _ => None,
+                    }
+                });
+                self.report_tablebase(&mut state);
+                self.report_analysis(&mut state);
+                self.drawing_area.queue_draw();
+            },
             _ => {}
         }
+
+        // pockets may have come or gone with the variant
+        self.negotiate_size(&state);
+    }
+}
+
+impl Ground {
+    /// Ask for a minimum size with readable squares and an allocation in
+    /// the aspect ratio of the board and pockets.
+    fn negotiate_size(&self, state: &State) {
+        let rows = board_rows(&state.board_state);
+        let ratio = 9.0 / rows as f32;
+
+        if (self.frame.ratio() - ratio).abs() > f32::EPSILON {
+            self.frame.set(0.5, 0.5, ratio, false);
+        }
+
+        let min = (9 * MIN_SQUARE_SIZE, rows * MIN_SQUARE_SIZE);
+        if self.drawing_area.size_request() != min {
+            self.drawing_area.set_size_request(min.0, min.1);
+        }
+    }
+
+    /// Play the pending premove if it became legal.
+    fn play_premove(&self, state: &mut State) {
+        if let Some((orig, dest)) = state.board_state.premove() {
+            state.board_state.set_premove(None);
+
+            let turn = state.pieces.figurine_at(orig).map(|f| f.piece().color);
+            if turn.is_some() && turn == state.board_state.turn() && state.board_state.valid_move(orig, dest) {
+                let dest = state.board_state.castling_rook(orig, dest).unwrap_or(dest);
+                self.model.stream.emit(GroundMsg::UserMove(orig, dest, None));
+            }
+        }
+    }
+
+    /// Tell the listeners about everything that follows from a new
+    /// position.
+    fn position_changed(&self, state: &mut State) {
+        self.report_outcome(state);
+        self.report_opening(state);
+        self.report_material(state);
+        self.follow_orientation(state);
+        self.report_tablebase(state);
+        self.report_analysis(state);
+    }
+
+    /// Probe the tablebase in few piece positions, emitting
+    /// `TablebaseProbed` and marking the best moves.
+    fn report_tablebase(&self, state: &mut State) {
+        let probed = match (state.tablebase.as_ref(), state.board_state.position()) {
+            (Some(tablebase), Some(VariantPosition::Chess(ref pos))) if tablebase.covers(pos) => {
+                match (tablebase.probe(pos), tablebase.probe_moves(pos)) {
+                    (Ok(probe), Ok(moves)) => Some((probe, moves)),
+                    _ => None,
+                }
+            },
+            _ => None,
+        };
+
+        match probed {
+            Some((probe, moves)) => {
+                state.board_state.set_tablebase_shapes(tablebase_shapes(&moves));
+                self.model.stream.emit(GroundMsg::TablebaseProbed(probe, moves));
+            },
+            None => state.board_state.set_tablebase_shapes(Vec::new()),
+        }
+    }
+
+    /// Emit `OpeningChanged` when the position reached a named opening.
+    /// Leaving the book keeps the last opening, and going back to the
+    /// starting position clears it.
+    fn report_opening(&self, state: &mut State) {
+        let opening = match state.board_state.position() {
+            Some(VariantPosition::Chess(ref pos)) if *pos.board() == Board::new() && pos.turn() == Color::White => None,
+            Some(VariantPosition::Chess(ref pos)) => match state.openings.get(pos) {
+                Some(opening) => Some(opening.clone()),
+                None => return,
+            },
+            _ => None,
+        };
+
+        if opening != state.opening {
+            state.opening = opening.clone();
+            self.model.stream.emit(GroundMsg::OpeningChanged(opening));
+        }
+    }
+
+    /// Turn the board to the side it follows with `SetAutoOrient`.
+    fn follow_orientation(&self, state: &mut State) {
+        if let Some(orientation) = state.board_state.followed_orientation() {
+            if orientation != state.board_state.orientation() {
+                state.set_orientation(orientation);
+                self.drawing_area.queue_draw();
+            }
+        }
+    }
+
+    /// Emit `MaterialChanged` when the captured pieces differ from the
+    /// last report.
+    fn report_material(&self, state: &mut State) {
+        let material = (
+            state.board_state.captured(Color::Black),
+            state.board_state.captured(Color::White),
+            state.board_state.material_advantage(Color::White),
+        );
+
+        if material != state.material {
+            state.material = material.clone();
+            let (white, black, advantage) = material;
+            self.model.stream.emit(GroundMsg::MaterialChanged(white, black, advantage));
+        }
+    }
+
+    /// Emit `GameEnd` when the game just ended, and stop the clock.
+    fn report_outcome(&self, state: &mut State) {
+        if state.report_game_state(&self.model.stream) {
+            self.drawing_area.queue_draw();
+        }
+    }
+
+    /// Check a move against the puzzle being solved. Right moves are
+    /// played, and the reply of the opponent follows after a moment.
+    fn puzzle_move(&self, state: &mut State, orig: Square, dest: Square, promotion: Option<Role>) {
+        let m = match state.board_state.legals().iter().find(|m| {
+            m.from() == Some(orig) && m.to() == dest && m.promotion() == promotion
+        }).cloned() {
+            Some(m) => m,
+            None => return,
+        };
+
+        let verdict = match state.puzzle {
+            Some(ref mut run) => run.try_move(&m),
+            None => return,
+        };
+
+        let reply = match verdict {
+            Verdict::Correct(reply) => reply,
+            Verdict::Solved(reply) => {
+                let run = state.puzzle.take().expect("solving puzzle");
+                self.model.stream.emit(GroundMsg::PuzzleSolved(run.puzzle().id().to_owned(), run.stats()));
+                reply
+            },
+            Verdict::Wrong | Verdict::Failed => {
+                // glide the piece back from the square it was moved to
+                if let Some(figurine) = state.pieces.figurine_at_mut(orig) {
+                    figurine.set_pos(square_to_pos(dest));
+                }
+                if verdict == Verdict::Failed {
+                    let run = state.puzzle.take().expect("solving puzzle");
+                    self.model.stream.emit(GroundMsg::PuzzleFailed(run.puzzle().id().to_owned(), run.stats()));
+                }
+                self.drawing_area.queue_draw();
+                return;
+            },
+        };
+
+        let before = state.snapshot();
+        if state.play(&m) {
+            state.press_clock(&self.model.stream);
+            self.position_changed(state);
+            state.queue_damage(&self.drawing_area, &before);
+        }
+
+        if let Some(reply) = reply {
+            self.schedule_reply(state, reply);
+        }
+    }
+
+    /// Check a move against the line being drilled. Right moves are
+    /// played and answered by the opponent, wrong moves glide back, and
+    /// after a few of them the move of the line is hinted with an arrow.
+    fn drill_move(&self, state: &mut State, orig: Square, dest: Square, promotion: Option<Role>) {
+        let m = match state.board_state.legals().iter().find(|m| {
+            m.from() == Some(orig) && m.to() == dest && m.promotion() == promotion
+        }).cloned() {
+            Some(m) => m,
+            None => return,
+        };
+
+        let recall = match state.drill {
+            Some(ref mut run) => run.try_move(&m),
+            None => return,
+        };
+
+        let reply = match recall {
+            Recall::Correct { fen, quality, reply } => {
+                self.model.stream.emit(GroundMsg::DrillRecalled(fen, quality));
+                reply
+            },
+            Recall::Wrong(expected) => {
+                // glide the piece back from the square it was moved to
+                if let Some(figurine) = state.pieces.figurine_at_mut(orig) {
+                    figurine.set_pos(square_to_pos(dest));
+                }
+                let misses = state.drill.as_ref().map_or(0, |run| run.misses());
+                if misses >= DRILL_HINT_AFTER {
+                    if let Some(from) = expected.from() {
+                        state.board_state.set_shapes(vec![DrawShape::new(from, expected.to(), DrawBrush::Green)]);
+                    }
+                }
+                self.drawing_area.queue_draw();
+                return;
+            },
+        };
+
+        state.board_state.set_shapes(Vec::new());
+        let before = state.snapshot();
+        if state.play(&m) {
+            state.press_clock(&self.model.stream);
+            self.position_changed(state);
+            state.queue_damage(&self.drawing_area, &before);
+        }
+
+        if state.drill.as_ref().map_or(false, |run| run.is_finished()) {
+            let run = state.drill.take().expect("drilling line");
+            self.model.stream.emit(GroundMsg::DrillFinished(run.mistakes()));
+        }
+
+        if let Some(reply) = reply {
+            self.schedule_reply(state, reply);
+        }
+    }
+
+    /// Play a move of the opponent in a puzzle or drill after a moment.
+    fn schedule_reply(&self, state: &mut State, reply: Move) {
+        state.auto_reply = Some(reply.clone());
+
+        let weak_state = Rc::downgrade(&self.model.state);
+        let stream = self.model.stream.clone();
+        glib::timeout_add_local(AUTO_REPLY_DELAY, move || {
+            if let Some(state) = weak_state.upgrade() {
+                // unless another puzzle or drill was set up in the meantime
+                let pending = state.borrow().auto_reply.as_ref() == Some(&reply);
+                if pending {
+                    state.borrow_mut().auto_reply = None;
+                    stream.emit(GroundMsg::PlayMove(reply.clone()));
+                }
+            }
+            Continue(false)
+        });
+    }
+
+    /// Play the moves queued by `CatchUp` one after another, faster than a
+    /// player would.
+    #[cfg(feature = "blockchain")]
+    fn fast_forward(&self) {
+        let weak_state = Rc::downgrade(&self.model.state);
+        let stream = self.model.stream.clone();
+
+        glib::timeout_add_local(CATCH_UP_INTERVAL, move || {
+            let state = match weak_state.upgrade() {
+                Some(state) => state,
+                None => return Continue(false),
+            };
+            let mut state = state.borrow_mut();
+
+            let record = match state.catch_up.pop_front() {
+                Some(record) => record,
+                None => {
+                    state.catching_up = false;
+                    return Continue(false);
+                }
+            };
+
+            let legal = state.board_state.legals().iter().any(|m| {
+                m.from() == Some(record.orig()) && m.to() == record.dest() && m.promotion() == record.promotion()
+            });
+
+            if legal {
+                stream.emit(GroundMsg::RemoteMove(record));
+                Continue(true)
+            } else {
+                state.catch_up.clear();
+                state.catching_up = false;
+                stream.emit(GroundMsg::ResyncFailed(record));
+                Continue(false)
+            }
+        });
+    }
+
+    fn send_lichess(&self, state: &State, orig: Square, dest: Square, promotion: Option<Role>) {
+        if let Some(ref game) = state.lichess {
+            let m = state.board_state.legals().iter().find(|m| {
+                m.from() == Some(orig) && m.to() == dest && m.promotion() == promotion
+            });
+            if let Some(m) = m {
+                game.send_move(Uci::from_move(m, state.board_state.castling_mode()));
+            }
+        }
+    }
+
+    /// Undo or redo a move, gliding the pieces to the restored position.
+    fn step_history(&self, state: &mut State, step: fn(&mut BoardState) -> bool) {
+        let before = state.snapshot();
+        if step(&mut state.board_state) {
+            state.sync_pieces();
+            state.press_clock(&self.model.stream);
+            self.position_changed(state);
+            state.queue_damage(&self.drawing_area, &before);
+        }
+    }
+
+    /// Let the analysis evaluate the position on the board.
+    fn report_analysis(&self, state: &mut State) {
+        if let Some(ref mut analysis) = state.analysis {
+            if let Some(pos) = state.board_state.position() {
+                let parent = state.board_state.previous_position();
+                analysis.analyse(state.board_state.ply(), &pos, parent.as_ref()).ok();
+            }
+        }
+    }
+
+    /// Emit the results of the analysis until it is removed.
+    /// Autosave periodically, so that the clock times are recent, too.
+    fn poll_autosave(&self, interval: Duration) {
+        let weak_state = Rc::downgrade(&self.model.state);
+        let stream = self.model.stream.clone();
+
+        glib::timeout_add_local(interval, move || {
+            let state = match weak_state.upgrade() {
+                Some(state) => state,
+                None => return Continue(false),
+            };
+            let mut state = state.borrow_mut();
+
+            if state.recovery.is_none() {
+                state.autosave_polling = false;
+                return Continue(false);
+            }
+
+            if !state.game_state.is_over() {
+                state.autosave(&stream);
+            }
+            Continue(true)
+        });
+    }
+
+    fn poll_analysis(&self) {
+        let weak_state = Rc::downgrade(&self.model.state);
+        let stream = self.model.stream.clone();
+
+        glib::timeout_add_local(Duration::from_millis(100), move || {
+            let state = match weak_state.upgrade() {
+                Some(state) => state,
+                None => return Continue(false),
+            };
+            let mut state = state.borrow_mut();
+            let state = &mut *state;
+
+            let events = match state.analysis.as_mut().map(Analysis::try_recv) {
+                Some(Ok(events)) => events,
+                Some(Err(_)) | None => {
+                    // the engine is gone
+                    state.analysis = None;
+                    state.analysis_polling = false;
+                    return Continue(false);
+                }
+            };
+
+            for event in events {
+                stream.emit(match event {
+                    AnalysisEvent::Evaluated { ply, score } => GroundMsg::Evaluated(ply, score),
+                    AnalysisEvent::Judged { ply, judgement, loss } => GroundMsg::MoveJudged(ply, judgement, loss),
+                });
+            }
+
+            Continue(true)
+        });
+    }
+
+    /// Let the bot think whenever it is its turn, and send its moves, until
+    /// it is removed.
+    fn poll_bot(&self) {
+        let weak_state = Rc::downgrade(&self.model.state);
+        let stream = self.model.stream.clone();
+
+        glib::timeout_add_local(Duration::from_millis(100), move || {
+            let state = match weak_state.upgrade() {
+                Some(state) => state,
+                None => return Continue(false),
+            };
+            let mut state = state.borrow_mut();
+            let state = &mut *state;
+
+            let bot = match state.bot {
+                Some(ref mut bot) => bot,
+                None => {
+                    state.bot_polling = false;
+                    return Continue(false);
+                }
+            };
+
+            if state.board_state.editing() || state.board_state.game_state().is_over() {
+                return Continue(true);
+            }
+            let pos = match state.board_state.position() {
+                Some(pos) => pos,
+                None => return Continue(true),
+            };
+
+            match bot.try_recv(&pos) {
+                Ok(Some(uci)) => {
+                    if let Ok(m) = uci.to_move(&pos) {
+                        stream.emit(match m {
+                            Move::Put { role, to } => GroundMsg::UserDrop(role, to),
+                            _ => GroundMsg::UserMove(m.from().expect("moves have an origin"), m.to(), m.promotion()),
+                        });
+                    }
+                },
+                Ok(None) => {
+                    bot.think(&pos).ok();
+                },
+                Err(_) => {
+                    // the engine is gone
+                    state.bot = None;
+                    state.bot_polling = false;
+                    return Continue(false);
+                }
+            }
+
+            Continue(true)
+        });
+    }
+
+    /// Forward the events of the Lichess game until it is replaced.
+    fn poll_lichess(&self) {
+        let weak_state = Rc::downgrade(&self.model.state);
+        let stream = self.model.stream.clone();
+
+        glib::timeout_add_local(Duration::from_millis(100), move || {
+            let state = match weak_state.upgrade() {
+                Some(state) => state,
+                None => return Continue(false),
+            };
+            let mut state = state.borrow_mut();
+            let state = &mut *state;
+
+            let game = match state.lichess {
+                Some(ref game) => game,
+                None => {
+                    state.lichess_polling = false;
+                    return Continue(false);
+                }
+            };
+
+            while let Ok(event) = game.try_recv() {
+                stream.emit(GroundMsg::Lichess(event));
+            }
+
+            Continue(true)
+        });
+    }
+
+    /// Show the position of the Lichess game. A single new move is played,
+    /// so that it is animated, anything else sets up the position.
+    fn sync_lichess(&self, state: &mut State, game: &LichessState) {
+        let plies = game.moves.len();
+        if state.lichess_plies == Some(plies) {
+            return;
+        }
+
+        let next = match (state.lichess_plies, game.moves.last(), state.board_state.position()) {
+            (Some(known), Some(uci), Some(pos)) if known + 1 == plies => uci.to_move(&pos).ok(),
+            _ => None,
+        };
+
+        let played = match next {
+            Some(ref m) => state.play(m),
+            None => false,
+        };
+
+        if !played {
+            match replay(&state.lichess_fen, &game.moves) {
+                Some((pos, last)) => {
+                    let mut pos = Pos::new(&pos);
+                    pos.set_last_move(last.as_ref());
+                    state.set_pos(pos);
+                },
+                None => return,
+            }
+        }
+
+        state.lichess_plies = Some(plies);
+        if let Some(ref mut clock) = state.clock {
+            clock.set_remaining(game.wtime, game.btime);
+        }
+        state.press_clock(&self.model.stream);
+        self.play_premove(state);
+        self.position_changed(state);
+        self.drawing_area.queue_draw();
+    }
+
+    #[cfg(feature = "blockchain")]
+    fn sign_move(&self, state: &State, orig: Square, dest: Square, promotion: Option<Role>) {
+        if let Some(ref signer) = state.signer {
+            let record = signer.sign_move(state.board_state.ply(), orig, dest, promotion);
+            self.model.stream.emit(GroundMsg::SignedMove(record));
+        }
+    }
+
+    /// Report the clock times and check for flags until the clock is
+    /// removed.
+    fn tick_clock(&self) {
+        let weak_state = Rc::downgrade(&self.model.state);
+        let stream = self.model.stream.clone();
+        let drawing_area = self.drawing_area.clone();
+
+        glib::timeout_add_local(Duration::from_millis(100), move || {
+            let state = match weak_state.upgrade() {
+                Some(state) => state,
+                None => return Continue(false),
+            };
+            let mut state = state.borrow_mut();
+            let state = &mut *state;
+
+            let flag = match state.clock {
+                Some(ref mut clock) => {
+                    let flag = clock.flag();
+
+                    // warn once per side when the time is running out
+                    if let Some(running) = clock.running() {
+                        let warned = running.fold_wb(&mut state.low_time.0, &mut state.low_time.1);
+                        if !*warned && clock.remaining(running) < LOW_TIME {
+                            *warned = true;
+                            state.audio.play(Sound::LowTime).ok();
+                            if let Some(ref speech) = state.speech {
+                                speech.announce_low_time(running).ok();
+                            }
+                        }
+                    }
+
+                    stream.emit(GroundMsg::ClockChanged(clock.remaining(Color::White),
+                                                        clock.remaining(Color::Black),
+                                                        clock.running()));
+                    flag
+                },
+                None => {
+                    state.ticking = false;
+                    return Continue(false);
+                },
+            };
+
+            if let Some(color) = flag {
+                if state.board_state.flag(color) {
+                    stream.emit(GroundMsg::Flag(color));
+                    state.report_game_state(&stream);
+                    drawing_area.queue_draw();
+                }
+            }
+
+            Continue(true)
+        });
+    }
+
+    /// Show the clock times recorded in the loaded game at a node, unless
+    /// a clock is running.
+    fn replay_clocks(&self, state: &mut State, node: NodeId) {
+        let (white, black) = match state.game.as_ref().map(|game| game.clocks_at(node)) {
+            Some((Some(white), Some(black))) => (white, black),
+            _ => return,
+        };
+
+        if let Some(ref mut clock) = state.clock {
+            if clock.running().is_some() {
+                return;
+            }
+            clock.set_remaining(white, black);
+        }
+        self.model.stream.emit(GroundMsg::ClockChanged(white, black, None));
+    }
+
+    fn go_to_node(&self, state: &mut State, node: NodeId) {
+        if let Some(ply) = state.replay(node) {
+            self.model.stream.emit(GroundMsg::PlyChanged(ply));
+            self.model.stream.emit(GroundMsg::NodeChanged(node));
+            self.replay_clocks(state, node);
+            self.report_opening(state);
+            self.report_material(state);
+            self.follow_orientation(state);
+            self.report_tablebase(state);
+            self.report_analysis(state);
+            self.drawing_area.queue_draw();
+        }
     }
 }
 
 impl Widget for Ground {
-    type Root = DrawingArea;
+    type Root = AspectFrame;
 
     fn root(&self) -> Self::Root {
-        self.drawing_area.clone()
+        self.frame.clone()
     }
 
     fn view(relm: &Relm<Self>, model: Model) -> Self {
@@ -228,25 +1619,44 @@ impl Widget for Ground {
         drawing_area.add_events(EventMask::BUTTON_PRESS_MASK |
                                 EventMask::BUTTON_RELEASE_MASK |
                                 EventMask::POINTER_MOTION_MASK |
-                                EventMask::SCROLL_MASK);
+                                EventMask::LEAVE_NOTIFY_MASK |
+                                EventMask::SCROLL_MASK |
+                                EventMask::KEY_PRESS_MASK |
+                                EventMask::TOUCH_MASK);
+
+        // accept keyboard focus for typing moves
+        drawing_area.set_can_focus(true);
+
+        if let Some(accessible) = drawing_area.accessible() {
+            accessible.set_name("Chessboard");
+        }
 
         {
             // draw
             let weak_state = Rc::downgrade(&model.state);
             drawing_area.connect_draw(move |widget, cr| {
                 if let Some(state) = weak_state.upgrade() {
-                    let state = state.borrow();
+                    let mut state = state.borrow_mut();
                     state.draw(widget, cr).unwrap();
 
-                    // queue next draw for animation
-                    let weak_state = Weak::clone(&weak_state);
-                    let widget = widget.clone();
-                    cairo::glib::idle_add_local(move || {
-                        if let Some(state) = weak_state.upgrade() {
-                            state.borrow_mut().queue_animation(&widget);
-                        }
-                        Continue(false)
-                    });
+                    // advance animations with the frame clock until
+                    // everything came to rest
+                    if !state.animating && state.is_animating() {
+                        state.animating = true;
+
+                        let weak_state = Weak::clone(&weak_state);
+                        widget.add_tick_callback(move |widget, _| {
+                            if let Some(state) = weak_state.upgrade() {
+                                let mut state = state.borrow_mut();
+                                state.queue_animation(widget);
+                                if state.is_animating() {
+                                    return Continue(true);
+                                }
+                                state.animating = false;
+                            }
+                            Continue(false)
+                        });
+                    }
                 }
                 Inhibit(false)
             });
@@ -257,6 +1667,7 @@ impl Widget for Ground {
             let state = Rc::downgrade(&model.state);
             let stream = relm.stream().clone();
             drawing_area.connect_button_press_event(move |widget, e| {
+                widget.grab_focus();
                 if let Some(state) = state.upgrade() {
                     let mut state = state.borrow_mut();
                     state.button_press_event(&stream, widget, e);
@@ -283,42 +1694,576 @@ impl Widget for Ground {
             let state = Rc::downgrade(&model.state);
             let stream = relm.stream().clone();
             drawing_area.connect_motion_notify_event(move |widget, e| {
+                if let Some(strong) = state.upgrade() {
+                    let mut strong = strong.borrow_mut();
+                    let hover = strong.motion_notify_event(&stream, widget, e);
+
+                    // show the hover hints once the pointer rested
+                    if let (Some(hover), Some(delay)) = (hover, strong.board_state.hover_hints()) {
+                        let state = Weak::clone(&state);
+                        let widget = widget.clone();
+                        glib::timeout_add_local(delay, move || {
+                            if let Some(state) = state.upgrade() {
+                                let mut state = state.borrow_mut();
+                                let ctx = WidgetContext::new(&state.board_state, &widget);
+                                state.pieces.show_hover(hover, &ctx);
+                            }
+                            Continue(false)
+                        });
+                    }
+                }
+                Inhibit(false)
+            });
+        }
+
+        {
+            // mouse leave
+            let state = Rc::downgrade(&model.state);
+            drawing_area.connect_leave_notify_event(move |widget, _| {
                 if let Some(state) = state.upgrade() {
                     let mut state = state.borrow_mut();
-                    state.motion_notify_event(&stream, widget, e);
+                    let ctx = WidgetContext::new(&state.board_state, widget);
+                    state.pieces.clear_hover(&ctx);
                 }
                 Inhibit(false)
             });
         }
 
+        {
+            // scroll
+            let state = Rc::downgrade(&model.state);
+            let stream = relm.stream().clone();
+            drawing_area.connect_scroll_event(move |widget, e| {
+                if let Some(state) = state.upgrade() {
+                    let mut state = state.borrow_mut();
+                    state.scroll_event(&stream, widget, e)
+                } else {
+                    Inhibit(false)
+                }
+            });
+        }
+
+        {
+            // touch, which replaces the emulated mouse events
+            let state = Rc::downgrade(&model.state);
+            let stream = relm.stream().clone();
+            drawing_area.connect_touch_event(move |widget, e| {
+                if let Some(state) = state.upgrade() {
+                    let mut state = state.borrow_mut();
+                    if let Ok(e) = e.clone().downcast::<EventTouch>() {
+                        return state.touch_event(&stream, widget, &e);
+                    }
+                }
+                Inhibit(false)
+            });
+        }
+
+        {
+            // describe the hovered square, or the square in focus when
+            // navigating with the keyboard
+            let state = Rc::downgrade(&model.state);
+            drawing_area.connect_query_tooltip(move |widget, x, y, keyboard_mode, tooltip| {
+                let state = match state.upgrade() {
+                    Some(state) => state,
+                    None => return false,
+                };
+                let state = state.borrow();
+
+                let text = if keyboard_mode {
+                    state.access.describe(&state.board_state)
+                } else {
+                    let ctx = WidgetContext::new(&state.board_state, widget);
+                    let alloc = widget.allocation();
+                    let pos = ctx.invert_pos((f64::from(x + alloc.x()), f64::from(y + alloc.y())));
+                    pos_to_square(pos).map(|square| describe_square(&state.board_state, square))
+                };
+
+                match text {
+                    Some(text) => {
+                        tooltip.set_text(Some(&text));
+                        true
+                    },
+                    None => false,
+                }
+            });
+        }
+
+        {
+            // key press
+            let state = Rc::downgrade(&model.state);
+            let stream = relm.stream().clone();
+            drawing_area.connect_key_press_event(move |widget, e| {
+                if let Some(state) = state.upgrade() {
+                    let mut state = state.borrow_mut();
+                    state.key_press_event(&stream, widget, e)
+                } else {
+                    Inhibit(false)
+                }
+            });
+        }
+
+        {
+            // repaint everything when resized, since the squares snap to
+            // whole pixels, and rasterize the pieces for the new size
+            let state = Rc::downgrade(&model.state);
+            drawing_area.connect_size_allocate(move |widget, _| {
+                if let Some(state) = state.upgrade() {
+                    state.borrow().board_state.piece_set().clear_cache();
+                }
+                widget.queue_draw();
+            });
+        }
+
+        {
+            // moved to a screen with a different scale factor
+            let state = Rc::downgrade(&model.state);
+            drawing_area.connect_scale_factor_notify(move |widget| {
+                if let Some(state) = state.upgrade() {
+                    state.borrow().board_state.piece_set().clear_cache();
+                }
+                widget.queue_draw();
+            });
+        }
+
         drawing_area.set_hexpand(true);
         drawing_area.set_vexpand(true);
         drawing_area.show();
 
-        Ground {
+        // keep the allocation in the shape of the board, centered in the
+        // available space
+        let frame = AspectFrame::new(None, 0.5, 0.5, 1.0, false);
+        frame.set_shadow_type(ShadowType::None);
+        frame.set_hexpand(true);
+        frame.set_vexpand(true);
+        frame.add(&drawing_area);
+        frame.show();
+
+        let ground = Ground {
+            frame,
             drawing_area,
             model,
-        }
+        };
+        ground.negotiate_size(&ground.model.state.borrow());
+        ground
     }
 }
 
+/// Remaining time below which the low time sound is played.
+const LOW_TIME: Duration = Duration::from_secs(10);
+
+/// Pause before the opponent replies in a puzzle or drill, so that the move of the
+/// trainee can be seen first.
+const AUTO_REPLY_DELAY: Duration = Duration::from_millis(500);
+
+/// Wrong moves at a ply of a drilled line before the right one is shown.
+const DRILL_HINT_AFTER: u32 = 2;
+
+/// Time between the moves played by `CatchUp`.
+#[cfg(feature = "blockchain")]
+const CATCH_UP_INTERVAL: Duration = Duration::from_millis(120);
+
+/// A second touch within this time after the first is a palm.
+const PALM: Duration = Duration::from_millis(150);
+
+/// Holding a finger on a square for this long makes a long press.
+const LONG_PRESS: Duration = Duration::from_millis(500);
+
+/// The finger followed as the pointer.
+struct TouchPoint {
+    sequence: EventSequence,
+    began: Instant,
+    square: Option<Square>,
+    still_since: Instant,
+    cancelled: bool,
+}
+
+/// How the board looked before a change, to find the squares that need
+/// to be redrawn.
+struct Snapshot {
+    board: Board,
+    pockets: Option<Material>,
+    highlighted: Bitboard,
+    turn: Option<Color>,
+    shapes: Vec<DrawShape>,
+}
+
 struct State {
     board_state: BoardState,
     drawable: Drawable,
     promotable: Promotable,
+    pockets: Pockets,
+    keyboard: Keyboard,
+    access: Access,
     pieces: Pieces,
+    /// Shared by the pieces, the promotion dialog, the clock and touches.
+    time_source: Arc<dyn TimeSource>,
+    game: Option<Game>,
+    node: Option<NodeId>,
+    #[cfg(feature = "blockchain")]
+    signer: Option<MoveSigner>,
+    clock: Option<Clock>,
+    low_time: (bool, bool),
+    /// A frame clock tick callback is advancing the animations.
+    animating: bool,
+    audio: Audio,
+    speech: Option<Speech>,
+    openings: Openings,
+    opening: Option<Opening>,
+    /// Pieces captured by white and black, and the material advantage of
+    /// white, as last reported.
+    material: (Vec<Role>, Vec<Role>, i32),
+    tablebase: Option<Tablebase>,
+    lichess: Option<LichessGame>,
+    lichess_fen: String,
+    lichess_plies: Option<usize>,
+    lichess_polling: bool,
+    touch: Option<TouchPoint>,
+    clock_turn: Option<Color>,
+    /// Game ply when the clock was last switched.
+    clock_ply: u32,
+    /// Side whose clock stands still.
+    untimed: Option<Color>,
+    ticking: bool,
+    game_state: GameState,
+    blindfold: Blindfold,
+    puzzle: Option<PuzzleRun>,
+    drill: Option<DrillRun>,
+    /// Reply of the opponent in the puzzle or drill, waiting to be played.
+    auto_reply: Option<Move>,
+    analysis: Option<Analysis>,
+    analysis_polling: bool,
+    bot: Option<Bot>,
+    bot_polling: bool,
+    /// Moves waiting to be played by `CatchUp`.
+    #[cfg(feature = "blockchain")]
+    catch_up: VecDeque<MoveRecord>,
+    #[cfg(feature = "blockchain")]
+    catching_up: bool,
+    recovery: Option<Recovery>,
+    autosave_polling: bool,
 }
 
 impl State {
     fn new() -> State {
+        let time_source = real_time();
+
         State {
             board_state: BoardState::new(),
             drawable: Drawable::new(),
-            promotable: Promotable::new(),
-            pieces: Pieces::new(),
+            promotable: Promotable::new().with_time_source(Arc::clone(&time_source)),
+            pockets: Pockets::new(),
+            keyboard: Keyboard::new(),
+            access: Access::new(),
+            pieces: Pieces::new().with_time_source(Arc::clone(&time_source)),
+            time_source,
+            game: None,
+            node: None,
+            #[cfg(feature = "blockchain")]
+            signer: None,
+            clock: None,
+            low_time: (false, false),
+            animating: false,
+            audio: Audio::new(SoundSet::new()),
+            speech: None,
+            openings: Openings::new(),
+            opening: None,
+            material: (Vec::new(), Vec::new(), 0),
+            tablebase: None,
+            lichess: None,
+            lichess_fen: "startpos".to_owned(),
+            lichess_plies: None,
+            lichess_polling: false,
+            touch: None,
+            clock_turn: None,
+            clock_ply: 0,
+            untimed: None,
+            ticking: false,
+            game_state: GameState::Ongoing,
+            blindfold: Blindfold::Off,
+            puzzle: None,
+            drill: None,
+            auto_reply: None,
+            analysis: None,
+            analysis_polling: false,
+            bot: None,
+            bot_polling: false,
+            #[cfg(feature = "blockchain")]
+            catch_up: VecDeque::new(),
+            #[cfg(feature = "blockchain")]
+            catching_up: false,
+            recovery: None,
+            autosave_polling: false,
+        }
+    }
+
+    /// Add the judgement to the main line move of the loaded game that
+    /// leads to the game ply.
+    fn record_judgement(&mut self, ply: u32, judgement: Judgement) {
+        let game = match self.game {
+            Some(ref mut game) => game,
+            None => return,
+        };
+
+        let initial = game.initial_position();
+        let first = 2 * (u32::from(initial.fullmoves()) - 1) + initial.turn().fold_wb(0, 1);
+        let index = match ply.checked_sub(first) {
+            Some(index) if index > 0 && (index as usize) <= game.len() => index as usize,
+            _ => return,
+        };
+
+        let node = game.node_at(index);
+        if let Some(m) = game.tree_mut().game_move_mut(node) {
+            if !m.nags().contains(&judgement.nag()) {
+                m.push_nag(judgement.nag());
+            }
+        }
+    }
+
+    /// Emit `GameEnd` when the game just ended, and stop the clock and
+    /// any interaction in progress. Returns whether it ended.
+    fn report_game_state(&mut self, stream: &Stream) -> bool {
+        let game_state = self.board_state.game_state();
+        if game_state == self.game_state {
+            return false;
+        }
+        self.game_state = game_state;
+
+        if !game_state.is_over() {
+            return false;
+        }
+
+        if let Some(ref mut clock) = self.clock {
+            clock.stop();
+        }
+        self.board_state.set_premove(None);
+        self.pieces.cancel();
+        self.promotable.cancel();
+        self.keyboard.cancel();
+        self.access.cancel();
+        self.audio.play(Sound::GameEnd).ok();
+        if let Some(ref speech) = self.speech {
+            speech.announce_end(game_state).ok();
+        }
+        if let Some(Err(err)) = self.recovery.as_ref().map(Recovery::discard) {
+            stream.emit(GroundMsg::AutosaveFailed(err.to_string()));
+        }
+        stream.emit(GroundMsg::GameEnd(game_state));
+        true
+    }
+
+    fn set_orientation(&mut self, orientation: Color) {
+        if self.board_state.orientation() != orientation {
+            self.board_state.set_orientation(orientation);
+            self.pieces.flip();
+        }
+    }
+
+    fn set_pos(&mut self, pos: Pos) {
+        self.pieces.set_board(&pos.setup.board);
+        self.board_state.set_setup(pos.setup);
+        self.promotable.update(&pos.legals);
+        self.board_state.set_check(pos.check);
+        self.board_state.set_last_move(pos.last_move);
+        self.board_state.set_turn(pos.turn);
+        *self.board_state.legals_mut() = *pos.legals;
+    }
+
+    fn set_chess960(&mut self, number: u32) {
+        self.board_state.set_chess960(number);
+        self.pieces.set_board(self.board_state.board());
+        self.promotable.cancel();
+        self.pockets.cancel();
+    }
+
+    fn set_variant(&mut self, variant: Variant) {
+        self.board_state.set_variant(variant);
+        self.pieces.set_board(self.board_state.board());
+        self.promotable.cancel();
+        self.pockets.cancel();
+    }
+
+    /// Animate the figurines to the position of the board state.
+    fn sync_pieces(&mut self) {
+        self.pieces.set_board(self.board_state.board());
+        self.promotable.update(self.board_state.legals());
+    }
+
+    /// Put the figurines on the position of the board state right away.
+    fn jump_pieces(&mut self) {
+        self.pieces.jump_to_board(self.board_state.board());
+        self.promotable.update(self.board_state.legals());
+    }
+
+    fn play(&mut self, m: &Move) -> bool {
+        // keep the premove, so that it can be played in reply
+        let premove = self.board_state.premove();
+        if self.board_state.play(m).is_err() {
+            return false;
+        }
+        self.board_state.set_premove(premove);
+
+        let (check, checkmate) = self.board_state.position().map_or((false, false), |pos| (pos.is_check(), pos.is_checkmate()));
+        self.audio.play(Sound::of_move(m, check)).ok();
+        if let Some(ref speech) = self.speech {
+            speech.announce_move(m, check, checkmate).ok();
+        }
+
+        self.pieces.set_board(self.board_state.board());
+        self.promotable.update(self.board_state.legals());
+        true
+    }
+
+    /// Switch the clock to the side to move, if the turn changed, and
+    /// emit `MoveTimed` and autosave for a new move.
+    fn press_clock(&mut self, stream: &Stream) {
+        let turn = self.board_state.turn();
+        if turn == self.clock_turn {
+            return;
+        }
+        self.clock_turn = turn;
+
+        // taking back a move also switches the clock
+        let ply = self.board_state.ply();
+        let advanced = ply > self.clock_ply;
+        self.clock_ply = ply;
+
+        if let (Some(clock), Some(turn)) = (self.clock.as_mut(), turn) {
+            match clock.running() {
+                Some(running) if running != turn => {
+                    if let Some(elapsed) = clock.press() {
+                        if advanced {
+                            stream.emit(GroundMsg::MoveTimed(ply, elapsed, clock.remaining(running)));
+                        }
+                    }
+                },
+                None => clock.start(turn),
+                _ => {}
+            }
+        }
+
+        if advanced {
+            self.autosave(stream);
+        }
+    }
+
+    /// Write the game on the board to the recovery file, if autosaving
+    /// and any move was played.
+    fn autosave(&self, stream: &Stream) {
+        let recovery = match self.recovery {
+            Some(ref recovery) => recovery,
+            None => return,
+        };
+        let game = match self.board_state.to_game() {
+            Some(ref game) if game.is_empty() => return,
+            Some(game) => game,
+            None => return,
+        };
+
+        let mut stored = recovery.stored_game(game);
+        stored.clock = self.clock.as_ref().map(|clock| (clock.remaining(Color::White), clock.remaining(Color::Black)));
+        if let Err(err) = recovery.save(&mut stored) {
+            stream.emit(GroundMsg::AutosaveFailed(err.to_string()));
+        }
+    }
+
+    fn set_fen(&mut self, fen: &str) -> bool {
+        if self.board_state.set_fen(fen).is_err() {
+            return false;
+        }
+
+        self.pieces.set_board(self.board_state.board());
+        self.promotable.update(self.board_state.legals());
+        true
+    }
+
+    /// The node after `ply` plies of the line through the current node.
+    fn node_at(&self, ply: usize) -> Option<NodeId> {
+        let tree = self.game.as_ref()?.tree();
+        let current = self.node.filter(|&n| tree.contains(n)).unwrap_or_else(|| tree.root());
+        let line = tree.path(tree.end(current));
+        Some(match ply.checked_sub(1) {
+            Some(index) => line.get(index).or_else(|| line.last()).cloned().unwrap_or_else(|| tree.root()),
+            None => tree.root(),
+        })
+    }
+
+    /// Show the position at a node of the loaded game, returning its ply.
+    fn replay(&mut self, node: NodeId) -> Option<usize> {
+        let (pos, ply) = {
+            let tree = self.game.as_ref()?.tree();
+            if !tree.contains(node) {
+                return None;
+            }
+            let mut pos = Pos::new(&tree.position_at(node));
+            pos.set_last_move(tree.game_move(node).map(GameMove::mv));
+            (pos, tree.ply(node))
+        };
+
+        self.node = Some(node);
+        self.set_pos(pos);
+        Some(ply)
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            board: self.board_state.board().clone(),
+            pockets: self.board_state.pockets().cloned(),
+            highlighted: self.board_state.highlighted(),
+            turn: self.board_state.turn(),
+            shapes: self.all_shapes(),
         }
     }
 
+    fn all_shapes(&self) -> Vec<DrawShape> {
+        self.board_state.tablebase_shapes().iter()
+            .chain(self.board_state.engine_shapes())
+            .chain(self.board_state.shapes())
+            .cloned()
+            .collect()
+    }
+
+    /// Queue a redraw of only the parts of the board that changed since
+    /// `before`. Moving figurines queue their own path while animating.
+    fn queue_damage(&self, drawing_area: &DrawingArea, before: &Snapshot) {
+        // arrows and pockets may cross the whole board, and so may the
+        // squares attacked after a move
+        if before.shapes != self.all_shapes() || before.pockets.as_ref() != self.board_state.pockets() ||
+           self.board_state.threats() {
+            drawing_area.queue_draw();
+            return;
+        }
+
+        let ctx = WidgetContext::new(&self.board_state, drawing_area);
+
+        let board = self.board_state.board();
+        let changed: Bitboard = (before.board.occupied() | board.occupied()).into_iter()
+            .filter(|&sq| before.board.piece_at(sq) != board.piece_at(sq))
+            .collect();
+
+        ctx.queue_draw_squares(changed | before.highlighted | self.board_state.highlighted());
+
+        // the turn indicator sits in the corners of the border
+        if before.turn != self.board_state.turn() {
+            ctx.queue_draw_rect(8.0, 8.0, 0.5, 0.5);
+            ctx.queue_draw_rect(8.0, -0.5, 0.5, 0.5);
+        }
+    }
+
+    fn set_view_only(&mut self, view_only: bool) {
+        self.board_state.set_view_only(view_only);
+        if view_only {
+            self.pieces.cancel();
+            self.promotable.cancel();
+            self.keyboard.cancel();
+            self.access.cancel();
+        self.access.cancel();
+        }
+    }
+
+    fn is_animating(&self) -> bool {
+        self.pieces.is_animating() || self.promotable.is_animating()
+    }
+
     fn queue_animation(&mut self, drawing_area: &DrawingArea) {
         let ctx = WidgetContext::new(&self.board_state, drawing_area);
         self.pieces.queue_animation(&ctx);
@@ -329,58 +2274,244 @@ impl State {
         let ctx = WidgetContext::new(&self.board_state, drawing_area);
         cr.set_matrix(ctx.matrix());
 
+        // only the exposed region needs to be painted
+        let clip = cr.clip_extents()?;
+
         // draw
         self.board_state.draw(cr)?;
-        self.pieces.draw(cr, &self.board_state, &self.promotable)?;
-        self.drawable.draw(cr)?;
-        self.pieces.draw_drag(cr, &self.board_state)?;
+        self.pieces.draw(cr, &self.board_state, &self.promotable, self.blindfold, clip)?;
+        self.drawable.draw(cr, &self.board_state)?;
+        self.pockets.draw(cr, &self.board_state)?;
+        self.pieces.draw_drag(cr, &self.board_state, self.blindfold)?;
+        self.pockets.draw_drag(cr, &self.board_state)?;
         self.promotable.draw(cr, &self.board_state)?;
+        self.access.draw(cr, &self.board_state)?;
+        self.keyboard.draw(cr, &self.board_state)?;
 
         Ok(())
     }
 
+    fn key_press_event(&mut self, stream: &Stream, drawing_area: &DrawingArea, e: &EventKey) -> Inhibit {
+        if self.board_state.editing() {
+            return Inhibit(false);
+        }
+
+        if e.keyval() == key::Escape {
+            let ctx = WidgetContext::new(&self.board_state, drawing_area);
+            if self.promotable.cancel_to_origin(&mut self.pieces, &ctx) {
+                return Inhibit(true);
+            }
+        }
+
+        // while typing a move, Enter submits it
+        if !self.keyboard.is_active() {
+            if let Inhibit(true) = self.access.key_press(&self.board_state, stream, e) {
+                self.access.announce(&self.board_state, drawing_area);
+                drawing_area.trigger_tooltip_query();
+                drawing_area.queue_draw();
+                return Inhibit(true);
+            }
+        }
+
+        let inhibit = self.keyboard.key_press(&self.board_state, stream, e);
+        if let Inhibit(true) = inhibit {
+            drawing_area.queue_draw();
+        }
+        inhibit
+    }
+
     fn button_release_event(&mut self, stream: &Stream, drawing_area: &DrawingArea, e: &EventButton) {
+        let ctx = EventContext::new(&self.board_state, stream, drawing_area, e.position()).with_button(e);
+        self.mouse_up(&ctx);
+    }
+
+    /// Returns the id of a hover that just started, see
+    /// `Pieces::hover_mouse_move()`.
+    fn motion_notify_event(&mut self, stream: &Stream, drawing_area: &DrawingArea, e: &EventMotion) -> Option<usize> {
         let ctx = EventContext::new(&self.board_state, stream, drawing_area, e.position());
-        self.pieces.drag_mouse_up(&ctx);
-        self.drawable.mouse_up(&ctx);
+        self.mouse_move(&ctx);
+        self.pieces.hover_mouse_move(&self.board_state, &ctx)
     }
 
-    fn motion_notify_event(&mut self, stream: &Stream, drawing_area: &DrawingArea, e: &EventMotion) {
+    fn scroll_event(&mut self, stream: &Stream, drawing_area: &DrawingArea, e: &EventScroll) -> Inhibit {
+        let forward = match e.direction() {
+            ScrollDirection::Down => true,
+            ScrollDirection::Up => false,
+            ScrollDirection::Smooth if e.delta().1 > 0.0 => true,
+            ScrollDirection::Smooth if e.delta().1 < 0.0 => false,
+            _ => return Inhibit(false),
+        };
+
+        // while dragging the wheel picks the promotion piece
         let ctx = EventContext::new(&self.board_state, stream, drawing_area, e.position());
-        self.promotable.mouse_move(&ctx);
-        self.pieces.drag_mouse_move(&ctx);
-        self.drawable.mouse_move(&ctx);
+        if self.pieces.is_dragging() {
+            return self.pieces.drag_scroll(&self.board_state, self.promotable.order(), &ctx, forward);
+        }
+
+        // otherwise it steps through the loaded game, animating the moves
+        if self.game.is_none() || self.pockets.is_dragging() || self.promotable.is_open() {
+            return Inhibit(false);
+        }
+
+        stream.emit(if forward { GroundMsg::StepForward } else { GroundMsg::StepBackward });
+        Inhibit(true)
     }
 
     fn button_press_event(&mut self, stream: &Stream, drawing_area: &DrawingArea, e: &EventButton) {
+        let ctx = EventContext::new(&self.board_state, stream, drawing_area, e.position()).with_button(e);
+        self.mouse_down(&ctx);
+    }
+
+    /// Touches are handled like the left mouse button. Only one finger
+    /// is followed. A second finger coming down right after the first is
+    /// taken as a palm resting on the screen, and cancels the touch.
+    fn touch_event(&mut self, stream: &Stream, drawing_area: &DrawingArea, e: &EventTouch) -> Inhibit {
+        let sequence = match e.event_sequence() {
+            Some(sequence) => sequence,
+            None => return Inhibit(false),
+        };
+        let now = self.time_source.now();
         let ctx = EventContext::new(&self.board_state, stream, drawing_area, e.position());
+
+        match e.event_type() {
+            EventType::TouchBegin => {
+                if let Some(ref touch) = self.touch {
+                    if now.duration_since(touch.began) < PALM {
+                        self.cancel_touch(drawing_area);
+                    }
+                    return Inhibit(true);
+                }
+
+                // ignore contacts at the edge of the widget, away from the
+                // board and the pockets
+                let (x, y) = ctx.pos();
+                if x < -0.5 || x > 8.5 || y < -1.5 || y > 9.5 {
+                    return Inhibit(false);
+                }
+
+                self.touch = Some(TouchPoint {
+                    sequence,
+                    began: now,
+                    square: ctx.square(),
+                    still_since: now,
+                    cancelled: false,
+                });
+                self.mouse_down(&ctx.with_touch(false));
+            },
+            EventType::TouchUpdate => {
+                match self.touch {
+                    Some(ref mut touch) if touch.sequence == sequence && !touch.cancelled => {
+                        if touch.square != ctx.square() {
+                            touch.square = ctx.square();
+                            touch.still_since = now;
+                        }
+                    },
+                    _ => return Inhibit(false),
+                }
+                self.mouse_move(&ctx.with_touch(false));
+            },
+            EventType::TouchEnd | EventType::TouchCancel => {
+                let touch = match self.touch.take() {
+                    Some(touch) if touch.sequence == sequence => touch,
+                    other => {
+                        self.touch = other;
+                        return Inhibit(false);
+                    }
+                };
+
+                if touch.cancelled || e.event_type() == EventType::TouchCancel {
+                    self.pieces.cancel();
+                    self.pockets.cancel();
+                    drawing_area.queue_draw();
+                } else {
+                    let long_press = now.duration_since(touch.still_since) >= LONG_PRESS;
+                    self.mouse_up(&ctx.with_touch(!long_press));
+                }
+            },
+            _ => return Inhibit(false),
+        }
+
+        Inhibit(true)
+    }
+
+    fn cancel_touch(&mut self, drawing_area: &DrawingArea) {
+        if let Some(ref mut touch) = self.touch {
+            touch.cancelled = true;
+        }
+        self.pieces.cancel();
+        self.pockets.cancel();
+        drawing_area.queue_draw();
+    }
+
+    fn mouse_up(&mut self, ctx: &EventContext) {
+        if !self.board_state.view_only() {
+            self.pockets.mouse_up(&self.board_state, ctx);
+            self.pieces.drag_mouse_up(&mut self.board_state, ctx);
+        }
+        self.drawable.mouse_up(&mut self.board_state, ctx);
+    }
+
+    fn mouse_move(&mut self, ctx: &EventContext) {
+        if !self.board_state.view_only() {
+            self.promotable.mouse_move(ctx);
+            self.pockets.mouse_move(ctx);
+            self.pieces.drag_mouse_move(&self.board_state, ctx);
+        }
+        self.drawable.mouse_move(ctx);
+    }
+
+    fn mouse_down(&mut self, ctx: &EventContext) {
+        if self.board_state.view_only() {
+            self.drawable.mouse_down(&mut self.board_state, ctx);
+            return;
+        }
+
         let promotable = &mut self.promotable;
         let pieces = &mut self.pieces;
 
-        if let Inhibit(false) = promotable.mouse_down(pieces, &ctx) {
-            pieces.selection_mouse_down(&ctx, e);
-            pieces.drag_mouse_down(&ctx, e);
-            self.drawable.mouse_down(&ctx, e);
+        if let Inhibit(false) = promotable.mouse_down(pieces, ctx) {
+            if let Inhibit(false) = self.pockets.mouse_down(&self.board_state, ctx) {
+                pieces.selection_mouse_down(&mut self.board_state, ctx);
+                pieces.drag_mouse_down(&self.board_state, ctx);
+                self.drawable.mouse_down(&mut self.board_state, ctx);
+            }
         }
     }
 }
 
+/// Height of the widget in squares, with the border and the pockets.
+fn board_rows(board_state: &BoardState) -> i32 {
+    if board_state.pockets().is_some() { 11 } else { 9 }
+}
+
+/// Smallest size of a square in pixels that the widget asks for.
+const MIN_SQUARE_SIZE: i32 = 20;
+
 pub(crate) struct WidgetContext<'a> {
     matrix: Matrix,
     drawing_area: &'a DrawingArea,
 }
 
 impl<'a> WidgetContext<'a> {
-    fn new(board_state: &'a BoardState, drawing_area: &'a DrawingArea) -> WidgetContext<'a>
+    fn new(board_state: &BoardState, drawing_area: &'a DrawingArea) -> WidgetContext<'a>
     {
         let alloc = drawing_area.allocation();
-        let size = max(min(alloc.width(), alloc.height()), 9);
+
+        // leave room for the pockets above and below the board
+        let rows = f64::from(board_rows(board_state));
+        let scale = (f64::from(max(alloc.width(), 9)) / 9.0).min(f64::from(max(alloc.height(), 9)) / rows);
+
+        // whole pixels per square and a pixel aligned border keep the
+        // edges of the squares crisp
+        let scale = scale.floor().max(1.0);
+        let left = (f64::from(alloc.width()) / 2.0 - 4.5 * scale).round();
+        let top = (f64::from(alloc.height()) / 2.0 - rows / 2.0 * scale).round();
 
         let mut matrix = Matrix::identity();
         matrix.translate(f64::from(alloc.x()), f64::from(alloc.y()));
 
-        matrix.translate(f64::from(alloc.width()) / 2.0, f64::from(alloc.height()) / 2.0);
-        matrix.scale(f64::from(size) / 9.0, f64::from(size) / 9.0);
+        matrix.translate(left + 4.5 * scale, top + rows / 2.0 * scale);
+        matrix.scale(scale, scale);
         matrix.rotate(board_state.orientation().fold_wb(0.0, PI));
         matrix.translate(-4.0, -4.0);
 
@@ -401,6 +2532,12 @@ impl<'a> WidgetContext<'a> {
         self.drawing_area.queue_draw()
     }
 
+    pub fn queue_draw_squares(&self, squares: Bitboard) {
+        for square in squares {
+            self.queue_draw_square(square);
+        }
+    }
+
     pub fn queue_draw_square(&self, square: Square) {
         self.queue_draw_rect(file_to_float(square.file()), 7.0 - rank_to_float(square.rank()), 1.0, 1.0);
     }
@@ -429,10 +2566,14 @@ pub(crate) struct EventContext<'a> {
     stream: &'a Stream,
     pos: (f64, f64),
     square: Option<Square>,
+    button: u32,
+    modifiers: ModifierType,
+    touch: bool,
+    quick_release: bool,
 }
 
 impl<'a> EventContext<'a> {
-    fn new(board_state: &'a BoardState,
+    fn new(board_state: &BoardState,
            stream: &'a Stream,
            drawing_area: &'a DrawingArea,
            pos: (f64, f64)) -> EventContext<'a>
@@ -448,9 +2589,27 @@ impl<'a> EventContext<'a> {
             stream,
             pos,
             square,
+            button: 0,
+            modifiers: ModifierType::empty(),
+            touch: false,
+            quick_release: false,
         }
     }
 
+    fn with_button(mut self, e: &EventButton) -> EventContext<'a> {
+        self.button = e.button();
+        self.modifiers = e.state();
+        self
+    }
+
+    /// A touch acts like the left mouse button.
+    fn with_touch(mut self, quick_release: bool) -> EventContext<'a> {
+        self.button = 1;
+        self.touch = true;
+        self.quick_release = quick_release;
+        self
+    }
+
     pub fn widget(&self) -> &WidgetContext<'a> {
         &self.widget
     }
@@ -466,4 +2625,22 @@ impl<'a> EventContext<'a> {
     pub fn square(&self) -> Option<Square> {
         self.square
     }
+
+    pub fn button(&self) -> u32 {
+        self.button
+    }
+
+    pub fn modifiers(&self) -> ModifierType {
+        self.modifiers
+    }
+
+    pub fn is_touch(&self) -> bool {
+        self.touch
+    }
+
+    /// Whether the finger was lifted without resting on the square for a
+    /// while, i.e. without a long press.
+    pub fn quick_release(&self) -> bool {
+        self.quick_release
+    }
 }
@@ -0,0 +1,563 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Board widget for GTK4.
+//!
+//! A plain board with the squares, highlights and pieces, and moves by
+//! dragging or clicking. It shares `BoardState` and the drawing code with
+//! `Ground`, but records into a snapshot instead of painting with cairo.
+//! Animations, pockets, shapes and the keyboard and screen reader input
+//! are only in the GTK3 widget. Promotions offer the pieces in a column
+//! like `Ground` does, unless auto queen is enabled.
+//!
+//! GTK3 and GTK4 can not be loaded into the same process, so this module
+//! requires building without the `gtk3` feature.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk4::{cairo, gdk, glib, graphene, gsk};
+use gtk4::prelude::*;
+use gtk4::subclass::prelude::*;
+
+use shakmaty::{Color, Move, Piece, Rank, Role, Square};
+use shakmaty::fen::ParseFenError;
+
+use boardstate::{BoardState, MovableSide};
+use game::IllegalMoveError;
+use pieceset::PieceSet;
+use renderer::Renderer;
+use theme::{Rgba, Theme};
+use util::{pos_to_square, square_to_pos};
+
+/// Size of the dragged piece relative to a square.
+const DRAG_SCALE: f64 = 1.5;
+
+/// Distance in squares before a press turns into a drag.
+const DRAG_THRESHOLD: f64 = 0.1;
+
+/// Order of the pieces offered when promoting, from the promotion square
+/// towards the center of the board.
+const PROMOTION_ORDER: [Role; 6] = [Role::Queen, Role::Rook, Role::Bishop, Role::Knight, Role::King, Role::Pawn];
+
+type UserMoveHandler = Rc<dyn Fn(&Gtk4Board, &Move)>;
+
+struct Drag {
+    square: Square,
+    piece: Piece,
+    start: (f64, f64),
+    pos: (f64, f64),
+    threshold: bool,
+}
+
+/// A pawn move waiting for the choice of a piece.
+struct Promoting {
+    color: Color,
+    orig: Square,
+    dest: Square,
+    roles: Vec<Role>,
+}
+
+impl Promoting {
+    /// The square showing the role at `offset` in the list, going from
+    /// the promotion square towards the center of the board.
+    fn square_at(&self, offset: usize) -> Square {
+        let towards = Color::from_white(self.dest.rank() > Rank::Fourth);
+        let rank = i8::from(self.dest.rank()) - towards.fold_wb(offset as i8, -(offset as i8));
+        Square::from_coords(self.dest.file(), Rank::new(rank as u32))
+    }
+
+    fn role_at(&self, square: Square) -> Option<Role> {
+        self.roles.iter()
+            .enumerate()
+            .find(|&(offset, _)| self.square_at(offset) == square)
+            .map(|(_, role)| *role)
+    }
+
+    fn draw(&self, r: &dyn Renderer, state: &BoardState) -> Result<(), cairo::Error> {
+        // make the board darker
+        r.fill_rect(0.0, 0.0, 8.0, 8.0, Rgba::new(0.0, 0.0, 0.0, 0.5))?;
+
+        for (offset, role) in self.roles.iter().enumerate() {
+            let square = self.square_at(offset);
+            if square.is_light() {
+                r.fill_square(square, Rgba::new(0.25, 0.25, 0.25, 1.0))?;
+            } else {
+                r.fill_square(square, Rgba::new(0.18, 0.18, 0.18, 1.0))?;
+            }
+            r.fill_circle(square_to_pos(square), 0.5, Rgba::new(0.69, 0.69, 0.69, 1.0))?;
+            r.piece(state.piece_set(), role.of(self.color), square_to_pos(square), 2f64.sqrt() * 0.5, state.orientation())?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Model {
+    state: BoardState,
+    selected: Option<Square>,
+    drag: Option<Drag>,
+    promoting: Option<Promoting>,
+}
+
+impl Default for Model {
+    fn default() -> Model {
+        Model {
+            state: BoardState::new(),
+            selected: None,
+            drag: None,
+            promoting: None,
+        }
+    }
+}
+
+impl Model {
+    fn draw(&self, r: &dyn Renderer) -> Result<(), cairo::Error> {
+        let state = &self.state;
+        state.draw_board(r)?;
+        state.draw_marks(r)?;
+
+        if let Some(selected) = self.selected {
+            r.fill_square(selected, state.theme().selected)?;
+
+            if state.move_hints() {
+                let occupied = state.board().occupied();
+                for square in state.move_targets(selected) {
+                    if occupied.contains(square) {
+                        r.stroke_circle(square_to_pos(square), 0.46, 0.08, state.theme().move_hint)?;
+                    } else {
+                        r.fill_circle(square_to_pos(square), 0.12, state.theme().move_hint)?;
+                    }
+                }
+            }
+        }
+
+        let dragged = self.drag.as_ref().filter(|d| d.threshold);
+
+        for square in state.board().occupied() {
+            if dragged.map_or(false, |d| d.square == square) {
+                continue;
+            }
+            if let Some(piece) = state.board().piece_at(square) {
+                r.piece(state.piece_set(), piece, square_to_pos(square), 1.0, state.orientation())?;
+            }
+        }
+
+        if let Some(drag) = dragged {
+            r.piece(state.piece_set(), drag.piece, drag.pos, DRAG_SCALE, state.orientation())?;
+        }
+
+        if let Some(ref promoting) = self.promoting {
+            promoting.draw(r, state)?;
+        }
+
+        Ok(())
+    }
+
+    /// The legal move from `orig` to `dest`, if any. Moving the king
+    /// onto its castling square castles. Pawn moves with a choice of
+    /// pieces start promoting instead, unless auto queen is on.
+    fn find_move(&mut self, orig: Square, dest: Square) -> Option<Move> {
+        let dest = self.state.castling_rook(orig, dest).unwrap_or(dest);
+        let moves: Vec<Move> = self.state.legals().iter()
+            .filter(|m| m.from() == Some(orig) && m.to() == dest)
+            .cloned()
+            .collect();
+
+        let roles: Vec<Role> = PROMOTION_ORDER.iter()
+            .cloned()
+            .filter(|&role| moves.iter().any(|m| m.promotion() == Some(role)))
+            .collect();
+
+        if roles.len() > 1 && !self.state.auto_queen() {
+            let color = self.state.board().color_at(orig)?;
+            self.promoting = Some(Promoting { color, orig, dest, roles });
+            return None;
+        }
+
+        let role = roles.first().cloned();
+        moves.into_iter().find(|m| m.promotion() == role)
+    }
+
+    /// Finish promoting with the piece shown on `square`, or cancel if
+    /// there is none.
+    fn promote(&mut self, square: Option<Square>) -> Option<Move> {
+        let promoting = self.promoting.take()?;
+        let role = square.and_then(|square| promoting.role_at(square))?;
+        self.state.legals().iter().find(|m| {
+            m.from() == Some(promoting.orig) && m.to() == promoting.dest && m.promotion() == Some(role)
+        }).cloned()
+    }
+}
+
+mod imp {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct BoardWidget {
+        pub(super) model: RefCell<Model>,
+        pub(super) user_move: RefCell<Vec<UserMoveHandler>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for BoardWidget {
+        const NAME: &'static str = "ChessgroundBoard";
+        type Type = super::Gtk4Board;
+        type ParentType = gtk4::Widget;
+    }
+
+    impl ObjectImpl for BoardWidget {
+        fn constructed(&self, obj: &Self::Type) {
+            self.parent_constructed(obj);
+            obj.set_focusable(true);
+            obj.set_size_request(160, 160);
+            obj.setup_controllers();
+        }
+    }
+
+    impl WidgetImpl for BoardWidget {
+        fn snapshot(&self, widget: &Self::Type, snapshot: &gtk4::Snapshot) {
+            widget.draw(snapshot);
+        }
+    }
+}
+
+glib::wrapper! {
+    /// Board widget for GTK4, see the module documentation for what it
+    /// leaves out compared to `Ground`.
+    pub struct Gtk4Board(ObjectSubclass<imp::BoardWidget>)
+        @extends gtk4::Widget,
+        @implements gtk4::Accessible, gtk4::Buildable, gtk4::ConstraintTarget;
+}
+
+impl Default for Gtk4Board {
+    fn default() -> Gtk4Board {
+        Gtk4Board::new()
+    }
+}
+
+impl Gtk4Board {
+    /// A board with the starting position.
+    pub fn new() -> Gtk4Board {
+        glib::Object::new(&[]).expect("create board widget")
+    }
+
+    /// Play a legal move, typically in answer to `connect_user_move()`.
+    pub fn play(&self, m: &Move) -> Result<(), IllegalMoveError> {
+        let result = self.update(|model| {
+            model.selected = None;
+            model.promoting = None;
+            model.state.play(m)
+        });
+        self.queue_draw();
+        result
+    }
+
+    pub fn set_fen(&self, fen: &str) -> Result<(), ParseFenError> {
+        let result = self.update(|model| {
+            model.selected = None;
+            model.drag = None;
+            model.promoting = None;
+            model.state.set_fen(fen)
+        });
+        self.queue_draw();
+        result
+    }
+
+    pub fn fen(&self) -> String {
+        self.imp().model.borrow().state.fen()
+    }
+
+    pub fn orientation(&self) -> Color {
+        self.imp().model.borrow().state.orientation()
+    }
+
+    pub fn set_orientation(&self, orientation: Color) {
+        self.update(|model| model.state.set_orientation(orientation));
+        self.queue_draw();
+    }
+
+    pub fn flip(&self) {
+        self.set_orientation(!self.orientation());
+    }
+
+    /// Which side the user may move.
+    pub fn set_movable(&self, movable: MovableSide) {
+        self.update(|model| {
+            model.selected = None;
+            model.state.set_movable(movable);
+        });
+        self.queue_draw();
+    }
+
+    /// Promote to queens without offering the other pieces.
+    pub fn set_auto_queen(&self, auto_queen: bool) {
+        self.update(|model| model.state.set_auto_queen(auto_queen));
+    }
+
+    pub fn set_theme(&self, theme: Theme) {
+        self.update(|model| model.state.set_theme(theme));
+        self.queue_draw();
+    }
+
+    pub fn set_piece_set(&self, piece_set: PieceSet) {
+        self.update(|model| model.state.set_piece_set(piece_set));
+        self.queue_draw();
+    }
+
+    /// Call `f` when the user made a legal move. The move is not played
+    /// until the application answers with `play()`.
+    pub fn connect_user_move<F: Fn(&Gtk4Board, &Move) + 'static>(&self, f: F) {
+        self.imp().user_move.borrow_mut().push(Rc::new(f));
+    }
+
+    fn update<T, F: FnOnce(&mut Model) -> T>(&self, f: F) -> T {
+        f(&mut self.imp().model.borrow_mut())
+    }
+
+    fn emit_user_move(&self, m: &Move) {
+        // handlers may call back into the widget, so do not hold the
+        // borrow while calling them
+        let handlers = self.imp().user_move.borrow().clone();
+        for handler in handlers {
+            handler(self, m);
+        }
+    }
+
+    /// Center of the widget and size of a square in widget coordinates.
+    fn geometry(&self) -> (f64, f64, f64) {
+        let (width, height) = (f64::from(self.width()), f64::from(self.height()));
+        (width / 2.0, height / 2.0, width.min(height) / 8.0)
+    }
+
+    /// Widget coordinates to board coordinates.
+    fn board_pos(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let (cx, cy, size) = self.geometry();
+        let (dx, dy) = ((x - cx) / size, (y - cy) / size);
+        self.orientation().fold_wb((4.0 + dx, 4.0 + dy), (4.0 - dx, 4.0 - dy))
+    }
+
+    fn draw(&self, snapshot: &gtk4::Snapshot) {
+        let (cx, cy, size) = self.geometry();
+        let model = self.imp().model.borrow();
+
+        snapshot.save();
+        snapshot.translate(&graphene::Point::new(cx as f32, cy as f32));
+        snapshot.scale(size as f32, size as f32);
+        snapshot.rotate(model.state.orientation().fold_wb(0.0, 180.0));
+        snapshot.translate(&graphene::Point::new(-4.0, -4.0));
+        model.draw(&SnapshotRenderer(snapshot)).unwrap();
+        snapshot.restore();
+    }
+
+    fn setup_controllers(&self) {
+        let drag = gtk4::GestureDrag::new();
+
+        let widget = self.downgrade();
+        drag.connect_drag_begin(move |_, x, y| {
+            if let Some(widget) = widget.upgrade() {
+                widget.grab_focus();
+                widget.drag_begin((x, y));
+            }
+        });
+
+        let widget = self.downgrade();
+        drag.connect_drag_update(move |gesture, dx, dy| {
+            if let (Some(widget), Some((x, y))) = (widget.upgrade(), gesture.start_point()) {
+                widget.drag_update((x + dx, y + dy));
+            }
+        });
+
+        let widget = self.downgrade();
+        drag.connect_drag_end(move |gesture, dx, dy| {
+            if let (Some(widget), Some((x, y))) = (widget.upgrade(), gesture.start_point()) {
+                widget.drag_end((x + dx, y + dy));
+            }
+        });
+
+        self.add_controller(&drag);
+
+        let key = gtk4::EventControllerKey::new();
+
+        let widget = self.downgrade();
+        key.connect_key_pressed(move |_, keyval, _, _| {
+            let widget = match widget.upgrade() {
+                Some(widget) => widget,
+                None => return gtk4::Inhibit(false),
+            };
+
+            if keyval == gdk::Key::f {
+                widget.flip();
+                gtk4::Inhibit(true)
+            } else if keyval == gdk::Key::Escape {
+                widget.update(|model| {
+                    model.selected = None;
+                    model.drag = None;
+                    model.promoting = None;
+                });
+                widget.queue_draw();
+                gtk4::Inhibit(true)
+            } else {
+                gtk4::Inhibit(false)
+            }
+        });
+
+        self.add_controller(&key);
+    }
+
+    fn drag_begin(&self, pos: (f64, f64)) {
+        let pos = self.board_pos(pos);
+        let square = pos_to_square(pos);
+
+        // choose a piece to promote to, or cancel with a click elsewhere
+        if self.imp().model.borrow().promoting.is_some() {
+            let m = self.update(|model| model.promote(square));
+            self.queue_draw();
+            if let Some(m) = m {
+                self.emit_user_move(&m);
+            }
+            return;
+        }
+
+        // click on a target of the selected piece
+        let m = self.update(|model| match (model.selected, square) {
+            (Some(orig), Some(dest)) if orig != dest && model.state.valid_move(orig, dest) => {
+                model.selected = None;
+                model.find_move(orig, dest)
+            },
+            _ => None,
+        });
+
+        if let Some(m) = m {
+            self.queue_draw();
+            self.emit_user_move(&m);
+            return;
+        }
+
+        self.update(|model| {
+            let piece = square.and_then(|square| model.state.board().piece_at(square));
+            match (square, piece) {
+                (Some(square), Some(piece)) if model.state.is_movable(piece) => {
+                    model.selected = Some(square);
+                    model.drag = Some(Drag { square, piece, start: pos, pos, threshold: false });
+                },
+                _ => model.selected = None,
+            }
+        });
+
+        self.queue_draw();
+    }
+
+    fn drag_update(&self, pos: (f64, f64)) {
+        let pos = self.board_pos(pos);
+
+        let dragging = self.update(|model| match model.drag {
+            Some(ref mut drag) => {
+                drag.pos = pos;
+                let (dx, dy) = (pos.0 - drag.start.0, pos.1 - drag.start.1);
+                drag.threshold |= dx.hypot(dy) > DRAG_THRESHOLD;
+                drag.threshold
+            },
+            None => false,
+        });
+
+        if dragging {
+            self.queue_draw();
+        }
+    }
+
+    fn drag_end(&self, pos: (f64, f64)) {
+        let pos = self.board_pos(pos);
+
+        // a click keeps the piece selected, a drag drops it
+        let m = self.update(|model| match model.drag.take() {
+            Some(ref drag) if drag.threshold => {
+                match pos_to_square(pos) {
+                    Some(dest) if dest != drag.square => {
+                        model.selected = None;
+                        model.find_move(drag.square, dest)
+                    },
+                    _ => None,
+                }
+            },
+            _ => None,
+        });
+
+        self.queue_draw();
+
+        if let Some(m) = m {
+            self.emit_user_move(&m);
+        }
+    }
+}
+
+/// Records drawing into a snapshot. Curves and pieces go through cairo
+/// nodes just large enough to hold them.
+struct SnapshotRenderer<'a>(&'a gtk4::Snapshot);
+
+impl<'a> SnapshotRenderer<'a> {
+    fn cairo(&self, (x, y): (f64, f64), half: f64) -> cairo::Context {
+        self.0.append_cairo(&rect(x - half, y - half, 2.0 * half, 2.0 * half))
+    }
+}
+
+impl<'a> Renderer for SnapshotRenderer<'a> {
+    fn fill_rect(&self, x: f64, y: f64, width: f64, height: f64, color: Rgba) -> Result<(), cairo::Error> {
+        self.0.append_color(&rgba(color), &rect(x, y, width, height));
+        Ok(())
+    }
+
+    fn stroke_rect(&self, x: f64, y: f64, width: f64, height: f64, line_width: f64, color: Rgba) -> Result<(), cairo::Error> {
+        let outline = gsk::RoundedRect::from_rect(rect(x, y, width, height), 0.0);
+        self.0.append_border(&outline, &[line_width as f32; 4], &[rgba(color); 4]);
+        Ok(())
+    }
+
+    fn fill_circle(&self, center: (f64, f64), radius: f64, color: Rgba) -> Result<(), cairo::Error> {
+        self.cairo(center, radius).fill_circle(center, radius, color)
+    }
+
+    fn stroke_circle(&self, center: (f64, f64), radius: f64, line_width: f64, color: Rgba) -> Result<(), cairo::Error> {
+        self.cairo(center, radius + line_width / 2.0).stroke_circle(center, radius, line_width, color)
+    }
+
+    fn fill_glow(&self, center: (f64, f64), radius: f64, color: Rgba) -> Result<(), cairo::Error> {
+        self.cairo(center, radius).fill_glow(center, radius, color)
+    }
+
+    fn piece(&self, piece_set: &PieceSet, piece: Piece, center: (f64, f64), size: f64, orientation: Color) -> Result<(), cairo::Error> {
+        self.cairo(center, size / 2.0).piece(piece_set, piece, center, size, orientation)
+    }
+
+    fn push_clip(&self, x: f64, y: f64, width: f64, height: f64) -> Result<(), cairo::Error> {
+        self.0.push_clip(&rect(x, y, width, height));
+        Ok(())
+    }
+
+    fn pop_clip(&self) -> Result<(), cairo::Error> {
+        self.0.pop();
+        Ok(())
+    }
+}
+
+fn rect(x: f64, y: f64, width: f64, height: f64) -> graphene::Rect {
+    graphene::Rect::new(x as f32, y as f32, width as f32, height as f32)
+}
+
+fn rgba(color: Rgba) -> gdk::RGBA {
+    gdk::RGBA::new(color.red as f32, color.green as f32, color.blue as f32, color.alpha as f32)
+}
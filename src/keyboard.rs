@@ -0,0 +1,244 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+use std::f64::consts::PI;
+
+use gtk::prelude::*;
+use gdk::EventKey;
+use gdk::keys::constants as key;
+use cairo::Context;
+use pango::FontDescription;
+
+use shakmaty::{Move, Position, Square};
+use shakmaty::san::San;
+use shakmaty::uci::Uci;
+
+use boardstate::BoardState;
+use ground::{GroundMsg, Stream};
+use notation::Notation;
+
+/// Entering moves in SAN (`Nf3`) or coordinate notation (`g1f3`) with
+/// the keyboard. The input is shown in a small box over the board. SAN
+/// is typed with the piece letters of the notation.
+pub struct Keyboard {
+    notation: Notation,
+    input: String,
+    completions: Vec<String>,
+    completion: usize,
+    invalid: bool,
+}
+
+impl Keyboard {
+    pub fn new() -> Keyboard {
+        Keyboard {
+            notation: Notation::english(),
+            input: String::new(),
+            completions: Vec::new(),
+            completion: 0,
+            invalid: false,
+        }
+    }
+
+    pub fn set_notation(&mut self, notation: Notation) {
+        self.notation = notation;
+        self.cancel();
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.input.is_empty()
+    }
+
+    pub fn cancel(&mut self) {
+        self.input.clear();
+        self.completions.clear();
+        self.invalid = false;
+    }
+
+    pub(crate) fn key_press(&mut self, state: &BoardState, stream: &Stream, e: &EventKey) -> Inhibit {
+        let keyval = e.keyval();
+
+        if keyval == key::Escape && self.is_active() {
+            self.cancel();
+        } else if keyval == key::BackSpace && self.is_active() {
+            self.input.pop();
+            self.edited();
+        } else if state.view_only() && (keyval == key::Tab || keyval == key::ISO_Left_Tab || keyval == key::Return || keyval == key::KP_Enter) {
+            // watching only, but the arrow keys still step through the game
+            return Inhibit(false);
+        } else if keyval == key::Tab || keyval == key::ISO_Left_Tab {
+            self.complete(state, keyval == key::ISO_Left_Tab);
+        } else if keyval == key::Return || keyval == key::KP_Enter {
+            self.submit(state, stream);
+        } else if keyval == key::Left {
+            stream.emit(GroundMsg::StepBackward);
+        } else if keyval == key::Right {
+            stream.emit(GroundMsg::StepForward);
+        } else if keyval == key::Up || keyval == key::Home {
+            stream.emit(GroundMsg::GoToPly(0));
+        } else if keyval == key::Down || keyval == key::End {
+            stream.emit(GroundMsg::GoToPly(usize::max_value()));
+        } else {
+            match keyval.to_unicode() {
+                Some(ch) if (ch.is_ascii_alphanumeric() || "=@-+#".contains(ch)) && self.input.len() < 10 && !state.view_only() => {
+                    self.input.push(ch);
+                    self.edited();
+                },
+                _ => return Inhibit(false),
+            }
+        }
+
+        Inhibit(true)
+    }
+
+    fn edited(&mut self) {
+        self.completions.clear();
+        self.invalid = false;
+    }
+
+    /// Cycle through the legal moves starting with the input, in SAN or
+    /// coordinate notation, whichever the input looks like.
+    fn complete(&mut self, state: &BoardState, backward: bool) {
+        if self.completions.is_empty() {
+            let pos = match state.position() {
+                Some(pos) => pos,
+                None => return,
+            };
+
+            let candidates = |coordinates: bool| -> Vec<String> {
+                pos.legal_moves().iter().map(|m| {
+                    if coordinates {
+                        Uci::from_move(m, state.castling_mode()).to_string()
+                    } else {
+                        self.notation.input_san(&San::from_move(&pos, m).to_string())
+                    }
+                }).filter(|c| c.starts_with(&self.input)).collect()
+            };
+
+            // after a square like "g1" coordinate notation is more likely,
+            // but "e4" is also a pawn move
+            let mut completions = match self.input.get(..2).filter(|sq| sq.parse::<Square>().is_ok()) {
+                Some(_) => candidates(true),
+                None => Vec::new(),
+            };
+            if completions.is_empty() {
+                completions = candidates(false);
+            }
+
+            completions.sort();
+            completions.dedup();
+
+            if completions.is_empty() {
+                self.invalid = true;
+                return;
+            }
+
+            self.completions = completions;
+            self.completion = if backward { self.completions.len() - 1 } else { 0 };
+        } else if backward {
+            self.completion = (self.completion + self.completions.len() - 1) % self.completions.len();
+        } else {
+            self.completion = (self.completion + 1) % self.completions.len();
+        }
+
+        self.input = self.completions[self.completion].clone();
+        self.invalid = false;
+    }
+
+    fn parse(&self, state: &BoardState) -> Option<Move> {
+        let pos = state.position()?;
+        let input = self.input.trim_end_matches(|c| c == '+' || c == '#');
+
+        if let Ok(uci) = input.parse::<Uci>() {
+            if let Ok(m) = uci.to_move(&pos) {
+                return Some(m);
+            }
+        }
+
+        self.notation.read_san(input).parse::<San>().ok()?.to_move(&pos).ok()
+    }
+
+    fn submit(&mut self, state: &BoardState, stream: &Stream) {
+        if !self.is_active() {
+            return;
+        }
+
+        if !state.turn().map_or(false, |turn| state.can_move(turn)) {
+            self.invalid = true;
+            return;
+        }
+
+        // a single completion is as good as typing it out
+        if self.parse(state).is_none() && self.completions.is_empty() {
+            self.complete(state, false);
+            if self.completions.len() != 1 {
+                self.invalid = true;
+                return;
+            }
+        }
+
+        match self.parse(state) {
+            Some(Move::Put { role, to }) => stream.emit(GroundMsg::UserDrop(role, to)),
+            Some(m) => stream.emit(GroundMsg::UserMove(m.from().unwrap_or_else(|| m.to()), m.to(), m.promotion())),
+            None => {
+                self.invalid = true;
+                return;
+            }
+        }
+
+        self.cancel();
+    }
+
+    pub(crate) fn draw(&self, cr: &Context, state: &BoardState) -> Result<(), cairo::Error> {
+        if !self.is_active() {
+            return Ok(());
+        }
+
+        cr.save()?;
+
+        // upright in the middle of the board, regardless of orientation
+        cr.translate(4.0, 4.0);
+        cr.rotate(state.orientation().fold_wb(0.0, PI));
+
+        cr.rectangle(-1.5, -0.4, 3.0, 0.8);
+        state.theme().border.set_source(cr);
+        cr.fill_preserve()?;
+        if self.invalid {
+            state.theme().check.set_source(cr);
+        } else {
+            state.theme().coordinates.set_source(cr);
+        }
+        cr.set_line_width(0.04);
+        cr.stroke()?;
+
+        if let Some(layout) = pangocairo::create_layout(cr) {
+            let mut font = FontDescription::from_string("Monospace");
+            font.set_absolute_size(40.0 * f64::from(pango::SCALE));
+            layout.set_font_description(Some(&font));
+            layout.set_text(&self.input);
+            let (width, height) = layout.pixel_size();
+
+            // lay out at 100 times the size, see BoardState::draw_text()
+            cr.scale(0.01, 0.01);
+            cr.move_to(-0.5 * f64::from(width), -0.5 * f64::from(height));
+            state.theme().coordinates.set_source(cr);
+            pangocairo::show_layout(cr, &layout);
+        }
+
+        cr.restore()?;
+        Ok(())
+    }
+}
@@ -20,24 +20,210 @@
 
 #![warn(missing_debug_implementations)]
 
+#[cfg(feature = "gtk3")]
 extern crate gtk;
+#[cfg(feature = "gtk3")]
 extern crate gdk;
 extern crate cairo;
+extern crate pango;
+extern crate pangocairo;
 extern crate rsvg;
 extern crate shakmaty;
-extern crate time;
+extern crate shakmaty_syzygy;
+#[cfg(feature = "gtk3")]
 extern crate relm;
+#[cfg(feature = "gtk3")]
 #[macro_use]
 extern crate relm_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate ureq;
+#[cfg(feature = "blockchain")]
+extern crate ed25519_dalek;
+extern crate rand_core;
+#[cfg(feature = "blockchain")]
+extern crate sha2;
+extern crate toml;
+extern crate rodio;
+extern crate sled;
+extern crate gif;
+#[cfg(test)]
+#[macro_use]
+extern crate proptest;
+#[cfg(all(feature = "gtk3", feature = "gtk4"))]
+compile_error!("GTK3 and GTK4 can not be used in the same process, disable the gtk3 feature for gtk4");
+#[cfg(feature = "gtk4")]
+extern crate gtk4;
+#[cfg(feature = "gpu")]
+#[macro_use]
+extern crate glium;
+#[cfg(feature = "gpu")]
+extern crate epoxy;
+#[cfg(feature = "gpu")]
+extern crate libloading;
 
+#[cfg(feature = "gtk3")]
 mod ground;
+#[cfg(feature = "gtk3")]
+mod boardgrid;
+#[cfg(all(feature = "gtk3", feature = "blockchain"))]
+mod simul;
 mod boardstate;
 mod pieceset;
+mod theme;
+mod config;
+#[cfg(feature = "gtk3")]
+mod preferences;
+#[cfg(feature = "gtk3")]
 mod pieces;
+#[cfg(feature = "gtk3")]
 mod promotable;
-mod drawable;
+#[cfg(feature = "gtk3")]
+mod pockets;
+#[cfg(feature = "gtk3")]
+mod keyboard;
+#[cfg(feature = "gtk3")]
+mod access;
+mod shapes;
+mod renderer;
+mod render;
+mod engine;
+mod analysis;
+mod opponent;
+mod game;
+mod gametree;
+mod pgn;
+mod notation;
+mod epd;
+#[cfg(feature = "gtk3")]
+mod palette;
+#[cfg(feature = "gtk3")]
+mod tray;
+#[cfg(feature = "gtk3")]
+mod movelist;
+#[cfg(feature = "gtk3")]
+mod annotation;
+#[cfg(all(feature = "gtk3", feature = "blockchain"))]
+mod chat;
+#[cfg(feature = "blockchain")]
+mod lobby;
+#[cfg(feature = "gtk3")]
+mod browser;
+mod rating;
+mod tournament;
+#[cfg(feature = "blockchain")]
+mod chain;
+#[cfg(feature = "blockchain")]
+mod daemon;
+#[cfg(feature = "blockchain")]
+mod crypto;
+#[cfg(feature = "blockchain")]
+mod commitment;
+#[cfg(feature = "blockchain")]
+mod net;
+mod clock;
+mod timing;
+mod audio;
+mod speech;
+mod openings;
+mod tablebase;
+mod lichess;
+mod puzzle;
+mod trainer;
+mod storage;
+mod recovery;
+mod zobrist;
+#[cfg(feature = "blockchain")]
+mod wager;
+#[cfg(feature = "nft")]
+mod nft;
+#[cfg(feature = "gtk4")]
+mod gtk4board;
+#[cfg(feature = "gpu")]
+mod gpu;
 mod util;
+#[cfg(all(test, feature = "gtk3"))]
+mod golden;
+#[cfg(test)]
+mod perft;
 
+#[cfg(feature = "gtk3")]
 pub use ground::{Ground, GroundMsg, Pos};
+pub use boardstate::{AutoOrient, BoardState, Coordinates, DrawReason, GameState, HighlightKind, MovableSide, MoveInput, SanError, UciMovesError};
+#[cfg(feature = "gtk3")]
 pub use GroundMsg::*;
-pub use drawable::{DrawBrush, DrawShape};
+#[cfg(feature = "gtk3")]
+pub use boardgrid::{BoardGrid, BoardGridMsg};
+#[cfg(all(feature = "gtk3", feature = "blockchain"))]
+pub use simul::{Simul, SimulMsg, SimulScore};
+pub use theme::{Rgba, Theme, ThemeError};
+pub use config::{config_dir, data_dir, Config, ConfigError, Settings};
+#[cfg(feature = "gtk3")]
+pub use preferences::{Preferences, PreferencesMsg};
+pub use pieceset::PieceSet;
+#[cfg(feature = "gtk3")]
+pub use pieces::Blindfold;
+pub use shapes::{Arrow, DrawBrush, DrawShape};
+pub use render::{draw_board, export_gif, render_png, render_svg, GifOptions, RenderError};
+pub use engine::{Engine, EngineEvent, Info, Limit, MultiPv, Score};
+pub use analysis::{centipawns, Analysis, AnalysisEvent, Judgement, Thresholds};
+pub use opponent::{Bot, MAX_LEVEL, MIN_LEVEL};
+pub use game::{nag_symbol, Game, GameMove, IllegalMoveError, NAG_SYMBOLS};
+pub use gametree::{GameTree, NodeId};
+pub use pgn::{read_games, PgnError};
+pub use notation::Notation;
+pub use epd::{read_epd, EpdError, EpdRecord};
+#[cfg(feature = "gtk3")]
+pub use palette::{Palette, PaletteMsg};
+#[cfg(feature = "gtk3")]
+pub use PaletteMsg::*;
+#[cfg(feature = "gtk3")]
+pub use tray::{CapturedTray, CapturedTrayMsg};
+#[cfg(feature = "gtk3")]
+pub use movelist::{MoveListView, MoveListMsg};
+#[cfg(feature = "gtk3")]
+pub use annotation::{AnnotationEditor, AnnotationMsg};
+#[cfg(all(feature = "gtk3", feature = "blockchain"))]
+pub use chat::{ChatPane, ChatMsg};
+#[cfg(feature = "blockchain")]
+pub use lobby::{Challenge, LiveGame, Lobby, LobbyEvent};
+#[cfg(all(feature = "gtk3", feature = "blockchain"))]
+pub use lobby::{LobbyMsg, LobbyView};
+#[cfg(feature = "gtk3")]
+pub use browser::{GameBrowser, GameBrowserMsg};
+#[cfg(feature = "blockchain")]
+pub use chain::{verify_game, verify_records, ChainClient, ChainConfig, ChainError, ChainEvent, ChainRecorder, Divergence, MoveRecord, VerificationReport};
+#[cfg(feature = "blockchain")]
+pub use crypto::{verify, verify_record, Identity, MoveSigner, PlayerKey};
+#[cfg(feature = "blockchain")]
+pub use daemon::Daemon;
+#[cfg(feature = "blockchain")]
+pub use commitment::{chain_hash, position_hash, Commitment, Hash};
+#[cfg(feature = "blockchain")]
+pub use net::{ChatMessage, ChatRooms, NetEvent, NetMessage, Peer, ResyncError};
+pub use clock::{Clock, Period, TimeControl};
+#[cfg(feature = "gtk3")]
+pub use clock::{ChessClock, ClockMsg};
+pub use timing::{ManualTime, RealTime, TimeSource};
+pub use audio::{Audio, AudioError, Sound, SoundSet};
+pub use speech::{Speech, Urgency, Vocabulary};
+pub use openings::{Opening, Openings};
+pub use puzzle::{read_puzzles, Puzzle, PuzzleError, PuzzleStats};
+pub use trainer::{Card, Drill, Repertoire, TrainerError};
+pub use tablebase::{MoveProbe, Probe, Tablebase, TablebaseError};
+pub use lichess::{LichessClient, LichessError, LichessEvent, LichessGame, LichessPlayer, LichessState};
+pub use rating::{rate_game, Rating, RatingChange, RatingStatus};
+pub use tournament::{Pairing, Standing, Tournament, TournamentFormat};
+pub use storage::{GameFilter, GameSummary, PositionMatch, Storage, StorageError, StoredGame};
+pub use recovery::Recovery;
+#[cfg(feature = "gtk3")]
+pub use recovery::prompt_restore;
+pub use zobrist::zobrist_hash;
+#[cfg(feature = "blockchain")]
+pub use wager::{Escrow, GameResult, Payout, RpcEscrow, Settlement, Stake, Wager, WagerError};
+#[cfg(feature = "gtk4")]
+pub use gtk4board::Gtk4Board;
+#[cfg(feature = "gpu")]
+pub use gpu::GpuBoard;
+#[cfg(feature = "nft")]
+pub use nft::{board_svg, game_metadata, mint_game, mint_game_in_background, MintError, Minter, RpcMinter};
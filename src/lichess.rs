@@ -0,0 +1,325 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! A client for the Lichess board API.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use shakmaty::{CastlingMode, Chess, Move, Position};
+use shakmaty::fen::Fen;
+use shakmaty::uci::Uci;
+
+/// Error when talking to Lichess.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LichessError {
+    /// Lichess could not be reached, or replied with an HTTP error, e.g.
+    /// because the token is invalid or lacks the `board:play` scope.
+    Http(String),
+    /// The reply could not be understood.
+    InvalidResponse,
+}
+
+impl fmt::Display for LichessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LichessError::Http(ref err) => write!(f, "lichess http error: {}", err),
+            LichessError::InvalidResponse => f.write_str("invalid lichess response"),
+        }
+    }
+}
+
+impl Error for LichessError {}
+
+impl From<ureq::Error> for LichessError {
+    fn from(err: ureq::Error) -> LichessError {
+        LichessError::Http(err.to_string())
+    }
+}
+
+/// A player of a Lichess game.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LichessPlayer {
+    /// User id, or `None` for the AI.
+    pub id: Option<String>,
+    pub name: String,
+    pub rating: Option<u32>,
+}
+
+impl LichessPlayer {
+    fn from_json(value: &Value) -> LichessPlayer {
+        LichessPlayer {
+            id: value["id"].as_str().map(str::to_owned),
+            name: value["name"].as_str()
+                .map(str::to_owned)
+                .or_else(|| value["aiLevel"].as_u64().map(|level| format!("Stockfish level {}", level)))
+                .unwrap_or_default(),
+            rating: value["rating"].as_u64().map(|r| r as u32),
+        }
+    }
+}
+
+/// The moves and clocks of a Lichess game.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LichessState {
+    pub moves: Vec<Uci>,
+    pub wtime: Duration,
+    pub btime: Duration,
+    /// `started` while the game is running, otherwise how it ended, e.g.
+    /// `mate`, `resign` or `outoftime`.
+    pub status: String,
+}
+
+impl LichessState {
+    fn from_json(value: &Value) -> Option<LichessState> {
+        Some(LichessState {
+            moves: value["moves"].as_str()?
+                .split_whitespace()
+                .map(|m| m.parse().ok())
+                .collect::<Option<_>>()?,
+            wtime: Duration::from_millis(value["wtime"].as_u64().unwrap_or(0)),
+            btime: Duration::from_millis(value["btime"].as_u64().unwrap_or(0)),
+            status: value["status"].as_str()?.to_owned(),
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.status == "created" || self.status == "started"
+    }
+}
+
+/// Updates from a game stream.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LichessEvent {
+    /// Sent first, with the players and the current state.
+    GameFull {
+        id: String,
+        white: LichessPlayer,
+        black: LichessPlayer,
+        /// FEN of the starting position.
+        initial_fen: String,
+        state: LichessState,
+    },
+    /// Sent whenever a move is played or the game ends.
+    GameState(LichessState),
+    ChatLine { username: String, text: String },
+    /// Streaming the game or sending a move failed.
+    Error(LichessError),
+}
+
+impl LichessEvent {
+    fn from_json(value: &Value) -> Option<LichessEvent> {
+        Some(match value["type"].as_str()? {
+            "gameFull" => LichessEvent::GameFull {
+                id: value["id"].as_str()?.to_owned(),
+                white: LichessPlayer::from_json(&value["white"]),
+                black: LichessPlayer::from_json(&value["black"]),
+                initial_fen: value["initialFen"].as_str().unwrap_or("startpos").to_owned(),
+                state: LichessState::from_json(&value["state"])?,
+            },
+            "gameState" => LichessEvent::GameState(LichessState::from_json(value)?),
+            "chatLine" => LichessEvent::ChatLine {
+                username: value["username"].as_str()?.to_owned(),
+                text: value["text"].as_str()?.to_owned(),
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// Replay the moves of a game from its starting position, as given by
+/// `LichessEvent::GameFull`, returning the final position and the last
+/// move. Lichess sends `startpos` for the standard starting position.
+pub fn replay(initial_fen: &str, moves: &[Uci]) -> Option<(Chess, Option<Move>)> {
+    let mut pos: Chess = if initial_fen == "startpos" {
+        Chess::default()
+    } else {
+        initial_fen.parse::<Fen>().ok()?.position(CastlingMode::Standard).ok()?
+    };
+    let mut last = None;
+
+    for uci in moves {
+        let m = uci.to_move(&pos).ok()?;
+        pos.play_unchecked(&m);
+        last = Some(m);
+    }
+
+    Some((pos, last))
+}
+
+/// A blocking client for the Lichess board API, logged in with a personal
+/// API token.
+#[derive(Clone)]
+pub struct LichessClient {
+    token: String,
+    base_url: String,
+}
+
+impl fmt::Debug for LichessClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // the token stays secret
+        f.debug_struct("LichessClient")
+            .field("base_url", &self.base_url)
+            .finish()
+    }
+}
+
+impl LichessClient {
+    pub fn new(token: &str) -> LichessClient {
+        LichessClient {
+            token: token.to_owned(),
+            base_url: "https://lichess.org".to_owned(),
+        }
+    }
+
+    /// Use another server, e.g. a local development instance.
+    pub fn with_base_url(mut self, base_url: &str) -> LichessClient {
+        self.base_url = base_url.trim_end_matches('/').to_owned();
+        self
+    }
+
+    fn authorization(&self) -> String {
+        format!("Bearer {}", self.token)
+    }
+
+    fn post(&self, path: &str) -> Result<(), LichessError> {
+        ureq::post(&format!("{}{}", self.base_url, path))
+            .set("Authorization", &self.authorization())
+            .call()?;
+        Ok(())
+    }
+
+    /// The user name of the account the token belongs to. Fails if the
+    /// token is invalid.
+    pub fn login(&self) -> Result<String, LichessError> {
+        let account: Value = ureq::get(&format!("{}/api/account", self.base_url))
+            .set("Authorization", &self.authorization())
+            .call()?
+            .into_json()
+            .map_err(|_| LichessError::InvalidResponse)?;
+
+        account["username"].as_str().map(str::to_owned).ok_or(LichessError::InvalidResponse)
+    }
+
+    pub fn make_move(&self, game_id: &str, uci: &Uci) -> Result<(), LichessError> {
+        self.post(&format!("/api/board/game/{}/move/{}", game_id, uci))
+    }
+
+    pub fn resign(&self, game_id: &str) -> Result<(), LichessError> {
+        self.post(&format!("/api/board/game/{}/resign", game_id))
+    }
+
+    /// Offer or accept a draw with `true`, decline with `false`.
+    pub fn handle_draw(&self, game_id: &str, accept: bool) -> Result<(), LichessError> {
+        self.post(&format!("/api/board/game/{}/draw/{}", game_id, if accept { "yes" } else { "no" }))
+    }
+
+    /// Stream the events of a game on a background thread. The thread
+    /// ends when the game stream is closed or the game is dropped.
+    pub fn stream_game(&self, game_id: &str) -> LichessGame {
+        let (tx, events) = mpsc::channel();
+        let client = self.clone();
+        let id = game_id.to_owned();
+        let errors = tx.clone();
+
+        thread::spawn(move || {
+            if let Err(err) = client.read_game(&id, &tx) {
+                let _ = tx.send(LichessEvent::Error(err));
+            }
+        });
+
+        LichessGame {
+            client: self.clone(),
+            game_id: game_id.to_owned(),
+            events,
+            errors,
+        }
+    }
+
+    fn read_game(&self, game_id: &str, events: &Sender<LichessEvent>) -> Result<(), LichessError> {
+        let response = ureq::get(&format!("{}/api/board/game/stream/{}", self.base_url, game_id))
+            .set("Authorization", &self.authorization())
+            .call()?;
+
+        for line in BufReader::new(response.into_reader()).lines() {
+            let line = line.map_err(|err| LichessError::Http(err.to_string()))?;
+
+            // empty lines keep the connection alive
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let value: Value = serde_json::from_str(&line).map_err(|_| LichessError::InvalidResponse)?;
+            if let Some(event) = LichessEvent::from_json(&value) {
+                if events.send(event).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An ongoing game, streamed by `LichessClient::stream_game()`. Hand it
+/// to the board with `GroundMsg::WatchLichess`.
+pub struct LichessGame {
+    client: LichessClient,
+    game_id: String,
+    events: Receiver<LichessEvent>,
+    errors: Sender<LichessEvent>,
+}
+
+impl fmt::Debug for LichessGame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LichessGame")
+            .field("game_id", &self.game_id)
+            .finish()
+    }
+}
+
+impl LichessGame {
+    pub fn game_id(&self) -> &str {
+        &self.game_id
+    }
+
+    /// The next event, if any arrived.
+    pub fn try_recv(&self) -> Result<LichessEvent, mpsc::TryRecvError> {
+        self.events.try_recv()
+    }
+
+    /// Send a move on a background thread. Failures arrive as
+    /// `LichessEvent::Error`, and the move itself as the next
+    /// `LichessEvent::GameState`.
+    pub fn send_move(&self, uci: Uci) {
+        let client = self.client.clone();
+        let game_id = self.game_id.clone();
+        let errors = self.errors.clone();
+
+        thread::spawn(move || {
+            if let Err(err) = client.make_move(&game_id, &uci) {
+                let _ = errors.send(LichessEvent::Error(err));
+            }
+        });
+    }
+}
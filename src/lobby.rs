@@ -0,0 +1,441 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! A lobby of open challenges, and a widget to list, create and accept
+//! them.
+//!
+//! The lobby does not own a connection. Its messages are `NetMessage`s that
+//! a hub, like the relay, sends to everyone in the lobby, including back to
+//! the sender, in the order it received them. The first acceptance of a
+//! challenge wins.
+
+#[cfg(feature = "gtk3")]
+use std::fmt;
+use std::time::Duration;
+
+#[cfg(feature = "gtk3")]
+use gtk::prelude::*;
+#[cfg(feature = "gtk3")]
+use gtk::{Adjustment, Button, CheckButton, ComboBoxText, Label, ListBox, Orientation, SelectionMode, SpinButton};
+
+#[cfg(feature = "gtk3")]
+use relm::{Relm, Widget, Update, StreamHandle};
+
+use serde_json::Value;
+
+use shakmaty::Color;
+use shakmaty::variant::Variant;
+
+use clock::{Clock, Period, TimeControl};
+use net::NetMessage;
+use rating::Rating;
+
+/// An open invitation to play.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub id: String,
+    /// Name of the player who created the challenge. They play white.
+    pub challenger: String,
+    /// Rounded rating of the challenger, if rated.
+    pub rating: Option<u32>,
+    /// The rating of the challenger is still provisional.
+    pub provisional: bool,
+    pub time_control: TimeControl,
+    pub variant: Variant,
+    pub rated: bool,
+    /// Amount each player deposits for a `Wager`, if any.
+    pub stake: Option<u64>,
+}
+
+impl Challenge {
+    /// The rating of the challenger, like `1500?` if provisional.
+    pub fn display_rating(&self) -> Option<String> {
+        self.rating.map(|rating| if self.provisional { format!("{}?", rating) } else { rating.to_string() })
+    }
+
+    /// A short description like `5+3 atomic rated, stake 100`.
+    pub fn describe(&self) -> String {
+        let mut text = match self.time_control.periods().first() {
+            Some(period) => format!("{}+{}", period.base().as_secs() / 60, period.increment().as_secs()),
+            None => "unlimited".to_owned(),
+        };
+        text.push(' ');
+        text.push_str(self.variant.uci());
+        if self.rated {
+            text.push_str(" rated");
+        }
+        if let Some(stake) = self.stake {
+            text.push_str(&format!(", stake {}", stake));
+        }
+        text
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "challenger": self.challenger,
+            "rating": self.rating,
+            "provisional": self.provisional,
+            "periods": self.time_control.periods().iter().map(|period| json!({
+                "moves": period.moves(),
+                "base": period.base().as_millis() as u64,
+                "increment": period.increment().as_millis() as u64,
+                "delay": period.delay().as_millis() as u64,
+            })).collect::<Vec<_>>(),
+            "variant": self.variant.uci(),
+            "rated": self.rated,
+            "stake": self.stake,
+        })
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Option<Challenge> {
+        let periods = value["periods"].as_array()?.iter().map(|period| {
+            let mut p = Period::new(Duration::from_millis(period["base"].as_u64()?))
+                .with_increment(Duration::from_millis(period["increment"].as_u64().unwrap_or(0)))
+                .with_delay(Duration::from_millis(period["delay"].as_u64().unwrap_or(0)));
+            if let Some(moves) = period["moves"].as_u64() {
+                p = p.with_moves(moves as u32);
+            }
+            Some(p)
+        }).collect::<Option<Vec<_>>>()?;
+
+        Some(Challenge {
+            id: value["id"].as_str()?.to_owned(),
+            challenger: value["challenger"].as_str()?.to_owned(),
+            rating: value["rating"].as_u64().map(|rating| rating as u32),
+            provisional: value["provisional"].as_bool().unwrap_or(false),
+            time_control: TimeControl::from_periods(periods),
+            variant: Variant::from_uci(value["variant"].as_str()?)?,
+            rated: value["rated"].as_bool().unwrap_or(false),
+            stake: value["stake"].as_u64(),
+        })
+    }
+}
+
+/// A challenge that was accepted and is now played.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveGame {
+    pub challenge: Challenge,
+    /// Game id for `Peer::connect()`, the same as the challenge id.
+    pub game_id: String,
+    pub color: Color,
+    pub opponent: String,
+}
+
+impl LiveGame {
+    /// A clock for the time control of the challenge.
+    pub fn clock(&self) -> Clock {
+        Clock::new(self.challenge.time_control.clone())
+    }
+}
+
+/// Changes of the lobby, from `Lobby::receive()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LobbyEvent {
+    /// A challenge was created.
+    Opened(Challenge),
+    /// A challenge was cancelled or accepted by other players.
+    Closed(String),
+    /// A challenge of the local player was accepted, or the local player
+    /// accepted a challenge first.
+    Started(LiveGame),
+}
+
+/// The open challenges, as seen by one player.
+#[derive(Debug, Clone)]
+pub struct Lobby {
+    player: String,
+    rating: Option<Rating>,
+    challenges: Vec<Challenge>,
+    next_id: u64,
+}
+
+impl Lobby {
+    pub fn new(player: &str) -> Lobby {
+        Lobby {
+            player: player.to_owned(),
+            rating: None,
+            challenges: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn player(&self) -> &str {
+        &self.player
+    }
+
+    /// Show this rating with the challenges of the local player, e.g. from
+    /// `Storage::rating()`.
+    pub fn set_rating(&mut self, rating: Option<Rating>) {
+        self.rating = rating;
+    }
+
+    pub fn challenges(&self) -> &[Challenge] {
+        &self.challenges
+    }
+
+    /// Create a challenge. Send the message to the hub.
+    pub fn create(&mut self, time_control: TimeControl, variant: Variant, rated: bool, stake: Option<u64>) -> NetMessage {
+        let id = format!("{}-{}", self.player, self.next_id);
+        self.next_id += 1;
+
+        NetMessage::Challenge(Challenge {
+            id,
+            challenger: self.player.clone(),
+            rating: self.rating.map(|r| r.rating.round().max(0.0) as u32),
+            provisional: self.rating.map_or(false, |r| r.is_provisional()),
+            time_control,
+            variant,
+            rated,
+            stake,
+        })
+    }
+
+    /// Withdraw an own challenge. Send the message to the hub.
+    pub fn cancel(&self, id: &str) -> Option<NetMessage> {
+        self.challenges.iter()
+            .find(|c| c.id == id && c.challenger == self.player)
+            .map(|c| NetMessage::CancelChallenge(c.id.clone()))
+    }
+
+    /// Accept a challenge of another player. Send the message to the hub;
+    /// the game starts when it comes back.
+    pub fn accept(&self, id: &str) -> Option<NetMessage> {
+        self.challenges.iter()
+            .find(|c| c.id == id && c.challenger != self.player)
+            .map(|c| NetMessage::AcceptChallenge { id: c.id.clone(), player: self.player.clone() })
+    }
+
+    /// Update the lobby with a message from the hub. Other messages are
+    /// ignored.
+    pub fn receive(&mut self, msg: &NetMessage) -> Option<LobbyEvent> {
+        match *msg {
+            NetMessage::Challenge(ref challenge) => {
+                if self.challenges.iter().any(|c| c.id == challenge.id) {
+                    return None;
+                }
+                self.challenges.push(challenge.clone());
+                Some(LobbyEvent::Opened(challenge.clone()))
+            },
+            NetMessage::CancelChallenge(ref id) => {
+                self.remove(id).map(|c| LobbyEvent::Closed(c.id))
+            },
+            NetMessage::AcceptChallenge { ref id, ref player } => {
+                let challenge = self.remove(id)?;
+                if challenge.challenger == self.player {
+                    Some(LobbyEvent::Started(LiveGame {
+                        game_id: challenge.id.clone(),
+                        color: Color::White,
+                        opponent: player.clone(),
+                        challenge,
+                    }))
+                } else if *player == self.player {
+                    Some(LobbyEvent::Started(LiveGame {
+                        game_id: challenge.id.clone(),
+                        color: Color::Black,
+                        opponent: challenge.challenger.clone(),
+                        challenge,
+                    }))
+                } else {
+                    Some(LobbyEvent::Closed(challenge.id))
+                }
+            },
+            _ => None,
+        }
+    }
+
+    fn remove(&mut self, id: &str) -> Option<Challenge> {
+        let index = self.challenges.iter().position(|c| c.id == id)?;
+        Some(self.challenges.remove(index))
+    }
+}
+
+/// Variants offered when creating a challenge.
+#[cfg(feature = "gtk3")]
+const VARIANTS: [Variant; 8] = [
+    Variant::Chess,
+    Variant::Crazyhouse,
+    Variant::Atomic,
+    Variant::Antichess,
+    Variant::KingOfTheHill,
+    Variant::ThreeCheck,
+    Variant::Horde,
+    Variant::RacingKings,
+];
+
+/// Lobby view messages.
+#[cfg(feature = "gtk3")]
+#[derive(Debug, Msg)]
+pub enum LobbyMsg {
+    /// List these challenges. Forward `Lobby::challenges()` here after
+    /// each `LobbyEvent`.
+    SetChallenges(Vec<Challenge>),
+    /// Offer to cancel the challenges of this player instead of accepting
+    /// them.
+    SetPlayer(String),
+
+    /// Sent when the user filled in and submitted a new challenge. Forward
+    /// to `Lobby::create()`.
+    Create(TimeControl, Variant, bool, Option<u64>),
+    /// Sent when the user accepted a challenge, with its id. Forward to
+    /// `Lobby::accept()`.
+    Accept(String),
+    /// Sent when the user cancelled an own challenge, with its id. Forward
+    /// to `Lobby::cancel()`.
+    Cancel(String),
+}
+
+/// A widget listing open challenges, with a form to create one.
+#[cfg(feature = "gtk3")]
+pub struct LobbyView {
+    vbox: gtk::Box,
+    list: ListBox,
+    player: String,
+    challenges: Vec<Challenge>,
+    stream: StreamHandle<LobbyMsg>,
+}
+
+#[cfg(feature = "gtk3")]
+impl fmt::Debug for LobbyView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LobbyView")
+            .field("player", &self.player)
+            .field("challenges", &self.challenges)
+            .finish()
+    }
+}
+
+#[cfg(feature = "gtk3")]
+impl LobbyView {
+    fn render(&self) {
+        for child in self.list.children() {
+            self.list.remove(&child);
+        }
+
+        for challenge in &self.challenges {
+            let hbox = gtk::Box::new(Orientation::Horizontal, 6);
+
+            let name = match challenge.display_rating() {
+                Some(rating) => format!("{} ({})", challenge.challenger, rating),
+                None => challenge.challenger.clone(),
+            };
+            let label = Label::new(Some(&format!("{} {}", name, challenge.describe())));
+            label.set_xalign(0.0);
+            hbox.pack_start(&label, true, true, 0);
+
+            let id = challenge.id.clone();
+            let stream = self.stream.clone();
+            let button = if challenge.challenger == self.player {
+                let button = Button::with_label("Cancel");
+                button.connect_clicked(move |_| stream.emit(LobbyMsg::Cancel(id.clone())));
+                button
+            } else {
+                let button = Button::with_label("Accept");
+                button.connect_clicked(move |_| stream.emit(LobbyMsg::Accept(id.clone())));
+                button
+            };
+            hbox.pack_end(&button, false, false, 0);
+
+            self.list.add(&hbox);
+        }
+
+        self.list.show_all();
+    }
+}
+
+#[cfg(feature = "gtk3")]
+impl Update for LobbyView {
+    type Model = ();
+    type ModelParam = ();
+    type Msg = LobbyMsg;
+
+    fn model(_: &Relm<Self>, _: ()) {}
+
+    fn update(&mut self, event: LobbyMsg) {
+        match event {
+            LobbyMsg::SetChallenges(challenges) => {
+                self.challenges = challenges;
+                self.render();
+            },
+            LobbyMsg::SetPlayer(player) => {
+                self.player = player;
+                self.render();
+            },
+            LobbyMsg::Create(..) | LobbyMsg::Accept(_) | LobbyMsg::Cancel(_) => {},
+        }
+    }
+}
+
+#[cfg(feature = "gtk3")]
+impl Widget for LobbyView {
+    type Root = gtk::Box;
+
+    fn root(&self) -> Self::Root {
+        self.vbox.clone()
+    }
+
+    fn view(relm: &Relm<Self>, _: ()) -> Self {
+        let list = ListBox::new();
+        list.set_selection_mode(SelectionMode::None);
+
+        let minutes = SpinButton::new(Some(&Adjustment::new(5.0, 0.0, 180.0, 1.0, 5.0, 0.0)), 1.0, 0);
+        let increment = SpinButton::new(Some(&Adjustment::new(3.0, 0.0, 60.0, 1.0, 5.0, 0.0)), 1.0, 0);
+        let variant = ComboBoxText::new();
+        for v in &VARIANTS {
+            variant.append_text(v.uci());
+        }
+        variant.set_active(Some(0));
+        let rated = CheckButton::with_label("Rated");
+        let stake = SpinButton::new(Some(&Adjustment::new(0.0, 0.0, 1e12, 1.0, 100.0, 0.0)), 1.0, 0);
+        stake.set_tooltip_text(Some("Stake, or 0 for none"));
+        let create = Button::with_label("Create");
+
+        {
+            let stream = relm.stream().clone();
+            let (minutes, increment, variant, rated, stake) = (minutes.clone(), increment.clone(), variant.clone(), rated.clone(), stake.clone());
+            create.connect_clicked(move |_| {
+                let time_control = TimeControl::new(Duration::from_secs(minutes.value_as_int() as u64 * 60),
+                                                    Duration::from_secs(increment.value_as_int() as u64));
+                let variant = variant.active().map_or(Variant::Chess, |index| VARIANTS[index as usize]);
+                let stake = Some(stake.value() as u64).filter(|&amount| amount > 0);
+                stream.emit(LobbyMsg::Create(time_control, variant, rated.is_active(), stake));
+            });
+        }
+
+        let form = gtk::Box::new(Orientation::Horizontal, 6);
+        form.pack_start(&minutes, false, false, 0);
+        form.pack_start(&Label::new(Some("+")), false, false, 0);
+        form.pack_start(&increment, false, false, 0);
+        form.pack_start(&variant, false, false, 0);
+        form.pack_start(&rated, false, false, 0);
+        form.pack_start(&stake, false, false, 0);
+        form.pack_end(&create, false, false, 0);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 6);
+        vbox.pack_start(&list, true, true, 0);
+        vbox.pack_start(&form, false, false, 0);
+        vbox.show_all();
+
+        LobbyView {
+            vbox,
+            list,
+            player: String::new(),
+            challenges: Vec::new(),
+            stream: relm.stream().clone(),
+        }
+    }
+}
@@ -0,0 +1,313 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use gtk::prelude::*;
+use gtk::{glib, Adjustment, Button, Grid, Label, PolicyType, ReliefStyle, ScrolledWindow};
+
+use relm::{Relm, Widget, Update, StreamHandle};
+
+use shakmaty::{Color, Position};
+
+use game::{nag_symbol, Game, GameMove};
+use analysis::Judgement;
+use clock::format_time;
+use notation::Notation;
+
+/// Move list messages.
+#[derive(Debug, Msg)]
+pub enum MoveListMsg {
+    /// Show the moves of a game.
+    SetGame(Game),
+    /// Append a move in Standard Algebraic Notation.
+    PushMove(String),
+    /// Write the moves in a localized or figurine notation.
+    SetNotation(Notation),
+    /// Highlight the move leading to a ply. Forward
+    /// `GroundMsg::PlyChanged` here.
+    SetPly(usize),
+    /// Mark the move leading to a game ply, counted from the standard
+    /// starting position. Forward `GroundMsg::MoveJudged` here.
+    JudgeMove(u32, Judgement),
+    /// Show the think time of the move leading to a game ply, counted
+    /// from the standard starting position. Forward
+    /// `GroundMsg::MoveTimed` here.
+    TimeMove(u32, Duration),
+    /// Replace the NAGs and comments of the move leading to a ply of the
+    /// main line, e.g. after `AnnotationMsg::Annotated`.
+    Annotate(usize, Vec<u8>, Vec<String>),
+
+    /// Sent when the move leading to a ply was clicked. Forward to
+    /// `GroundMsg::GoToPly`.
+    MoveClicked(usize),
+}
+
+/// A move with everything shown about it.
+struct Entry {
+    label: Label,
+    san: String,
+    nags: Vec<u8>,
+    comments: Vec<String>,
+    elapsed: Option<Duration>,
+}
+
+impl Entry {
+    /// The move in the notation with its NAG symbols and think time in
+    /// small print, in bold if it is the move shown on the board, followed
+    /// by its comments.
+    fn markup(&self, notation: &Notation, current: bool) -> String {
+        let mut text = notation.write_san(&self.san);
+        for &nag in &self.nags {
+            match nag_symbol(nag) {
+                Some(symbol) => text.push_str(symbol),
+                None => text.push_str(&format!(" ${}", nag)),
+            }
+        }
+
+        let text = glib::markup_escape_text(&text);
+        let mut markup = if current { format!("<b>{}</b>", text) } else { text.to_string() };
+        if let Some(elapsed) = self.elapsed {
+            markup.push_str(&format!(" <small>{}</small>", format_time(elapsed)));
+        }
+        for comment in &self.comments {
+            markup.push_str(&format!("\n<small><i>{}</i></small>", glib::markup_escape_text(comment)));
+        }
+        markup
+    }
+}
+
+/// A side panel with the moves of a game in two columns.
+pub struct MoveListView {
+    scrolled: ScrolledWindow,
+    grid: Grid,
+    stream: StreamHandle<MoveListMsg>,
+    first_move: u32,
+    black_first: bool,
+    moves: Vec<Entry>,
+    ply: usize,
+    follow: Rc<Cell<bool>>,
+    notation: Notation,
+}
+
+impl fmt::Debug for MoveListView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MoveListView")
+            .field("moves", &self.moves.len())
+            .field("ply", &self.ply)
+            .finish()
+    }
+}
+
+impl MoveListView {
+    fn clear(&mut self) {
+        for child in self.grid.children() {
+            self.grid.remove(&child);
+        }
+        self.moves.clear();
+        self.ply = 0;
+    }
+
+    fn push(&mut self, san: String) {
+        let index = self.moves.len() + if self.black_first { 1 } else { 0 };
+        let (row, column) = ((index / 2) as i32, (index % 2) as i32 + 1);
+
+        if column == 1 || self.moves.is_empty() {
+            let number = Label::new(Some(&format!("{}.", self.first_move + row as u32)));
+            number.set_xalign(1.0);
+            number.set_yalign(0.0);
+            self.grid.attach(&number, 0, row, 1, 1);
+
+            if column == 2 {
+                self.grid.attach(&Label::new(Some("\u{2026}")), 1, row, 1, 1);
+            }
+        }
+
+        let label = Label::new(None);
+        label.set_xalign(0.0);
+        label.set_line_wrap(true);
+
+        let button = Button::new();
+        button.set_relief(ReliefStyle::None);
+        button.add(&label);
+
+        let ply = self.moves.len() + 1;
+        let stream = self.stream.clone();
+        button.connect_clicked(move |_| stream.emit(MoveListMsg::MoveClicked(ply)));
+
+        self.grid.attach(&button, column, row, 1, 1);
+        self.grid.show_all();
+
+        let entry = Entry { label, san, nags: Vec::new(), comments: Vec::new(), elapsed: None };
+        entry.label.set_markup(&entry.markup(&self.notation, false));
+        self.moves.push(entry);
+    }
+
+    fn push_game_move(&mut self, m: &GameMove) {
+        self.push(m.san().to_owned());
+        self.edit_entry(self.moves.len() - 1, |entry| {
+            entry.nags = m.nags().to_vec();
+            entry.comments = m.comments().to_vec();
+            entry.elapsed = m.elapsed();
+        });
+    }
+
+    /// Change the move at an index and show it again.
+    fn edit_entry<F: FnOnce(&mut Entry)>(&mut self, index: usize, f: F) {
+        let current = self.ply == index + 1;
+        if let Some(entry) = self.moves.get_mut(index) {
+            f(entry);
+            entry.label.set_markup(&entry.markup(&self.notation, current));
+        }
+    }
+
+    /// Index of the move leading to a game ply.
+    fn index(&self, ply: u32) -> Option<usize> {
+        let first = 2 * (self.first_move - 1) + if self.black_first { 1 } else { 0 };
+        ply.checked_sub(first + 1).map(|index| index as usize)
+    }
+
+    fn set_ply(&mut self, ply: usize) {
+        let previous = self.ply;
+        self.ply = ply;
+        if let Some(index) = previous.checked_sub(1) {
+            self.edit_entry(index, |_| ());
+        }
+
+        // keep following new moves while the last move is shown
+        self.follow.set(ply >= self.moves.len());
+
+        if let Some(index) = ply.checked_sub(1) {
+            self.edit_entry(index, |_| ());
+
+            if let Some(label) = self.moves.get(index).map(|entry| &entry.label) {
+                if let Some((_, y)) = label.translate_coordinates(&self.grid, 0, 0) {
+                    let adjustment = self.scrolled.vadjustment();
+                    adjustment.clamp_page(f64::from(y), f64::from(y + label.allocated_height()));
+                }
+            }
+        }
+    }
+}
+
+impl Update for MoveListView {
+    type Model = ();
+    type ModelParam = ();
+    type Msg = MoveListMsg;
+
+    fn model(_: &Relm<Self>, _: ()) {}
+
+    fn update(&mut self, event: MoveListMsg) {
+        match event {
+            MoveListMsg::SetGame(game) => {
+                self.clear();
+                let initial = game.initial_position();
+                self.first_move = u32::from(initial.fullmoves());
+                self.black_first = initial.turn() == Color::Black;
+                for m in game.moves() {
+                    self.push_game_move(m);
+                }
+                let ply = self.moves.len();
+                self.set_ply(ply);
+            },
+            MoveListMsg::PushMove(san) => {
+                let follow = self.ply == self.moves.len();
+                self.push(san);
+                if follow {
+                    let ply = self.moves.len();
+                    self.set_ply(ply);
+                }
+            },
+            MoveListMsg::SetNotation(notation) => {
+                self.notation = notation;
+                for index in 0..self.moves.len() {
+                    self.edit_entry(index, |_| ());
+                }
+            },
+            MoveListMsg::SetPly(ply) => {
+                self.set_ply(ply);
+            },
+            MoveListMsg::JudgeMove(ply, judgement) => {
+                if let Some(index) = self.index(ply) {
+                    // a judgement replaces the assessment of the move
+                    self.edit_entry(index, |entry| {
+                        entry.nags.retain(|&nag| nag > 6);
+                        entry.nags.insert(0, judgement.nag());
+                    });
+                }
+            },
+            MoveListMsg::TimeMove(ply, elapsed) => {
+                if let Some(index) = self.index(ply) {
+                    self.edit_entry(index, |entry| entry.elapsed = Some(elapsed));
+                }
+            },
+            MoveListMsg::Annotate(ply, nags, comments) => {
+                if let Some(index) = ply.checked_sub(1) {
+                    self.edit_entry(index, |entry| {
+                        entry.nags = nags;
+                        entry.comments = comments;
+                    });
+                }
+            },
+            MoveListMsg::MoveClicked(_) => {},
+        }
+    }
+}
+impl Widget for MoveListView {
+    type Root = ScrolledWindow;
+
+    fn root(&self) -> Self::Root {
+        self.scrolled.clone()
+    }
+
+    fn view(relm: &Relm<Self>, _: ()) -> Self {
+        let grid = Grid::new();
+        grid.set_column_spacing(4);
+
+        let scrolled = ScrolledWindow::new(None::<&Adjustment>, None::<&Adjustment>);
+        scrolled.set_policy(PolicyType::Never, PolicyType::Automatic);
+        scrolled.add(&grid);
+
+        // scroll to the end as moves are added
+        let follow = Rc::new(Cell::new(true));
+        {
+            let follow = Rc::clone(&follow);
+            scrolled.vadjustment().connect_changed(move |adjustment| {
+                if follow.get() {
+                    adjustment.set_value(adjustment.upper() - adjustment.page_size());
+                }
+            });
+        }
+
+        scrolled.show_all();
+
+        MoveListView {
+            scrolled,
+            grid,
+            stream: relm.stream().clone(),
+            first_move: 1,
+            black_first: false,
+            moves: Vec::new(),
+            ply: 0,
+            follow,
+            notation: Notation::english(),
+        }
+    }
+}
@@ -0,0 +1,598 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+
+use shakmaty::Outcome;
+
+use boardstate::BoardState;
+use chain::MoveRecord;
+use commitment::Hash;
+use crypto::{Identity, PlayerKey};
+use lobby::Challenge;
+use tournament::{outcome_from_str, Pairing};
+use wager::GameResult;
+use util::{from_hex, to_hex};
+
+/// Messages exchanged between the two players of a game.
+///
+/// On the wire each message is a JSON object on its own line.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum NetMessage {
+    /// Sent after (re)connecting, with the number of plies the sender knows
+    /// and, if it has the moves, the state root after them. The other side
+    /// replies with `Resync`.
+    Hello { game_id: String, ply: u32, root: Option<Hash> },
+    Move(MoveRecord),
+    /// Reply to `Hello` with the moves the other side is missing, in order.
+    Resync(Vec<MoveRecord>),
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    Resign,
+    /// Remaining time of both players.
+    Clock { white: Duration, black: Duration },
+    /// A line of chat, from a player or a spectator.
+    Chat(ChatMessage),
+    /// A new challenge in the lobby.
+    Challenge(Challenge),
+    /// The challenger withdrew the challenge with this id.
+    CancelChallenge(String),
+    /// A player accepted the challenge with this id.
+    AcceptChallenge { id: String, player: String },
+    /// The pairings of a tournament round.
+    Pairings { tournament: String, round: u32, pairings: Vec<Pairing> },
+    /// A game ended, e.g. to report a tournament result.
+    GameOver { game_id: String, outcome: Outcome },
+    /// Proves the key of the player to a relay, which keeps their seat
+    /// for that key across reconnects. Sent before `Hello`.
+    Identify(Identity),
+}
+
+/// A line of chat in the room of a game.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ChatMessage {
+    /// The room, which is the id of the game.
+    pub room: String,
+    pub author: String,
+    pub text: String,
+    /// Milliseconds since the Unix epoch when it was sent.
+    pub sent: u64,
+}
+
+impl ChatMessage {
+    /// A message sent now.
+    pub fn new(room: &str, author: &str, text: &str) -> ChatMessage {
+        ChatMessage {
+            room: room.to_owned(),
+            author: author.to_owned(),
+            text: text.to_owned(),
+            sent: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "room": self.room,
+            "author": self.author,
+            "text": self.text,
+            "sent": self.sent,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<ChatMessage> {
+        Some(ChatMessage {
+            room: value["room"].as_str()?.to_owned(),
+            author: value["author"].as_str()?.to_owned(),
+            text: value["text"].as_str()?.to_owned(),
+            sent: value["sent"].as_u64().unwrap_or(0),
+        })
+    }
+}
+
+/// Chat rooms by game, with moderation: muted authors and a filter that
+/// can reject any message.
+pub struct ChatRooms {
+    rooms: HashMap<String, Vec<ChatMessage>>,
+    muted: HashSet<String>,
+    filter: Option<Box<dyn Fn(&ChatMessage) -> bool>>,
+    /// Most messages kept per room.
+    capacity: usize,
+}
+
+impl fmt::Debug for ChatRooms {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChatRooms")
+            .field("rooms", &self.rooms.len())
+            .field("muted", &self.muted)
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+impl Default for ChatRooms {
+    fn default() -> ChatRooms {
+        ChatRooms::new()
+    }
+}
+
+impl ChatRooms {
+    pub fn new() -> ChatRooms {
+        ChatRooms {
+            rooms: HashMap::new(),
+            muted: HashSet::new(),
+            filter: None,
+            capacity: 500,
+        }
+    }
+
+    /// Keep at most this many messages per room, dropping the oldest.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        for messages in self.rooms.values_mut() {
+            let excess = messages.len().saturating_sub(capacity);
+            messages.drain(..excess);
+        }
+    }
+
+    /// Store a message in its room. Returns `false` if it was rejected
+    /// because the author is muted or the filter refused it.
+    pub fn receive(&mut self, msg: ChatMessage) -> bool {
+        if self.muted.contains(&msg.author) || self.filter.as_ref().map_or(false, |filter| !filter(&msg)) {
+            return false;
+        }
+
+        let messages = self.rooms.entry(msg.room.clone()).or_insert_with(Vec::new);
+        messages.push(msg);
+        if messages.len() > self.capacity {
+            messages.remove(0);
+        }
+        true
+    }
+
+    /// The messages of a room, oldest first.
+    pub fn messages(&self, room: &str) -> &[ChatMessage] {
+        self.rooms.get(room).map_or(&[], |messages| &messages[..])
+    }
+
+    /// Hide future messages of an author, and remove the stored ones.
+    pub fn mute(&mut self, author: &str) {
+        self.muted.insert(author.to_owned());
+        for messages in self.rooms.values_mut() {
+            messages.retain(|msg| msg.author != author);
+        }
+    }
+
+    pub fn unmute(&mut self, author: &str) {
+        self.muted.remove(author);
+    }
+
+    pub fn is_muted(&self, author: &str) -> bool {
+        self.muted.contains(author)
+    }
+
+    pub fn muted(&self) -> &HashSet<String> {
+        &self.muted
+    }
+
+    /// Only accept messages for which the filter returns `true`, e.g. to
+    /// reject links or profanity.
+    pub fn set_filter<F: Fn(&ChatMessage) -> bool + 'static>(&mut self, filter: F) {
+        self.filter = Some(Box::new(filter));
+    }
+
+    pub fn clear_filter(&mut self) {
+        self.filter = None;
+    }
+}
+
+impl NetMessage {
+    pub fn to_json(&self) -> Value {
+        match *self {
+            NetMessage::Hello { ref game_id, ply, ref root } => json!({
+                "type": "hello",
+                "game": game_id,
+                "ply": ply,
+                "root": root.as_ref().map(|root| to_hex(root)),
+            }),
+            NetMessage::Move(ref record) => json!({ "type": "move", "move": record.to_payload() }),
+            NetMessage::Resync(ref records) => json!({
+                "type": "resync",
+                "moves": records.iter().map(MoveRecord::to_payload).collect::<Vec<_>>(),
+            }),
+            NetMessage::OfferDraw => json!({ "type": "offer_draw" }),
+            NetMessage::AcceptDraw => json!({ "type": "accept_draw" }),
+            NetMessage::DeclineDraw => json!({ "type": "decline_draw" }),
+            NetMessage::Resign => json!({ "type": "resign" }),
+            NetMessage::Clock { white, black } => json!({
+                "type": "clock",
+                "white": white.as_millis() as u64,
+                "black": black.as_millis() as u64,
+            }),
+            NetMessage::Chat(ref msg) => json!({ "type": "chat", "chat": msg.to_json() }),
+            NetMessage::Challenge(ref challenge) => json!({ "type": "challenge", "challenge": challenge.to_json() }),
+            NetMessage::CancelChallenge(ref id) => json!({ "type": "cancel_challenge", "id": id }),
+            NetMessage::AcceptChallenge { ref id, ref player } => json!({ "type": "accept_challenge", "id": id, "player": player }),
+            NetMessage::Pairings { ref tournament, round, ref pairings } => json!({
+                "type": "pairings",
+                "tournament": tournament,
+                "round": round,
+                "pairings": pairings.iter().map(Pairing::to_json).collect::<Vec<_>>(),
+            }),
+            NetMessage::GameOver { ref game_id, outcome } => json!({
+                "type": "game_over",
+                "game": game_id,
+                "result": GameResult::from_outcome(outcome).as_str(),
+            }),
+            NetMessage::Identify(ref identity) => json!({ "type": "identify", "identity": identity.to_json() }),
+        }
+    }
+
+    pub fn from_json(value: &Value) -> Option<NetMessage> {
+        Some(match value["type"].as_str()? {
+            "hello" => NetMessage::Hello {
+                game_id: value["game"].as_str()?.to_owned(),
+                ply: u32::try_from(value["ply"].as_u64()?).ok()?,
+                root: match value["root"].as_str() {
+                    Some(hex) => {
+                        let bytes = from_hex(hex)?;
+                        if bytes.len() != 32 {
+                            return None;
+                        }
+                        let mut root = [0; 32];
+                        root.copy_from_slice(&bytes);
+                        Some(root)
+                    },
+                    None => None,
+                },
+            },
+            "move" => NetMessage::Move(MoveRecord::from_payload(&value["move"])?),
+            "resync" => NetMessage::Resync(
+                value["moves"].as_array()?.iter().map(MoveRecord::from_payload).collect::<Option<_>>()?
+            ),
+            "offer_draw" => NetMessage::OfferDraw,
+            "accept_draw" => NetMessage::AcceptDraw,
+            "decline_draw" => NetMessage::DeclineDraw,
+            "resign" => NetMessage::Resign,
+            "clock" => NetMessage::Clock {
+                white: Duration::from_millis(value["white"].as_u64()?),
+                black: Duration::from_millis(value["black"].as_u64()?),
+            },
+            "chat" => NetMessage::Chat(ChatMessage::from_json(&value["chat"])?),
+            "challenge" => NetMessage::Challenge(Challenge::from_json(&value["challenge"])?),
+            "cancel_challenge" => NetMessage::CancelChallenge(value["id"].as_str()?.to_owned()),
+            "accept_challenge" => NetMessage::AcceptChallenge {
+                id: value["id"].as_str()?.to_owned(),
+                player: value["player"].as_str()?.to_owned(),
+            },
+            "pairings" => NetMessage::Pairings {
+                tournament: value["tournament"].as_str()?.to_owned(),
+                round: u32::try_from(value["round"].as_u64()?).ok()?,
+                pairings: value["pairings"].as_array()?.iter().map(Pairing::from_json).collect::<Option<_>>()?,
+            },
+            "game_over" => NetMessage::GameOver {
+                game_id: value["game"].as_str()?.to_owned(),
+                outcome: outcome_from_str(value["result"].as_str()?)?,
+            },
+            "identify" => NetMessage::Identify(Identity::from_json(&value["identity"])?),
+            _ => return None,
+        })
+    }
+}
+
+/// A disagreement about the moves of a game, found while resynchronizing.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ResyncError {
+    /// The other side has another state root after a ply both know.
+    RootMismatch { ply: u32 },
+    /// A received move differs from the one already known at its ply.
+    ConflictingMove { known: MoveRecord, received: MoveRecord },
+    /// A received move is not legal after the known moves.
+    IllegalMove(MoveRecord),
+    /// A move arrived for a later ply than the next one.
+    MissingPlies { expected: u32, found: u32 },
+    /// A move arrived for a ply too large to count the plies after it.
+    PlyOutOfRange(u32),
+}
+
+impl fmt::Display for ResyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResyncError::RootMismatch { ply } => write!(f, "state root after ply {} differs", ply),
+            ResyncError::ConflictingMove { ref received, .. } => write!(f, "conflicting move at ply {}", received.ply()),
+            ResyncError::IllegalMove(ref record) => {
+                write!(f, "illegal move {}{} at ply {}", record.orig(), record.dest(), record.ply())
+            }
+            ResyncError::MissingPlies { expected, found } => write!(f, "expected ply {}, found {}", expected, found),
+            ResyncError::PlyOutOfRange(ply) => write!(f, "ply {} is out of range", ply),
+        }
+    }
+}
+
+impl Error for ResyncError {}
+
+/// Events received from the other player.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum NetEvent {
+    Message(NetMessage),
+    /// The other side disagrees about the moves so far. The game cannot
+    /// continue over this connection.
+    Conflict(ResyncError),
+    /// The connection was closed. Clients can try `Peer::reconnect()`.
+    Disconnected,
+}
+
+/// A connection to the other player of a game.
+///
+/// Incoming messages are read on a background thread. Moves sent and
+/// received are remembered and replayed from the standard starting
+/// position, so that after a reconnect both sides can compare state roots
+/// and the missing moves can be resent.
+#[derive(Debug)]
+pub struct Peer {
+    game_id: String,
+    addr: Option<SocketAddr>,
+    writer: TcpStream,
+    tx: Sender<NetEvent>,
+    events: Receiver<NetEvent>,
+    moves: Vec<MoveRecord>,
+    replica: BoardState,
+    ply: u32,
+    /// Key to identify with on every (re)connect, for relays.
+    key: Option<PlayerKey>,
+}
+
+impl Peer {
+    /// Connect to a player waiting with `Peer::accept()`. `ply` is the
+    /// number of plies already known, usually 0 for a new game.
+    pub fn connect<A: ToSocketAddrs>(addr: A, game_id: &str, ply: u32) -> io::Result<Peer> {
+        let stream = TcpStream::connect(addr)?;
+        let addr = stream.peer_addr()?;
+        Peer::from_stream(stream, Some(addr), game_id, ply, None)
+    }
+
+    /// Like `Peer::connect()`, but identify with `key` first, so that a
+    /// relay seats the player and keeps the seat across reconnects. The
+    /// moves must be signed with the same key.
+    pub fn connect_as<A: ToSocketAddrs>(addr: A, game_id: &str, ply: u32, key: PlayerKey) -> io::Result<Peer> {
+        let stream = TcpStream::connect(addr)?;
+        let addr = stream.peer_addr()?;
+        Peer::from_stream(stream, Some(addr), game_id, ply, Some(key))
+    }
+
+    /// Wait for the other player to connect.
+    pub fn accept(listener: &TcpListener, game_id: &str, ply: u32) -> io::Result<Peer> {
+        let (stream, _) = listener.accept()?;
+        Peer::from_stream(stream, None, game_id, ply, None)
+    }
+
+    fn from_stream(stream: TcpStream, addr: Option<SocketAddr>, game_id: &str, ply: u32, key: Option<PlayerKey>) -> io::Result<Peer> {
+        let (tx, events) = mpsc::channel();
+
+        let mut peer = Peer {
+            game_id: game_id.to_owned(),
+            addr,
+            writer: stream,
+            tx,
+            events,
+            moves: Vec::new(),
+            replica: BoardState::new(),
+            ply,
+            key,
+        };
+
+        peer.start()?;
+        Ok(peer)
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        let reader = self.writer.try_clone()?;
+        let tx = self.tx.clone();
+        thread::spawn(move || read_messages(reader, &tx));
+
+        if let Some(identity) = self.key.as_ref().map(Identity::new) {
+            self.write(&NetMessage::Identify(identity))?;
+        }
+
+        let hello = NetMessage::Hello {
+            game_id: self.game_id.clone(),
+            ply: self.ply,
+            root: self.root_at(self.ply),
+        };
+        self.write(&hello)
+    }
+
+    /// Connect again after `NetEvent::Disconnected`. Only possible for the
+    /// side that used `Peer::connect()`.
+    pub fn reconnect(&mut self) -> io::Result<()> {
+        let addr = self.addr.ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "accepted peers cannot reconnect"))?;
+        self.writer = TcpStream::connect(addr)?;
+        self.start()
+    }
+
+    /// Take over a new connection of the other player after a disconnect.
+    pub fn reaccept(&mut self, listener: &TcpListener) -> io::Result<()> {
+        let (stream, _) = listener.accept()?;
+        self.writer = stream;
+        self.start()
+    }
+
+    pub fn game_id(&self) -> &str {
+        &self.game_id
+    }
+
+    /// Number of plies sent or received.
+    pub fn ply(&self) -> u32 {
+        self.ply
+    }
+
+    /// All moves sent and received, by ply.
+    pub fn moves(&self) -> &[MoveRecord] {
+        &self.moves
+    }
+
+    /// State root after a ply, if its moves are known.
+    fn root_at(&self, ply: u32) -> Option<Hash> {
+        self.replica.commitment().roots().get(ply as usize).cloned()
+    }
+
+    /// Remember a move. Returns `Ok(false)` for a move that is already
+    /// known.
+    fn record(&mut self, record: &MoveRecord) -> Result<bool, ResyncError> {
+        // the moves from before the peer was created are unknown, so the
+        // later ones cannot be checked
+        if self.moves.is_empty() && self.ply > 0 {
+            let next = record.ply().checked_add(1).ok_or(ResyncError::PlyOutOfRange(record.ply()))?;
+            let new = record.ply() >= self.ply;
+            self.ply = self.ply.max(next);
+            return Ok(new);
+        }
+
+        let expected = self.moves.len() as u32;
+        if record.ply() < expected {
+            let known = &self.moves[record.ply() as usize];
+            return if known.orig() == record.orig() && known.dest() == record.dest() && known.promotion() == record.promotion() {
+                Ok(false)
+            } else {
+                Err(ResyncError::ConflictingMove { known: known.clone(), received: record.clone() })
+            };
+        }
+
+        if record.ply() > expected {
+            return Err(ResyncError::MissingPlies { expected, found: record.ply() });
+        }
+
+        let m = self.replica.legals().iter().find(|m| {
+            m.from() == Some(record.orig()) && m.to() == record.dest() && m.promotion() == record.promotion()
+        }).cloned();
+
+        match m {
+            Some(ref m) if self.replica.play(m).is_ok() => {
+                self.moves.push(record.clone());
+                self.ply = self.ply.max(record.ply() + 1);
+                Ok(true)
+            },
+            _ => Err(ResyncError::IllegalMove(record.clone())),
+        }
+    }
+
+    fn write(&mut self, msg: &NetMessage) -> io::Result<()> {
+        writeln!(self.writer, "{}", msg.to_json())?;
+        self.writer.flush()
+    }
+
+    /// Send a message. Moves that conflict with the known moves are
+    /// refused with `io::ErrorKind::InvalidInput`.
+    pub fn send(&mut self, msg: NetMessage) -> io::Result<()> {
+        if let NetMessage::Move(ref record) = msg {
+            self.record(record).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+        }
+
+        self.write(&msg)
+    }
+
+    /// Send a line of chat to the room of the game.
+    pub fn chat(&mut self, author: &str, text: &str) -> io::Result<()> {
+        let msg = ChatMessage::new(&self.game_id, author, text);
+        self.write(&NetMessage::Chat(msg))
+    }
+
+    /// Get the next event without blocking.
+    ///
+    /// `Hello` messages are answered automatically with `Resync`, but are
+    /// still returned. Moves that were already known and messages of other
+    /// games are dropped. A `Resync` only contains the new moves, which can
+    /// be passed on to `GroundMsg::CatchUp`.
+    pub fn try_recv(&mut self) -> Option<NetEvent> {
+        loop {
+            let event = match self.events.try_recv() {
+                Ok(event) => event,
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => return None,
+            };
+
+            match event {
+                NetEvent::Message(NetMessage::Hello { ref game_id, .. }) if *game_id != self.game_id => continue,
+                NetEvent::Message(NetMessage::Move(ref record)) if record.game_id() != self.game_id => continue,
+                NetEvent::Message(NetMessage::Chat(ref msg)) if msg.room != self.game_id => continue,
+                NetEvent::Message(NetMessage::GameOver { ref game_id, .. }) if *game_id != self.game_id => continue,
+                NetEvent::Message(NetMessage::Hello { ply, root, .. }) => {
+                    if let (Some(root), Some(known)) = (root, self.root_at(ply)) {
+                        if root != known {
+                            return Some(NetEvent::Conflict(ResyncError::RootMismatch { ply }));
+                        }
+                    }
+
+                    let missing: Vec<_> = self.moves.iter().filter(|r| r.ply() >= ply).cloned().collect();
+                    if !missing.is_empty() {
+                        let _ = self.write(&NetMessage::Resync(missing));
+                    }
+                }
+                NetEvent::Message(NetMessage::Move(ref record)) => {
+                    match self.record(record) {
+                        Ok(true) => (),
+                        Ok(false) => continue,
+                        Err(err) => return Some(NetEvent::Conflict(err)),
+                    }
+                }
+                NetEvent::Message(NetMessage::Resync(records)) => {
+                    let mut new = Vec::new();
+                    for record in records {
+                        match self.record(&record) {
+                            Ok(true) => new.push(record),
+                            Ok(false) => (),
+                            Err(err) => return Some(NetEvent::Conflict(err)),
+                        }
+                    }
+                    if new.is_empty() {
+                        continue;
+                    }
+                    return Some(NetEvent::Message(NetMessage::Resync(new)));
+                }
+                _ => (),
+            }
+
+            return Some(event);
+        }
+    }
+}
+
+fn read_messages(stream: TcpStream, tx: &Sender<NetEvent>) {
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        let msg = serde_json::from_str(&line).ok().and_then(|value| NetMessage::from_json(&value));
+        if let Some(msg) = msg {
+            if tx.send(NetEvent::Message(msg)).is_err() {
+                return;
+            }
+        }
+    }
+
+    let _ = tx.send(NetEvent::Disconnected);
+}
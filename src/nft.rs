@@ -0,0 +1,146 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Minting finished games as NFTs. Enabled with the `nft` feature.
+
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use serde_json::Value;
+
+use shakmaty::{Board, Position, Square, EnPassantMode};
+use shakmaty::fen::Fen;
+
+use chain::{ChainClient, ChainError};
+use game::Game;
+use util::{figurine, file_to_float, rank_to_float};
+
+/// Error when minting a game.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MintError {
+    /// The game has no result yet.
+    Unfinished,
+    /// The minter rejected the token or could not be reached.
+    Chain(ChainError),
+}
+
+impl fmt::Display for MintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MintError::Unfinished => f.write_str("only finished games can be minted"),
+            MintError::Chain(ref err) => write!(f, "minting failed: {}", err),
+        }
+    }
+}
+
+impl Error for MintError {}
+
+impl From<ChainError> for MintError {
+    fn from(err: ChainError) -> MintError {
+        MintError::Chain(err)
+    }
+}
+
+/// Creates tokens from metadata. Implement this for other chains or
+/// contracts.
+pub trait Minter {
+    /// Mint a token and return its id.
+    fn mint(&self, metadata: &Value) -> Result<String, MintError>;
+}
+
+/// Mints through the `nft_mint` method of the JSON-RPC endpoint of a
+/// `ChainClient`.
+#[derive(Debug, Clone)]
+pub struct RpcMinter {
+    client: ChainClient,
+}
+
+impl RpcMinter {
+    pub fn new(client: ChainClient) -> RpcMinter {
+        RpcMinter { client }
+    }
+}
+
+impl Minter for RpcMinter {
+    fn mint(&self, metadata: &Value) -> Result<String, MintError> {
+        let token = self.client.call("nft_mint", json!([metadata]))?;
+        match token {
+            Value::String(id) => Ok(id),
+            Value::Number(id) => Ok(id.to_string()),
+            _ => Err(MintError::Chain(ChainError::InvalidResponse)),
+        }
+    }
+}
+
+/// A diagram of a board as SVG, with white at the bottom.
+pub fn board_svg(board: &Board) -> String {
+    let mut svg = String::from(r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 8 8">"#);
+
+    for square in Square::ALL.iter().cloned() {
+        let (x, y) = (file_to_float(square.file()), 7.0 - rank_to_float(square.rank()));
+        let fill = if square.is_light() { "#dee3e6" } else { "#8ca2ad" };
+        svg.push_str(&format!(r#"<rect x="{}" y="{}" width="1" height="1" fill="{}"/>"#, x, y, fill));
+
+        if let Some(piece) = board.piece_at(square) {
+            svg.push_str(&format!(r#"<text x="{}" y="{}" font-size="0.9" text-anchor="middle">{}</text>"#,
+                                  x + 0.5, y + 0.85, figurine(piece)));
+        }
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Token metadata in the common ERC-721 layout: the final position as
+/// image, the players and result as attributes, and the full PGN.
+pub fn game_metadata(game: &Game) -> Result<Value, MintError> {
+    let result = game.header("Result").filter(|r| *r != "*").ok_or(MintError::Unfinished)?;
+    let white = game.header("White").unwrap_or("?");
+    let black = game.header("Black").unwrap_or("?");
+    let pos = game.position();
+
+    Ok(json!({
+        "name": format!("{} vs. {}, {}", white, black, result),
+        "description": game.header("Event").unwrap_or("Chess game"),
+        "image_data": board_svg(pos.board()),
+        "attributes": [
+            { "trait_type": "White", "value": white },
+            { "trait_type": "Black", "value": black },
+            { "trait_type": "Result", "value": result },
+            { "trait_type": "Moves", "value": (game.len() + 1) / 2 },
+        ],
+        "fen": Fen::from_position(pos, EnPassantMode::Legal).to_string(),
+        "pgn": game.to_pgn(),
+    }))
+}
+
+/// Mint a finished game, returning the token id.
+pub fn mint_game<M: Minter>(minter: &M, game: &Game) -> Result<String, MintError> {
+    minter.mint(&game_metadata(game)?)
+}
+
+/// Like `mint_game()`, but on a background thread, so that the token id
+/// can be shown once it arrives.
+pub fn mint_game_in_background<M: Minter + Send + 'static>(minter: M, game: Game) -> Receiver<Result<String, MintError>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(mint_game(&minter, &game));
+    });
+    rx
+}
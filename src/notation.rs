@@ -0,0 +1,118 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Localized Standard Algebraic Notation.
+//!
+//! Moves are kept in English SAN internally and in PGN files. For display
+//! the piece letters are replaced with those of a language, like `S` for
+//! the German Springer, or with figurines.
+
+use shakmaty::{Color, Piece, Role};
+
+use util::figurine;
+
+const ROLES: [Role; 5] = [Role::Knight, Role::Bishop, Role::Rook, Role::Queen, Role::King];
+
+/// How piece letters of SAN are written.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Notation {
+    language: &'static str,
+    /// Letters of knight, bishop, rook, queen and king.
+    letters: [char; 5],
+    figurines: bool,
+}
+
+impl Default for Notation {
+    fn default() -> Notation {
+        Notation::english()
+    }
+}
+
+impl Notation {
+    /// The languages with piece letters.
+    pub const LANGUAGES: [&'static str; 6] = ["en", "de", "fr", "es", "it", "nl"];
+
+    /// Standard SAN, like `Nf3`.
+    pub fn english() -> Notation {
+        Notation { language: "en", letters: ['N', 'B', 'R', 'Q', 'K'], figurines: false }
+    }
+
+    /// The piece letters of a language code like `de` or `de-AT`.
+    pub fn for_language(language: &str) -> Option<Notation> {
+        let (language, letters) = match language.split(|c| c == '-' || c == '_').next()? {
+            "en" => ("en", ['N', 'B', 'R', 'Q', 'K']),
+            "de" => ("de", ['S', 'L', 'T', 'D', 'K']),
+            "fr" => ("fr", ['C', 'F', 'T', 'D', 'R']),
+            "es" => ("es", ['C', 'A', 'T', 'D', 'R']),
+            "it" => ("it", ['C', 'A', 'T', 'D', 'R']),
+            "nl" => ("nl", ['P', 'L', 'T', 'D', 'K']),
+            _ => return None,
+        };
+        Some(Notation { language, letters, figurines: false })
+    }
+
+    /// Figurines like `♘f3` instead of letters when displaying moves. The
+    /// letters are still used for typing moves.
+    pub fn with_figurines(mut self, figurines: bool) -> Notation {
+        self.figurines = figurines;
+        self
+    }
+
+    pub fn language(&self) -> &'static str {
+        self.language
+    }
+
+    pub fn figurines(&self) -> bool {
+        self.figurines
+    }
+
+    /// The letter of a piece, or `None` for pawns.
+    pub fn letter(&self, role: Role) -> Option<char> {
+        ROLES.iter().position(|&r| r == role).map(|i| self.letters[i])
+    }
+
+    fn translate(&self, san: &str, figurines: bool) -> String {
+        san.chars().map(|c| {
+            match Role::from_char(c.to_ascii_lowercase()).filter(|&role| c.is_ascii_uppercase() && role != Role::Pawn) {
+                Some(role) if figurines => figurine(Piece { color: Color::White, role }),
+                Some(role) => self.letter(role).unwrap_or(c),
+                None => c,
+            }
+        }).collect()
+    }
+
+    /// An English SAN move for display, like `Sf3` in German.
+    pub fn write_san(&self, san: &str) -> String {
+        self.translate(san, self.figurines)
+    }
+
+    /// An English SAN move as it would be typed, always with letters.
+    pub fn input_san(&self, san: &str) -> String {
+        self.translate(san, false)
+    }
+
+    /// English SAN from a move typed with the letters of this notation.
+    /// English letters that are not used otherwise are understood, too.
+    pub fn read_san(&self, input: &str) -> String {
+        input.chars().map(|c| {
+            match self.letters.iter().position(|&l| l == c) {
+                Some(i) => ROLES[i].char().to_ascii_uppercase(),
+                None => c,
+            }
+        }).collect()
+    }
+}
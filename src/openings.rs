@@ -0,0 +1,164 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Opening names and ECO codes.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use shakmaty::{Chess, EnPassantMode, Position};
+use shakmaty::fen::Epd;
+use shakmaty::san::San;
+
+use game::Game;
+
+/// The built-in database of `eco`, `name` and `pgn` columns, separated by
+/// tabs.
+const OPENINGS: &str = include_str!("openings.tsv");
+
+/// A named opening.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Opening {
+    eco: String,
+    name: String,
+    ply: usize,
+}
+
+impl Opening {
+    /// Code in the Encyclopaedia of Chess Openings, like `B90`.
+    pub fn eco(&self) -> &str {
+        &self.eco
+    }
+
+    /// Full name, like `Sicilian Defense: Najdorf Variation`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The family of the opening, like `Sicilian Defense`.
+    pub fn family(&self) -> &str {
+        self.name.split(':').next().unwrap_or(&self.name)
+    }
+
+    /// Number of plies of the line that defines the opening.
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+}
+
+impl fmt::Display for Opening {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.eco, self.name)
+    }
+}
+
+fn epd<P: Position + Clone>(pos: &P) -> String {
+    Epd::from_position(pos.clone(), EnPassantMode::Legal).to_string()
+}
+
+/// Openings by position, so that transpositions are recognized.
+pub struct Openings {
+    by_epd: HashMap<String, Opening>,
+}
+
+impl fmt::Debug for Openings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Openings")
+            .field("len", &self.by_epd.len())
+            .finish()
+    }
+}
+
+impl Default for Openings {
+    fn default() -> Openings {
+        Openings::from_tsv(OPENINGS)
+    }
+}
+
+impl Openings {
+    /// The built-in database.
+    pub fn new() -> Openings {
+        Openings::default()
+    }
+
+    /// Read a database with tab separated `eco`, `name` and `pgn` columns,
+    /// like `B01`, `Scandinavian Defense` and `1. e4 d5`. A header line and lines
+    /// with illegal moves are skipped. When several lines reach the same
+    /// position, the first one names it.
+    pub fn from_tsv(data: &str) -> Openings {
+        let mut by_epd = HashMap::new();
+
+        for line in data.lines() {
+            let mut columns = line.split('\t');
+            let (eco, name, pgn) = match (columns.next(), columns.next(), columns.next()) {
+                (Some(eco), Some(name), Some(pgn)) => (eco, name, pgn),
+                _ => continue,
+            };
+
+            let mut pos = Chess::default();
+            let mut ply = 0;
+            let mut legal = true;
+
+            for token in pgn.split_whitespace().filter(|t| !t.ends_with('.')) {
+                match token.parse::<San>().ok().and_then(|san| san.to_move(&pos).ok()) {
+                    Some(m) => {
+                        pos.play_unchecked(&m);
+                        ply += 1;
+                    },
+                    None => {
+                        legal = false;
+                        break;
+                    }
+                }
+            }
+
+            if legal && ply > 0 {
+                by_epd.entry(epd(&pos)).or_insert_with(|| Opening {
+                    eco: eco.to_owned(),
+                    name: name.to_owned(),
+                    ply,
+                });
+            }
+        }
+
+        Openings { by_epd }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_epd.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_epd.is_empty()
+    }
+
+    /// The opening that ends exactly in a position.
+    pub fn get<P: Position + Clone>(&self, pos: &P) -> Option<&Opening> {
+        self.by_epd.get(&epd(pos))
+    }
+
+    /// The deepest named opening reached in the first `ply` plies of the
+    /// main line of a game.
+    pub fn classify_at(&self, game: &Game, ply: usize) -> Option<&Opening> {
+        (1..=ply.min(game.len())).rev().find_map(|ply| self.get(&game.position_at(ply)))
+    }
+
+    /// The deepest named opening reached in the main line of a game.
+    pub fn classify(&self, game: &Game) -> Option<&Opening> {
+        self.classify_at(game, game.len())
+    }
+}
@@ -0,0 +1,177 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! A computer opponent with difficulty levels from 1 to 8.
+//!
+//! The bot searches with a UCI engine, limited in depth, and on the lower
+//! levels sometimes picks one of the weaker lines it found instead of the
+//! best move.
+
+use std::io;
+use std::sync::mpsc::TryRecvError;
+
+use rand_core::{OsRng, RngCore};
+
+use shakmaty::{Color, EnPassantMode, Position};
+use shakmaty::fen::Fen;
+use shakmaty::uci::Uci;
+
+use analysis::centipawns;
+use engine::{Engine, EngineEvent, Limit, MultiPv};
+
+/// The weakest level.
+pub const MIN_LEVEL: u8 = 1;
+
+/// The strongest level, playing the best move at full depth.
+pub const MAX_LEVEL: u8 = 8;
+
+/// How a level searches and chooses: search depth, number of lines,
+/// chance to pick a weaker line in percent, and the most centipawns such a
+/// line may lose against the best one.
+const LEVELS: [(u32, u32, u32, i32); 8] = [
+    (1, 5, 60, 600),
+    (2, 5, 45, 400),
+    (3, 4, 35, 250),
+    (5, 4, 25, 150),
+    (7, 3, 15, 100),
+    (9, 3, 8, 60),
+    (12, 2, 3, 30),
+    (18, 1, 0, 0),
+];
+
+/// A computer opponent playing one side.
+///
+/// Give it to `GroundMsg::SetBot`. Its moves arrive as `UserMove` or
+/// `UserDrop`, exactly like the moves of a human at the board, so they go
+/// through the same handling, e.g. answering with `PlayMove` or sending
+/// them on chain.
+#[derive(Debug)]
+pub struct Bot {
+    engine: Engine,
+    level: u8,
+    color: Color,
+    lines: MultiPv,
+    /// The position being searched.
+    thinking: Option<String>,
+    /// The last position a move was chosen for.
+    answered: Option<String>,
+}
+
+impl Bot {
+    /// A bot playing the given side at a level from 1 to 8.
+    pub fn new(engine: Engine, level: u8, color: Color) -> io::Result<Bot> {
+        let mut bot = Bot {
+            engine,
+            level: MAX_LEVEL,
+            color,
+            lines: MultiPv::new(),
+            thinking: None,
+            answered: None,
+        };
+        bot.set_level(level)?;
+        bot.engine.new_game()?;
+        Ok(bot)
+    }
+
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    /// Change the level, clamped to 1 to 8. Applies from the next search.
+    pub fn set_level(&mut self, level: u8) -> io::Result<()> {
+        self.level = level.max(MIN_LEVEL).min(MAX_LEVEL);
+        let (_, lines, _, _) = self.settings();
+        self.engine.set_multipv(lines)
+    }
+
+    fn settings(&self) -> (u32, u32, u32, i32) {
+        LEVELS[usize::from(self.level - MIN_LEVEL)]
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    pub fn is_thinking(&self) -> bool {
+        self.thinking.is_some()
+    }
+
+    /// Start searching a position, unless it is not the turn of the bot,
+    /// it is already searching, or it already moved in this position.
+    pub(crate) fn think<P: Position + Clone>(&mut self, pos: &P) -> io::Result<()> {
+        let key = key(pos);
+        if pos.turn() != self.color || self.thinking.is_some() || self.answered.as_ref() == Some(&key) {
+            return Ok(());
+        }
+
+        let (depth, _, _, _) = self.settings();
+        self.lines.clear();
+        self.engine.go(pos, Limit::Depth(depth))?;
+        self.thinking = Some(key);
+        Ok(())
+    }
+
+    /// The move chosen for the position that was searched, if the search
+    /// is finished and the position is still the same.
+    pub(crate) fn try_recv<P: Position + Clone>(&mut self, pos: &P) -> Result<Option<Uci>, TryRecvError> {
+        loop {
+            match self.engine.events().try_recv() {
+                Ok(EngineEvent::Info(info)) => {
+                    self.lines.update(info);
+                },
+                Ok(EngineEvent::BestMove { best, .. }) => {
+                    let searched = match self.thinking.take() {
+                        Some(searched) => searched,
+                        None => continue,
+                    };
+                    // the position changed while thinking, e.g. by undo
+                    if searched != key(pos) {
+                        continue;
+                    }
+                    self.answered = Some(searched);
+                    return Ok(self.choose().or(best));
+                },
+                Ok(EngineEvent::Terminated) => return Err(TryRecvError::Disconnected),
+                Ok(_) => (),
+                Err(err) => return if err == TryRecvError::Empty { Ok(None) } else { Err(err) },
+            }
+        }
+    }
+
+    /// Pick the best line, or sometimes a weaker one that does not lose
+    /// too much.
+    fn choose(&self) -> Option<Uci> {
+        let (_, _, chance, max_loss) = self.settings();
+        let lines = self.lines.lines();
+        let best = lines.first()?;
+        let best_score = centipawns(best.score?);
+
+        let weaker: Vec<_> = lines[1..].iter()
+            .filter(|line| line.score.map_or(false, |score| best_score - centipawns(score) <= max_loss))
+            .collect();
+
+        if !weaker.is_empty() && OsRng.next_u32() % 100 < chance {
+            let line = weaker[OsRng.next_u32() as usize % weaker.len()];
+            return line.pv.first().cloned();
+        }
+        best.pv.first().cloned()
+    }
+}
+
+fn key<P: Position + Clone>(pos: &P) -> String {
+    Fen::from_position(pos.clone(), EnPassantMode::Legal).to_string()
+}
@@ -0,0 +1,101 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt;
+
+use gtk::prelude::*;
+use gtk::{Orientation, RadioButton};
+
+use relm::{Relm, Widget, Update};
+
+use shakmaty::{Color, Role, Piece};
+
+use util::figurine;
+
+/// Palette events.
+#[derive(Debug, Msg)]
+pub enum PaletteMsg {
+    /// Sent when a spare piece is picked, or `None` if moving pieces is
+    /// selected.
+    SparePiece(Option<Piece>),
+}
+
+/// A row of buttons to pick the piece placed on the board in edit mode.
+/// Forward `PaletteMsg::SparePiece` to `GroundMsg::SetSparePiece`.
+pub struct Palette {
+    hbox: gtk::Box,
+}
+
+impl fmt::Debug for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Palette").finish()
+    }
+}
+
+impl Update for Palette {
+    type Model = ();
+    type ModelParam = ();
+    type Msg = PaletteMsg;
+
+    fn model(_: &Relm<Self>, _: ()) {}
+
+    fn update(&mut self, _: PaletteMsg) {}
+}
+
+impl Widget for Palette {
+    type Root = gtk::Box;
+
+    fn root(&self) -> Self::Root {
+        self.hbox.clone()
+    }
+
+    fn view(relm: &Relm<Self>, _: ()) -> Self {
+        let hbox = gtk::Box::new(Orientation::Horizontal, 0);
+
+        let mut pieces = vec![None];
+        for &color in &[Color::White, Color::Black] {
+            for &role in &[Role::King, Role::Queen, Role::Rook, Role::Bishop, Role::Knight, Role::Pawn] {
+                pieces.push(Some(role.of(color)));
+            }
+        }
+
+        let mut group: Option<RadioButton> = None;
+
+        for piece in pieces {
+            let label = piece.map_or_else(|| "\u{2725}".to_owned(), |p| figurine(p).to_string());
+
+            let button = match group {
+                Some(ref group) => RadioButton::with_label_from_widget(group, &label),
+                None => RadioButton::with_label(&label),
+            };
+            button.set_mode(false);
+
+            let stream = relm.stream().clone();
+            button.connect_toggled(move |button| {
+                if button.is_active() {
+                    stream.emit(PaletteMsg::SparePiece(piece));
+                }
+            });
+
+            hbox.pack_start(&button, true, true, 0);
+            group.get_or_insert(button);
+        }
+
+        hbox.show_all();
+
+        Palette { hbox }
+    }
+}
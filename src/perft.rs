@@ -0,0 +1,205 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Move generation tests of `BoardState`.
+//!
+//! Perft walks the move tree with `play()` and `undo()` and counts the
+//! leaves, which must match the published node counts and shakmaty
+//! itself. Every undo has to restore the exact position and hash. The
+//! property tests take random walks through games and check that FEN,
+//! SAN and UCI survive a round trip.
+
+use proptest::prelude::*;
+
+use shakmaty::{perft as shakmaty_perft, CastlingMode, Move};
+use shakmaty::uci::Uci;
+use shakmaty::variant::Variant;
+
+use boardstate::BoardState;
+use zobrist::zobrist_hash;
+
+fn perft(state: &mut BoardState, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves: Vec<Move> = state.legals().iter().cloned().collect();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    let (fen, zobrist) = (state.fen(), state.zobrist());
+    let mut nodes = 0;
+
+    for m in &moves {
+        state.play(m).expect("legal move");
+        assert_eq!(state.zobrist(), zobrist_hash(state.setup()), "incremental hash after {:?} from {}", m, fen);
+        nodes += perft(state, depth - 1);
+        assert!(state.undo(), "undo {:?} from {}", m, fen);
+        assert_eq!(state.fen(), fen, "position after undoing {:?}", m);
+        assert_eq!(state.zobrist(), zobrist, "hash after undoing {:?} from {}", m, fen);
+    }
+
+    nodes
+}
+
+/// Check the node counts from depth 1 on.
+fn assert_perft(variant: Variant, mode: CastlingMode, fen: &str, expected: &[u64]) {
+    let mut state = BoardState::new();
+    state.set_castling_mode(mode);
+    state.set_variant(variant);
+    state.set_fen(fen).expect("valid fen");
+
+    let pos = state.position().expect("legal position");
+
+    for (depth, &nodes) in (1..).zip(expected) {
+        assert_eq!(shakmaty_perft(&pos, depth), nodes, "shakmaty perft({}) of {}", depth, fen);
+        assert_eq!(perft(&mut state, depth), nodes, "perft({}) of {}", depth, fen);
+    }
+}
+
+const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[test]
+fn perft_starting_position() {
+    assert_perft(Variant::Chess, CastlingMode::Standard, STARTING_FEN, &[20, 400, 8902]);
+}
+
+#[test]
+fn perft_kiwipete() {
+    assert_perft(Variant::Chess, CastlingMode::Standard,
+                 "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                 &[48, 2039]);
+}
+
+#[test]
+fn perft_en_passant_and_pins() {
+    assert_perft(Variant::Chess, CastlingMode::Standard,
+                 "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                 &[14, 191, 2812]);
+}
+
+#[test]
+fn perft_promotions() {
+    assert_perft(Variant::Chess, CastlingMode::Standard,
+                 "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                 &[6, 264, 9467]);
+    assert_perft(Variant::Chess, CastlingMode::Standard,
+                 "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                 &[44, 1486]);
+}
+
+#[test]
+fn perft_chess960() {
+    assert_perft(Variant::Chess, CastlingMode::Chess960,
+                 "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                 &[21, 528, 12189]);
+}
+
+#[test]
+fn perft_variants() {
+    // no castling in antichess
+    assert_perft(Variant::Antichess, CastlingMode::Standard,
+                 "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+                 &[20, 400, 8067]);
+    assert_perft(Variant::Atomic, CastlingMode::Standard, STARTING_FEN, &[20, 400, 8902]);
+    assert_perft(Variant::KingOfTheHill, CastlingMode::Standard, STARTING_FEN, &[20, 400, 8902]);
+    assert_perft(Variant::RacingKings, CastlingMode::Standard,
+                 "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1",
+                 &[21, 421, 11264]);
+    assert_perft(Variant::Horde, CastlingMode::Standard,
+                 "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1",
+                 &[8, 128, 1274]);
+}
+
+/// Play a random walk of moves from the starting position, picking the
+/// move at each index modulo the number of legal moves.
+fn walk(indices: &[usize]) -> (BoardState, Vec<Move>) {
+    let mut state = BoardState::new();
+    let mut played = Vec::new();
+
+    for &index in indices {
+        let m = match state.legals().iter().nth(index % state.legals().len().max(1)) {
+            Some(m) => m.clone(),
+            None => break,
+        };
+        state.play(&m).expect("legal move");
+        played.push(m);
+    }
+
+    (state, played)
+}
+
+fn walks() -> impl Strategy<Value = Vec<usize>> {
+    prop::collection::vec(any::<usize>(), 0..80)
+}
+
+proptest! {
+    #[test]
+    fn fen_round_trip(indices in walks()) {
+        let (state, _) = walk(&indices);
+
+        let mut parsed = BoardState::new();
+        parsed.set_fen(&state.fen()).expect("own fen parses");
+        prop_assert_eq!(parsed.fen(), state.fen());
+        prop_assert_eq!(parsed.zobrist(), state.zobrist());
+        prop_assert_eq!(parsed.legals().len(), state.legals().len());
+    }
+
+    #[test]
+    fn san_round_trip(indices in walks()) {
+        let (state, _) = walk(&indices);
+
+        for m in state.legals() {
+            let san = state.san(m).expect("legal move has san");
+            prop_assert_eq!(&state.parse_san(&san).expect("own san parses"), m, "san {}", san);
+        }
+    }
+
+    #[test]
+    fn uci_round_trip(indices in walks()) {
+        let (state, played) = walk(&indices);
+
+        let pos = state.position().expect("legal position");
+        for m in state.legals() {
+            let uci = Uci::from_move(m, state.castling_mode());
+            prop_assert_eq!(&uci.to_move(&pos).expect("own uci is legal"), m, "uci {}", uci);
+        }
+
+        // replaying the game as a uci move list reaches the same position
+        let moves: Vec<String> = played.iter().map(|m| Uci::from_move(m, CastlingMode::Standard).to_string()).collect();
+        let mut replayed = BoardState::new();
+        prop_assert_eq!(replayed.apply_uci_moves(&moves.join(" ")).expect("own uci moves apply"), played.len());
+        prop_assert_eq!(replayed.fen(), state.fen());
+    }
+
+    #[test]
+    fn undo_redo_round_trip(indices in walks()) {
+        let (mut state, played) = walk(&indices);
+        let fen = state.fen();
+
+        for _ in 0..played.len() {
+            prop_assert!(state.undo());
+        }
+        prop_assert_eq!(state.fen(), STARTING_FEN);
+
+        for _ in 0..played.len() {
+            prop_assert!(state.redo());
+        }
+        prop_assert_eq!(state.fen(), fen);
+    }
+}
@@ -0,0 +1,627 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::error::Error;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::time::Duration;
+
+use shakmaty::{Chess, CastlingMode, Color, EnPassantMode, Position};
+use shakmaty::fen::Fen;
+use shakmaty::san::SanPlus;
+
+use game::{Game, GameMove};
+use gametree::{GameTree, NodeId};
+use notation::Notation;
+
+/// Error when parsing PGN.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PgnError {
+    /// A `{` comment is not closed.
+    UnterminatedComment,
+    /// A `[` header tag is not closed.
+    UnterminatedTag,
+    /// A header tag is not of the form `[Name "Value"]`.
+    InvalidTag(String),
+    /// The `FEN` header does not describe a legal position.
+    InvalidFen(String),
+    /// A move could not be parsed or is illegal in the position.
+    InvalidMove { ply: usize, san: String },
+    /// The text does not contain a game.
+    NoGame,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PgnError::UnterminatedComment => f.write_str("unterminated comment"),
+            PgnError::UnterminatedTag => f.write_str("unterminated header tag"),
+            PgnError::InvalidTag(ref tag) => write!(f, "invalid header tag: [{}]", tag),
+            PgnError::InvalidFen(ref fen) => write!(f, "invalid fen header: {}", fen),
+            PgnError::InvalidMove { ply, ref san } => write!(f, "invalid move at ply {}: {}", ply + 1, san),
+            PgnError::NoGame => f.write_str("no game found"),
+        }
+    }
+}
+
+impl Error for PgnError {}
+
+/// The Seven Tag Roster with placeholder values.
+const ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+impl Game {
+    /// Parse the first game of a PGN text, including variations.
+    pub fn from_pgn(pgn: &str) -> Result<Game, PgnError> {
+        read_games(pgn)?.into_iter().next().ok_or(PgnError::NoGame)
+    }
+
+    /// Write the game as PGN.
+    ///
+    /// The Seven Tag Roster comes first, using placeholders for missing
+    /// headers, followed by all other headers. Comments, NAGs, clock
+    /// and think times are written after each move, followed by its variations.
+    pub fn to_pgn(&self) -> String {
+        self.to_pgn_with(&Notation::english())
+    }
+
+    /// Like `to_pgn()`, but with the moves in a localized or figurine
+    /// notation for reading or printing. Other programs only read PGN in
+    /// English notation.
+    pub fn to_pgn_with(&self, notation: &Notation) -> String {
+        let mut pgn = String::new();
+
+        for &(name, default) in &ROSTER {
+            write_tag(&mut pgn, name, self.header(name).unwrap_or(default));
+        }
+
+        let initial_fen = Fen::from_position(self.initial_position().clone(), EnPassantMode::Legal).to_string();
+        if self.header("FEN").is_none() && initial_fen != Fen::from_position(Chess::default(), EnPassantMode::Legal).to_string() {
+            write_tag(&mut pgn, "SetUp", "1");
+            write_tag(&mut pgn, "FEN", &initial_fen);
+        }
+
+        // the castling rights of the FEN only read back in the right mode
+        if self.header("Variant").is_none() && self.initial_position().castles().mode() == CastlingMode::Chess960 {
+            write_tag(&mut pgn, "Variant", "Chess960");
+        }
+
+        for &(ref name, ref value) in self.headers() {
+            if !ROSTER.iter().any(|&(n, _)| n == name) {
+                write_tag(&mut pgn, name, value);
+            }
+        }
+
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+
+        if let Some(comment) = self.comment() {
+            tokens.push(format!("{{ {} }}", escape_comment(comment)));
+        }
+
+        let tree = self.tree();
+        write_moves(tree, notation, &mut tokens, tree.root(), self.initial_position().clone(), false);
+
+        tokens.push(self.header("Result").unwrap_or("*").to_owned());
+
+        // wrap movetext at 80 columns
+        let mut line = 0;
+        for token in tokens {
+            let width = token.chars().count();
+            if line > 0 && line + 1 + width > 79 {
+                pgn.push('\n');
+                line = 0;
+            } else if line > 0 {
+                pgn.push(' ');
+                line += 1;
+            }
+            line += width;
+            pgn.push_str(&token);
+        }
+
+        pgn.push_str("\n\n");
+        pgn
+    }
+}
+
+fn write_tag(pgn: &mut String, name: &str, value: &str) {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+}
+
+fn escape_comment(comment: &str) -> String {
+    comment.replace('}', "")
+}
+
+fn format_command(name: &str, time: Duration) -> String {
+    let secs = time.as_secs();
+    format!("[%{} {}:{:02}:{:02}]", name, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Write the moves after `node`, where `pos` is the position at `node`.
+/// `numbered` tells if the move number of a black reply can be omitted.
+fn write_moves(tree: &GameTree, notation: &Notation, tokens: &mut Vec<String>, mut node: NodeId, mut pos: Chess, mut numbered: bool) {
+    while let Some(main) = tree.next(node) {
+        let m = tree.game_move(main).expect("only the root has no move");
+        write_move(notation, tokens, &pos, m, &mut numbered);
+
+        for &variation in &tree.children(node)[1..] {
+            let m = tree.game_move(variation).expect("only the root has no move");
+
+            let start = tokens.len();
+            let mut variation_numbered = false;
+            write_move(notation, tokens, &pos, m, &mut variation_numbered);
+            tokens[start].insert(0, '(');
+
+            let mut variation_pos = pos.clone();
+            variation_pos.play_unchecked(m.mv());
+            write_moves(tree, notation, tokens, variation, variation_pos, variation_numbered);
+
+            if let Some(last) = tokens.last_mut() {
+                last.push(')');
+            }
+
+            // resume the main line with a move number
+            numbered = false;
+        }
+
+        pos.play_unchecked(m.mv());
+        node = main;
+    }
+}
+
+fn write_move(notation: &Notation, tokens: &mut Vec<String>, pos: &Chess, m: &GameMove, numbered: &mut bool) {
+    let number = u32::from(pos.fullmoves());
+    match pos.turn() {
+        Color::White => tokens.push(format!("{}.", number)),
+        Color::Black if !*numbered => tokens.push(format!("{}...", number)),
+        Color::Black => (),
+    }
+
+    tokens.push(notation.write_san(m.san()));
+    tokens.extend(m.nags().iter().map(|nag| format!("${}", nag)));
+
+    let mut comment = m.comments().iter().map(|c| escape_comment(c)).collect::<Vec<_>>().join(" ");
+    let commands = m.clock().map(|clock| format_command("clk", clock)).into_iter()
+        .chain(m.elapsed().map(|elapsed| format_command("emt", elapsed)))
+        .collect::<Vec<_>>();
+    if !commands.is_empty() {
+        let commands = commands.join(" ");
+        comment = if comment.is_empty() { commands } else { format!("{} {}", commands, comment) };
+    }
+
+    // a comment interrupts the move pair, so the next black move
+    // needs its own number
+    *numbered = comment.is_empty();
+    if !comment.is_empty() {
+        tokens.push(format!("{{ {} }}", comment));
+    }
+}
+
+/// Split a time command like `[%clk h:mm:ss]` from a comment.
+fn extract_time(comment: &str, name: &str) -> (Option<Duration>, String) {
+    let command = format!("[%{}", name);
+    let start = match comment.find(&command) {
+        Some(start) => start,
+        None => return (None, comment.to_owned()),
+    };
+
+    let end = match comment[start..].find(']') {
+        Some(end) => start + end,
+        None => return (None, comment.to_owned()),
+    };
+
+    let mut secs = 0.0;
+    for part in comment[start + command.len()..end].trim().split(':') {
+        match part.parse::<f64>() {
+            Ok(value) => secs = secs * 60.0 + value,
+            Err(_) => return (None, comment.to_owned()),
+        }
+    }
+
+    let rest = format!("{} {}", comment[..start].trim(), comment[end + 1..].trim());
+    (Some(Duration::from_millis((secs * 1000.0) as u64)), rest.trim().to_owned())
+}
+
+/// Parse the base time and increment of a `TimeControl` header like
+/// `300+2`.
+fn parse_time_control(value: &str) -> Option<(Duration, Duration)> {
+    let mut parts = value.splitn(2, '+');
+    let base = parts.next()?.parse().ok()?;
+    let increment = parts.next().map_or(Some(0), |inc| inc.parse().ok())?;
+    Some((Duration::from_secs(base), Duration::from_secs(increment)))
+}
+
+/// Fill in the think times of moves without `[%emt]` from the clock
+/// times of the side before and after the move, and the increment of the
+/// `TimeControl` header.
+fn derive_elapsed(game: &mut Game) {
+    let (base, increment) = match game.header("TimeControl").and_then(parse_time_control) {
+        Some((base, increment)) => (Some(base), increment),
+        None => (None, Duration::from_secs(0)),
+    };
+
+    let tree = game.tree_mut();
+    let root = tree.root();
+    let mut stack = tree.children(root).to_vec();
+
+    while let Some(node) = stack.pop() {
+        stack.extend_from_slice(tree.children(node));
+
+        // the clock of the same side one move earlier
+        let before = match tree.parent(node).and_then(|parent| tree.parent(parent)) {
+            Some(earlier) if earlier != root => tree.game_move(earlier).and_then(GameMove::clock),
+            _ => base,
+        };
+
+        if let Some(m) = tree.game_move_mut(node) {
+            if m.elapsed().is_none() {
+                let elapsed = match (before, m.clock()) {
+                    (Some(before), Some(after)) => (before + increment).checked_sub(after),
+                    _ => None,
+                };
+                m.set_elapsed(elapsed);
+            }
+        }
+    }
+}
+
+/// Parse all games of a PGN text.
+pub fn read_games(pgn: &str) -> Result<Vec<Game>, PgnError> {
+    let mut games = Vec::new();
+    let mut builder: Option<Builder> = None;
+
+    for token in tokenize(pgn)? {
+        match token {
+            Token::Tag(name, value) => {
+                // a tag after the movetext starts the next game
+                if builder.as_ref().map_or(false, |b| b.game.is_some()) {
+                    games.extend(builder.take().map(Builder::finish));
+                }
+
+                builder.get_or_insert_with(Builder::new).headers.push((name, value));
+            }
+            token => {
+                builder.get_or_insert_with(Builder::new).token(token)?;
+            }
+        }
+    }
+
+    games.extend(builder.map(Builder::finish));
+    Ok(games)
+}
+
+#[derive(Debug)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    Open,
+    Close,
+    Symbol(String),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                line_start = true;
+                continue;
+            }
+            '%' if line_start => skip_line(&mut chars),
+            ';' => skip_line(&mut chars),
+            c if c.is_whitespace() => continue,
+            '[' => tokens.push(read_tag(&mut chars)?),
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err(PgnError::UnterminatedComment),
+                    }
+                }
+                tokens.push(Token::Comment(comment.trim().to_owned()));
+            }
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '$' => {
+                let mut nag = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_digit() {
+                        break;
+                    }
+                    nag.push(c);
+                    chars.next();
+                }
+                if let Ok(nag) = nag.parse() {
+                    tokens.push(Token::Nag(nag));
+                }
+            }
+            c => {
+                let mut symbol = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "[]{}();$".contains(c) {
+                        break;
+                    }
+                    symbol.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Symbol(symbol));
+            }
+        }
+
+        line_start = false;
+    }
+
+    Ok(tokens)
+}
+
+fn skip_line(chars: &mut Peekable<Chars>) {
+    while let Some(&c) = chars.peek() {
+        if c == '\n' {
+            break;
+        }
+        chars.next();
+    }
+}
+
+fn read_tag(chars: &mut Peekable<Chars>) -> Result<Token, PgnError> {
+    let mut content = String::new();
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+
+    loop {
+        let c = chars.next().ok_or(PgnError::UnterminatedTag)?;
+
+        if quoted {
+            content.push(c);
+            if escaped {
+                value.push(c);
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                quoted = false;
+            } else {
+                value.push(c);
+            }
+        } else if c == ']' {
+            break;
+        } else {
+            content.push(c);
+            quoted = c == '"';
+        }
+    }
+
+    let name: String = content.trim_start().chars().take_while(|c| !c.is_whitespace() && *c != '"').collect();
+    if name.is_empty() || !content.contains('"') {
+        return Err(PgnError::InvalidTag(content));
+    }
+
+    Ok(Token::Tag(name, value))
+}
+
+fn nag_from_suffix(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+struct Builder {
+    headers: Vec<(String, String)>,
+    game: Option<Game>,
+    /// The last move read, and the position after it.
+    node: Option<(NodeId, Chess)>,
+    /// Where to continue after each open variation.
+    variations: Vec<(NodeId, Chess)>,
+    /// Comments before the first move of a variation.
+    pending: Vec<String>,
+}
+
+impl Builder {
+    fn new() -> Builder {
+        Builder {
+            headers: Vec::new(),
+            game: None,
+            node: None,
+            variations: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn game(&mut self) -> Result<&mut Game, PgnError> {
+        if self.game.is_none() {
+            let mode = match self.headers.iter().find(|h| h.0 == "Variant") {
+                Some(h) if h.1.eq_ignore_ascii_case("chess960") => CastlingMode::Chess960,
+                _ => CastlingMode::Standard,
+            };
+
+            let initial = match self.headers.iter().find(|h| h.0 == "FEN") {
+                Some(h) => h.1.parse::<Fen>().ok()
+                    .and_then(|fen| fen.into_position(mode).ok())
+                    .ok_or_else(|| PgnError::InvalidFen(h.1.clone()))?,
+                None => Chess::default(),
+            };
+
+            let mut game = Game::from_position(initial.clone());
+            for &(ref name, ref value) in &self.headers {
+                game.set_header(name, value);
+            }
+            self.node = Some((game.tree().root(), initial));
+            self.game = Some(game);
+        }
+
+        Ok(self.game.as_mut().expect("game started"))
+    }
+
+    /// The node of the last move read and the position after it.
+    fn node(&mut self) -> Result<(&mut Game, &mut (NodeId, Chess)), PgnError> {
+        self.game()?;
+        match (self.game.as_mut(), self.node.as_mut()) {
+            (Some(game), Some(node)) => Ok((game, node)),
+            _ => unreachable!("game started"),
+        }
+    }
+
+    fn token(&mut self, token: Token) -> Result<(), PgnError> {
+        match token {
+            Token::Open => {
+                // a variation replaces the last move
+                let (resume, parent) = {
+                    let (game, node) = self.node()?;
+                    let tree = game.tree();
+                    let parent = tree.parent(node.0).unwrap_or_else(|| tree.root());
+                    (node.clone(), (parent, tree.position_at(parent)))
+                };
+                self.variations.push(resume);
+                self.node = Some(parent);
+                self.pending.clear();
+            }
+            Token::Close => {
+                if let Some(node) = self.variations.pop() {
+                    self.node = Some(node);
+                }
+                self.pending.clear();
+            }
+            Token::Comment(comment) => {
+                let in_variation = !self.variations.is_empty();
+                let (game, &mut (node, _)) = self.node()?;
+                if let Some(last) = game.tree_mut().game_move_mut(node) {
+                    let (clock, comment) = extract_time(&comment, "clk");
+                    if clock.is_some() {
+                        last.set_clock(clock);
+                    }
+                    let (elapsed, comment) = extract_time(&comment, "emt");
+                    if elapsed.is_some() {
+                        last.set_elapsed(elapsed);
+                    }
+                    if !comment.is_empty() {
+                        last.push_comment(comment);
+                    }
+                } else if !in_variation {
+                    game.set_comment(Some(comment));
+                } else {
+                    self.pending.push(comment);
+                }
+            }
+            Token::Nag(nag) => self.nag(nag)?,
+            Token::Symbol(symbol) => self.symbol(&symbol)?,
+            Token::Tag(..) => (),
+        }
+
+        Ok(())
+    }
+
+    fn nag(&mut self, nag: u8) -> Result<(), PgnError> {
+        let (game, &mut (node, _)) = self.node()?;
+        if let Some(last) = game.tree_mut().game_move_mut(node) {
+            last.push_nag(nag);
+        }
+        Ok(())
+    }
+
+    fn symbol(&mut self, symbol: &str) -> Result<(), PgnError> {
+        if let "1-0" | "0-1" | "1/2-1/2" | "*" = symbol {
+            let game = self.game()?;
+            if game.header("Result").is_none() {
+                game.set_header("Result", symbol);
+            }
+            return Ok(());
+        }
+
+        // strip move numbers like 12. or 12...
+        let digits = symbol.find(|c: char| !c.is_ascii_digit()).unwrap_or(symbol.len());
+        let symbol = if digits > 0 && symbol[digits..].starts_with('.') {
+            symbol[digits..].trim_start_matches('.')
+        } else {
+            symbol
+        };
+
+        let (san, suffix) = symbol.split_at(symbol.trim_end_matches(|c: char| c == '!' || c == '?').len());
+
+        if !san.is_empty() {
+            let node = {
+                let (game, &mut (ref mut node, ref mut pos)) = self.node()?;
+                // the ply walks back to the root, so only count it for errors
+                let parent = *node;
+                let invalid = |game: &Game| PgnError::InvalidMove { ply: game.tree().ply(parent), san: san.to_owned() };
+
+                let m = match san.replace('0', "O").parse::<SanPlus>().ok().and_then(|san| san.san.to_move(&*pos).ok()) {
+                    Some(m) => m,
+                    None => return Err(invalid(game)),
+                };
+
+                pos.play_unchecked(&m);
+                *node = match game.tree_mut().push(parent, m) {
+                    Ok(node) => node,
+                    Err(_) => return Err(invalid(game)),
+                };
+                *node
+            };
+
+            // comments at the start of a variation belong to its first move
+            let pending = self.pending.split_off(0);
+            if let Some(first) = self.game.as_mut().and_then(|g| g.tree_mut().game_move_mut(node)) {
+                for comment in pending {
+                    first.push_comment(comment);
+                }
+            }
+        }
+
+        if let Some(nag) = nag_from_suffix(suffix) {
+            self.nag(nag)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Game {
+        match self.game {
+            Some(mut game) => {
+                derive_elapsed(&mut game);
+                game
+            },
+            None => {
+                let mut game = Game::new();
+                for (name, value) in self.headers {
+                    game.set_header(&name, &value);
+                }
+                game
+            }
+        }
+    }
+}
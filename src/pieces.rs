@@ -14,26 +14,82 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::VecDeque;
 use std::f64::consts::PI;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use time::SteadyTime;
-
-use gdk::EventButton;
 use cairo::Context;
-use rsvg::HandleExt;
+use gdk::ModifierType;
 
-use shakmaty::{Square, Piece, Bitboard, Board};
+use shakmaty::{Square, Role, Piece, Bitboard, Board};
 
-use util::{ease, file_to_float, pos_to_square, rank_to_float, square_to_pos};
+use util::{ease, file_to_float, flip_pos, rank_to_float, snap_to_square, square_to_pos};
 use promotable::Promotable;
-use boardstate::BoardState;
+use boardstate::{BoardState, MoveInput};
 use ground::{GroundMsg, EventContext, WidgetContext};
+use renderer::Renderer;
+use theme::Rgba;
+use timing::{real_time, TimeSource};
+
+/// Size of the dragged piece relative to a square.
+const DRAG_SCALE: f64 = 1.5;
+
+/// Opacity of the ghost left on the origin square while dragging.
+const GHOST_ALPHA: f64 = 0.3;
+
+/// Opacity of the tint on a square the dragged piece cannot go to.
+const ILLEGAL_ALPHA: f64 = 0.4;
+
+/// Opacity of the shade on squares the hovered piece cannot go to.
+const HOVER_DIM_ALPHA: f64 = 0.25;
+
+/// A piece dropped this recently snaps to its square instead of gliding.
+const SNAP_AFTER_DRAG: Duration = Duration::from_millis(200);
+
+/// How the pieces are shown, e.g. for blindfold training.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Blindfold {
+    /// Pieces are shown normally.
+    Off,
+    /// Pieces are not shown at all.
+    Hidden,
+    /// Every piece is shown as the same token, hiding its role and color.
+    Tokens,
+}
 
 pub struct Pieces {
     figurines: Vec<Figurine>,
     selected: Option<Square>,
     drag: Option<Drag>,
-    past: SteadyTime,
+    time_source: Arc<dyn TimeSource>,
+    past: Instant,
+    /// How long a piece takes to glide to its square, in microseconds.
+    animation: f64,
+    hover: Option<Hover>,
+    /// Number of hovers so far, to tell them apart.
+    hovers: usize,
+    /// Board changes still being animated, oldest first.
+    transitions: VecDeque<Transition>,
+    next_transition: usize,
+}
+
+/// A piece of the side to move under the pointer. Its legal squares are
+/// shown once the pointer rested on it for the delay of
+/// `BoardState::hover_hints()`.
+struct Hover {
+    square: Square,
+    id: usize,
+    shown: bool,
+}
+
+/// A change of the board being animated, like a move, castling, or a
+/// takeback. All figurines it moves, captures or brings back share its
+/// start time, so they set off and arrive together.
+struct Transition {
+    id: usize,
+    /// When the change started, taken at the next frame.
+    time: Option<Instant>,
 }
 
 struct Drag {
@@ -42,17 +98,43 @@ struct Drag {
     start: (f64, f64),
     pos: (f64, f64),
     threshold: bool,
+    /// Role picked with the scroll wheel for a promotion on the square.
+    promotion: Option<(Square, Role)>,
+    /// Legal destinations, computed when the drag starts, or `None` if
+    /// any square is accepted, like in edit mode or for premoves.
+    dests: Option<Bitboard>,
+}
+
+impl Drag {
+    fn accepts(&self, square: Square) -> bool {
+        self.dests.map_or(true, |dests| dests.contains(square))
+    }
+
+    /// The square the piece would be dropped on, snapping to the nearest
+    /// accepted square within `radius`.
+    fn target(&self, radius: f64) -> Option<Square> {
+        snap_to_square(self.pos, radius, |square| square == self.square || self.accepts(square))
+    }
 }
 
 pub struct Figurine {
     square: Square,
     piece: Piece,
     start: (f64, f64),
+    /// Control point bending the path to the square, e.g. around the
+    /// corner of a knight jump, or `None` to glide straight.
+    via: Option<(f64, f64)>,
     elapsed: f64,
-    time: SteadyTime,
-    last_drag: SteadyTime,
+    /// When the current animation started, taken at the next frame.
+    time: Option<Instant>,
+    /// The board change the current animation belongs to, or `None` if
+    /// it runs on its own.
+    transition: Option<usize>,
+    last_drag: Instant,
     fading: bool,
     replaced: bool,
+    /// Fading in, e.g. a captured piece brought back by a takeback.
+    appearing: bool,
     dragging: bool,
 }
 
@@ -62,30 +144,54 @@ impl Pieces {
     }
 
     pub fn new_from_board(board: &Board) -> Pieces {
-        let now = SteadyTime::now();
+        let time_source = real_time();
+        let now = time_source.now();
 
         Pieces {
             selected: None,
             drag: None,
+            time_source,
             past: now,
+            animation: 300_000.0,
+            hover: None,
+            hovers: 0,
+            transitions: VecDeque::new(),
+            next_transition: 0,
             figurines: board.clone().into_iter().map(|(square, piece)| Figurine {
                 square,
                 piece,
                 start: (0.5 + file_to_float(square.file()), 7.5 - rank_to_float(square.rank())),
+                via: None,
                 elapsed: 0.0,
-                time: now,
+                time: None,
+                transition: None,
                 last_drag: now,
                 fading: false,
                 replaced: false,
+                appearing: false,
                 dragging: false,
             }).collect(),
         }
     }
 
+    /// Take the time for animations and drags from `time_source`.
+    pub fn with_time_source(mut self, time_source: Arc<dyn TimeSource>) -> Pieces {
+        let now = time_source.now();
+        self.past = now;
+        for figurine in &mut self.figurines {
+            figurine.last_drag = now;
+        }
+        self.time_source = time_source;
+        self
+    }
+
     pub fn set_board(&mut self, board: &Board) {
         // clean faded figurines
-        let now = SteadyTime::now();
+        let now = self.time_source.now();
         self.figurines.retain(|f| !f.fading || f.alpha() > 0.0001);
+        self.hover = None;
+
+        let transition = self.begin_transition();
 
         // diff
         let mut added: Vec<_> = board.clone().into_iter().filter(|&(sq, piece)| {
@@ -101,8 +207,11 @@ impl Pieces {
             if !board.by_piece(figurine.piece).contains(figurine.square) {
                 // checkpoint animation
                 figurine.start = figurine.pos();
+                figurine.via = None;
                 figurine.elapsed = 0.0;
-                figurine.time = now;
+                figurine.time = None;
+                figurine.transition = Some(transition);
+                figurine.appearing = false;
 
                 // cancel drag
                 if figurine.dragging {
@@ -118,12 +227,24 @@ impl Pieces {
 
                 if let Some(best) = best {
                     // found a close square it could have moved to
+                    let orig = figurine.square;
                     figurine.square = best;
                     added.retain(|&(sq, _)| sq != best);
 
                     // snap dragged figurine to square
-                    if (now - figurine.last_drag).num_milliseconds() < 200 {
+                    if now.duration_since(figurine.last_drag) < SNAP_AFTER_DRAG {
                         figurine.start = square_to_pos(figurine.square);
+                    } else if figurine.piece.role == Role::Knight && orig.distance(best) == 2 &&
+                              orig.file() != best.file() && orig.rank() != best.rank() {
+                        // jump around the corner of the L, along the
+                        // longer leg first
+                        let end = square_to_pos(best);
+                        let (dx, dy) = (end.0 - figurine.start.0, end.1 - figurine.start.1);
+                        figurine.via = Some(if dx.abs() > dy.abs() {
+                            (end.0, figurine.start.1)
+                        } else {
+                            (figurine.start.0, end.1)
+                        });
                     }
                 } else {
                     // fade it out
@@ -133,20 +254,153 @@ impl Pieces {
             }
         }
 
-        // add new figurines
+        // add new figurines, bringing back those still fading out on
+        // their square
         for (square, piece) in added {
+            let revived = self.figurines.iter_mut().find(|f| {
+                f.fading && f.piece == piece && f.start == square_to_pos(square)
+            });
+
+            if let Some(figurine) = revived {
+                figurine.square = square;
+                figurine.fading = false;
+                figurine.replaced = false;
+                figurine.appearing = true;
+                figurine.elapsed = 0.0;
+                figurine.time = None;
+                figurine.transition = Some(transition);
+                continue;
+            }
+
             self.figurines.push(Figurine {
                 square,
                 piece,
                 start: (0.5 + file_to_float(square.file()), 7.5 - rank_to_float(square.rank())),
+                via: None,
                 elapsed: 0.0,
-                time: now,
+                time: None,
+                transition: Some(transition),
                 last_drag: self.past,
                 fading: false,
                 replaced: false,
+                appearing: true,
                 dragging: false,
             });
         }
+
+        self.end_transition(transition);
+    }
+
+    /// Queue a new board change and return its id.
+    fn begin_transition(&mut self) -> usize {
+        let id = self.next_transition;
+        self.next_transition = self.next_transition.wrapping_add(1);
+        self.transitions.push_back(Transition { id, time: None });
+        id
+    }
+
+    /// Drop a board change again if it did not affect any figurine.
+    fn end_transition(&mut self, id: usize) {
+        if !self.figurines.iter().any(|f| f.transition == Some(id)) {
+            self.transitions.retain(|t| t.id != id);
+        }
+    }
+
+    /// Put the figurines on the squares of a board right away, without
+    /// gliding or fading.
+    pub fn jump_to_board(&mut self, board: &Board) {
+        self.set_board(board);
+        self.figurines.retain(|f| !f.fading);
+        for figurine in &mut self.figurines {
+            figurine.elapsed = 1.0;
+            figurine.transition = None;
+        }
+        self.transitions.clear();
+    }
+
+    /// Called when the orientation changed. The figurines start from
+    /// where they were on the screen and glide to their mirrored squares,
+    /// instead of jumping. A piece being dragged stays under the pointer.
+    pub fn flip(&mut self) {
+        let transition = self.begin_transition();
+
+        for figurine in &mut self.figurines {
+            let pos = figurine.pos();
+            figurine.start = flip_pos(pos);
+            figurine.via = None;
+
+            // fading figurines keep fading where they are
+            if !figurine.fading {
+                figurine.elapsed = 0.0;
+                figurine.time = None;
+                figurine.transition = Some(transition);
+                figurine.appearing = false;
+            }
+        }
+
+        self.end_transition(transition);
+
+        if let Some(ref mut drag) = self.drag {
+            drag.start = flip_pos(drag.start);
+            drag.pos = flip_pos(drag.pos);
+        }
+    }
+
+    /// Drop the current drag and selection without moving, e.g. when a
+    /// touch turned out to be a palm resting on the screen.
+    pub fn cancel(&mut self) {
+        self.drag = None;
+        self.selected = None;
+
+        for figurine in &mut self.figurines {
+            figurine.dragging = false;
+        }
+    }
+
+    /// Track the piece under the pointer for hover hints. Returns the id
+    /// of a new hover, to be shown with `show_hover()` after the delay.
+    pub(crate) fn hover_mouse_move(&mut self, state: &BoardState, ctx: &EventContext) -> Option<usize> {
+        let square = ctx.square().filter(|&square| {
+            state.hover_hints().is_some() && !state.view_only() && !state.editing() &&
+            self.drag.is_none() && self.selected.is_none() &&
+            self.figurine_at(square).map_or(false, |f| state.is_movable(f.piece) && state.turn() == Some(f.piece.color))
+        });
+
+        if self.hover.as_ref().map(|h| h.square) == square {
+            return None;
+        }
+
+        self.clear_hover(ctx.widget());
+
+        square.map(|square| {
+            self.hovers = self.hovers.wrapping_add(1);
+            self.hover = Some(Hover { square, id: self.hovers, shown: false });
+            self.hovers
+        })
+    }
+
+    /// Show the legal squares of the hovered piece, unless the pointer
+    /// moved on since the hover started.
+    pub(crate) fn show_hover(&mut self, id: usize, ctx: &WidgetContext) {
+        if let Some(ref mut hover) = self.hover {
+            if hover.id == id && !hover.shown {
+                hover.shown = true;
+                ctx.queue_draw();
+            }
+        }
+    }
+
+    /// Stop hovering, e.g. when the pointer left the board.
+    pub(crate) fn clear_hover(&mut self, ctx: &WidgetContext) {
+        if let Some(hover) = self.hover.take() {
+            if hover.shown {
+                ctx.queue_draw();
+            }
+        }
+    }
+
+    pub fn selected(&self) -> Option<Square> {
+        self.selected
     }
 
     pub fn occupied(&self) -> Bitboard {
@@ -161,21 +415,94 @@ impl Pieces {
         self.figurines.iter_mut().find(|f| !f.fading && f.square == square)
     }
 
+    pub(crate) fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
     pub fn dragging_mut(&mut self) -> Option<&mut Figurine> {
         self.figurines.iter_mut().find(|f| f.dragging)
     }
 
-    pub(crate) fn selection_mouse_down(&mut self, ctx: &EventContext, e: &EventButton) {
+    /// Emit a move, or store it as a premove if the moved piece does not
+    /// belong to the side to move. In edit mode the piece is just moved.
+    /// Castling is always emitted as the king moving to the rook.
+    fn user_move(&mut self, state: &mut BoardState, ctx: &EventContext, orig: Square, dest: Square, role: Option<Role>) {
+        if state.editing() {
+            state.edit_move(orig, Some(dest));
+            self.edited(state, ctx);
+            return;
+        }
+
+        let dest = state.castling_rook(orig, dest).unwrap_or(dest);
+
+        let premove = match (state.turn(), self.figurine_at(orig)) {
+            (Some(turn), Some(figurine)) => figurine.piece.color != turn,
+            _ => false,
+        };
+
+        // dragging a pawn with the finger promotes to a queen, unless the
+        // finger rests on the square to bring up the choice of pieces.
+        // with auto queen a modifier key does the same.
+        let quick = if ctx.is_touch() {
+            ctx.quick_release()
+        } else {
+            state.auto_queen() &&
+            !ctx.modifiers().intersects(ModifierType::SHIFT_MASK | ModifierType::CONTROL_MASK | ModifierType::MOD1_MASK)
+        };
+
+        let promoting = state.legals().iter().any(|m| m.from() == Some(orig) && m.to() == dest && m.promotion().is_some());
+
+        let promotion = if role.is_some() {
+            role
+        } else if quick && promoting {
+            Some(Role::Queen)
+        } else {
+            None
+        };
+
+        if premove {
+            state.set_premove(Some((orig, dest)));
+            ctx.widget().queue_draw();
+        } else {
+            ctx.stream().emit(GroundMsg::UserMove(orig, dest, promotion));
+        }
+    }
+
+    fn edited(&mut self, state: &BoardState, ctx: &EventContext) {
+        self.set_board(state.board());
+        ctx.stream().emit(GroundMsg::FenEdited(state.fen()));
+        ctx.widget().queue_draw();
+    }
+
+    pub(crate) fn selection_mouse_down(&mut self, state: &mut BoardState, ctx: &EventContext) {
         let orig = self.selected.take();
 
-        if e.button() == 1 {
+        if ctx.button() == 1 {
+            // any click cancels a pending premove
+            state.set_premove(None);
+
+            // place spare pieces in edit mode
+            if let (true, Some(piece), Some(square)) = (state.editing(), state.spare_piece(), ctx.square()) {
+                state.edit_put(square, piece);
+                self.edited(state, ctx);
+                return;
+            }
+
             let dest = ctx.square();
-            self.selected = dest.filter(|sq| self.occupied().contains(*sq));
+            self.selected = dest.filter(|sq| self.figurine_at(*sq).map_or(false, |f| state.is_movable(f.piece)));
+
+            // the second click moves the selected piece
+            let orig = orig.filter(|_| state.move_input() != MoveInput::Drag);
 
             if let (Some(orig), Some(dest)) = (orig, dest) {
-                self.selected = None;
-                if orig != dest {
-                    ctx.stream().emit(GroundMsg::UserMove(orig, dest, None));
+                if orig == dest {
+                    // clicking the selected piece again deselects it
+                    self.selected = None;
+                } else if self.reselects(state, orig, dest) {
+                    // keep the other piece of the same side selected
+                } else {
+                    self.selected = None;
+                    self.user_move(state, ctx, orig, dest, None);
                 }
             }
         }
@@ -183,14 +510,39 @@ impl Pieces {
         ctx.widget().queue_draw();
     }
 
-    pub(crate) fn drag_mouse_down(&mut self, ctx: &EventContext, e: &EventButton) {
-        if e.button() == 1 {
+    /// Whether clicking `dest` while `orig` is selected selects another
+    /// piece instead of moving. Castling by clicking the own rook is a
+    /// move.
+    fn reselects(&self, state: &BoardState, orig: Square, dest: Square) -> bool {
+        if state.editing() || state.valid_move(orig, dest) || state.castling_rook(orig, dest).is_some() {
+            return false;
+        }
+
+        match (self.figurine_at(orig), self.figurine_at(dest)) {
+            (Some(o), Some(d)) => o.piece.color == d.piece.color,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn drag_mouse_down(&mut self, state: &BoardState, ctx: &EventContext) {
+        if state.move_input() == MoveInput::Click {
+            return;
+        }
+
+        if ctx.button() == 1 {
             if let Some(square) = ctx.square() {
-                let piece = if let Some(figurine) = self.figurine_at_mut(square) {
-                    figurine.dragging = true;
-                    figurine.piece
+                let piece = match self.figurine_at_mut(square) {
+                    Some(figurine) if state.is_movable(figurine.piece) => {
+                        figurine.dragging = true;
+                        figurine.piece
+                    },
+                    _ => return,
+                };
+
+                let dests = if state.editing() || state.turn() != Some(piece.color) {
+                    None
                 } else {
-                    return;
+                    Some(state.move_targets(square))
                 };
 
                 self.drag = Some(Drag {
@@ -199,20 +551,23 @@ impl Pieces {
                     start: ctx.pos(),
                     pos: ctx.pos(),
                     threshold: false,
+                    promotion: None,
+                    dests,
                 });
             }
         }
     }
 
-    pub(crate) fn drag_mouse_move(&mut self, ctx: &EventContext) {
+    pub(crate) fn drag_mouse_move(&mut self, state: &BoardState, ctx: &EventContext) {
         if let Some(ref mut drag) = self.drag {
-            ctx.widget().queue_draw_rect(drag.pos.0 - 0.5, drag.pos.1 - 0.5, 1.0, 1.0);
-            if let Some(sq) = pos_to_square(drag.pos) {
+            let radius = state.snap_radius();
+            ctx.widget().queue_draw_rect(drag.pos.0 - DRAG_SCALE / 2.0, drag.pos.1 - DRAG_SCALE / 2.0, DRAG_SCALE, DRAG_SCALE);
+            if let Some(sq) = drag.target(radius) {
                 ctx.widget().queue_draw_square(sq);
             }
             drag.pos = ctx.pos();
-            ctx.widget().queue_draw_rect(drag.pos.0 - 0.5, drag.pos.1 - 0.5, 1.0, 1.0);
-            if let Some(sq) = pos_to_square(drag.pos) {
+            ctx.widget().queue_draw_rect(drag.pos.0 - DRAG_SCALE / 2.0, drag.pos.1 - DRAG_SCALE / 2.0, DRAG_SCALE, DRAG_SCALE);
+            if let Some(sq) = drag.target(radius) {
                 ctx.widget().queue_draw_square(sq);
             }
 
@@ -232,19 +587,48 @@ impl Pieces {
         }
     }
 
-    pub(crate) fn drag_mouse_up(&mut self, ctx: &EventContext) {
-        let (orig, dest) = if let Some(drag) = self.drag.take() {
+    pub(crate) fn drag_mouse_up(&mut self, state: &mut BoardState, ctx: &EventContext) {
+        let (orig, dest, promotion) = if let Some(drag) = self.drag.take() {
             ctx.widget().queue_draw();
 
+            let now = self.time_source.now();
             if let Some(ref mut figurine) = self.dragging_mut() {
-                figurine.last_drag = SteadyTime::now();
+                figurine.last_drag = now;
                 figurine.dragging = false;
             }
 
-            let dest = ctx.square().unwrap_or(drag.square);
+            // drops slightly off a legal square or the board still land
+            let target = drag.target(state.snap_radius());
+
+            // dropping a piece off the board removes it in edit mode
+            if target.is_none() && state.editing() {
+                self.selected = None;
+                state.edit_move(drag.square, None);
+                self.edited(state, ctx);
+                return;
+            }
+
+            let dest = target.unwrap_or(drag.square);
+
+            // without click moves the selection only lasts for the drag
+            if state.move_input() == MoveInput::Drag {
+                self.selected = None;
+            }
+
+            // an illegal target glides the piece back from where it was
+            // dropped
+            if dest != drag.square && !drag.accepts(dest) {
+                if let Some(figurine) = self.figurine_at_mut(drag.square) {
+                    figurine.set_pos(drag.pos);
+                }
+                self.selected = None;
+                return;
+            }
+
+            let promotion = drag.promotion.filter(|&(square, _)| square == dest).map(|(_, role)| role);
 
             if drag.square != dest {
-                (drag.square, dest)
+                (drag.square, dest, promotion)
             } else {
                 return;
             }
@@ -255,48 +639,122 @@ impl Pieces {
         self.selected = None;
 
         if orig != dest {
-            ctx.stream().emit(GroundMsg::UserMove(orig, dest, None));
+            self.user_move(state, ctx, orig, dest, promotion);
         }
     }
 
+    /// Scrolling while a pawn hovers over a square it could promote on
+    /// cycles through the roles in `order`, so that dropping it skips the
+    /// promotion dialog.
+    pub(crate) fn drag_scroll(&mut self, state: &BoardState, order: &[Role], ctx: &EventContext, forward: bool) -> Inhibit {
+        if let Some(ref mut drag) = self.drag {
+            let dest = match drag.target(state.snap_radius()) {
+                Some(dest) if drag.threshold => dest,
+                _ => return Inhibit(false),
+            };
+
+            let roles: Vec<Role> = order.iter().cloned().filter(|role| state.legal_move(drag.square, dest, Some(*role))).collect();
+            if roles.is_empty() {
+                return Inhibit(false);
+            }
+
+            let current = drag.promotion
+                .filter(|&(square, _)| square == dest)
+                .and_then(|(_, role)| roles.iter().position(|r| *r == role));
+
+            let next = match current {
+                Some(index) if forward => (index + 1) % roles.len(),
+                Some(index) => (index + roles.len() - 1) % roles.len(),
+                None if forward => 0,
+                None => roles.len() - 1,
+            };
+
+            drag.promotion = Some((dest, roles[next]));
+            ctx.widget().queue_draw_rect(drag.pos.0 - DRAG_SCALE / 2.0, drag.pos.1 - DRAG_SCALE / 2.0, DRAG_SCALE, DRAG_SCALE);
+            return Inhibit(true);
+        }
+
+        Inhibit(false)
+    }
+
+    pub(crate) fn is_animating(&self) -> bool {
+        self.figurines.iter().any(|f| f.elapsed < 1.0)
+    }
+
+    /// Set how long pieces glide to their squares. Zero moves them
+    /// instantly.
+    pub fn set_animation(&mut self, duration: Duration) {
+        self.animation = duration.as_secs_f64() * 1_000_000.0;
+    }
+
     pub(crate) fn queue_animation(&mut self, ctx: &WidgetContext) {
+        self.step(&mut |x, y, width, height| ctx.queue_draw_rect(x, y, width, height));
+    }
+
+    /// Advance the animations to the current time, passing the areas
+    /// that need to be redrawn to `damage` as `(x, y, width, height)`.
+    pub(crate) fn step(&mut self, damage: &mut dyn FnMut(f64, f64, f64, f64)) {
+        // board changes start with the first frame after them, and all
+        // their figurines take the same start time
+        let now = self.time_source.now();
+        for transition in &mut self.transitions {
+            transition.time.get_or_insert(now);
+        }
+
         for figurine in &mut self.figurines {
-            figurine.queue_animation(ctx);
+            if let Some(id) = figurine.transition {
+                figurine.time = self.transitions.iter().find(|t| t.id == id).and_then(|t| t.time);
+            }
+            figurine.step(damage, now, self.animation);
         }
+
+        // forget the changes that came to rest
+        let figurines = &self.figurines;
+        self.transitions.retain(|t| figurines.iter().any(|f| f.transition == Some(t.id) && f.elapsed < 1.0));
     }
 
-    pub(crate) fn draw(&self, cr: &Context, state: &BoardState, promotable: &Promotable) -> Result<(), cairo::Error> {
+    /// Draw the figurines that overlap `clip`, given as
+    /// `(x1, y1, x2, y2)` in board coordinates.
+    pub(crate) fn draw(&self, cr: &Context, state: &BoardState, promotable: &Promotable, blindfold: Blindfold, clip: (f64, f64, f64, f64)) -> Result<(), cairo::Error> {
         self.draw_selection(cr, state)?;
         self.draw_move_hints(cr, state)?;
+        self.draw_hover_hints(cr, state)?;
 
-        for figurine in &self.figurines {
+        let (x1, y1, x2, y2) = clip;
+        let (xmin, xmax, ymin, ymax) = (x1.min(x2), x1.max(x2), y1.min(y2), y1.max(y2));
+        let visible = |figurine: &Figurine| {
+            let (x, y) = figurine.pos();
+            x + 0.5 > xmin && x - 0.5 < xmax && y + 0.5 > ymin && y - 0.5 < ymax
+        };
+
+        for figurine in self.figurines.iter().filter(|f| visible(f)) {
             if figurine.fading {
-                self.draw_figurine(cr, figurine, state, promotable)?;
+                self.draw_figurine(cr, figurine, state, promotable, blindfold)?;
             }
         }
 
-        for figurine in &self.figurines {
+        for figurine in self.figurines.iter().filter(|f| visible(f)) {
             if !figurine.fading && figurine.elapsed >= 1.0 {
-                self.draw_figurine(cr, figurine, state, promotable)?;
+                self.draw_figurine(cr, figurine, state, promotable, blindfold)?;
             }
         }
 
-        for figurine in &self.figurines {
+        for figurine in self.figurines.iter().filter(|f| visible(f)) {
             if !figurine.fading && figurine.elapsed < 1.0 {
-                self.draw_figurine(cr, figurine, state, promotable)?;
+                self.draw_figurine(cr, figurine, state, promotable, blindfold)?;
             }
         }
 
         Ok(())
     }
 
-    fn draw_figurine(&self, cr: &Context, figurine: &Figurine, state: &BoardState, promotable: &Promotable) -> Result<(), cairo::Error> {
+    fn draw_figurine(&self, cr: &Context, figurine: &Figurine, state: &BoardState, promotable: &Promotable, blindfold: Blindfold) -> Result<(), cairo::Error> {
         // hide piece while promotion dialog is open
-        if promotable.is_promoting(figurine.square) {
+        if promotable.is_promoting(figurine.square) || blindfold == Blindfold::Hidden {
             return Ok(());
         }
 
-        // draw ghost when dragging
+        // leave a ghost on the origin square when dragging
         let dragging =
             figurine.dragging &&
             self.drag.as_ref().map_or(false, |d| d.threshold && d.square == figurine.square);
@@ -307,28 +765,23 @@ impl Pieces {
         cr.translate(x, y);
         cr.rotate(state.orientation().fold_wb(0.0, PI));
         cr.translate(-0.5, -0.5);
-        cr.scale(state.piece_set().scale(), state.piece_set().scale());
-
-        state.piece_set().by_piece(&figurine.piece).render_cairo(cr);
+        render_piece(cr, state, blindfold, figurine.piece)?;
 
         cr.pop_group_to_source()?;
 
-        cr.paint_with_alpha(if dragging { 0.2 } else { figurine.alpha() })?;
+        cr.paint_with_alpha(if dragging { GHOST_ALPHA } else { figurine.alpha() })?;
 
         Ok(())
     }
 
-    fn draw_selection(&self, cr: &Context, state: &BoardState) -> Result<(), cairo::Error> {
+    fn draw_selection(&self, r: &dyn Renderer, state: &BoardState) -> Result<(), cairo::Error> {
         if let Some(selected) = self.selected {
-            cr.rectangle(file_to_float(selected.file()), 7.0 - rank_to_float(selected.rank()), 1.0, 1.0);
-            cr.set_source_rgba(0.08, 0.47, 0.11, 0.5);
-            cr.fill()?;
+            r.fill_square(selected, state.theme().selected)?;
 
-            if let Some(hovered) = self.drag.as_ref().and_then(|d| pos_to_square(d.pos)) {
+            if let Some(hovered) = self.drag.as_ref().and_then(|d| d.target(state.snap_radius())) {
                 if state.valid_move(selected, hovered) {
-                    cr.rectangle(file_to_float(hovered.file()), 7.0 - rank_to_float(hovered.rank()), 1.0, 1.0);
-                    cr.set_source_rgba(0.08, 0.47, 0.11, 0.25);
-                    cr.fill()?;
+                    let selected = state.theme().selected;
+                    r.fill_square(hovered, selected.with_alpha(0.5 * selected.alpha))?;
                 }
             }
         }
@@ -336,43 +789,22 @@ impl Pieces {
         Ok(())
     }
 
-    fn draw_move_hints(&self, cr: &Context, state: &BoardState) -> Result<(), cairo::Error> {
-        if let Some(selected) = self.selected {
-            cr.set_source_rgba(0.08, 0.47, 0.11, 0.5);
+    /// Dots on empty squares the selected piece can move to, and rings
+    /// around pieces it can capture. Castling targets the own rook, so it
+    /// gets a ring, too.
+    fn draw_move_hints(&self, r: &dyn Renderer, state: &BoardState) -> Result<(), cairo::Error> {
+        if !state.move_hints() {
+            return Ok(());
+        }
 
-            let radius = 0.12;
-            let corner = 1.8 * radius;
+        if let Some(selected) = self.selected {
+            let color = state.theme().move_hint;
 
             for square in state.move_targets(selected) {
                 if self.occupied().contains(square) {
-                    cr.move_to(file_to_float(square.file()), 7.0 - rank_to_float(square.rank()));
-                    cr.rel_line_to(corner, 0.0);
-                    cr.rel_line_to(-corner, corner);
-                    cr.rel_line_to(0.0, -corner);
-                    cr.fill()?;
-
-                    cr.move_to(1.0 + file_to_float(square.file()), 7.0 - rank_to_float(square.rank()));
-                    cr.rel_line_to(0.0, corner);
-                    cr.rel_line_to(-corner, -corner);
-                    cr.rel_line_to(corner, 0.0);
-                    cr.fill()?;
-
-                    cr.move_to(file_to_float(square.file()), 8.0 - rank_to_float(square.rank()));
-                    cr.rel_line_to(corner, 0.0);
-                    cr.rel_line_to(-corner, -corner);
-                    cr.rel_line_to(0.0, corner);
-                    cr.fill()?;
-
-                    cr.move_to(1.0 + file_to_float(square.file()), 8.0 - rank_to_float(square.rank()));
-                    cr.rel_line_to(-corner, 0.0);
-                    cr.rel_line_to(corner, -corner);
-                    cr.rel_line_to(0.0, corner);
-                    cr.fill()?;
+                    r.stroke_circle(square_to_pos(square), 0.46, 0.08, color)?;
                 } else {
-                    cr.arc(0.5 + file_to_float(square.file()),
-                           7.5 - rank_to_float(square.rank()),
-                           radius, 0.0, 2.0 * PI);
-                    cr.fill()?;
+                    r.fill_circle(square_to_pos(square), 0.12, color)?;
                 }
             }
         }
@@ -380,15 +812,58 @@ impl Pieces {
         Ok(())
     }
 
-    pub(crate) fn draw_drag(&self, cr: &Context, state: &BoardState) -> Result<(), cairo::Error> {
+    /// Shade the squares the hovered piece cannot go to, and outline the
+    /// ones it can.
+    fn draw_hover_hints(&self, r: &dyn Renderer, state: &BoardState) -> Result<(), cairo::Error> {
+        let square = match self.hover {
+            Some(Hover { square, shown: true, .. }) if self.selected.is_none() && self.drag.is_none() => square,
+            _ => return Ok(()),
+        };
+
+        let targets = state.move_targets(square);
+
+        for sq in Square::ALL {
+            if sq != square && !targets.contains(sq) {
+                r.fill_square(sq, Rgba::new(0.0, 0.0, 0.0, HOVER_DIM_ALPHA))?;
+            }
+        }
+
+        for sq in targets {
+            r.stroke_square(sq, 0.08, state.theme().move_hint)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn draw_drag(&self, cr: &Context, state: &BoardState, blindfold: Blindfold) -> Result<(), cairo::Error> {
         match self.drag {
             Some(ref drag) if drag.threshold => {
+                // outline the square the piece would be dropped on, or
+                // tint it if the piece cannot go there
+                let target = drag.target(state.snap_radius());
+                if let Some(target) = target {
+                    if target != drag.square && drag.accepts(target) {
+                        cr.stroke_square(target, 0.08, state.theme().move_hint)?;
+                    } else if target != drag.square {
+                        cr.fill_square(target, state.theme().check.with_alpha(ILLEGAL_ALPHA))?;
+                    }
+                }
+
+                if blindfold == Blindfold::Hidden {
+                    return Ok(());
+                }
+
+                // enlarged piece centered under the cursor
                 cr.push_group();
                 cr.translate(drag.pos.0, drag.pos.1);
                 cr.rotate(state.orientation().fold_wb(0.0, PI));
+                cr.scale(DRAG_SCALE, DRAG_SCALE);
                 cr.translate(-0.5, -0.5);
-                cr.scale(state.piece_set().scale(), state.piece_set().scale());
-                state.piece_set().by_piece(&drag.piece).render_cairo(cr);
+                let piece = match drag.promotion {
+                    Some((square, role)) if target == Some(square) => role.of(drag.piece.color),
+                    _ => drag.piece,
+                };
+                render_piece(cr, state, blindfold, piece)?;
                 cr.pop_group_to_source()?;
                 cr.paint()?;
             }
@@ -399,6 +874,37 @@ impl Pieces {
     }
 }
 
+/// Render a piece into the unit square, or a token in its place when
+/// blindfolded.
+fn render_piece(cr: &Context, state: &BoardState, blindfold: Blindfold, piece: Piece) -> Result<(), cairo::Error> {
+    match blindfold {
+        Blindfold::Off => state.piece_set().render(cr, piece),
+        Blindfold::Hidden => Ok(()),
+        Blindfold::Tokens => {
+            cr.arc(0.5, 0.5, 0.3, 0.0, 2.0 * PI);
+            cr.set_source_rgb(0.5, 0.5, 0.5);
+            cr.fill_preserve()?;
+            cr.set_source_rgb(0.2, 0.2, 0.2);
+            cr.set_line_width(0.04);
+            cr.stroke()
+        },
+    }
+}
+
+/// Point of an animation from `start` to `end` after `elapsed`, from 0 to
+/// 1, easing in and out. With a control point the path is a quadratic
+/// curve bending towards it.
+fn interpolate(start: (f64, f64), via: Option<(f64, f64)>, end: (f64, f64), elapsed: f64) -> (f64, f64) {
+    let t = ease(0.0, 1.0, elapsed);
+    match via {
+        Some(via) => {
+            let (a, b, c) = ((1.0 - t) * (1.0 - t), 2.0 * (1.0 - t) * t, t * t);
+            (a * start.0 + b * via.0 + c * end.0, a * start.1 + b * via.1 + c * end.1)
+        },
+        None => (start.0 + (end.0 - start.0) * t, start.1 + (end.1 - start.1) * t),
+    }
+}
+
 impl Figurine {
     pub fn piece(&self) -> &Piece {
         &self.piece
@@ -406,7 +912,10 @@ impl Figurine {
 
     pub fn set_pos(&mut self, pos: (f64, f64)) {
         self.start = pos;
-        self.time = SteadyTime::now();
+        self.via = None;
+        self.time = None;
+        self.transition = None;
+        self.appearing = false;
         self.elapsed = 0.0;
     }
 
@@ -414,31 +923,38 @@ impl Figurine {
         if self.fading {
             self.start
         } else {
-            let end = square_to_pos(self.square);
-            (ease(self.start.0, end.0, self.elapsed), ease(self.start.1, end.1, self.elapsed))
+            interpolate(self.start, self.via, square_to_pos(self.square), self.elapsed)
         }
     }
 
     fn alpha(&self) -> f64 {
         if self.replaced {
-            ease(0.5, 0.0, self.elapsed)
+            // a captured piece stays until the capturing piece is close,
+            // then fades out under it
+            ease(1.0, 0.0, 2.0 * self.elapsed - 1.0)
         } else if self.fading {
             ease(1.0, 0.0, self.elapsed)
+        } else if self.appearing {
+            ease(0.0, 1.0, self.elapsed)
         } else {
             1.0
         }
     }
 
-    fn queue_animation(&mut self, ctx: &WidgetContext) {
+    fn step(&mut self, damage: &mut dyn FnMut(f64, f64, f64, f64), now: Instant, duration: f64) {
         if self.elapsed < 1.0 {
             let pos = self.pos();
-            ctx.queue_draw_rect(pos.0 - 0.5, pos.1 - 0.5, 1.0, 1.0);
+            damage(pos.0 - 0.5, pos.1 - 0.5, 1.0, 1.0);
 
-            let now = SteadyTime::now();
-            self.elapsed = ((now - self.time).num_milliseconds() as f64 / 300.0).min(1.0);
+            let time = *self.time.get_or_insert(now);
+            self.elapsed = if duration > 0.0 {
+                (now.duration_since(time).as_micros() as f64 / duration).min(1.0)
+            } else {
+                1.0
+            };
 
             let pos = self.pos();
-            ctx.queue_draw_rect(pos.0 - 0.5, pos.1 - 0.5, 1.0, 1.0);
+            damage(pos.0 - 0.5, pos.1 - 0.5, 1.0, 1.0);
         }
     }
 }
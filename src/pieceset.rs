@@ -14,70 +14,205 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
-use rsvg::Handle;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use cairo::{Context, Format, ImageSurface};
+use rsvg::{Handle, HandleExt};
 
 use shakmaty::{Color, Role, Piece};
 
-struct PieceSetSide {
-    pawn: Handle,
-    knight: Handle,
-    bishop: Handle,
-    rook: Handle,
-    queen: Handle,
-    king: Handle,
-}
+const ROLES: [Role; 6] = [Role::Pawn, Role::Knight, Role::Bishop, Role::Rook, Role::Queen, Role::King];
 
-impl PieceSetSide {
-    fn by_role(&self, role: Role) -> &Handle {
-        match role {
-            Role::Pawn => &self.pawn,
-            Role::Knight => &self.knight,
-            Role::Bishop => &self.bishop,
-            Role::Rook => &self.rook,
-            Role::Queen => &self.queen,
-            Role::King => &self.king,
-        }
-    }
+/// Number of rasterized sizes kept before the cache is cleared, e.g. while
+/// the window is resized.
+const CACHED_SIZES: usize = 4;
+
+enum Source {
+    Merida,
+    Directory(PathBuf),
 }
 
+/// Images of the pieces.
+///
+/// A piece set directory contains SVG files named like `wK.svg` for the
+/// white king or `bN.svg` for the black knight. The SVG files are loaded
+/// when first drawn, and rasterized once per size.
 pub struct PieceSet {
-    black: PieceSetSide,
-    white: PieceSetSide,
+    name: String,
+    source: Source,
+    handles: RefCell<HashMap<Piece, Option<Handle>>>,
+    cache: RefCell<HashMap<(Piece, i32), ImageSurface>>,
 }
 
-impl PieceSet {
-    fn by_color(&self, color: Color) -> &PieceSetSide {
-        color.fold_wb(&self.white, &self.black)
+impl fmt::Debug for PieceSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PieceSet")
+            .field("name", &self.name)
+            .finish()
     }
+}
 
-    pub fn by_piece(&self, piece: &Piece) -> &Handle {
-        self.by_color(piece.color).by_role(piece.role)
+impl Clone for PieceSet {
+    fn clone(&self) -> PieceSet {
+        PieceSet {
+            name: self.name.clone(),
+            source: match self.source {
+                Source::Merida => Source::Merida,
+                Source::Directory(ref path) => Source::Directory(path.clone()),
+            },
+            handles: RefCell::new(HashMap::new()),
+            cache: RefCell::new(HashMap::new()),
+        }
     }
+}
+
+fn file_name(piece: Piece) -> String {
+    format!("{}{}.svg", piece.color.fold_wb('w', 'b'), piece.role.char().to_ascii_uppercase())
+}
 
-    pub fn scale(&self) -> f64 {
-        1.0 / 177.0
+fn merida_data(piece: Piece) -> &'static [u8] {
+    match (piece.color, piece.role) {
+        (Color::Black, Role::Pawn) => include_bytes!("merida/bP.svg"),
+        (Color::Black, Role::Knight) => include_bytes!("merida/bN.svg"),
+        (Color::Black, Role::Bishop) => include_bytes!("merida/bB.svg"),
+        (Color::Black, Role::Rook) => include_bytes!("merida/bR.svg"),
+        (Color::Black, Role::Queen) => include_bytes!("merida/bQ.svg"),
+        (Color::Black, Role::King) => include_bytes!("merida/bK.svg"),
+        (Color::White, Role::Pawn) => include_bytes!("merida/wP.svg"),
+        (Color::White, Role::Knight) => include_bytes!("merida/wN.svg"),
+        (Color::White, Role::Bishop) => include_bytes!("merida/wB.svg"),
+        (Color::White, Role::Rook) => include_bytes!("merida/wR.svg"),
+        (Color::White, Role::Queen) => include_bytes!("merida/wQ.svg"),
+        (Color::White, Role::King) => include_bytes!("merida/wK.svg"),
     }
 }
 
 impl PieceSet {
+    /// The built-in merida set.
     pub fn merida() -> PieceSet {
+        PieceSet::new("merida".to_owned(), Source::Merida)
+    }
+
+    fn new(name: String, source: Source) -> PieceSet {
         PieceSet {
-            black: PieceSetSide {
-                pawn: Handle::from_data(include_bytes!("merida/bP.svg")).expect("merida/bP.svg"),
-                knight: Handle::from_data(include_bytes!("merida/bN.svg")).expect("merida/bN.svg"),
-                bishop: Handle::from_data(include_bytes!("merida/bB.svg")).expect("merida/bB.svg"),
-                rook: Handle::from_data(include_bytes!("merida/bR.svg")).expect("merida/bR.svg"),
-                queen: Handle::from_data(include_bytes!("merida/bQ.svg")).expect("merida/bQ.svg"),
-                king: Handle::from_data(include_bytes!("merida/bK.svg")).expect("merida/bK.svg"),
-            },
-            white: PieceSetSide {
-                pawn: Handle::from_data(include_bytes!("merida/wP.svg")).expect("merida/wP.svg"),
-                knight: Handle::from_data(include_bytes!("merida/wN.svg")).expect("merida/wN.svg"),
-                bishop: Handle::from_data(include_bytes!("merida/wB.svg")).expect("merida/wB.svg"),
-                rook: Handle::from_data(include_bytes!("merida/wR.svg")).expect("merida/wR.svg"),
-                queen: Handle::from_data(include_bytes!("merida/wQ.svg")).expect("merida/wQ.svg"),
-                king: Handle::from_data(include_bytes!("merida/wK.svg")).expect("merida/wK.svg"),
-            },
+            name,
+            source,
+            handles: RefCell::new(HashMap::new()),
+            cache: RefCell::new(HashMap::new()),
         }
     }
+
+    /// A piece set from a directory of SVG files, named after the
+    /// directory.
+    pub fn from_dir<P: AsRef<Path>>(path: P) -> PieceSet {
+        let path = path.as_ref();
+        let name = path.file_name().map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+        PieceSet::new(name, Source::Directory(path.to_owned()))
+    }
+
+    /// Find piece sets in the subdirectories of a directory, like
+    /// `pieces/cburnett` or `pieces/alpha`, sorted by name. Subdirectories
+    /// without all twelve pieces are skipped.
+    pub fn scan<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PieceSet>> {
+        let mut sets = Vec::new();
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let complete = [Color::White, Color::Black].iter().all(|&color| {
+                ROLES.iter().all(|&role| path.join(file_name(role.of(color))).is_file())
+            });
+
+            if complete {
+                sets.push(PieceSet::from_dir(path));
+            }
+        }
+
+        sets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(sets)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The directory of the set, or `None` for the built-in set.
+    pub fn path(&self) -> Option<&Path> {
+        match self.source {
+            Source::Merida => None,
+            Source::Directory(ref path) => Some(path),
+        }
+    }
+
+    fn load(&self, piece: Piece) -> Option<Handle> {
+        let data = match self.source {
+            Source::Merida => merida_data(piece).to_vec(),
+            Source::Directory(ref path) => fs::read(path.join(file_name(piece))).ok()?,
+        };
+
+        Handle::from_data(&data).ok()
+    }
+
+    fn handle(&self, piece: Piece) -> Option<Handle> {
+        self.handles.borrow_mut()
+            .entry(piece)
+            .or_insert_with(|| self.load(piece))
+            .clone()
+    }
+
+    fn rasterize(&self, piece: Piece, size: i32) -> Result<Option<ImageSurface>, cairo::Error> {
+        if let Some(surface) = self.cache.borrow().get(&(piece, size)) {
+            return Ok(Some(surface.clone()));
+        }
+
+        let handle = match self.handle(piece) {
+            Some(handle) => handle,
+            None => return Ok(None),
+        };
+
+        let surface = ImageSurface::create(Format::ARgb32, size, size)?;
+        {
+            let cr = Context::new(&surface)?;
+            let width = f64::from(handle.get_dimensions().width.max(1));
+            cr.scale(f64::from(size) / width, f64::from(size) / width);
+            handle.render_cairo(&cr);
+        }
+
+        let mut cache = self.cache.borrow_mut();
+        if cache.len() >= 12 * CACHED_SIZES {
+            cache.clear();
+        }
+        cache.insert((piece, size), surface.clone());
+        Ok(Some(surface))
+    }
+
+    /// Forget the rasterized pieces, e.g. when the board was resized or
+    /// moved to a screen with a different scale factor.
+    pub(crate) fn clear_cache(&self) {
+        self.cache.borrow_mut().clear();
+    }
+
+    /// Draw a piece into the unit square at the origin.
+    pub(crate) fn render(&self, cr: &Context, piece: Piece) -> Result<(), cairo::Error> {
+        // rasterize in physical pixels, which on HiDPI screens are smaller
+        // than the device units of the widget
+        let (dx, dy) = cr.user_to_device_distance(1.0, 0.0)?;
+        let (scale, _) = cr.target().device_scale();
+        let size = (dx.hypot(dy) * scale).round().max(1.0) as i32;
+
+        if let Some(surface) = self.rasterize(piece, size)? {
+            cr.save()?;
+            cr.scale(1.0 / f64::from(size), 1.0 / f64::from(size));
+            cr.set_source_surface(&surface, 0.0, 0.0)?;
+            cr.paint()?;
+            cr.restore()?;
+        }
+
+        Ok(())
+    }
 }
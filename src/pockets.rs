@@ -0,0 +1,200 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+use std::f64::consts::PI;
+
+use gtk::prelude::*;
+use cairo::Context;
+
+use shakmaty::{Color, Role, Piece};
+
+use util::{file_to_float, rank_to_float};
+use boardstate::BoardState;
+use ground::{GroundMsg, EventContext};
+
+const ROLES: [Role; 5] = [Role::Pawn, Role::Knight, Role::Bishop, Role::Rook, Role::Queen];
+
+/// Dragging pieces in hand onto the board in Crazyhouse.
+pub struct Pockets {
+    drag: Option<PocketDrag>,
+}
+
+struct PocketDrag {
+    piece: Piece,
+    pos: (f64, f64),
+}
+
+/// Top left corner of the pocket slot of a piece. White's pocket is below
+/// the board and black's pocket is above, so that the pocket of the
+/// player at the bottom is always the one closer to them.
+fn slot_pos(piece: Piece) -> (f64, f64) {
+    let index = ROLES.iter().position(|&r| r == piece.role).unwrap_or(0) as f64;
+    match piece.color {
+        Color::White => (1.5 + index, 8.5),
+        Color::Black => (5.5 - index, -1.5),
+    }
+}
+
+fn slot_at((x, y): (f64, f64)) -> Option<Piece> {
+    for &color in &[Color::White, Color::Black] {
+        for &role in &ROLES {
+            let piece = role.of(color);
+            let (sx, sy) = slot_pos(piece);
+            if sx <= x && x < sx + 1.0 && sy <= y && y < sy + 1.0 {
+                return Some(piece);
+            }
+        }
+    }
+
+    None
+}
+
+impl Pockets {
+    pub fn new() -> Pockets {
+        Pockets {
+            drag: None,
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        self.drag = None;
+    }
+
+    pub(crate) fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    pub(crate) fn mouse_down(&mut self, state: &BoardState, ctx: &EventContext) -> Inhibit {
+        if ctx.button() != 1 || state.editing() {
+            return Inhibit(false);
+        }
+
+        let piece = match (state.pockets(), slot_at(ctx.pos())) {
+            (Some(pockets), Some(piece)) if pockets.by_color(piece.color).by_role(piece.role) > 0 => piece,
+            _ => return Inhibit(false),
+        };
+
+        if state.turn() != Some(piece.color) || !state.is_movable(piece) {
+            return Inhibit(false);
+        }
+
+        self.drag = Some(PocketDrag {
+            piece,
+            pos: ctx.pos(),
+        });
+
+        ctx.widget().queue_draw();
+        Inhibit(true)
+    }
+
+    pub(crate) fn mouse_move(&mut self, ctx: &EventContext) {
+        if let Some(ref mut drag) = self.drag {
+            drag.pos = ctx.pos();
+            ctx.widget().queue_draw();
+        }
+    }
+
+    pub(crate) fn mouse_up(&mut self, state: &BoardState, ctx: &EventContext) {
+        if let Some(drag) = self.drag.take() {
+            ctx.widget().queue_draw();
+
+            if let Some(square) = ctx.square() {
+                if state.valid_drop(drag.piece.role, square) {
+                    ctx.stream().emit(GroundMsg::UserDrop(drag.piece.role, square));
+                }
+            }
+        }
+    }
+
+    pub(crate) fn draw(&self, cr: &Context, state: &BoardState) -> Result<(), cairo::Error> {
+        let pockets = match state.pockets() {
+            Some(pockets) => pockets,
+            None => return Ok(()),
+        };
+
+        cr.set_font_size(0.25);
+
+        for &color in &[Color::White, Color::Black] {
+            for &role in &ROLES {
+                let piece = role.of(color);
+                let (x, y) = slot_pos(piece);
+
+                cr.rectangle(x, y, 1.0, 1.0);
+                state.theme().border.set_source(cr);
+                cr.fill()?;
+
+                let count = pockets.by_color(color).by_role(role);
+                if count == 0 {
+                    continue;
+                }
+
+                let dragging = self.drag.as_ref().map_or(false, |d| d.piece == piece);
+                let alpha = if dragging && count == 1 { 0.2 } else { 1.0 };
+                self.draw_piece(cr, state, piece, (x + 0.5, y + 0.5), alpha)?;
+
+                if count > 1 {
+                    cr.save()?;
+                    cr.translate(x + 0.5, y + 0.5);
+                    cr.rotate(state.orientation().fold_wb(0.0, PI));
+                    cr.move_to(0.25, 0.45);
+                    state.theme().coordinates.set_source(cr);
+                    cr.show_text(&count.to_string())?;
+                    cr.restore()?;
+                }
+            }
+        }
+
+        self.draw_drop_hints(cr, state)
+    }
+
+    fn draw_drop_hints(&self, cr: &Context, state: &BoardState) -> Result<(), cairo::Error> {
+        if !state.move_hints() {
+            return Ok(());
+        }
+
+        if let Some(ref drag) = self.drag {
+            state.theme().move_hint.set_source(cr);
+
+            for square in state.drop_targets(drag.piece.role) {
+                cr.arc(0.5 + file_to_float(square.file()),
+                       7.5 - rank_to_float(square.rank()),
+                       0.12, 0.0, 2.0 * PI);
+                cr.fill()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn draw_drag(&self, cr: &Context, state: &BoardState) -> Result<(), cairo::Error> {
+        match self.drag {
+            Some(ref drag) => self.draw_piece(cr, state, drag.piece, drag.pos, 1.0),
+            None => Ok(()),
+        }
+    }
+
+    fn draw_piece(&self, cr: &Context, state: &BoardState, piece: Piece, (x, y): (f64, f64), alpha: f64) -> Result<(), cairo::Error> {
+        cr.push_group();
+        cr.translate(x, y);
+        cr.rotate(state.orientation().fold_wb(0.0, PI));
+        cr.translate(-0.5, -0.5);
+        state.piece_set().render(cr, piece)?;
+        cr.pop_group_to_source()?;
+        cr.paint_with_alpha(alpha)?;
+        Ok(())
+    }
+}
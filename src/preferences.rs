@@ -0,0 +1,399 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! A preferences dialog for the settings of a `Config`.
+//!
+//! Changes are applied right away: each edit is saved with
+//! `Config::update()`, which tells the listeners of the config.
+
+use std::cell::{Cell, RefCell};
+use std::f64::consts::PI;
+use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+use gtk::prelude::*;
+use gtk::{Adjustment, Button, ComboBoxText, Dialog, DrawingArea, Entry, FileChooserAction, FileChooserButton, Grid, Label, ResponseType, SpinButton};
+
+use cairo::Context;
+
+use relm::{Relm, Widget, Update, StreamHandle};
+
+use config::{config_dir, Config, Settings};
+use pieceset::PieceSet;
+use theme::Theme;
+
+/// Preferences dialog messages.
+#[derive(Debug, Msg)]
+pub enum PreferencesMsg {
+    /// Fill in the widgets from the settings again, e.g. after
+    /// `Config::reload()`.
+    Refresh,
+    /// A theme preset or file was picked.
+    ThemeSelected(String),
+    /// A piece set was picked, `None` for the built-in set.
+    PieceSetSelected(Option<PathBuf>),
+    /// An engine was picked, or cleared with `None`.
+    EngineSelected(Option<PathBuf>),
+    /// The chain endpoint was edited.
+    ChainEndpointEdited(String),
+    /// The relay address was edited.
+    RelayEdited(String),
+    /// The default clock was changed, with the base time and increment.
+    ClockEdited(Duration, Duration),
+
+    /// Sent when the settings could not be saved, with the reason.
+    SaveFailed(String),
+}
+
+/// A dialog to edit the theme, piece set, engine, endpoints and default
+/// clock. Listeners of the config are called while it is borrowed, so
+/// they must not borrow it again.
+pub struct Preferences {
+    dialog: Dialog,
+    config: Rc<RefCell<Config>>,
+    theme: ComboBoxText,
+    theme_file: FileChooserButton,
+    preview: DrawingArea,
+    piece_set: ComboBoxText,
+    /// Directories of the piece sets offered, by their index as combo box
+    /// id, with `None` for the built-in set.
+    piece_sets: Rc<RefCell<Vec<Option<PathBuf>>>>,
+    engine: FileChooserButton,
+    chain_endpoint: Entry,
+    relay: Entry,
+    minutes: SpinButton,
+    increment: SpinButton,
+    stream: StreamHandle<PreferencesMsg>,
+    /// Set while the widgets are filled in, to not report it as an edit.
+    updating: Rc<Cell<bool>>,
+}
+
+impl fmt::Debug for Preferences {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Preferences")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+/// Draw a corner of a board in the theme, with a last move and a
+/// selected piece, like `e2`-`e4` and a knight picked up on `g1`.
+fn draw_preview(cr: &Context, theme: &Theme, size: f64) {
+    let square = size / 4.0;
+    for rank in 0..4 {
+        for file in 0..4 {
+            let color = if (rank + file) % 2 == 0 { theme.light } else { theme.dark };
+            color.set_source(cr);
+            cr.rectangle(f64::from(file) * square, f64::from(rank) * square, square, square);
+            cr.fill().ok();
+        }
+    }
+
+    theme.last_move.set_source(cr);
+    cr.rectangle(0.0, square, square, square);
+    cr.rectangle(0.0, 3.0 * square, square, square);
+    cr.fill().ok();
+
+    theme.selected.set_source(cr);
+    cr.rectangle(2.0 * square, 3.0 * square, square, square);
+    cr.fill().ok();
+
+    theme.move_hint.set_source(cr);
+    cr.arc(1.5 * square, 1.5 * square, 0.15 * square, 0.0, 2.0 * PI);
+    cr.arc(3.5 * square, 1.5 * square, 0.15 * square, 0.0, 2.0 * PI);
+    cr.fill().ok();
+}
+
+impl Preferences {
+    /// Show the dialog.
+    pub fn present(&self) {
+        self.dialog.present();
+    }
+
+    fn fill(&self) {
+        let settings = self.config.borrow().settings().clone();
+        self.updating.set(true);
+
+        if !self.theme.set_active_id(Some(settings.theme.as_str())) {
+            self.theme.set_active(None);
+            self.theme_file.set_filename(&settings.theme);
+        }
+
+        // offer a configured set outside the pieces directory, too
+        let index = {
+            let mut piece_sets = self.piece_sets.borrow_mut();
+            match piece_sets.iter().position(|path| *path == settings.piece_set) {
+                Some(index) => index,
+                None => {
+                    let name = PieceSet::from_dir(settings.piece_set.clone().unwrap_or_default()).name().to_owned();
+                    self.piece_set.append(Some(&piece_sets.len().to_string()), &name);
+                    piece_sets.push(settings.piece_set.clone());
+                    piece_sets.len() - 1
+                },
+            }
+        };
+        self.piece_set.set_active_id(Some(&index.to_string()));
+
+        match settings.engine {
+            Some(ref engine) => { self.engine.set_filename(engine); },
+            None => self.engine.unselect_all(),
+        }
+        self.chain_endpoint.set_text(&settings.chain_endpoint);
+        self.relay.set_text(&settings.relay);
+        self.minutes.set_value((settings.clock_base.as_secs() / 60) as f64);
+        self.increment.set_value(settings.clock_increment.as_secs() as f64);
+
+        self.updating.set(false);
+        self.preview.queue_draw();
+    }
+
+    fn save<F: FnOnce(&mut Settings)>(&self, f: F) {
+        if let Err(err) = self.config.borrow_mut().update(f) {
+            self.stream.emit(PreferencesMsg::SaveFailed(err.to_string()));
+        }
+    }
+}
+
+impl Update for Preferences {
+    type Model = Rc<RefCell<Config>>;
+    type ModelParam = Rc<RefCell<Config>>;
+    type Msg = PreferencesMsg;
+
+    fn model(_: &Relm<Self>, config: Rc<RefCell<Config>>) -> Rc<RefCell<Config>> {
+        config
+    }
+
+    fn update(&mut self, event: PreferencesMsg) {
+        match event {
+            PreferencesMsg::Refresh => self.fill(),
+            PreferencesMsg::ThemeSelected(theme) => {
+                self.save(|settings| settings.theme = theme);
+                self.preview.queue_draw();
+            },
+            PreferencesMsg::PieceSetSelected(piece_set) => self.save(|settings| settings.piece_set = piece_set),
+            PreferencesMsg::EngineSelected(engine) => {
+                if engine.is_none() {
+                    self.updating.set(true);
+                    self.engine.unselect_all();
+                    self.updating.set(false);
+                }
+                self.save(|settings| settings.engine = engine);
+            },
+            PreferencesMsg::ChainEndpointEdited(endpoint) => self.save(|settings| settings.chain_endpoint = endpoint),
+            PreferencesMsg::RelayEdited(relay) => self.save(|settings| settings.relay = relay),
+            PreferencesMsg::ClockEdited(base, increment) => self.save(|settings| {
+                settings.clock_base = base;
+                settings.clock_increment = increment;
+            }),
+            PreferencesMsg::SaveFailed(_) => {},
+        }
+    }
+}
+
+impl Widget for Preferences {
+    type Root = Dialog;
+
+    fn root(&self) -> Self::Root {
+        self.dialog.clone()
+    }
+
+    fn view(relm: &Relm<Self>, config: Rc<RefCell<Config>>) -> Self {
+        let updating = Rc::new(Cell::new(false));
+
+        let theme = ComboBoxText::new();
+        for &name in Theme::PRESETS.iter() {
+            theme.append(Some(name), name);
+        }
+        {
+            let stream = relm.stream().clone();
+            let updating = Rc::clone(&updating);
+            theme.connect_changed(move |theme| {
+                if let Some(name) = theme.active_id() {
+                    if !updating.get() {
+                        stream.emit(PreferencesMsg::ThemeSelected(name.to_string()));
+                    }
+                }
+            });
+        }
+
+        let theme_file = FileChooserButton::new("Theme file", FileChooserAction::Open);
+        {
+            let stream = relm.stream().clone();
+            let updating = Rc::clone(&updating);
+            theme_file.connect_file_set(move |button| {
+                if let Some(path) = button.filename() {
+                    if !updating.get() {
+                        stream.emit(PreferencesMsg::ThemeSelected(path.to_string_lossy().into_owned()));
+                    }
+                }
+            });
+        }
+
+        let preview = DrawingArea::new();
+        preview.set_size_request(96, 96);
+        {
+            let config = Rc::clone(&config);
+            preview.connect_draw(move |widget, cr| {
+                let size = f64::from(widget.allocated_width().min(widget.allocated_height()));
+                let theme = config.borrow().settings().theme().unwrap_or_default();
+                draw_preview(cr, &theme, size);
+                Inhibit(false)
+            });
+        }
+
+        // the built-in set, and the sets installed with the settings
+        let mut sets = vec![PieceSet::merida()];
+        sets.extend(PieceSet::scan(config_dir().join("pieces")).unwrap_or_default());
+        let piece_set = ComboBoxText::new();
+        for (index, set) in sets.iter().enumerate() {
+            piece_set.append(Some(&index.to_string()), set.name());
+        }
+        let piece_sets = Rc::new(RefCell::new(sets.iter().map(|set| set.path().map(|path| path.to_owned())).collect::<Vec<_>>()));
+        {
+            let stream = relm.stream().clone();
+            let updating = Rc::clone(&updating);
+            let piece_sets = Rc::clone(&piece_sets);
+            piece_set.connect_changed(move |combo| {
+                let path = combo.active_id()
+                    .and_then(|id| id.parse::<usize>().ok())
+                    .and_then(|index| piece_sets.borrow().get(index).cloned());
+                if let Some(path) = path {
+                    if !updating.get() {
+                        stream.emit(PreferencesMsg::PieceSetSelected(path));
+                    }
+                }
+            });
+        }
+
+        let engine = FileChooserButton::new("Engine", FileChooserAction::Open);
+        {
+            let stream = relm.stream().clone();
+            let updating = Rc::clone(&updating);
+            engine.connect_file_set(move |button| {
+                if !updating.get() {
+                    stream.emit(PreferencesMsg::EngineSelected(button.filename()));
+                }
+            });
+        }
+        let no_engine = Button::with_label("None");
+        {
+            let stream = relm.stream().clone();
+            no_engine.connect_clicked(move |_| stream.emit(PreferencesMsg::EngineSelected(None)));
+        }
+
+        let chain_endpoint = Entry::new();
+        {
+            let stream = relm.stream().clone();
+            let updating = Rc::clone(&updating);
+            chain_endpoint.connect_changed(move |entry| {
+                if !updating.get() {
+                    stream.emit(PreferencesMsg::ChainEndpointEdited(entry.text().to_string()));
+                }
+            });
+        }
+
+        let relay = Entry::new();
+        {
+            let stream = relm.stream().clone();
+            let updating = Rc::clone(&updating);
+            relay.connect_changed(move |entry| {
+                if !updating.get() {
+                    stream.emit(PreferencesMsg::RelayEdited(entry.text().to_string()));
+                }
+            });
+        }
+
+        let minutes = SpinButton::new(Some(&Adjustment::new(5.0, 0.0, 180.0, 1.0, 5.0, 0.0)), 1.0, 0);
+        let increment = SpinButton::new(Some(&Adjustment::new(3.0, 0.0, 60.0, 1.0, 5.0, 0.0)), 1.0, 0);
+        for spin in &[&minutes, &increment] {
+            let stream = relm.stream().clone();
+            let updating = Rc::clone(&updating);
+            let (minutes, increment) = (minutes.clone(), increment.clone());
+            spin.connect_value_changed(move |_| {
+                if !updating.get() {
+                    stream.emit(PreferencesMsg::ClockEdited(Duration::from_secs(minutes.value_as_int() as u64 * 60),
+                                                            Duration::from_secs(increment.value_as_int() as u64)));
+                }
+            });
+        }
+
+        let engine_row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        engine_row.pack_start(&engine, true, true, 0);
+        engine_row.pack_start(&no_engine, false, false, 0);
+
+        let theme_row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        theme_row.pack_start(&theme, false, false, 0);
+        theme_row.pack_start(&theme_file, true, true, 0);
+
+        let clock_row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        clock_row.pack_start(&minutes, false, false, 0);
+        clock_row.pack_start(&Label::new(Some("+")), false, false, 0);
+        clock_row.pack_start(&increment, false, false, 0);
+
+        let grid = Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(12);
+        grid.set_border_width(12);
+        let rows: [(&str, &gtk::Widget); 7] = [
+            ("Theme", theme_row.upcast_ref()),
+            ("", preview.upcast_ref()),
+            ("Pieces", piece_set.upcast_ref()),
+            ("Engine", engine_row.upcast_ref()),
+            ("Chain endpoint", chain_endpoint.upcast_ref()),
+            ("Relay", relay.upcast_ref()),
+            ("Clock", clock_row.upcast_ref()),
+        ];
+        for (row, &(title, widget)) in rows.iter().enumerate() {
+            let label = Label::new(Some(title));
+            label.set_xalign(1.0);
+            grid.attach(&label, 0, row as i32, 1, 1);
+            grid.attach(widget, 1, row as i32, 1, 1);
+        }
+
+        let dialog = Dialog::new();
+        dialog.set_title("Preferences");
+        dialog.add_button("Close", ResponseType::Close);
+        dialog.content_area().add(&grid);
+        dialog.connect_response(|dialog, _| dialog.hide());
+        dialog.connect_delete_event(|dialog, _| {
+            dialog.hide();
+            Inhibit(true)
+        });
+        grid.show_all();
+
+        let preferences = Preferences {
+            dialog,
+            config,
+            theme,
+            theme_file,
+            preview,
+            piece_set,
+            piece_sets,
+            engine,
+            chain_endpoint,
+            relay,
+            minutes,
+            increment,
+            stream: relm.stream().clone(),
+            updating,
+        };
+        preferences.fill();
+        preferences
+    }
+}
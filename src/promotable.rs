@@ -14,53 +14,98 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
-use std::f64::consts::PI;
 
-use time::SteadyTime;
+use std::sync::Arc;
+use std::time::Instant;
 
 use gtk::prelude::*;
-use cairo::Context;
-use rsvg::HandleExt;
 
 use shakmaty::{Square, Rank, Color, Role, MoveList};
 
-use util::{ease, file_to_float, square_to_pos};
+use util::{ease, square_to_pos};
 use pieces::Pieces;
 use boardstate::BoardState;
 use ground::{WidgetContext, EventContext, GroundMsg};
+use renderer::Renderer;
+use theme::Rgba;
+use timing::{real_time, TimeSource};
+
+/// Default order of the pieces offered when promoting, from the
+/// promotion square towards the center of the board. King and pawn only
+/// show up in variants where they are legal, like antichess.
+pub const PROMOTION_ORDER: [Role; 6] = [Role::Queen, Role::Rook, Role::Bishop, Role::Knight, Role::King, Role::Pawn];
 
 pub struct Promotable {
+    order: Vec<Role>,
     promoting: Option<Promoting>,
+    time_source: Arc<dyn TimeSource>,
 }
 
 struct Promoting {
     color: Color,
     orig: Square,
     dest: Square,
+    roles: Vec<Role>,
     hover: Option<Hover>,
 }
 
 struct Hover {
     square: Square,
-    since: SteadyTime,
+    /// When the hover effect started, taken at the next frame.
+    since: Option<Instant>,
     elapsed: f64,
 }
 
 impl Promotable {
     pub fn new() -> Promotable {
         Promotable {
+            order: PROMOTION_ORDER.to_vec(),
             promoting: None,
+            time_source: real_time(),
         }
     }
 
-    pub fn start(&mut self, color: Color, orig: Square, dest: Square) {
+    /// Take the time for the hover effect from `time_source`.
+    pub fn with_time_source(mut self, time_source: Arc<dyn TimeSource>) -> Promotable {
+        self.time_source = time_source;
+        self
+    }
+
+    pub fn order(&self) -> &[Role] {
+        &self.order
+    }
+
+    /// Change the order of the offered pieces. Roles that are not listed
+    /// are never offered.
+    pub fn set_order(&mut self, mut order: Vec<Role>) {
+        let mut seen = Vec::new();
+        order.retain(|role| if seen.contains(role) {
+            false
+        } else {
+            seen.push(*role);
+            true
+        });
+        self.order = order;
+    }
+
+    pub fn start(&mut self, state: &BoardState, color: Color, orig: Square, dest: Square) {
+        let roles: Vec<Role> = self.order.iter()
+            .cloned()
+            .filter(|role| state.legal_move(orig, dest, Some(*role)))
+            .collect();
+
+        if roles.is_empty() {
+            return;
+        }
+
         self.promoting = Some(Promoting {
             color,
             orig,
             dest,
+            roles,
             hover: Some(Hover {
                 square: dest,
-                since: SteadyTime::now(),
+                since: None,
                 elapsed: 0.0,
             }),
         });
@@ -71,11 +116,12 @@ impl Promotable {
     }
 
     pub fn update(&mut self, legals: &MoveList) {
-        let cancel = if let Some(ref promoting) = self.promoting {
-            !legals.iter().any(|m| {
-                m.from() == Some(promoting.orig) && m.to() == promoting.dest &&
-                m.promotion().is_some()
-            })
+        let cancel = if let Some(ref mut promoting) = self.promoting {
+            let (orig, dest) = (promoting.orig, promoting.dest);
+            promoting.roles.retain(|role| {
+                legals.iter().any(|m| m.from() == Some(orig) && m.to() == dest && m.promotion() == Some(*role))
+            });
+            promoting.roles.is_empty()
         } else {
             false
         };
@@ -89,76 +135,98 @@ impl Promotable {
         self.promoting.as_ref().map_or(false, |p| p.orig == orig)
     }
 
+    /// Whether the choice of pieces for a promotion is open.
+    pub fn is_open(&self) -> bool {
+        self.promoting.is_some()
+    }
+
+    pub(crate) fn is_animating(&self) -> bool {
+        match self.promoting {
+            Some(Promoting { hover: Some(ref hover), .. }) => hover.elapsed < 1.0,
+            _ => false,
+        }
+    }
+
     pub(crate) fn queue_animation(&mut self, ctx: &WidgetContext) {
+        self.step(&mut |x, y, width, height| ctx.queue_draw_rect(x, y, width, height));
+    }
+
+    /// Advance the hover effect to the current time, passing the area
+    /// that needs to be redrawn to `damage` as `(x, y, width, height)`.
+    pub(crate) fn step(&mut self, damage: &mut dyn FnMut(f64, f64, f64, f64)) {
         if let Some(Promoting { hover: Some(ref mut hover), .. }) = self.promoting {
             if hover.elapsed < 1.0 {
-                ctx.queue_draw_square(hover.square);
-            }
+                let (x, y) = square_to_pos(hover.square);
+                damage(x - 0.5, y - 0.5, 1.0, 1.0);
 
-            hover.elapsed = ((SteadyTime::now() - hover.since).num_milliseconds() as f64 / 1000.0).min(1.0);
+                let now = self.time_source.now();
+                let since = *hover.since.get_or_insert(now);
+                hover.elapsed = now.duration_since(since).as_secs_f64().min(1.0);
+            }
         }
     }
 
     pub(crate) fn mouse_move(&mut self, ctx: &EventContext) {
+        self.hover(ctx.square(), &mut |sq| ctx.widget().queue_draw_square(sq));
+    }
+
+    /// Start the hover effect on a square of the choice, or end it with
+    /// `None`, passing the squares that need to be redrawn to `damage`.
+    pub(crate) fn hover(&mut self, square: Option<Square>, damage: &mut dyn FnMut(Square)) {
         if let Some(ref mut promoting) = self.promoting {
             let previous = promoting.hover.as_ref().map(|h| h.square);
-            let square = ctx.square().filter(|sq| sq.file() == promoting.dest.file());
+            let square = square.filter(|sq| sq.file() == promoting.dest.file());
 
             if square != previous {
                 if let Some(sq) = previous {
-                    ctx.widget().queue_draw_square(sq);
+                    damage(sq);
                 }
                 if let Some(sq) = square {
-                    ctx.widget().queue_draw_square(sq);
+                    damage(sq);
                 }
 
                 promoting.hover = square.map(|square| Hover {
                     square,
-                    since: SteadyTime::now(),
+                    since: None,
                     elapsed: 0.0,
                 });
             }
         }
     }
 
+    /// Abandon the pending promotion and slide the pawn back to its origin
+    /// square. Returns `false` if there was nothing to cancel.
+    pub(crate) fn cancel_to_origin(&mut self, pieces: &mut Pieces, ctx: &WidgetContext) -> bool {
+        match self.promoting.take() {
+            Some(promoting) => {
+                promoting.release(pieces, ctx);
+                true
+            },
+            None => false,
+        }
+    }
+
     pub(crate) fn mouse_down(&mut self, pieces: &mut Pieces, ctx: &EventContext) -> Inhibit {
         if let Some(promoting) = self.promoting.take() {
-            ctx.widget().queue_draw();
+            promoting.release(pieces, ctx.widget());
 
-            if let Some(figurine) = pieces.figurine_at_mut(promoting.orig) {
-                // animate the figurine when cancelling
-                figurine.set_pos(square_to_pos(promoting.dest));
+            // right click only cancels
+            if ctx.button() == 3 {
+                return Inhibit(true);
             }
 
-            if let Some(square) = ctx.square() {
-                let side = promoting.orientation();
-                let base = i8::from(promoting.dest.rank());
-
-                if square.file() == promoting.dest.file() {
-                    let role = match i8::from(square.rank()) {
-                        r if r == base => Some(Role::Queen),
-                        r if r == base + side.fold_wb(-1, 1) => Some(Role::Rook),
-                        r if r == base + side.fold_wb(-2, 2) => Some(Role::Bishop),
-                        r if r == base + side.fold_wb(-3, 3) => Some(Role::Knight),
-                        r if r == base + side.fold_wb(-4, 4) => Some(Role::King),
-                        r if r == base + side.fold_wb(-5, 5) => Some(Role::Pawn),
-                        _ => None,
-                    };
-
-                    if role.is_some() {
-                        ctx.stream().emit(GroundMsg::UserMove(promoting.orig, promoting.dest, role));
-                        return Inhibit(true);
-                    }
-                }
+            if let Some(role) = ctx.square().and_then(|square| promoting.role_at(square)) {
+                ctx.stream().emit(GroundMsg::UserMove(promoting.orig, promoting.dest, Some(role)));
+                return Inhibit(true);
             }
         }
 
         Inhibit(false)
     }
 
-    pub(crate) fn draw(&self, cr: &Context, state: &BoardState) -> Result<(), cairo::Error> {
+    pub(crate) fn draw(&self, r: &dyn Renderer, state: &BoardState) -> Result<(), cairo::Error> {
         if let Some(ref p) = self.promoting {
-            p.draw(cr, state)?;
+            p.draw(r, state)?;
         }
 
         Ok(())
@@ -166,62 +234,69 @@ impl Promotable {
 }
 
 impl Promoting {
+    /// Direction from the promotion square towards the center of the
+    /// board, independent of how the board is oriented on screen.
     fn orientation(&self) -> Color {
         Color::from_white(self.dest.rank() > Rank::Fourth)
     }
 
-    fn draw(&self, cr: &Context, state: &BoardState) -> Result<(), cairo::Error> {
-        // make the board darker
-        cr.rectangle(0.0, 0.0, 8.0, 8.0);
-        cr.set_source_rgba(0.0, 0.0, 0.0, 0.5);
-        cr.fill()?;
+    /// The square showing the role at `offset` in the list.
+    fn square_at(&self, offset: usize) -> Square {
+        let rank = i8::from(self.dest.rank()) - self.orientation().fold_wb(offset as i8, -(offset as i8));
+        Square::from_coords(self.dest.file(), Rank::new(rank as u32))
+    }
 
-        for (offset, role) in [Role::Queen, Role::Rook, Role::Bishop, Role::Knight, Role::King, Role::Pawn].iter().enumerate() {
-            if !state.legal_move(self.orig, self.dest, Some(*role)) {
-                continue;
-            }
+    /// Let the hidden pawn reappear, animated from the promotion square.
+    fn release(&self, pieces: &mut Pieces, ctx: &WidgetContext) {
+        ctx.queue_draw();
+
+        if let Some(figurine) = pieces.figurine_at_mut(self.orig) {
+            figurine.set_pos(square_to_pos(self.dest));
+        }
+    }
+
+    fn role_at(&self, square: Square) -> Option<Role> {
+        self.roles.iter()
+            .enumerate()
+            .find(|&(offset, _)| self.square_at(offset) == square)
+            .map(|(_, role)| *role)
+    }
+
+    fn draw(&self, r: &dyn Renderer, state: &BoardState) -> Result<(), cairo::Error> {
+        // make the board darker
+        r.fill_rect(0.0, 0.0, 8.0, 8.0, Rgba::new(0.0, 0.0, 0.0, 0.5))?;
 
-            let rank = i8::from(self.dest.rank()) - self.orientation().fold_wb(offset as i8, -(offset as i8));
-            let light = (i8::from(self.dest.file()) + rank) & 1 == 1;
+        for (offset, role) in self.roles.iter().enumerate() {
+            let square = self.square_at(offset);
+            let (x, y) = square_to_pos(square);
 
-            cr.save()?;
-            cr.rectangle(file_to_float(self.dest.file()), 7.0 - f64::from(rank), 1.0, 1.0);
+            r.push_clip(x - 0.5, y - 0.5, 1.0, 1.0)?;
 
             // draw background
-            if light {
-                cr.set_source_rgb(0.25, 0.25, 0.25);
+            if square.is_light() {
+                r.fill_square(square, Rgba::new(0.25, 0.25, 0.25, 1.0))?;
             } else {
-                cr.set_source_rgb(0.18, 0.18, 0.18);
+                r.fill_square(square, Rgba::new(0.18, 0.18, 0.18, 1.0))?;
             }
-            cr.fill_preserve()?;
-            cr.clip();
 
             // draw piece
-            let radius = match self.hover {
-                Some(ref hover) if i8::from(hover.square.rank()) == rank => {
-                    cr.set_source_rgb(ease(0.69, 1.0, hover.elapsed),
-                                      ease(0.69, 0.65, hover.elapsed),
-                                      ease(0.69, 0.0, hover.elapsed));
-
-                    ease(0.5, 0.5f64.hypot(0.5), hover.elapsed)
-                },
-                _ => {
-                    cr.set_source_rgb(0.69, 0.69, 0.69);
-                    0.5
+            let (color, radius) = match self.hover {
+                Some(ref hover) if hover.square == square => {
+                    (Rgba::new(ease(0.69, 1.0, hover.elapsed),
+                               ease(0.69, 0.65, hover.elapsed),
+                               ease(0.69, 0.0, hover.elapsed),
+                               1.0),
+                     ease(0.5, 0.5f64.hypot(0.5), hover.elapsed))
                 },
+                _ => (Rgba::new(0.69, 0.69, 0.69, 1.0), 0.5),
             };
 
-            cr.arc(0.5 + file_to_float(self.dest.file()), 7.5 - f64::from(rank), radius, 0.0, 2.0 * PI);
-            cr.fill()?;
+            r.fill_circle((x, y), radius, color)?;
 
-            cr.translate(0.5 + file_to_float(self.dest.file()), 7.5 - f64::from(rank));
-            cr.scale(2f64.sqrt() * radius, 2f64.sqrt() * radius);
-            cr.rotate(state.orientation().fold_wb(0.0, PI));
-            cr.translate(-0.5, -0.5);
-            cr.scale(state.piece_set().scale(), state.piece_set().scale());
-            state.piece_set().by_piece(&role.of(self.color)).render_cairo(cr);
+            // keep the piece upright when the board is flipped
+            r.piece(state.piece_set(), role.of(self.color), (x, y), 2f64.sqrt() * radius, state.orientation())?;
 
-            cr.restore()?;
+            r.pop_clip()?;
         }
 
         Ok(())
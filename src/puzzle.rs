@@ -0,0 +1,269 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Puzzles: find the best line from a position, move by move.
+
+use std::error::Error;
+use std::fmt;
+
+use shakmaty::{CastlingMode, Chess, Color, FromSetup, Move, Position};
+use shakmaty::fen::Fen;
+use shakmaty::uci::Uci;
+
+/// Wrong moves after which a puzzle counts as failed.
+const MAX_MISTAKES: u32 = 3;
+
+/// Error when loading a puzzle.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PuzzleError {
+    /// The FEN does not describe a legal standard chess position.
+    InvalidFen(String),
+    /// A move of the line could not be parsed or is illegal.
+    InvalidMove { ply: usize, uci: String },
+    /// The line does not contain a move for the solver.
+    NoSolution,
+    /// A line of a puzzle database does not have enough columns.
+    MissingColumn(usize),
+}
+
+impl fmt::Display for PuzzleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PuzzleError::InvalidFen(ref fen) => write!(f, "invalid puzzle fen: {}", fen),
+            PuzzleError::InvalidMove { ply, ref uci } => write!(f, "invalid puzzle move at ply {}: {}", ply + 1, uci),
+            PuzzleError::NoSolution => f.write_str("puzzle has no solution"),
+            PuzzleError::MissingColumn(line) => write!(f, "missing puzzle column in line {}", line + 1),
+        }
+    }
+}
+
+impl Error for PuzzleError {}
+
+/// A position with the line that solves it. The solver moves first.
+#[derive(Debug, Clone)]
+pub struct Puzzle {
+    id: String,
+    position: Chess,
+    last_move: Option<Move>,
+    solution: Vec<Move>,
+    rating: Option<u32>,
+}
+
+impl Puzzle {
+    /// Create a puzzle from a FEN and the solution line in UCI notation,
+    /// starting with the move of the solver.
+    pub fn new(id: &str, fen: &str, solution: &[&str]) -> Result<Puzzle, PuzzleError> {
+        let position = parse_fen(fen)?;
+        let solution = parse_line(&position, solution)?;
+        if solution.is_empty() {
+            return Err(PuzzleError::NoSolution);
+        }
+
+        Ok(Puzzle {
+            id: id.to_owned(),
+            position,
+            last_move: None,
+            solution,
+            rating: None,
+        })
+    }
+
+    /// Parse a line of the Lichess puzzle database, with the columns
+    /// `PuzzleId,FEN,Moves,Rating,...`. The first move is the one of the
+    /// opponent leading to the puzzle, so it is played right away.
+    pub fn from_lichess_csv(line: &str) -> Result<Puzzle, PuzzleError> {
+        let mut columns = line.split(',');
+        let id = columns.next().ok_or(PuzzleError::MissingColumn(0))?;
+        let fen = columns.next().ok_or(PuzzleError::MissingColumn(0))?;
+        let moves: Vec<&str> = columns.next().ok_or(PuzzleError::MissingColumn(0))?.split_whitespace().collect();
+        let rating = columns.next().and_then(|rating| rating.parse().ok());
+
+        let mut position = parse_fen(fen)?;
+        let mut line = parse_line(&position, &moves)?.into_iter();
+        let last_move = line.next();
+        if let Some(ref m) = last_move {
+            position.play_unchecked(m);
+        }
+
+        let solution: Vec<Move> = line.collect();
+        if solution.is_empty() {
+            return Err(PuzzleError::NoSolution);
+        }
+
+        Ok(Puzzle {
+            id: id.to_owned(),
+            position,
+            last_move,
+            solution,
+            rating,
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The position the solver faces.
+    pub fn position(&self) -> &Chess {
+        &self.position
+    }
+
+    /// The move of the opponent that led to the position, if known.
+    pub fn last_move(&self) -> Option<&Move> {
+        self.last_move.as_ref()
+    }
+
+    /// The solution, alternating between moves of the solver and replies
+    /// of the opponent.
+    pub fn solution(&self) -> &[Move] {
+        &self.solution
+    }
+
+    pub fn rating(&self) -> Option<u32> {
+        self.rating
+    }
+
+    /// The side that solves the puzzle.
+    pub fn solver(&self) -> Color {
+        self.position.turn()
+    }
+}
+
+/// Parse the lines of a Lichess puzzle database, skipping the header and
+/// empty lines.
+pub fn read_puzzles(csv: &str) -> Result<Vec<Puzzle>, PuzzleError> {
+    csv.lines()
+        .enumerate()
+        .filter(|&(_, line)| !line.trim().is_empty() && !line.starts_with("PuzzleId"))
+        .map(|(n, line)| Puzzle::from_lichess_csv(line.trim()).map_err(|err| match err {
+            PuzzleError::MissingColumn(_) => PuzzleError::MissingColumn(n),
+            err => err,
+        }))
+        .collect()
+}
+
+fn parse_fen(fen: &str) -> Result<Chess, PuzzleError> {
+    fen.parse::<Fen>()
+        .ok()
+        .and_then(|fen| Chess::from_setup(fen.into_setup(), CastlingMode::Standard).ok())
+        .ok_or_else(|| PuzzleError::InvalidFen(fen.to_owned()))
+}
+
+fn parse_line(position: &Chess, line: &[&str]) -> Result<Vec<Move>, PuzzleError> {
+    let mut pos = position.clone();
+    let mut moves = Vec::with_capacity(line.len());
+
+    for (ply, uci) in line.iter().enumerate() {
+        let m = uci.parse::<Uci>()
+            .ok()
+            .and_then(|parsed| parsed.to_move(&pos).ok())
+            .ok_or_else(|| PuzzleError::InvalidMove { ply, uci: (*uci).to_owned() })?;
+        pos.play_unchecked(&m);
+        moves.push(m);
+    }
+
+    Ok(moves)
+}
+
+/// How the solver did on a puzzle.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct PuzzleStats {
+    /// Moves tried, right or wrong.
+    pub attempts: u32,
+    /// Moves that were not part of the solution.
+    pub mistakes: u32,
+}
+
+/// The answer to a move of the solver.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Verdict {
+    /// The move is part of the solution. The opponent replies with the
+    /// move, if any.
+    Correct(Option<Move>),
+    /// The move completes the solution, possibly followed by a last reply
+    /// of the opponent.
+    Solved(Option<Move>),
+    /// The move is wrong and has to be taken back.
+    Wrong,
+    /// The move is wrong, and there were too many mistakes.
+    Failed,
+}
+
+/// A puzzle being solved.
+#[derive(Debug, Clone)]
+pub(crate) struct PuzzleRun {
+    puzzle: Puzzle,
+    pos: Chess,
+    ply: usize,
+    stats: PuzzleStats,
+}
+
+impl PuzzleRun {
+    pub fn new(puzzle: Puzzle) -> PuzzleRun {
+        PuzzleRun {
+            pos: puzzle.position.clone(),
+            puzzle,
+            ply: 0,
+            stats: PuzzleStats::default(),
+        }
+    }
+
+    pub fn puzzle(&self) -> &Puzzle {
+        &self.puzzle
+    }
+
+    pub fn stats(&self) -> PuzzleStats {
+        self.stats
+    }
+
+    /// Check a move of the solver. A checkmate also solves the puzzle if
+    /// it is not the expected move.
+    pub fn try_move(&mut self, m: &Move) -> Verdict {
+        self.stats.attempts += 1;
+
+        let expected = self.puzzle.solution.get(self.ply);
+        let mut after = self.pos.clone();
+        after.play_unchecked(m);
+
+        if after.is_checkmate() || (expected == Some(m) && self.ply + 1 == self.puzzle.solution.len()) {
+            self.ply = self.puzzle.solution.len();
+            self.pos = after;
+            Verdict::Solved(None)
+        } else if expected == Some(m) {
+            let reply = self.puzzle.solution.get(self.ply + 1).cloned();
+            if let Some(ref reply) = reply {
+                after.play_unchecked(reply);
+            }
+            self.ply += 2;
+            self.pos = after;
+
+            if self.ply >= self.puzzle.solution.len() {
+                // the line ends with a reply of the opponent
+                Verdict::Solved(reply)
+            } else {
+                Verdict::Correct(reply)
+            }
+        } else {
+            self.stats.mistakes += 1;
+            if self.stats.mistakes >= MAX_MISTAKES {
+                Verdict::Failed
+            } else {
+                Verdict::Wrong
+            }
+        }
+    }
+}
@@ -0,0 +1,254 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Glicko-2 ratings of players.
+//!
+//! See Mark Glickman, *Example of the Glicko-2 system*.
+
+use std::f64::consts::PI;
+
+use serde_json::Value;
+
+use shakmaty::{Color, Outcome};
+
+/// Scale between Glicko and Glicko-2 ratings.
+const SCALE: f64 = 173.7178;
+
+/// Constrains the change of volatility over time.
+const TAU: f64 = 0.5;
+
+/// Convergence tolerance of the volatility iteration.
+const EPSILON: f64 = 0.000001;
+
+/// Rating deviation of new players, and the upper bound for everyone else.
+const MAX_DEVIATION: f64 = 350.0;
+
+/// Ratings with a deviation above this are provisional.
+const PROVISIONAL_DEVIATION: f64 = 110.0;
+
+/// Whether a rating can be trusted yet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RatingStatus {
+    /// Too few games were played, usually shown with a question mark.
+    Provisional,
+    Established,
+}
+
+/// A Glicko-2 rating, on the familiar Glicko scale.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for Rating {
+    fn default() -> Rating {
+        Rating {
+            rating: 1500.0,
+            deviation: MAX_DEVIATION,
+            volatility: 0.06,
+        }
+    }
+}
+
+impl Rating {
+    pub fn status(&self) -> RatingStatus {
+        if self.deviation > PROVISIONAL_DEVIATION {
+            RatingStatus::Provisional
+        } else {
+            RatingStatus::Established
+        }
+    }
+
+    pub fn is_provisional(&self) -> bool {
+        self.status() == RatingStatus::Provisional
+    }
+
+    /// The rounded rating, like `1500?` if provisional.
+    pub fn display(&self) -> String {
+        match self.status() {
+            RatingStatus::Provisional => format!("{}?", self.rating.round()),
+            RatingStatus::Established => format!("{}", self.rating.round()),
+        }
+    }
+
+    /// Expected score against an opponent.
+    pub fn expected_score(&self, opponent: &Rating) -> f64 {
+        let (mu, _) = self.scaled();
+        let (mu_j, phi_j) = opponent.scaled();
+        expectation(mu, mu_j, phi_j)
+    }
+
+    fn scaled(&self) -> (f64, f64) {
+        ((self.rating - 1500.0) / SCALE, self.deviation / SCALE)
+    }
+
+    /// The rating after a rating period with the given results, each an
+    /// opponent and the score against them: 1 for a win, 0.5 for a draw
+    /// and 0 for a loss. Without results only the deviation grows.
+    pub fn update(&self, results: &[(Rating, f64)]) -> Rating {
+        let (mu, phi) = self.scaled();
+        let sigma = self.volatility;
+
+        if results.is_empty() {
+            return Rating {
+                deviation: ((phi * phi + sigma * sigma).sqrt() * SCALE).min(MAX_DEVIATION),
+                ..*self
+            };
+        }
+
+        let mut inverse_v = 0.0;
+        let mut sum = 0.0;
+        for &(ref opponent, score) in results {
+            let (mu_j, phi_j) = opponent.scaled();
+            let g_j = g(phi_j);
+            let e = expectation(mu, mu_j, phi_j);
+            inverse_v += g_j * g_j * e * (1.0 - e);
+            sum += g_j * (score - e);
+        }
+        let v = 1.0 / inverse_v;
+        let delta = v * sum;
+
+        let sigma = new_volatility(phi, sigma, v, delta);
+        let phi_star = (phi * phi + sigma * sigma).sqrt();
+        let phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+        let mu = mu + phi * phi * sum;
+
+        Rating {
+            rating: mu * SCALE + 1500.0,
+            deviation: (phi * SCALE).min(MAX_DEVIATION),
+            volatility: sigma,
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "rating": self.rating,
+            "deviation": self.deviation,
+            "volatility": self.volatility,
+        })
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Option<Rating> {
+        Some(Rating {
+            rating: value["rating"].as_f64()?,
+            deviation: value["deviation"].as_f64()?,
+            volatility: value["volatility"].as_f64()?,
+        })
+    }
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+fn expectation(mu: f64, mu_j: f64, phi_j: f64) -> f64 {
+    1.0 / (1.0 + (-g(phi_j) * (mu - mu_j)).exp())
+}
+
+/// Find the new volatility with the Illinois algorithm.
+fn new_volatility(phi: f64, sigma: f64, v: f64, delta: f64) -> f64 {
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let d = phi * phi + v + ex;
+        ex * (delta * delta - phi * phi - v - ex) / (2.0 * d * d) - (x - a) / (TAU * TAU)
+    };
+
+    let mut big_a = a;
+    let mut big_b = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+
+    let mut f_a = f(big_a);
+    let mut f_b = f(big_b);
+    while (big_b - big_a).abs() > EPSILON {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+        if f_c * f_b <= 0.0 {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2.0;
+        }
+        big_b = big_c;
+        f_b = f_c;
+    }
+
+    (big_a / 2.0).exp()
+}
+
+/// The score of a side in a finished game.
+fn score(outcome: Outcome, color: Color) -> f64 {
+    match outcome {
+        Outcome::Decisive { winner } if winner == color => 1.0,
+        Outcome::Decisive { .. } => 0.0,
+        Outcome::Draw => 0.5,
+    }
+}
+
+/// The new ratings of white and black after a game between them.
+pub fn rate_game(white: &Rating, black: &Rating, outcome: Outcome) -> (Rating, Rating) {
+    (white.update(&[(*black, score(outcome, Color::White))]),
+     black.update(&[(*white, score(outcome, Color::Black))]))
+}
+
+/// A change of the rating of a player after a game, for the rating
+/// history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatingChange {
+    pub game_id: String,
+    pub opponent: String,
+    pub before: Rating,
+    pub after: Rating,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl RatingChange {
+    /// The difference in rating points.
+    pub fn diff(&self) -> f64 {
+        self.after.rating - self.before.rating
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "game": self.game_id,
+            "opponent": self.opponent,
+            "before": self.before.to_json(),
+            "after": self.after.to_json(),
+            "timestamp": self.timestamp,
+        })
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Option<RatingChange> {
+        Some(RatingChange {
+            game_id: value["game"].as_str()?.to_owned(),
+            opponent: value["opponent"].as_str()?.to_owned(),
+            before: Rating::from_json(&value["before"])?,
+            after: Rating::from_json(&value["after"])?,
+            timestamp: value["timestamp"].as_u64()?,
+        })
+    }
+}
@@ -0,0 +1,147 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Autosaving the game in progress to a recovery file.
+//!
+//! The file is written after every move and periodically while the game
+//! runs, and removed when the game ends or autosaving is stopped. If it is
+//! still there on startup, the app was interrupted, and the game can be
+//! restored.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "gtk3")]
+use gtk::prelude::*;
+#[cfg(feature = "gtk3")]
+use gtk::{ButtonsType, DialogFlags, MessageDialog, MessageType, ResponseType, Window};
+
+use serde_json::Value;
+
+#[cfg(feature = "gtk3")]
+use game::Game;
+use storage::{StorageError, StoredGame};
+
+/// Where and how often to autosave a game.
+#[derive(Debug, Clone)]
+pub struct Recovery {
+    path: PathBuf,
+    id: String,
+    interval: Duration,
+    headers: Vec<(String, String)>,
+}
+
+impl Recovery {
+    /// Autosave the game with an id to a file, every 30 seconds and after
+    /// every move.
+    pub fn new<P: AsRef<Path>>(path: P, id: &str) -> Recovery {
+        Recovery {
+            path: path.as_ref().to_owned(),
+            id: id.to_owned(),
+            interval: Duration::from_secs(30),
+            headers: Vec::new(),
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Recovery {
+        self.interval = interval;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Set a PGN header of the saved game, like the names of the players.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.retain(|&(ref n, _)| n != name);
+        self.headers.push((name.to_owned(), value.to_owned()));
+    }
+
+    /// The game interrupted when the app last quit, if the recovery file
+    /// was left behind.
+    pub fn interrupted(&self) -> Result<Option<StoredGame>, StorageError> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let corrupted = || StorageError::Corrupted(self.path.display().to_string());
+        let value: Value = serde_json::from_slice(&bytes).map_err(|_| corrupted())?;
+        let id = value["id"].as_str().ok_or_else(corrupted)?;
+        StoredGame::from_json(id, &value["game"]).map(Some)
+    }
+
+    /// Write the game, replacing the file only once it is complete, so
+    /// that a crash while saving keeps the previous state.
+    pub fn save(&self, game: &mut StoredGame) -> Result<(), StorageError> {
+        for &(ref name, ref value) in &self.headers {
+            game.game.set_header(name, value);
+        }
+        game.updated = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+
+        let json = json!({
+            "id": game.id,
+            "game": game.to_json(),
+        });
+
+        let partial = self.path.with_extension("partial");
+        fs::write(&partial, json.to_string())?;
+        fs::rename(&partial, &self.path)?;
+        Ok(())
+    }
+
+    /// The game to save under the id of this recovery file.
+    #[cfg(feature = "gtk3")]
+    pub(crate) fn stored_game(&self, game: Game) -> StoredGame {
+        StoredGame::new(&self.id, game)
+    }
+
+    /// Remove the recovery file, after the game ended or when quitting
+    /// normally.
+    pub fn discard(&self) -> Result<(), StorageError> {
+        match fs::remove_file(&self.path) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result.map_err(StorageError::from),
+        }
+    }
+}
+
+/// Ask whether to restore an interrupted game.
+#[cfg(feature = "gtk3")]
+pub fn prompt_restore(parent: Option<&Window>, game: &StoredGame) -> bool {
+    let players = match (game.game.header("White"), game.game.header("Black")) {
+        (Some(white), Some(black)) => format!(" between {} and {}", white, black),
+        _ => String::new(),
+    };
+
+    let dialog = MessageDialog::new(parent,
+                                    DialogFlags::MODAL,
+                                    MessageType::Question,
+                                    ButtonsType::YesNo,
+                                    &format!("The game{} was interrupted after {} plies. Restore it?", players, game.game.len()));
+    let response = dialog.run();
+    dialog.close();
+    response == ResponseType::Yes
+}
@@ -0,0 +1,295 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Draw a board without a window, for example to generate thumbnails of
+//! finished games.
+
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use cairo::{Context, Format, ImageSurface, Matrix, SvgSurface};
+use shakmaty::{Color, File, Move, Piece, Square};
+
+use boardstate::BoardState;
+use game::Game;
+use util::{ease, square_to_pos};
+
+/// Error when rendering a board to an image.
+#[derive(Debug)]
+pub enum RenderError {
+    /// Drawing failed.
+    Cairo(cairo::Error),
+    /// Writing the image failed.
+    Io(io::Error),
+    /// Encoding the animation failed.
+    Gif(gif::EncodingError),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenderError::Cairo(ref err) => write!(f, "rendering failed: {}", err),
+            RenderError::Io(ref err) => write!(f, "writing image failed: {}", err),
+            RenderError::Gif(ref err) => write!(f, "encoding gif failed: {}", err),
+        }
+    }
+}
+
+impl Error for RenderError {}
+
+impl From<cairo::Error> for RenderError {
+    fn from(err: cairo::Error) -> RenderError {
+        RenderError::Cairo(err)
+    }
+}
+
+impl From<io::Error> for RenderError {
+    fn from(err: io::Error) -> RenderError {
+        RenderError::Io(err)
+    }
+}
+
+impl From<gif::EncodingError> for RenderError {
+    fn from(err: gif::EncodingError) -> RenderError {
+        RenderError::Gif(err)
+    }
+}
+
+impl From<cairo::IoError> for RenderError {
+    fn from(err: cairo::IoError) -> RenderError {
+        match err {
+            cairo::IoError::Cairo(err) => RenderError::Cairo(err),
+            cairo::IoError::Io(err) => RenderError::Io(err),
+        }
+    }
+}
+
+/// The transformation from board coordinates to a square image of
+/// `size` pixels, including the border. Mirrors the matrix of the widget.
+pub(crate) fn board_matrix(state: &BoardState, size: f64) -> Matrix {
+    let scale = size / 9.0;
+
+    let mut matrix = Matrix::identity();
+    matrix.translate(size / 2.0, size / 2.0);
+    matrix.scale(scale, scale);
+    matrix.rotate(state.orientation().fold_wb(0.0, PI));
+    matrix.translate(-4.0, -4.0);
+    matrix
+}
+
+/// Draw the board, highlights, pieces and arrows onto `cr`, filling a
+/// square of `size` units at the origin.
+pub fn draw_board(cr: &Context, state: &BoardState, size: f64) -> Result<(), cairo::Error> {
+    let pieces: Vec<_> = state.board().pieces().map(|(square, piece)| (piece, square_to_pos(square))).collect();
+    draw_with_pieces(cr, state, size, &pieces)
+}
+
+/// Like `draw_board`, but with the pieces at arbitrary positions, so that
+/// they can be drawn in the middle of a move.
+fn draw_with_pieces(cr: &Context, state: &BoardState, size: f64, pieces: &[(Piece, (f64, f64))]) -> Result<(), cairo::Error> {
+    cr.save()?;
+    cr.transform(board_matrix(state, size));
+
+    state.draw(cr)?;
+
+    for &(piece, (x, y)) in pieces {
+        cr.save()?;
+        cr.translate(x, y);
+        cr.rotate(state.orientation().fold_wb(0.0, PI));
+        cr.translate(-0.5, -0.5);
+        state.piece_set().render(cr, piece)?;
+        cr.restore()?;
+    }
+
+    for shape in state.tablebase_shapes().iter().chain(state.engine_shapes()).chain(state.shapes()) {
+        shape.draw(cr)?;
+    }
+
+    cr.restore()
+}
+
+/// Render the board as a PNG image of `size` × `size` pixels.
+pub fn render_png(state: &BoardState, size: i32) -> Result<Vec<u8>, RenderError> {
+    let surface = ImageSurface::create(Format::ARgb32, size, size)?;
+
+    {
+        let cr = Context::new(&surface)?;
+        draw_board(&cr, state, f64::from(size))?;
+    }
+
+    let mut png = Vec::new();
+    surface.write_to_png(&mut png)?;
+    Ok(png)
+}
+
+/// Render the board as an SVG document of `size` × `size` points.
+pub fn render_svg(state: &BoardState, size: f64) -> Result<Vec<u8>, RenderError> {
+    let surface = SvgSurface::for_stream(size, size, Vec::<u8>::new())?;
+
+    {
+        let cr = Context::new(&surface)?;
+        draw_board(&cr, state, size)?;
+    }
+
+    let stream = surface.finish_output_stream().map_err(|err| err.error)?;
+    Ok(*stream.downcast::<Vec<u8>>().expect("svg stream is a vec"))
+}
+
+/// Options for `export_gif`.
+#[derive(Debug, Clone)]
+pub struct GifOptions {
+    /// Width and height of the animation in pixels.
+    pub size: u16,
+    /// How long each position is shown.
+    pub delay: Duration,
+    /// How long the final position is shown before the animation loops.
+    pub final_delay: Duration,
+    /// Number of intermediate frames sliding the moved piece from its
+    /// origin to its destination. `0` disables the move animation.
+    pub tween_frames: u32,
+    /// Duration of the move animation.
+    pub tween_duration: Duration,
+    /// The side at the bottom of the board.
+    pub orientation: Color,
+}
+
+impl Default for GifOptions {
+    fn default() -> GifOptions {
+        GifOptions {
+            size: 360,
+            delay: Duration::from_millis(1000),
+            final_delay: Duration::from_millis(3000),
+            tween_frames: 0,
+            tween_duration: Duration::from_millis(200),
+            orientation: Color::White,
+        }
+    }
+}
+
+/// Render every ply of the main line of `game` and encode the frames as
+/// an endlessly looping animated GIF.
+pub fn export_gif(game: &Game, options: &GifOptions) -> Result<Vec<u8>, RenderError> {
+    let mut state = BoardState::new();
+    state.set_orientation(options.orientation);
+    state.set_position(game.initial_position());
+
+    let size = i32::from(options.size);
+    let moves = game.moves();
+    let tween_delay = options.tween_duration / options.tween_frames.max(1);
+
+    let mut gif = Vec::new();
+
+    {
+        let mut encoder = gif::Encoder::new(&mut gif, options.size, options.size, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        let delay = if moves.is_empty() { options.final_delay } else { options.delay };
+        encoder.write_frame(&frame(size, delay, |cr| draw_board(cr, &state, f64::from(size)))?)?;
+
+        for (ply, game_move) in moves.iter().enumerate() {
+            let m = game_move.mv();
+
+            if options.tween_frames > 0 {
+                let slides = slides(m);
+                let pieces: Vec<_> = state.board().pieces()
+                    .filter(|&(square, _)| slides.iter().all(|&(orig, _)| orig != square))
+                    .collect();
+
+                for i in 1..=options.tween_frames {
+                    let t = f64::from(i) / f64::from(options.tween_frames + 1);
+
+                    let mut tweened: Vec<_> = pieces.iter().map(|&(square, piece)| (piece, square_to_pos(square))).collect();
+                    for &(orig, dest) in &slides {
+                        if let Some(piece) = state.board().piece_at(orig) {
+                            let (ox, oy) = square_to_pos(orig);
+                            let (dx, dy) = square_to_pos(dest);
+                            tweened.push((piece, (ease(ox, dx, t), ease(oy, dy, t))));
+                        }
+                    }
+
+                    encoder.write_frame(&frame(size, tween_delay, |cr| draw_with_pieces(cr, &state, f64::from(size), &tweened))?)?;
+                }
+            }
+
+            if state.play(m).is_err() {
+                break;
+            }
+
+            let delay = if ply + 1 == moves.len() { options.final_delay } else { options.delay };
+            encoder.write_frame(&frame(size, delay, |cr| draw_board(cr, &state, f64::from(size)))?)?;
+        }
+    }
+
+    Ok(gif)
+}
+
+/// Pieces that move from one square to another when playing `m`. Drops
+/// just appear.
+fn slides(m: &Move) -> Vec<(Square, Square)> {
+    match *m {
+        Move::Castle { king, rook } => {
+            let (king_file, rook_file) = if rook.file() > king.file() { (File::G, File::F) } else { (File::C, File::D) };
+            vec![
+                (king, Square::from_coords(king_file, king.rank())),
+                (rook, Square::from_coords(rook_file, rook.rank())),
+            ]
+        },
+        Move::Put { .. } => Vec::new(),
+        _ => m.from().map(|from| vec![(from, m.to())]).unwrap_or_default(),
+    }
+}
+
+/// Draw a single animation frame and convert it to the palette based
+/// GIF format.
+fn frame<F>(size: i32, delay: Duration, draw: F) -> Result<gif::Frame<'static>, RenderError>
+    where F: FnOnce(&Context) -> Result<(), cairo::Error>
+{
+    let mut surface = ImageSurface::create(Format::ARgb32, size, size)?;
+
+    {
+        let cr = Context::new(&surface)?;
+        draw(&cr)?;
+    }
+
+    surface.flush();
+    let stride = surface.stride() as usize;
+    let data = surface.data().map_err(|err| match err {
+        cairo::BorrowError::Cairo(err) => RenderError::Cairo(err),
+        cairo::BorrowError::NonExclusive => unreachable!("context dropped before reading the surface"),
+    })?;
+
+    // cairo stores premultiplied native endian ARGB, gif wants RGBA
+    let mut rgba = Vec::with_capacity(size as usize * size as usize * 4);
+    for row in data.chunks(stride).take(size as usize) {
+        for pixel in row[..size as usize * 4].chunks(4) {
+            let argb = u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+            let a = argb >> 24;
+            let unpremultiply = |c: u32| if a == 0 { 0 } else { (c * 255 / a) as u8 };
+            rgba.push(unpremultiply((argb >> 16) & 0xff));
+            rgba.push(unpremultiply((argb >> 8) & 0xff));
+            rgba.push(unpremultiply(argb & 0xff));
+            rgba.push(a as u8);
+        }
+    }
+
+    let mut frame = gif::Frame::from_rgba_speed(size as u16, size as u16, &mut rgba, 10);
+    frame.delay = (delay.as_millis() / 10).min(u128::from(u16::max_value())) as u16;
+    Ok(frame)
+}
@@ -0,0 +1,123 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Drawing primitives shared by the GTK3 and GTK4 widgets.
+//!
+//! Coordinates are board coordinates, with a unit per square and the
+//! board from `(0, 0)` to `(8, 8)`. The GTK3 widget draws with cairo, and
+//! the GTK4 widget records into a snapshot.
+
+use std::f64::consts::PI;
+
+use cairo::{Context, RadialGradient};
+
+use shakmaty::{Color, Piece, Square};
+
+use pieceset::PieceSet;
+use theme::Rgba;
+use util::{file_to_float, rank_to_float};
+
+pub(crate) trait Renderer {
+    fn fill_rect(&self, x: f64, y: f64, width: f64, height: f64, color: Rgba) -> Result<(), cairo::Error>;
+
+    /// Outline a rectangle, with the line inside of it.
+    fn stroke_rect(&self, x: f64, y: f64, width: f64, height: f64, line_width: f64, color: Rgba) -> Result<(), cairo::Error>;
+
+    fn fill_circle(&self, center: (f64, f64), radius: f64, color: Rgba) -> Result<(), cairo::Error>;
+
+    fn stroke_circle(&self, center: (f64, f64), radius: f64, line_width: f64, color: Rgba) -> Result<(), cairo::Error>;
+
+    /// Radial gradient from `color` in the center, fading out towards
+    /// `radius`, like the glow under a king in check.
+    fn fill_glow(&self, center: (f64, f64), radius: f64, color: Rgba) -> Result<(), cairo::Error>;
+
+    /// Draw a piece `size` squares large, centered on `center`, and upright
+    /// for a board seen from the side of `orientation`.
+    fn piece(&self, piece_set: &PieceSet, piece: Piece, center: (f64, f64), size: f64, orientation: Color) -> Result<(), cairo::Error>;
+
+    /// Restrict drawing to a rectangle until `pop_clip()`.
+    fn push_clip(&self, x: f64, y: f64, width: f64, height: f64) -> Result<(), cairo::Error>;
+
+    fn pop_clip(&self) -> Result<(), cairo::Error>;
+
+    fn fill_square(&self, square: Square, color: Rgba) -> Result<(), cairo::Error> {
+        self.fill_rect(file_to_float(square.file()), 7.0 - rank_to_float(square.rank()), 1.0, 1.0, color)
+    }
+
+    fn stroke_square(&self, square: Square, line_width: f64, color: Rgba) -> Result<(), cairo::Error> {
+        self.stroke_rect(file_to_float(square.file()), 7.0 - rank_to_float(square.rank()), 1.0, 1.0, line_width, color)
+    }
+}
+
+impl Renderer for Context {
+    fn fill_rect(&self, x: f64, y: f64, width: f64, height: f64, color: Rgba) -> Result<(), cairo::Error> {
+        color.set_source(self);
+        self.rectangle(x, y, width, height);
+        self.fill()
+    }
+
+    fn stroke_rect(&self, x: f64, y: f64, width: f64, height: f64, line_width: f64, color: Rgba) -> Result<(), cairo::Error> {
+        color.set_source(self);
+        self.set_line_width(line_width);
+        let half = line_width / 2.0;
+        self.rectangle(x + half, y + half, width - line_width, height - line_width);
+        self.stroke()
+    }
+
+    fn fill_circle(&self, (x, y): (f64, f64), radius: f64, color: Rgba) -> Result<(), cairo::Error> {
+        color.set_source(self);
+        self.arc(x, y, radius, 0.0, 2.0 * PI);
+        self.fill()
+    }
+
+    fn stroke_circle(&self, (x, y): (f64, f64), radius: f64, line_width: f64, color: Rgba) -> Result<(), cairo::Error> {
+        color.set_source(self);
+        self.set_line_width(line_width);
+        self.arc(x, y, radius, 0.0, 2.0 * PI);
+        self.stroke()
+    }
+
+    fn fill_glow(&self, (x, y): (f64, f64), radius: f64, c: Rgba) -> Result<(), cairo::Error> {
+        let gradient = RadialGradient::new(x, y, 0.0, x, y, radius);
+        gradient.add_color_stop_rgba(0.0, c.red, c.green, c.blue, c.alpha);
+        gradient.add_color_stop_rgba(0.25, 0.91 * c.red, 0.91 * c.green, 0.91 * c.blue, c.alpha);
+        gradient.add_color_stop_rgba(0.89, 0.66 * c.red, 0.66 * c.green, 0.66 * c.blue, 0.0);
+        self.set_source(&gradient)?;
+        self.paint()
+    }
+
+    fn piece(&self, piece_set: &PieceSet, piece: Piece, (x, y): (f64, f64), size: f64, orientation: Color) -> Result<(), cairo::Error> {
+        self.save()?;
+        self.translate(x, y);
+        self.rotate(orientation.fold_wb(0.0, PI));
+        self.scale(size, size);
+        self.translate(-0.5, -0.5);
+        piece_set.render(self, piece)?;
+        self.restore()
+    }
+
+    fn push_clip(&self, x: f64, y: f64, width: f64, height: f64) -> Result<(), cairo::Error> {
+        self.save()?;
+        self.rectangle(x, y, width, height);
+        self.clip();
+        Ok(())
+    }
+
+    fn pop_clip(&self) -> Result<(), cairo::Error> {
+        self.restore()
+    }
+}
@@ -0,0 +1,291 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::f64::consts::PI;
+
+#[cfg(feature = "gtk3")]
+use gdk::ModifierType;
+use cairo::Context;
+
+use shakmaty::Square;
+
+#[cfg(feature = "gtk3")]
+use ground::{EventContext, GroundMsg};
+#[cfg(feature = "gtk3")]
+use boardstate::BoardState;
+use theme::Rgba;
+use util::{file_to_float, rank_to_float};
+
+/// Shape colors.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum DrawBrush {
+    Green,
+    Red,
+    Blue,
+    Yellow,
+}
+
+/// An arrow or circle drawn on the board.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct DrawShape {
+    orig: Square,
+    dest: Square,
+    brush: DrawBrush,
+    weight: u8,
+}
+
+/// An arrow set by the caller, e.g. to show the idea of a puzzle or a
+/// tutorial step. Arrows stay until replaced and are drawn beneath the
+/// shapes of the user.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Arrow {
+    pub from: Square,
+    pub to: Square,
+    pub color: Rgba,
+    /// Thickness of the shaft relative to a square, `0.2` for an arrow as
+    /// thick as one drawn by the user.
+    pub width: f64,
+}
+
+impl Arrow {
+    pub(crate) fn draw(&self, cr: &Context) -> Result<(), cairo::Error> {
+        self.color.set_source(cr);
+
+        if self.from == self.to {
+            let (x, y) = square_center(self.to);
+            let stroke = 0.25 * self.width;
+            cr.set_line_width(stroke);
+            cr.arc(x, y, 0.5 * (1.0 - stroke), 0.0, 2.0 * PI);
+            cr.stroke()
+        } else {
+            draw_arrow(cr, self.from, self.to, self.width, 3.75 * self.width)
+        }
+    }
+}
+
+/// Handles drawing shapes with the right mouse button. The finished shapes
+/// are stored in the `BoardState`.
+#[cfg(feature = "gtk3")]
+pub struct Drawable {
+    drawing: Option<DrawShape>,
+    enabled: bool,
+    erase_on_click: bool,
+}
+
+#[cfg(feature = "gtk3")]
+impl Drawable {
+    pub fn new() -> Drawable {
+        Drawable {
+            drawing: None,
+            enabled: true,
+            erase_on_click: true,
+        }
+    }
+
+    pub(crate) fn mouse_down(&mut self, state: &mut BoardState, ctx: &EventContext) {
+        if !self.enabled {
+            return;
+        }
+
+        match ctx.button() {
+            1 => {
+                if self.erase_on_click && !state.shapes().is_empty() {
+                    state.set_shapes(Vec::new());
+                    ctx.stream().emit(GroundMsg::ShapesChanged(Vec::new()));
+                    ctx.widget().queue_draw();
+                }
+            }
+            3 => {
+                self.drawing = ctx.square().map(|square| {
+                    let brush = if ctx.modifiers().contains(ModifierType::MOD1_MASK | ModifierType::SHIFT_MASK) {
+                        DrawBrush::Yellow
+                    } else if ctx.modifiers().contains(ModifierType::MOD1_MASK) {
+                        DrawBrush::Blue
+                    } else if ctx.modifiers().contains(ModifierType::SHIFT_MASK) {
+                        DrawBrush::Red
+                    } else {
+                        DrawBrush::Green
+                    };
+
+                    DrawShape::new(square, square, brush)
+                });
+
+                ctx.widget().queue_draw();
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn mouse_move(&mut self, ctx: &EventContext) {
+        if let Some(ref mut drawing) = self.drawing {
+            let dest = ctx.square().unwrap_or(drawing.orig);
+            if drawing.dest != dest {
+                ctx.widget().queue_draw();
+            }
+            drawing.dest = dest;
+        }
+    }
+
+    pub(crate) fn mouse_up(&mut self, state: &mut BoardState, ctx: &EventContext) {
+        if let Some(mut drawing) = self.drawing.take() {
+            if self.enabled {
+                drawing.dest = ctx.square().unwrap_or(drawing.orig);
+
+                // remove or add shape
+                let shapes = state.shapes_mut();
+                let num_shapes = shapes.len();
+                shapes.retain(|s| s.orig != drawing.orig || s.dest != drawing.dest);
+                if num_shapes == shapes.len() {
+                    shapes.push(drawing);
+                }
+
+                ctx.stream().emit(GroundMsg::ShapesChanged(shapes.clone()));
+            }
+
+            ctx.widget().queue_draw();
+        }
+    }
+
+    pub(crate) fn draw(&self, cr: &Context, state: &BoardState) -> Result<(), cairo::Error> {
+        for shape in state.tablebase_shapes() {
+            shape.draw(cr)?;
+        }
+
+        for shape in state.engine_shapes() {
+            shape.draw(cr)?;
+        }
+
+        for arrow in state.arrows() {
+            arrow.draw(cr)?;
+        }
+
+        for shape in state.shapes() {
+            shape.draw(cr)?;
+        }
+
+        if let Some(ref shape) = self.drawing {
+            shape.draw(cr)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl DrawShape {
+    /// Create a shape. It is a circle if `orig` and `dest` are the same
+    /// square, and an arrow otherwise.
+    pub fn new(orig: Square, dest: Square, brush: DrawBrush) -> DrawShape {
+        DrawShape { orig, dest, brush, weight: 100 }
+    }
+
+    /// The same shape drawn thinner and fainter, with `weight` from `0`
+    /// to `100`.
+    pub fn with_weight(mut self, weight: u8) -> DrawShape {
+        self.weight = weight.min(100);
+        self
+    }
+
+    /// First square.
+    pub fn orig(&self) -> Square {
+        self.orig
+    }
+
+    /// Second square.
+    pub fn dest(&self) -> Square {
+        self.dest
+    }
+
+    /// Shape color.
+    pub fn brush(&self) -> DrawBrush {
+        self.brush
+    }
+
+    /// Thickness and opacity in percent of a normal shape.
+    pub fn weight(&self) -> u8 {
+        self.weight
+    }
+
+    /// Check if the shape is a circle.
+    pub fn is_circle(&self) -> bool {
+        self.orig == self.dest
+    }
+
+    /// Check if the shape is an arrow.
+    pub fn is_arrow(&self) -> bool {
+        self.orig != self.dest
+    }
+
+    pub(crate) fn draw(&self, cr: &Context) -> Result<(), cairo::Error> {
+        let weight = f64::from(self.weight) / 100.0;
+        let opacity = 0.5 * (0.3 + 0.7 * weight);
+
+        match self.brush {
+            DrawBrush::Green => cr.set_source_rgba(0.08, 0.47, 0.11, opacity),
+            DrawBrush::Red => cr.set_source_rgba(0.53, 0.13, 0.13, opacity),
+            DrawBrush::Blue => cr.set_source_rgba(0.0, 0.19, 0.53, opacity),
+            DrawBrush::Yellow => cr.set_source_rgba(0.90, 0.94, 0.0, opacity),
+        }
+
+        if self.is_circle() {
+            // draw circle
+            let (dest_x, dest_y) = square_center(self.dest);
+            let stroke = 0.05;
+            cr.set_line_width(stroke);
+            cr.arc(dest_x, dest_y, 0.5 * (1.0 - stroke), 0.0, 2.0 * PI);
+            cr.stroke()?;
+        } else {
+            draw_arrow(cr, self.orig, self.dest, 0.2 * (0.4 + 0.6 * weight), 0.75 * (0.6 + 0.4 * weight))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn square_center(square: Square) -> (f64, f64) {
+    (0.5 + file_to_float(square.file()), 7.5 - rank_to_float(square.rank()))
+}
+
+/// Draw an arrow with the current source, with the shaft `stroke` thick
+/// and a head `marker_size` long.
+fn draw_arrow(cr: &Context, orig: Square, dest: Square, stroke: f64, marker_size: f64) -> Result<(), cairo::Error> {
+    let (orig_x, orig_y) = square_center(orig);
+    let (dest_x, dest_y) = square_center(dest);
+    let margin = 0.1;
+
+    let (dx, dy) = (dest_x - orig_x, dest_y - orig_y);
+    let hypot = dx.hypot(dy);
+
+    let shaft_x = dest_x - dx * (marker_size + margin) / hypot;
+    let shaft_y = dest_y - dy * (marker_size + margin) / hypot;
+
+    let head_x = dest_x - dx * margin / hypot;
+    let head_y = dest_y - dy * margin / hypot;
+
+    cr.set_line_width(stroke);
+
+    // shaft
+    cr.move_to(orig_x, orig_y);
+    cr.line_to(shaft_x, shaft_y);
+    cr.stroke()?;
+
+    // arrow head
+    cr.move_to(head_x, head_y);
+    cr.line_to(shaft_x - dy * 0.5 * marker_size / hypot,
+               shaft_y + dx * 0.5 * marker_size / hypot);
+    cr.line_to(shaft_x + dy * 0.5 * marker_size / hypot,
+               shaft_y - dx * 0.5 * marker_size / hypot);
+    cr.fill()
+}
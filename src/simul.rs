@@ -0,0 +1,337 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Simultaneous exhibitions, where one player gives a game on many boards.
+
+use std::fmt;
+use std::time::Duration;
+
+use gtk::prelude::*;
+use gtk::{Button, Label, Orientation, Stack};
+
+use relm::{Component, Relm, StreamHandle, Update, Widget};
+
+use shakmaty::{Color, Outcome, Role, Square};
+
+use boardstate::MovableSide;
+use chain::MoveRecord;
+use clock::{format_time, TimeControl};
+use ground::{Ground, GroundMsg};
+
+/// Results of the simul giver.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SimulScore {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    /// Games still in progress.
+    pub playing: usize,
+}
+
+impl SimulScore {
+    /// Points of the simul giver so far.
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.0
+    }
+}
+
+impl fmt::Display for SimulScore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "+{} ={} -{}", self.wins, self.draws, self.losses)?;
+        if self.playing > 0 {
+            write!(f, ", {} playing", self.playing)?;
+        }
+        Ok(())
+    }
+}
+
+/// Simul messages.
+#[derive(Debug, Msg)]
+pub enum SimulMsg {
+    /// Add a board against an opponent, with the color of the simul giver
+    /// on that board.
+    AddBoard(String, Color),
+    /// Start clocks for the simul giver on all boards, or remove them with
+    /// `None`. The clock of a board only runs while the giver is to move
+    /// there.
+    SetTimeControl(Option<TimeControl>),
+    /// Play the move of the opponent on a board.
+    OpponentMove(usize, MoveRecord),
+    /// Pass a message to the board with the given index.
+    ToBoard(usize, GroundMsg),
+    /// Show the board with the given index.
+    Show(usize),
+    Next,
+    Previous,
+
+    /// Sent when the simul giver moved on a board. Forward to the opponent
+    /// of that board. The carousel then moves on to the next board where
+    /// the giver is to move.
+    GiverMove(usize, Square, Square, Option<Role>),
+    /// Sent when a game ended, with the results of the giver so far.
+    ResultsChanged(SimulScore),
+    /// Sent when the board with the given index emits an event, like
+    /// `GameEnd` or `Flag`.
+    BoardEvent(usize, GroundMsg),
+}
+
+struct SimulBoard {
+    ground: Component<Ground>,
+    opponent: String,
+    giver: Color,
+    ply: u32,
+    giver_to_move: bool,
+    giver_time: Option<Duration>,
+    outcome: Option<Option<Outcome>>,
+}
+
+/// A carousel of boards for the simul giver, showing one board at a time.
+pub struct Simul {
+    vbox: gtk::Box,
+    stack: Stack,
+    title: Label,
+    results: Label,
+    stream: StreamHandle<SimulMsg>,
+    boards: Vec<SimulBoard>,
+    current: usize,
+    time_control: Option<TimeControl>,
+}
+
+impl fmt::Debug for Simul {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Simul")
+            .field("boards", &self.boards.len())
+            .field("current", &self.current)
+            .field("score", &self.score())
+            .finish()
+    }
+}
+
+impl Simul {
+    fn add_board(&mut self, opponent: String, giver: Color) {
+        let ground = relm::create_component::<Ground>(());
+
+        let index = self.boards.len();
+        let stream = self.stream.clone();
+        ground.stream().observe(move |msg: &GroundMsg| {
+            if let Some(event) = msg.clone_event() {
+                stream.emit(SimulMsg::BoardEvent(index, event));
+            }
+        });
+
+        ground.emit(GroundMsg::SetOrientation(giver));
+        ground.emit(GroundMsg::SetMovable(giver.fold_wb(MovableSide::White, MovableSide::Black)));
+        ground.emit(GroundMsg::SetUntimedSide(Some(!giver)));
+        ground.emit(GroundMsg::SetTimeControl(self.time_control.clone()));
+
+        ground.widget().show();
+        self.stack.add(ground.widget());
+
+        self.boards.push(SimulBoard {
+            ground,
+            opponent,
+            giver,
+            ply: 0,
+            giver_to_move: giver == Color::White,
+            giver_time: self.time_control.as_ref().and_then(|c| c.periods().first()).map(|p| p.base()),
+            outcome: None,
+        });
+
+        if index == 0 {
+            self.show(0);
+        }
+        self.render();
+    }
+
+    fn show(&mut self, index: usize) {
+        if let Some(board) = self.boards.get(index) {
+            self.current = index;
+            self.stack.set_visible_child(board.ground.widget());
+            self.render();
+        }
+    }
+
+    /// Walk on to the next board where the giver is to move.
+    fn next_to_move(&mut self) {
+        let n = self.boards.len();
+        let next = (1..=n).map(|offset| (self.current + offset) % n).find(|&index| {
+            let board = &self.boards[index];
+            board.giver_to_move && board.outcome.is_none()
+        });
+        if let Some(next) = next {
+            self.show(next);
+        }
+    }
+
+    fn score(&self) -> SimulScore {
+        let mut score = SimulScore::default();
+        for board in &self.boards {
+            match board.outcome {
+                None => score.playing += 1,
+                Some(Some(Outcome::Decisive { winner })) if winner == board.giver => score.wins += 1,
+                Some(Some(Outcome::Decisive { .. })) => score.losses += 1,
+                Some(Some(Outcome::Draw)) => score.draws += 1,
+                Some(None) => (), // aborted
+            }
+        }
+        score
+    }
+
+    fn render(&self) {
+        if let Some(board) = self.boards.get(self.current) {
+            let mut text = format!("Board {} of {} · {}", self.current + 1, self.boards.len(), board.opponent);
+            if let Some(time) = board.giver_time {
+                text.push_str(" · ");
+                text.push_str(&format_time(time));
+            }
+            if board.outcome.is_none() && board.giver_to_move {
+                text.push_str(" · your move");
+            }
+            self.title.set_text(&text);
+        }
+        self.results.set_text(&self.score().to_string());
+    }
+
+    fn board_event(&mut self, index: usize, event: &GroundMsg) {
+        let board = match self.boards.get_mut(index) {
+            Some(board) => board,
+            None => return,
+        };
+
+        match *event {
+            GroundMsg::UserMove(orig, dest, promotion) if board.giver_to_move && board.outcome.is_none() => {
+                let record = MoveRecord::new(&format!("simul-{}", index), board.ply, orig, dest, promotion);
+                board.ground.emit(GroundMsg::RemoteMove(record));
+                board.ply += 1;
+                board.giver_to_move = false;
+                self.stream.emit(SimulMsg::GiverMove(index, orig, dest, promotion));
+                self.next_to_move();
+            },
+            GroundMsg::ClockChanged(white, black, _) => {
+                board.giver_time = Some(board.giver.fold_wb(white, black));
+                if index == self.current {
+                    self.render();
+                }
+            },
+            GroundMsg::GameEnd(game_state) => {
+                board.outcome = Some(game_state.outcome());
+                let score = self.score();
+                self.stream.emit(SimulMsg::ResultsChanged(score));
+                self.render();
+            },
+            _ => (),
+        }
+    }
+}
+
+impl Update for Simul {
+    type Model = ();
+    type ModelParam = ();
+    type Msg = SimulMsg;
+
+    fn model(_: &Relm<Self>, _: ()) {}
+
+    fn update(&mut self, event: SimulMsg) {
+        match event {
+            SimulMsg::AddBoard(opponent, giver) => self.add_board(opponent, giver),
+            SimulMsg::SetTimeControl(control) => {
+                for board in &mut self.boards {
+                    board.ground.emit(GroundMsg::SetTimeControl(control.clone()));
+                    board.giver_time = control.as_ref().and_then(|c| c.periods().first()).map(|p| p.base());
+                }
+                self.time_control = control;
+                self.render();
+            },
+            SimulMsg::OpponentMove(index, record) => {
+                if let Some(board) = self.boards.get_mut(index) {
+                    if !board.giver_to_move && board.outcome.is_none() {
+                        board.ground.emit(GroundMsg::RemoteMove(record));
+                        board.ply += 1;
+                        board.giver_to_move = true;
+                    }
+                }
+                if index == self.current {
+                    self.render();
+                }
+            },
+            SimulMsg::ToBoard(index, msg) => {
+                if let Some(board) = self.boards.get(index) {
+                    board.ground.emit(msg);
+                }
+            },
+            SimulMsg::Show(index) => self.show(index),
+            SimulMsg::Next if !self.boards.is_empty() => {
+                let next = (self.current + 1) % self.boards.len();
+                self.show(next);
+            },
+            SimulMsg::Previous if !self.boards.is_empty() => {
+                let previous = (self.current + self.boards.len() - 1) % self.boards.len();
+                self.show(previous);
+            },
+            SimulMsg::BoardEvent(index, ref event) => self.board_event(index, event),
+            SimulMsg::Next | SimulMsg::Previous |
+            SimulMsg::GiverMove(..) | SimulMsg::ResultsChanged(_) => {},
+        }
+    }
+}
+
+impl Widget for Simul {
+    type Root = gtk::Box;
+
+    fn root(&self) -> Self::Root {
+        self.vbox.clone()
+    }
+
+    fn view(relm: &Relm<Self>, _: ()) -> Self {
+        let previous = Button::with_label("◀");
+        let next = Button::with_label("▶");
+        {
+            let stream = relm.stream().clone();
+            previous.connect_clicked(move |_| stream.emit(SimulMsg::Previous));
+        }
+        {
+            let stream = relm.stream().clone();
+            next.connect_clicked(move |_| stream.emit(SimulMsg::Next));
+        }
+
+        let title = Label::new(None);
+        let header = gtk::Box::new(Orientation::Horizontal, 6);
+        header.pack_start(&previous, false, false, 0);
+        header.pack_start(&title, true, true, 0);
+        header.pack_end(&next, false, false, 0);
+
+        let stack = Stack::new();
+        let results = Label::new(None);
+
+        let vbox = gtk::Box::new(Orientation::Vertical, 6);
+        vbox.pack_start(&header, false, false, 0);
+        vbox.pack_start(&stack, true, true, 0);
+        vbox.pack_end(&results, false, false, 0);
+        vbox.show_all();
+
+        Simul {
+            vbox,
+            stack,
+            title,
+            results,
+            stream: relm.stream().clone(),
+            boards: Vec::new(),
+            current: 0,
+            time_control: None,
+        }
+    }
+}
@@ -0,0 +1,312 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Spoken announcements of moves, game events and clock warnings.
+//!
+//! Text is spoken with `spd-say` from speech-dispatcher, which picks a
+//! voice for the selected language.
+
+use std::fmt;
+use std::io;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use shakmaty::{CastlingSide, Color, Move, Outcome, Role};
+
+use boardstate::GameState;
+
+/// The words of announcements in one language.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Vocabulary {
+    /// Language code for speech-dispatcher, like `en`.
+    pub language: &'static str,
+    /// Names of pawn, knight, bishop, rook, queen and king.
+    pub roles: [&'static str; 6],
+    pub colors: [&'static str; 2],
+    pub takes: &'static str,
+    pub check: &'static str,
+    pub checkmate: &'static str,
+    pub short_castle: &'static str,
+    pub long_castle: &'static str,
+    pub promotes: &'static str,
+    pub en_passant: &'static str,
+    pub drops: &'static str,
+    pub wins: &'static str,
+    pub draw: &'static str,
+    pub game_aborted: &'static str,
+    pub low_time: &'static str,
+}
+
+const ENGLISH: Vocabulary = Vocabulary {
+    language: "en",
+    roles: ["pawn", "knight", "bishop", "rook", "queen", "king"],
+    colors: ["white", "black"],
+    takes: "takes",
+    check: "check",
+    checkmate: "checkmate",
+    short_castle: "castles short",
+    long_castle: "castles long",
+    promotes: "promotes to",
+    en_passant: "en passant",
+    drops: "drops on",
+    wins: "wins",
+    draw: "draw",
+    game_aborted: "game aborted",
+    low_time: "low time",
+};
+
+const GERMAN: Vocabulary = Vocabulary {
+    language: "de",
+    roles: ["Bauer", "Springer", "Läufer", "Turm", "Dame", "König"],
+    colors: ["Weiß", "Schwarz"],
+    takes: "schlägt",
+    check: "Schach",
+    checkmate: "Schachmatt",
+    short_castle: "kurze Rochade",
+    long_castle: "lange Rochade",
+    promotes: "wird zu",
+    en_passant: "en passant",
+    drops: "setzt auf",
+    wins: "gewinnt",
+    draw: "Remis",
+    game_aborted: "Partie abgebrochen",
+    low_time: "wenig Zeit",
+};
+
+const FRENCH: Vocabulary = Vocabulary {
+    language: "fr",
+    roles: ["pion", "cavalier", "fou", "tour", "dame", "roi"],
+    colors: ["les blancs", "les noirs"],
+    takes: "prend",
+    check: "échec",
+    checkmate: "échec et mat",
+    short_castle: "petit roque",
+    long_castle: "grand roque",
+    promotes: "promu en",
+    en_passant: "en passant",
+    drops: "posé en",
+    wins: "gagnent",
+    draw: "nulle",
+    game_aborted: "partie annulée",
+    low_time: "peu de temps",
+};
+
+const SPANISH: Vocabulary = Vocabulary {
+    language: "es",
+    roles: ["peón", "caballo", "alfil", "torre", "dama", "rey"],
+    colors: ["blancas", "negras"],
+    takes: "captura",
+    check: "jaque",
+    checkmate: "jaque mate",
+    short_castle: "enroque corto",
+    long_castle: "enroque largo",
+    promotes: "corona",
+    en_passant: "al paso",
+    drops: "coloca en",
+    wins: "ganan",
+    draw: "tablas",
+    game_aborted: "partida anulada",
+    low_time: "poco tiempo",
+};
+
+impl Default for Vocabulary {
+    fn default() -> Vocabulary {
+        ENGLISH
+    }
+}
+
+impl Vocabulary {
+    /// The languages with a vocabulary.
+    pub const LANGUAGES: [&'static str; 4] = ["en", "de", "fr", "es"];
+
+    /// The vocabulary of a language code like `de` or `de-AT`.
+    pub fn for_language(language: &str) -> Option<Vocabulary> {
+        match language.split(|c| c == '-' || c == '_').next()? {
+            "en" => Some(ENGLISH),
+            "de" => Some(GERMAN),
+            "fr" => Some(FRENCH),
+            "es" => Some(SPANISH),
+            _ => None,
+        }
+    }
+
+    fn role(&self, role: Role) -> &'static str {
+        self.roles[role as usize - 1]
+    }
+
+    fn color(&self, color: Color) -> &'static str {
+        color.fold_wb(self.colors[0], self.colors[1])
+    }
+
+    /// A move in words, like "knight takes e5, check".
+    pub fn spoken_move(&self, m: &Move, check: bool, checkmate: bool) -> String {
+        let mut text = match *m {
+            Move::Castle { .. } => match m.castling_side() {
+                Some(CastlingSide::KingSide) => self.short_castle.to_owned(),
+                _ => self.long_castle.to_owned(),
+            },
+            Move::Put { role, to } => format!("{} {} {}", self.role(role), self.drops, to),
+            Move::EnPassant { from, to } => format!("{} {} {} {}", from.file().char(), self.takes, to, self.en_passant),
+            Move::Normal { role, from, capture, to, promotion } => {
+                let mut text = match (role, capture) {
+                    (Role::Pawn, Some(_)) => format!("{} {} {}", from.file().char(), self.takes, to),
+                    (Role::Pawn, None) => to.to_string(),
+                    (role, Some(_)) => format!("{} {} {}", self.role(role), self.takes, to),
+                    (role, None) => format!("{} {}", self.role(role), to),
+                };
+                if let Some(promotion) = promotion {
+                    text.push_str(&format!(" {} {}", self.promotes, self.role(promotion)));
+                }
+                text
+            },
+        };
+
+        if checkmate {
+            text.push_str(&format!(", {}", self.checkmate));
+        } else if check {
+            text.push_str(&format!(", {}", self.check));
+        }
+        text
+    }
+
+    /// The end of a game in words, like "black wins".
+    pub fn spoken_end(&self, game_state: GameState) -> Option<String> {
+        Some(match game_state {
+            GameState::Ongoing => return None,
+            GameState::Aborted => self.game_aborted.to_owned(),
+            _ => match game_state.outcome()? {
+                Outcome::Decisive { winner } => format!("{} {}", self.color(winner), self.wins),
+                Outcome::Draw => self.draw.to_owned(),
+            },
+        })
+    }
+
+    /// A clock warning in words, like "white, low time".
+    pub fn spoken_low_time(&self, color: Color) -> String {
+        format!("{}, {}", self.color(color), self.low_time)
+    }
+}
+
+/// How urgent an announcement is. More important ones interrupt others.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Urgency {
+    Message,
+    Important,
+}
+
+/// Speaks announcements with speech-dispatcher.
+#[derive(Clone)]
+pub struct Speech {
+    vocabulary: Vocabulary,
+    clock_warnings: bool,
+    muted: bool,
+}
+
+impl fmt::Debug for Speech {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Speech")
+            .field("language", &self.vocabulary.language)
+            .field("clock_warnings", &self.clock_warnings)
+            .field("muted", &self.muted)
+            .finish()
+    }
+}
+
+impl Default for Speech {
+    fn default() -> Speech {
+        Speech::new(Vocabulary::default())
+    }
+}
+
+impl Speech {
+    /// Announce moves and game events. Clock warnings are off.
+    pub fn new(vocabulary: Vocabulary) -> Speech {
+        Speech {
+            vocabulary,
+            clock_warnings: false,
+            muted: false,
+        }
+    }
+
+    pub fn vocabulary(&self) -> &Vocabulary {
+        &self.vocabulary
+    }
+
+    pub fn set_vocabulary(&mut self, vocabulary: Vocabulary) {
+        self.vocabulary = vocabulary;
+    }
+
+    /// Say when the time of a side runs low.
+    pub fn clock_warnings(&self) -> bool {
+        self.clock_warnings
+    }
+
+    pub fn set_clock_warnings(&mut self, clock_warnings: bool) {
+        self.clock_warnings = clock_warnings;
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Speak a text in the background, unless muted.
+    pub fn say(&self, text: &str, urgency: Urgency) -> io::Result<()> {
+        if self.muted {
+            return Ok(());
+        }
+
+        let mut child = Command::new("spd-say")
+            .arg("--language").arg(self.vocabulary.language)
+            .arg("--priority").arg(match urgency {
+                Urgency::Message => "message",
+                Urgency::Important => "important",
+            })
+            .arg("--")
+            .arg(text)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        // reap the process when it is done
+        thread::spawn(move || child.wait());
+        Ok(())
+    }
+
+    pub(crate) fn announce_move(&self, m: &Move, check: bool, checkmate: bool) -> io::Result<()> {
+        self.say(&self.vocabulary.spoken_move(m, check, checkmate), Urgency::Message)
+    }
+
+    pub(crate) fn announce_end(&self, game_state: GameState) -> io::Result<()> {
+        match self.vocabulary.spoken_end(game_state) {
+            Some(text) => self.say(&text, Urgency::Important),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn announce_low_time(&self, color: Color) -> io::Result<()> {
+        if self.clock_warnings {
+            self.say(&self.vocabulary.spoken_low_time(color), Urgency::Important)
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,526 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Persistence of games in progress, e.g. correspondence games, and of
+//! player ratings.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+
+use shakmaty::{EnPassantMode, Outcome, Position};
+
+use game::Game;
+use openings::Opening;
+use pgn::PgnError;
+use rating::{rate_game, Rating, RatingChange};
+use trainer::Repertoire;
+use zobrist::zobrist_hash;
+
+/// Error when reading or writing the game database.
+#[derive(Debug)]
+pub enum StorageError {
+    /// The database could not be opened, read or written.
+    Db(sled::Error),
+    /// A stored game is not valid JSON or misses fields.
+    Corrupted(String),
+    /// The moves of a stored game could not be read.
+    Pgn(PgnError),
+    /// A file like the recovery file could not be read or written.
+    Io(io::Error),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StorageError::Db(ref err) => write!(f, "game database error: {}", err),
+            StorageError::Corrupted(ref id) => write!(f, "stored game {} is corrupted", id),
+            StorageError::Pgn(ref err) => write!(f, "invalid stored game: {}", err),
+            StorageError::Io(ref err) => write!(f, "could not access game file: {}", err),
+        }
+    }
+}
+
+impl Error for StorageError {}
+
+impl From<sled::Error> for StorageError {
+    fn from(err: sled::Error) -> StorageError {
+        StorageError::Db(err)
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(err: io::Error) -> StorageError {
+        StorageError::Io(err)
+    }
+}
+
+impl From<PgnError> for StorageError {
+    fn from(err: PgnError) -> StorageError {
+        StorageError::Pgn(err)
+    }
+}
+
+/// A game with everything needed to resume it.
+#[derive(Debug, Clone)]
+pub struct StoredGame {
+    /// Key of the game, e.g. the game id used on chain.
+    pub id: String,
+    /// Moves with comments, annotations and variations.
+    pub game: Game,
+    /// Remaining time of white and black, if played with a clock.
+    pub clock: Option<(Duration, Duration)>,
+    /// Transaction ids of the moves recorded on chain, by ply.
+    pub transactions: Vec<(u32, String)>,
+    /// Milliseconds since the Unix epoch of the last `Storage::save()`.
+    pub updated: u64,
+}
+
+impl StoredGame {
+    pub fn new(id: &str, game: Game) -> StoredGame {
+        StoredGame {
+            id: id.to_owned(),
+            game,
+            clock: None,
+            transactions: Vec::new(),
+            updated: 0,
+        }
+    }
+
+    /// The game is still in progress, as far as the `Result` header
+    /// tells.
+    pub fn is_resumable(&self) -> bool {
+        self.game.header("Result").map_or(true, |result| result == "*")
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "pgn": self.game.to_pgn(),
+            "clock": self.clock.map(|(white, black)| json!({
+                "white": white.as_millis() as u64,
+                "black": black.as_millis() as u64,
+            })),
+            "transactions": self.transactions.iter().map(|&(ply, ref tx)| json!({
+                "ply": ply,
+                "tx": tx,
+            })).collect::<Vec<_>>(),
+            "updated": self.updated,
+        })
+    }
+
+    pub(crate) fn from_json(id: &str, value: &Value) -> Result<StoredGame, StorageError> {
+        let corrupted = || StorageError::Corrupted(id.to_owned());
+
+        let clock = match value["clock"] {
+            Value::Null => None,
+            ref clock => Some((
+                Duration::from_millis(clock["white"].as_u64().ok_or_else(corrupted)?),
+                Duration::from_millis(clock["black"].as_u64().ok_or_else(corrupted)?),
+            )),
+        };
+
+        let transactions = value["transactions"].as_array().ok_or_else(corrupted)?.iter().map(|t| {
+            Some((t["ply"].as_u64()? as u32, t["tx"].as_str()?.to_owned()))
+        }).collect::<Option<Vec<_>>>().ok_or_else(corrupted)?;
+
+        Ok(StoredGame {
+            id: id.to_owned(),
+            game: Game::from_pgn(value["pgn"].as_str().ok_or_else(corrupted)?)?,
+            clock,
+            transactions,
+            updated: value["updated"].as_u64().unwrap_or(0),
+        })
+    }
+}
+
+/// Criteria for listing stored games, like in the `GameBrowser`. Empty texts and `None`
+/// match all games.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GameFilter {
+    /// Part of the name of white or black, ignoring case.
+    pub player: String,
+    /// Start of the `Date` header, like `2023` or `2023.05`.
+    pub date: String,
+    /// Part of the opening name, or the start of its ECO code, ignoring
+    /// case.
+    pub opening: String,
+    /// The `Result` header, like `1-0`, or `*` for unfinished games.
+    pub result: Option<String>,
+    /// The `Variant` header, `Standard` for games without one.
+    pub variant: Option<String>,
+    /// Whether any move of the game was recorded on chain.
+    pub on_chain: Option<bool>,
+}
+
+impl GameFilter {
+    /// The filter accepts the game, named `opening`, e.g. by
+    /// `Openings::classify()`.
+    pub fn matches(&self, game: &StoredGame, opening: Option<&Opening>) -> bool {
+        let contains = |text: &str, part: &str| text.to_lowercase().contains(&part.to_lowercase());
+
+        if !self.player.is_empty() {
+            let player = self.player.trim();
+            if !["White", "Black"].iter().any(|name| game.game.header(name).map_or(false, |name| contains(name, player))) {
+                return false;
+            }
+        }
+
+        if !self.date.is_empty() {
+            let date = game.game.header("Date").map(normalize_date).unwrap_or_default();
+            if !date.starts_with(&normalize_date(self.date.trim())) {
+                return false;
+            }
+        }
+
+        if !self.opening.is_empty() {
+            let part = self.opening.trim();
+            let named = opening.map_or(false, |opening| {
+                contains(opening.name(), part) || opening.eco().to_lowercase().starts_with(&part.to_lowercase())
+            });
+            if !named {
+                return false;
+            }
+        }
+
+        if let Some(ref result) = self.result {
+            if game.game.header("Result").unwrap_or("*") != result {
+                return false;
+            }
+        }
+
+        if let Some(ref variant) = self.variant {
+            if variant_name(&game.game) != variant {
+                return false;
+            }
+        }
+
+        if let Some(on_chain) = self.on_chain {
+            if game.transactions.is_empty() == on_chain {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Dates like `2023-05-01` or `2023/05/01` as in PGN, `2023.05.01`.
+fn normalize_date(date: &str) -> String {
+    date.replace(|c| c == '-' || c == '/', ".")
+}
+
+/// The `Variant` header, `Standard` for games without one.
+pub(crate) fn variant_name(game: &Game) -> &str {
+    game.header("Variant").unwrap_or("Standard")
+}
+
+/// Overview of a stored game, for listing games without reading all moves.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct GameSummary {
+    pub id: String,
+    pub white: Option<String>,
+    pub black: Option<String>,
+    /// Number of plies of the main line.
+    pub ply: usize,
+    pub updated: u64,
+}
+
+/// A stored game reaching a searched position.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PositionMatch {
+    pub id: String,
+    /// Ply of the main line at which the position occurred.
+    pub ply: usize,
+    /// The move played from the position in Standard Algebraic Notation,
+    /// or `None` if the game ended there.
+    pub next: Option<String>,
+}
+
+/// The positions of the main line of a game by Zobrist hash, with the
+/// plies and the moves that continued.
+fn positions(game: &Game) -> BTreeMap<u64, Vec<(usize, Option<String>)>> {
+    let mut positions: BTreeMap<u64, Vec<_>> = BTreeMap::new();
+    let mut pos = game.initial_position().clone();
+
+    for (ply, m) in game.moves().into_iter().enumerate() {
+        let hash = zobrist_hash(&pos.clone().into_setup(EnPassantMode::Legal));
+        positions.entry(hash).or_default().push((ply, Some(m.san().to_owned())));
+        pos.play_unchecked(m.mv());
+    }
+
+    let hash = zobrist_hash(&pos.into_setup(EnPassantMode::Legal));
+    positions.entry(hash).or_default().push((game.len(), None));
+    positions
+}
+
+/// Key of the index entry of a game, starting with the hash so that all
+/// games reaching a position can be found with a prefix scan.
+fn position_key(hash: u64, id: &str) -> Vec<u8> {
+    let mut key = hash.to_be_bytes().to_vec();
+    key.extend_from_slice(id.as_bytes());
+    key
+}
+
+/// Key of a rating history entry, ordered by player and time.
+fn history_key(player: &str, timestamp: u64, game_id: &str) -> Vec<u8> {
+    let mut key = player.as_bytes().to_vec();
+    key.push(0);
+    key.extend_from_slice(&timestamp.to_be_bytes());
+    key.extend_from_slice(game_id.as_bytes());
+    key
+}
+
+/// A database of games, stored with sled.
+pub struct Storage {
+    db: sled::Db,
+    /// Index of the positions of the games.
+    positions: sled::Tree,
+    /// Current rating of each player.
+    ratings: sled::Tree,
+    rating_history: sled::Tree,
+    /// Opening repertoires by name.
+    repertoires: sled::Tree,
+}
+
+impl fmt::Debug for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Storage")
+            .field("len", &self.db.len())
+            .finish()
+    }
+}
+
+impl Storage {
+    /// Open or create the database in a directory.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Storage, StorageError> {
+        let db = sled::open(path)?;
+        Ok(Storage {
+            positions: db.open_tree("positions")?,
+            ratings: db.open_tree("ratings")?,
+            rating_history: db.open_tree("rating_history")?,
+            repertoires: db.open_tree("repertoires")?,
+            db,
+        })
+    }
+
+    /// Insert or replace a game, and update its timestamp. The game is on
+    /// disk when this returns.
+    pub fn save(&self, game: &mut StoredGame) -> Result<(), StorageError> {
+        game.updated = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        if let Ok(Some(old)) = self.load(&game.id) {
+            self.unindex(&old)?;
+        }
+        self.db.insert(game.id.as_bytes(), game.to_json().to_string().into_bytes())?;
+        self.index(game)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn index(&self, stored: &StoredGame) -> Result<(), StorageError> {
+        for (hash, hits) in positions(&stored.game) {
+            let value = Value::Array(hits.into_iter().map(|(ply, next)| json!({
+                "ply": ply,
+                "next": next,
+            })).collect());
+            self.positions.insert(position_key(hash, &stored.id), value.to_string().into_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn unindex(&self, stored: &StoredGame) -> Result<(), StorageError> {
+        for hash in positions(&stored.game).keys() {
+            self.positions.remove(position_key(*hash, &stored.id))?;
+        }
+        Ok(())
+    }
+
+    /// Rebuild the position index from all stored games, e.g. for a
+    /// database written before games were indexed.
+    pub fn reindex(&self) -> Result<(), StorageError> {
+        self.positions.clear()?;
+        for stored in self.games()? {
+            self.index(&stored)?;
+        }
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// The stored games reaching a position with the given Zobrist hash,
+    /// with the moves that continued from it.
+    pub fn find_position(&self, hash: u64) -> Result<Vec<PositionMatch>, StorageError> {
+        let mut matches = Vec::new();
+
+        for entry in self.positions.scan_prefix(hash.to_be_bytes()) {
+            let (key, bytes) = entry?;
+            let id = String::from_utf8_lossy(&key[8..]).into_owned();
+            let value: Value = serde_json::from_slice(&bytes).map_err(|_| StorageError::Corrupted(id.clone()))?;
+
+            for hit in value.as_array().ok_or_else(|| StorageError::Corrupted(id.clone()))? {
+                matches.push(PositionMatch {
+                    id: id.clone(),
+                    ply: hit["ply"].as_u64().ok_or_else(|| StorageError::Corrupted(id.clone()))? as usize,
+                    next: hit["next"].as_str().map(str::to_owned),
+                });
+            }
+        }
+
+        Ok(matches)
+    }
+
+    /// The stored games reaching the position.
+    pub fn search<P: Position + Clone>(&self, pos: &P) -> Result<Vec<PositionMatch>, StorageError> {
+        self.find_position(zobrist_hash(&pos.clone().into_setup(EnPassantMode::Legal)))
+    }
+
+    pub fn load(&self, id: &str) -> Result<Option<StoredGame>, StorageError> {
+        match self.db.get(id.as_bytes())? {
+            Some(bytes) => {
+                let value: Value = serde_json::from_slice(&bytes).map_err(|_| StorageError::Corrupted(id.to_owned()))?;
+                StoredGame::from_json(id, &value).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Remove a game, e.g. after it ended. Returns whether it existed.
+    pub fn remove(&self, id: &str) -> Result<bool, StorageError> {
+        if let Ok(Some(old)) = self.load(id) {
+            self.unindex(&old)?;
+        }
+        let existed = self.db.remove(id.as_bytes())?.is_some();
+        self.db.flush()?;
+        Ok(existed)
+    }
+
+    /// All stored games, most recently updated first.
+    pub fn games(&self) -> Result<Vec<StoredGame>, StorageError> {
+        let mut games = Vec::new();
+
+        for entry in self.db.iter() {
+            let (key, bytes) = entry?;
+            let id = String::from_utf8_lossy(&key).into_owned();
+            let value: Value = serde_json::from_slice(&bytes).map_err(|_| StorageError::Corrupted(id.clone()))?;
+            games.push(StoredGame::from_json(&id, &value)?);
+        }
+
+        games.sort_by(|a, b| b.updated.cmp(&a.updated));
+        Ok(games)
+    }
+
+    /// The games still in progress, to offer resuming them on startup.
+    pub fn resumable(&self) -> Result<Vec<GameSummary>, StorageError> {
+        Ok(self.games()?.into_iter().filter(StoredGame::is_resumable).map(|stored| GameSummary {
+            white: stored.game.header("White").map(str::to_owned),
+            black: stored.game.header("Black").map(str::to_owned),
+            ply: stored.game.len(),
+            updated: stored.updated,
+            id: stored.id,
+        }).collect())
+    }
+
+    /// The rating of a player, or the default rating of new players.
+    pub fn rating(&self, player: &str) -> Result<Rating, StorageError> {
+        match self.ratings.get(player.as_bytes())? {
+            Some(bytes) => serde_json::from_slice(&bytes).ok()
+                .and_then(|value: Value| Rating::from_json(&value))
+                .ok_or_else(|| StorageError::Corrupted(player.to_owned())),
+            None => Ok(Rating::default()),
+        }
+    }
+
+    /// Update the ratings of both players after a game and add the changes
+    /// to their histories. Returns the changes of white and black.
+    pub fn record_result(&self, game_id: &str, white: &str, black: &str, outcome: Outcome) -> Result<(RatingChange, RatingChange), StorageError> {
+        let before = (self.rating(white)?, self.rating(black)?);
+        let after = rate_game(&before.0, &before.1, outcome);
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+
+        let changes = (
+            RatingChange { game_id: game_id.to_owned(), opponent: black.to_owned(), before: before.0, after: after.0, timestamp },
+            RatingChange { game_id: game_id.to_owned(), opponent: white.to_owned(), before: before.1, after: after.1, timestamp },
+        );
+
+        for &(player, ref change) in &[(white, &changes.0), (black, &changes.1)] {
+            self.ratings.insert(player.as_bytes(), change.after.to_json().to_string().into_bytes())?;
+            self.rating_history.insert(history_key(player, timestamp, game_id), change.to_json().to_string().into_bytes())?;
+        }
+        self.db.flush()?;
+
+        Ok(changes)
+    }
+
+    /// The rating changes of a player, oldest first.
+    pub fn rating_history(&self, player: &str) -> Result<Vec<RatingChange>, StorageError> {
+        let mut prefix = player.as_bytes().to_vec();
+        prefix.push(0);
+
+        let mut history = Vec::new();
+        for entry in self.rating_history.scan_prefix(prefix) {
+            let (_, bytes) = entry?;
+            let change = serde_json::from_slice(&bytes).ok()
+                .and_then(|value: Value| RatingChange::from_json(&value))
+                .ok_or_else(|| StorageError::Corrupted(player.to_owned()))?;
+            history.push(change);
+        }
+        Ok(history)
+    }
+
+    /// Insert or replace a repertoire with its review schedule.
+    pub fn save_repertoire(&self, repertoire: &Repertoire) -> Result<(), StorageError> {
+        self.repertoires.insert(repertoire.name().as_bytes(), repertoire.to_json().to_string().into_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub fn load_repertoire(&self, name: &str) -> Result<Option<Repertoire>, StorageError> {
+        match self.repertoires.get(name.as_bytes())? {
+            Some(bytes) => serde_json::from_slice(&bytes).ok()
+                .and_then(|value: Value| Repertoire::from_json(name, &value))
+                .map(Some)
+                .ok_or_else(|| StorageError::Corrupted(name.to_owned())),
+            None => Ok(None),
+        }
+    }
+
+    /// All stored repertoires, by name.
+    pub fn repertoires(&self) -> Result<Vec<Repertoire>, StorageError> {
+        let mut repertoires = Vec::new();
+        for entry in self.repertoires.iter() {
+            let (key, _) = entry?;
+            let name = String::from_utf8_lossy(&key).into_owned();
+            repertoires.extend(self.load_repertoire(&name)?);
+        }
+        Ok(repertoires)
+    }
+
+    /// The names of the repertoires with lines due for review, and the
+    /// number of those lines, to flag them on startup.
+    pub fn due_repertoires(&self) -> Result<Vec<(String, usize)>, StorageError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        Ok(self.repertoires()?.into_iter().filter_map(|repertoire| {
+            match repertoire.due_lines(now).len() {
+                0 => None,
+                due => Some((repertoire.name().to_owned(), due)),
+            }
+        }).collect())
+    }
+}
@@ -0,0 +1,180 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use shakmaty::{Chess, Move, Position};
+use shakmaty_syzygy::{Dtz, SyzygyError, Wdl};
+
+use shapes::{DrawBrush, DrawShape};
+
+/// Error when probing the tablebase.
+#[derive(Debug)]
+pub enum TablebaseError {
+    /// The position has more pieces than the tables that were added.
+    TooManyPieces,
+    /// The table for the material configuration is missing or corrupted.
+    Syzygy(SyzygyError),
+}
+
+impl fmt::Display for TablebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TablebaseError::TooManyPieces => f.write_str("too many pieces for the tablebase"),
+            TablebaseError::Syzygy(ref err) => write!(f, "tablebase probe failed: {}", err),
+        }
+    }
+}
+
+impl Error for TablebaseError {}
+
+impl From<SyzygyError> for TablebaseError {
+    fn from(err: SyzygyError) -> TablebaseError {
+        TablebaseError::Syzygy(err)
+    }
+}
+
+/// Result of probing a position, from the point of view of the side to
+/// move.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Probe {
+    /// Win, draw or loss, considering the 50-move rule.
+    pub wdl: Wdl,
+    /// Plies to the next capture or pawn move with optimal play.
+    pub dtz: Dtz,
+}
+
+/// A legal move and the result after it, from the point of view of the
+/// side playing it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MoveProbe {
+    pub m: Move,
+    pub probe: Probe,
+}
+
+/// Local Syzygy tables for endgames with up to 7 pieces.
+pub struct Tablebase {
+    tables: shakmaty_syzygy::Tablebase<Chess>,
+}
+
+impl fmt::Debug for Tablebase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tablebase")
+            .field("max_pieces", &self.max_pieces())
+            .finish()
+    }
+}
+
+impl Default for Tablebase {
+    fn default() -> Tablebase {
+        Tablebase::new()
+    }
+}
+
+impl Tablebase {
+    /// A tablebase without tables. Add some with `add_directory()`.
+    pub fn new() -> Tablebase {
+        Tablebase {
+            tables: shakmaty_syzygy::Tablebase::new(),
+        }
+    }
+
+    /// Add the `.rtbw` and `.rtbz` files of a directory, returning how
+    /// many were found.
+    pub fn add_directory<P: AsRef<Path>>(&mut self, path: P) -> io::Result<usize> {
+        self.tables.add_directory(path)
+    }
+
+    /// Most pieces of any table that was added, 0 if there are none.
+    pub fn max_pieces(&self) -> usize {
+        self.tables.max_pieces()
+    }
+
+    /// Whether the position has few enough pieces to be probed.
+    pub fn covers(&self, pos: &Chess) -> bool {
+        let pieces = pos.board().occupied().count();
+        pieces <= self.max_pieces() && pos.castles().is_empty()
+    }
+
+    pub fn probe(&self, pos: &Chess) -> Result<Probe, TablebaseError> {
+        if !self.covers(pos) {
+            return Err(TablebaseError::TooManyPieces);
+        }
+
+        Ok(Probe {
+            wdl: self.tables.probe_wdl(pos)?,
+            dtz: self.tables.probe_dtz(pos)?,
+        })
+    }
+
+    /// Probe the positions after all legal moves, best moves first: wins
+    /// with the fastest zeroing move, then draws, then losses that take
+    /// the longest.
+    pub fn probe_moves(&self, pos: &Chess) -> Result<Vec<MoveProbe>, TablebaseError> {
+        let mut probes = Vec::new();
+
+        for m in pos.legal_moves() {
+            let mut after = pos.clone();
+            after.play_unchecked(&m);
+
+            let probe = self.probe(&after)?;
+            probes.push(MoveProbe {
+                m,
+                probe: Probe {
+                    wdl: -probe.wdl,
+                    dtz: -probe.dtz,
+                },
+            });
+        }
+
+        probes.sort_by_key(|p| {
+            let dtz = p.probe.dtz.0.abs();
+            let rank = wdl_rank(p.probe.wdl);
+            (-rank, if rank > 0 { dtz } else { -dtz })
+        });
+        Ok(probes)
+    }
+}
+
+fn wdl_rank(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Loss => -2,
+        Wdl::BlessedLoss => -1,
+        Wdl::Draw => 0,
+        Wdl::CursedWin => 1,
+        Wdl::Win => 2,
+    }
+}
+
+/// Arrows for the moves that keep the best result. Winning moves are
+/// green, drawing moves (including cursed wins and blessed losses) are
+/// yellow. Losing moves are not marked.
+pub(crate) fn tablebase_shapes(probes: &[MoveProbe]) -> Vec<DrawShape> {
+    let best = match probes.iter().map(|p| wdl_rank(p.probe.wdl)).max() {
+        Some(best) if best > -2 => best,
+        _ => return Vec::new(),
+    };
+
+    let brush = if best == 2 { DrawBrush::Green } else { DrawBrush::Yellow };
+
+    probes.iter().filter(|p| wdl_rank(p.probe.wdl) == best).map(|p| {
+        DrawShape::new(p.m.from().unwrap_or_else(|| p.m.to()), p.m.to(), brush)
+    }).collect()
+}
@@ -0,0 +1,258 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Board colors.
+//!
+//! A theme can be loaded from a TOML file with colors in `#rrggbb` or
+//! `#rrggbbaa` notation. Missing colors are taken from a built-in preset,
+//! `blue` unless another one is named:
+//!
+//! ```toml
+//! preset = "brown"
+//! light = "#f0d9b5"
+//! last_move = "#9bc70069"
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use cairo::Context;
+use toml::Value;
+
+/// A color with alpha channel, each component from `0.0` to `1.0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgba {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+    pub alpha: f64,
+}
+
+impl Rgba {
+    pub fn new(red: f64, green: f64, blue: f64, alpha: f64) -> Rgba {
+        Rgba { red, green, blue, alpha }
+    }
+
+    /// An opaque color.
+    pub fn rgb(red: f64, green: f64, blue: f64) -> Rgba {
+        Rgba::new(red, green, blue, 1.0)
+    }
+
+    /// Parse `#rrggbb` or `#rrggbbaa`.
+    pub fn from_hex(hex: &str) -> Option<Rgba> {
+        let hex = hex.trim_start_matches('#');
+        if hex.len() != 6 && hex.len() != 8 {
+            return None;
+        }
+
+        let component = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                .map(|c| f64::from(c) / 255.0)
+        };
+
+        Some(Rgba {
+            red: component(0)?,
+            green: component(2)?,
+            blue: component(4)?,
+            alpha: if hex.len() == 8 { component(6)? } else { 1.0 },
+        })
+    }
+
+    /// Format as `#rrggbbaa`.
+    pub fn to_hex(&self) -> String {
+        let byte = |c: f64| (c.max(0.0).min(1.0) * 255.0).round() as u8;
+        format!("#{:02x}{:02x}{:02x}{:02x}", byte(self.red), byte(self.green), byte(self.blue), byte(self.alpha))
+    }
+
+    /// The same color with another alpha.
+    pub fn with_alpha(self, alpha: f64) -> Rgba {
+        Rgba { alpha, ..self }
+    }
+
+    pub(crate) fn set_source(&self, cr: &Context) {
+        cr.set_source_rgba(self.red, self.green, self.blue, self.alpha);
+    }
+}
+
+/// Error when loading a theme.
+#[derive(Debug)]
+pub enum ThemeError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file is not valid TOML.
+    Toml(toml::de::Error),
+    /// A color is not a string in `#rrggbb` or `#rrggbbaa` notation.
+    InvalidColor(String),
+    /// There is no preset with the name.
+    UnknownPreset(String),
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ThemeError::Io(ref err) => write!(f, "could not read theme: {}", err),
+            ThemeError::Toml(ref err) => write!(f, "invalid theme: {}", err),
+            ThemeError::InvalidColor(ref key) => write!(f, "invalid color for {}", key),
+            ThemeError::UnknownPreset(ref name) => write!(f, "unknown theme preset: {}", name),
+        }
+    }
+}
+
+impl Error for ThemeError {}
+
+/// Colors of the board and its highlights.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// Light squares.
+    pub light: Rgba,
+    /// Dark squares.
+    pub dark: Rgba,
+    /// Frame around the board, and the background of pockets.
+    pub border: Rgba,
+    /// File and rank labels.
+    pub coordinates: Rgba,
+    /// Translucent fill of the origin and destination of the last move.
+    pub last_move: Rgba,
+    /// Fill of the origin and destination of a premove.
+    pub premove: Rgba,
+    /// Center of the radial gradient under a king in check.
+    pub check: Rgba,
+    /// Fill of the selected square.
+    pub selected: Rgba,
+    /// Dots and rings on legal destinations.
+    pub move_hint: Rgba,
+    /// Fill of squares highlighted as threatened.
+    pub threat: Rgba,
+    /// Fill of squares highlighted as a hint.
+    pub hint: Rgba,
+}
+
+impl Theme {
+    /// Names of the built-in presets.
+    pub const PRESETS: [&'static str; 4] = ["blue", "brown", "green", "marble"];
+
+    pub fn blue() -> Theme {
+        Theme {
+            light: Rgba::rgb(0.87, 0.89, 0.90),
+            dark: Rgba::rgb(0.55, 0.64, 0.68),
+            border: Rgba::rgb(0.2, 0.2, 0.5),
+            coordinates: Rgba::rgb(0.8, 0.8, 0.8),
+            last_move: Rgba::new(0.61, 0.78, 0.0, 0.41),
+            premove: Rgba::new(0.08, 0.12, 0.33, 0.5),
+            check: Rgba::rgb(1.0, 0.0, 0.0),
+            selected: Rgba::new(0.08, 0.47, 0.11, 0.5),
+            move_hint: Rgba::new(0.08, 0.47, 0.11, 0.5),
+            threat: Rgba::new(0.85, 0.12, 0.10, 0.35),
+            hint: Rgba::new(0.20, 0.45, 0.90, 0.4),
+        }
+    }
+
+    pub fn brown() -> Theme {
+        Theme {
+            light: Rgba::rgb(0.94, 0.85, 0.71),
+            dark: Rgba::rgb(0.71, 0.53, 0.39),
+            border: Rgba::rgb(0.42, 0.29, 0.17),
+            coordinates: Rgba::rgb(0.94, 0.85, 0.71),
+            ..Theme::blue()
+        }
+    }
+
+    pub fn green() -> Theme {
+        Theme {
+            light: Rgba::rgb(1.0, 1.0, 0.87),
+            dark: Rgba::rgb(0.53, 0.65, 0.40),
+            border: Rgba::rgb(0.29, 0.40, 0.19),
+            coordinates: Rgba::rgb(1.0, 1.0, 0.87),
+            last_move: Rgba::new(0.0, 0.59, 0.78, 0.41),
+            ..Theme::blue()
+        }
+    }
+
+    pub fn marble() -> Theme {
+        Theme {
+            light: Rgba::rgb(0.86, 0.85, 0.80),
+            dark: Rgba::rgb(0.58, 0.56, 0.52),
+            border: Rgba::rgb(0.30, 0.29, 0.27),
+            coordinates: Rgba::rgb(0.86, 0.85, 0.80),
+            ..Theme::blue()
+        }
+    }
+
+    /// Get a built-in preset by name, see `Theme::PRESETS`.
+    pub fn preset(name: &str) -> Option<Theme> {
+        match name {
+            "blue" => Some(Theme::blue()),
+            "brown" => Some(Theme::brown()),
+            "green" => Some(Theme::green()),
+            "marble" => Some(Theme::marble()),
+            _ => None,
+        }
+    }
+
+    /// Parse a theme from TOML.
+    pub fn from_toml(text: &str) -> Result<Theme, ThemeError> {
+        let value = text.parse::<Value>().map_err(ThemeError::Toml)?;
+
+        let mut theme = match value.get("preset") {
+            Some(&Value::String(ref name)) => Theme::preset(name).ok_or_else(|| ThemeError::UnknownPreset(name.clone()))?,
+            Some(_) => return Err(ThemeError::UnknownPreset(value["preset"].to_string())),
+            None => Theme::blue(),
+        };
+
+        {
+            let colors: [(&str, &mut Rgba); 11] = [
+                ("light", &mut theme.light),
+                ("dark", &mut theme.dark),
+                ("border", &mut theme.border),
+                ("coordinates", &mut theme.coordinates),
+                ("last_move", &mut theme.last_move),
+                ("premove", &mut theme.premove),
+                ("check", &mut theme.check),
+                ("selected", &mut theme.selected),
+                ("move_hint", &mut theme.move_hint),
+                ("threat", &mut theme.threat),
+                ("hint", &mut theme.hint),
+            ];
+
+            for (key, color) in colors {
+                if let Some(v) = value.get(key) {
+                    *color = v.as_str()
+                        .and_then(Rgba::from_hex)
+                        .ok_or_else(|| ThemeError::InvalidColor(key.to_owned()))?;
+                }
+            }
+        }
+
+        Ok(theme)
+    }
+
+    /// Load a theme from a TOML file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Theme, ThemeError> {
+        let text = fs::read_to_string(path).map_err(ThemeError::Io)?;
+        Theme::from_toml(&text)
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::blue()
+    }
+}
@@ -0,0 +1,78 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Where the widgets take the current time from.
+//!
+//! Animations, drags, touches and the chess clock ask a `TimeSource`
+//! instead of the system, so that tests can step time by hand with a
+//! `ManualTime`.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A monotonic clock.
+pub trait TimeSource: fmt::Debug + Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// The time of the system, from `Instant::now()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealTime;
+
+impl TimeSource for RealTime {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Time that only passes when told to. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct ManualTime {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl Default for ManualTime {
+    fn default() -> ManualTime {
+        ManualTime::new()
+    }
+}
+
+impl ManualTime {
+    /// Start at the current time of the system.
+    pub fn new() -> ManualTime {
+        ManualTime {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Let `duration` pass.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().expect("manual time") += duration;
+    }
+}
+
+impl TimeSource for ManualTime {
+    fn now(&self) -> Instant {
+        *self.now.lock().expect("manual time")
+    }
+}
+
+/// The default time source of the widgets.
+pub(crate) fn real_time() -> Arc<dyn TimeSource> {
+    Arc::new(RealTime)
+}
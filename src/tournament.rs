@@ -0,0 +1,398 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Swiss and round-robin tournaments.
+//!
+//! The organizer announces each round with `Tournament::announce()`. The
+//! players of a pairing connect with `Peer::connect()` using its game id,
+//! and report the result with `NetMessage::GameOver` when the game ended,
+//! which the organizer passes to `Tournament::receive()`.
+
+use std::cmp::Ordering;
+
+use serde_json::Value;
+
+use shakmaty::{Color, Outcome};
+
+use boardstate::BoardState;
+#[cfg(feature = "blockchain")]
+use net::NetMessage;
+
+/// How the players are paired.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TournamentFormat {
+    /// Players with equal scores meet, for a fixed number of rounds.
+    Swiss { rounds: u32 },
+    /// Everyone plays everyone once.
+    RoundRobin,
+}
+
+/// A game of a round, or a bye if there is no black player.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pairing {
+    pub game_id: String,
+    pub white: String,
+    pub black: Option<String>,
+}
+
+impl Pairing {
+    pub fn is_bye(&self) -> bool {
+        self.black.is_none()
+    }
+
+    /// The color of a player in this pairing.
+    pub fn color_of(&self, player: &str) -> Option<Color> {
+        if self.white == player {
+            Some(Color::White)
+        } else if self.black.as_ref().map_or(false, |black| black == player) {
+            Some(Color::Black)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "game": self.game_id,
+            "white": self.white,
+            "black": self.black,
+        })
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Option<Pairing> {
+        Some(Pairing {
+            game_id: value["game"].as_str()?.to_owned(),
+            white: value["white"].as_str()?.to_owned(),
+            black: value["black"].as_str().map(str::to_owned),
+        })
+    }
+}
+
+/// The place of a player in the standings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standing {
+    pub player: String,
+    /// One point for a win or a bye, half a point for a draw.
+    pub points: f64,
+    /// Sum of the points of the opponents.
+    pub buchholz: f64,
+    /// Sum of the points of the opponents beaten, and half of those of the
+    /// opponents drawn.
+    pub sonneborn_berger: f64,
+}
+
+#[derive(Debug, Clone)]
+struct Board {
+    pairing: Pairing,
+    outcome: Option<Outcome>,
+}
+
+/// A tournament in progress.
+#[derive(Debug, Clone)]
+pub struct Tournament {
+    id: String,
+    format: TournamentFormat,
+    players: Vec<String>,
+    rounds: Vec<Vec<Board>>,
+}
+
+impl Tournament {
+    /// A tournament between players, in the order of their seeds.
+    pub fn new(id: &str, format: TournamentFormat, players: Vec<String>) -> Tournament {
+        Tournament {
+            id: id.to_owned(),
+            format,
+            players,
+            rounds: Vec::new(),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn format(&self) -> TournamentFormat {
+        self.format
+    }
+
+    pub fn players(&self) -> &[String] {
+        &self.players
+    }
+
+    /// Number of rounds of the whole tournament.
+    pub fn total_rounds(&self) -> u32 {
+        match self.format {
+            TournamentFormat::Swiss { rounds } => rounds,
+            TournamentFormat::RoundRobin => {
+                let n = self.players.len() as u32;
+                if n < 2 { 0 } else { n + n % 2 - 1 }
+            },
+        }
+    }
+
+    /// Number of rounds paired so far.
+    pub fn round(&self) -> u32 {
+        self.rounds.len() as u32
+    }
+
+    /// The pairings of a round, starting at 1.
+    pub fn pairings(&self, round: u32) -> Vec<Pairing> {
+        round.checked_sub(1)
+            .and_then(|index| self.rounds.get(index as usize))
+            .map_or(Vec::new(), |boards| boards.iter().map(|b| b.pairing.clone()).collect())
+    }
+
+    /// All games of the current round have a result.
+    pub fn is_round_complete(&self) -> bool {
+        self.rounds.last().map_or(true, |boards| {
+            boards.iter().all(|b| b.pairing.is_bye() || b.outcome.is_some())
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.round() >= self.total_rounds() && self.is_round_complete()
+    }
+
+    /// Pair the next round, once the current one is complete.
+    pub fn pair_next_round(&mut self) -> Option<Vec<Pairing>> {
+        if !self.is_round_complete() || self.round() >= self.total_rounds() {
+            return None;
+        }
+
+        let pairs = match self.format {
+            TournamentFormat::Swiss { .. } => self.swiss_pairs(),
+            TournamentFormat::RoundRobin => self.round_robin_pairs(self.round()),
+        };
+
+        let round = self.round() + 1;
+        let boards: Vec<Board> = pairs.into_iter().enumerate().map(|(index, (white, black))| Board {
+            pairing: Pairing {
+                game_id: format!("{}-r{}-b{}", self.id, round, index + 1),
+                white: self.players[white].clone(),
+                black: black.map(|black| self.players[black].clone()),
+            },
+            outcome: None,
+        }).collect();
+
+        let pairings = boards.iter().map(|b| b.pairing.clone()).collect();
+        self.rounds.push(boards);
+        Some(pairings)
+    }
+
+    /// The message announcing the current round.
+    #[cfg(feature = "blockchain")]
+    pub fn announce(&self) -> Option<NetMessage> {
+        if self.rounds.is_empty() {
+            return None;
+        }
+        Some(NetMessage::Pairings {
+            tournament: self.id.clone(),
+            round: self.round(),
+            pairings: self.pairings(self.round()),
+        })
+    }
+
+    /// Record the result of a game of the current round. Returns `false`
+    /// if there is no such game, or it already has a result.
+    pub fn record_result(&mut self, game_id: &str, outcome: Outcome) -> bool {
+        let board = self.rounds.last_mut().and_then(|boards| {
+            boards.iter_mut().find(|b| b.pairing.game_id == game_id && !b.pairing.is_bye())
+        });
+
+        match board {
+            Some(ref mut board) if board.outcome.is_none() => {
+                board.outcome = Some(outcome);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Record the result of a finished board. Games that are not over, or
+    /// were aborted, are not recorded.
+    pub fn record_board(&mut self, game_id: &str, state: &BoardState) -> bool {
+        match state.outcome() {
+            Some(outcome) => self.record_result(game_id, outcome),
+            None => false,
+        }
+    }
+
+    /// Record the result of a `NetMessage::GameOver`. Other messages are
+    /// ignored.
+    #[cfg(feature = "blockchain")]
+    pub fn receive(&mut self, msg: &NetMessage) -> bool {
+        match *msg {
+            NetMessage::GameOver { ref game_id, outcome } => self.record_result(game_id, outcome),
+            _ => false,
+        }
+    }
+
+    /// The opponents of each player with the points scored against them.
+    /// Byes have no opponent.
+    fn results(&self) -> Vec<Vec<(Option<usize>, f64)>> {
+        let mut results = vec![Vec::new(); self.players.len()];
+
+        for board in self.rounds.iter().flat_map(|boards| boards.iter()) {
+            let white = self.index(&board.pairing.white);
+            let black = board.pairing.black.as_ref().and_then(|black| self.index(black));
+
+            match (white, black, board.outcome) {
+                (Some(white), None, _) => results[white].push((None, 1.0)),
+                (Some(white), Some(black), Some(outcome)) => {
+                    results[white].push((Some(black), points(outcome, Color::White)));
+                    results[black].push((Some(white), points(outcome, Color::Black)));
+                },
+                _ => (),
+            }
+        }
+
+        results
+    }
+
+    fn index(&self, player: &str) -> Option<usize> {
+        self.players.iter().position(|p| p == player)
+    }
+
+    fn scores(&self) -> Vec<f64> {
+        self.results().iter().map(|r| r.iter().map(|&(_, p)| p).sum()).collect()
+    }
+
+    /// The standings, best first. Ties are broken by Buchholz, then by
+    /// Sonneborn-Berger, then by seed.
+    pub fn standings(&self) -> Vec<Standing> {
+        let results = self.results();
+        let points = self.scores();
+
+        let mut standings: Vec<(usize, Standing)> = self.players.iter().enumerate().map(|(i, player)| {
+            let mut buchholz = 0.0;
+            let mut sonneborn_berger = 0.0;
+            for &(opponent, score) in &results[i] {
+                if let Some(opponent) = opponent {
+                    buchholz += points[opponent];
+                    sonneborn_berger += score * points[opponent];
+                }
+            }
+            (i, Standing { player: player.clone(), points: points[i], buchholz, sonneborn_berger })
+        }).collect();
+
+        standings.sort_by(|&(a_seed, ref a), &(b_seed, ref b)| {
+            b.points.partial_cmp(&a.points).unwrap_or(Ordering::Equal)
+                .then(b.buchholz.partial_cmp(&a.buchholz).unwrap_or(Ordering::Equal))
+                .then(b.sonneborn_berger.partial_cmp(&a.sonneborn_berger).unwrap_or(Ordering::Equal))
+                .then(a_seed.cmp(&b_seed))
+        });
+
+        standings.into_iter().map(|(_, standing)| standing).collect()
+    }
+
+    /// Pairs of the circle method: the first player stays, the others
+    /// rotate. With an odd number of players, whoever meets the missing
+    /// player has a bye.
+    fn round_robin_pairs(&self, round: u32) -> Vec<(usize, Option<usize>)> {
+        let mut circle: Vec<Option<usize>> = (0..self.players.len()).map(Some).collect();
+        if circle.len() % 2 == 1 {
+            circle.push(None);
+        }
+        let n = circle.len();
+        if n < 2 {
+            return Vec::new();
+        }
+        circle[1..].rotate_right(round as usize % (n - 1));
+
+        let mut pairs = Vec::new();
+        for i in 0..n / 2 {
+            // alternate the colors of the fixed player
+            let (a, b) = if i == 0 && round % 2 == 1 { (circle[n - 1], circle[0]) } else { (circle[i], circle[n - 1 - i]) };
+            match (a, b) {
+                (Some(a), Some(b)) => pairs.push((a, Some(b))),
+                (Some(player), None) | (None, Some(player)) => pairs.push((player, None)),
+                (None, None) => (),
+            }
+        }
+
+        // byes last
+        pairs.sort_by_key(|&(_, black)| black.is_none());
+        pairs
+    }
+
+    /// Pair players with equal points, top down, avoiding rematches where
+    /// possible. The lowest player without a bye yet gets one if the number
+    /// of players is odd.
+    fn swiss_pairs(&self) -> Vec<(usize, Option<usize>)> {
+        let results = self.results();
+        let points = self.scores();
+
+        let mut ranking: Vec<usize> = (0..self.players.len()).collect();
+        ranking.sort_by(|&a, &b| points[b].partial_cmp(&points[a]).unwrap_or(Ordering::Equal).then(a.cmp(&b)));
+
+        let mut bye = None;
+        if ranking.len() % 2 == 1 {
+            let had_bye = |player: usize| results[player].iter().any(|&(opponent, _)| opponent.is_none());
+            let index = ranking.iter().rposition(|&p| !had_bye(p)).unwrap_or(ranking.len() - 1);
+            bye = Some(ranking.remove(index));
+        }
+
+        let whites = |player: usize| {
+            self.rounds.iter().flat_map(|boards| boards.iter())
+                .filter(|b| !b.pairing.is_bye())
+                .map(|b| match b.pairing.color_of(&self.players[player]) {
+                    Some(Color::White) => 1,
+                    Some(Color::Black) => -1,
+                    None => 0,
+                })
+                .sum::<i32>()
+        };
+        let met = |a: usize, b: usize| results[a].iter().any(|&(opponent, _)| opponent == Some(b));
+
+        let mut pairs = Vec::new();
+        while !ranking.is_empty() {
+            let player = ranking.remove(0);
+            let index = ranking.iter().position(|&other| !met(player, other)).unwrap_or(0);
+            let opponent = ranking.remove(index);
+
+            // white for the player who had white less often
+            if whites(player) <= whites(opponent) {
+                pairs.push((player, Some(opponent)));
+            } else {
+                pairs.push((opponent, Some(player)));
+            }
+        }
+
+        if let Some(bye) = bye {
+            pairs.push((bye, None));
+        }
+        pairs
+    }
+}
+
+fn points(outcome: Outcome, color: Color) -> f64 {
+    match outcome {
+        Outcome::Decisive { winner } if winner == color => 1.0,
+        Outcome::Decisive { .. } => 0.0,
+        Outcome::Draw => 0.5,
+    }
+}
+
+/// Parse PGN notation of an outcome, e.g. `1-0`.
+pub(crate) fn outcome_from_str(s: &str) -> Option<Outcome> {
+    match s {
+        "1-0" => Some(Outcome::Decisive { winner: Color::White }),
+        "0-1" => Some(Outcome::Decisive { winner: Color::Black }),
+        "1/2-1/2" => Some(Outcome::Draw),
+        _ => None,
+    }
+}
@@ -0,0 +1,363 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! An opening repertoire trainer with SM-2 spaced repetition.
+//!
+//! The lines of a repertoire are kept as a game tree with variations. In a
+//! drill the board plays the opponent side of a line, and the recall of
+//! each move of the trainee is scheduled with a card per position.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use shakmaty::{Chess, Color, EnPassantMode, Move, Position};
+use shakmaty::fen::Fen;
+use shakmaty::san::San;
+
+use game::Game;
+use gametree::NodeId;
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Error when adding a line to a repertoire.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TrainerError {
+    /// A move of the line is not legal, by ply starting at 0.
+    InvalidMove { ply: usize, san: String },
+}
+
+impl fmt::Display for TrainerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TrainerError::InvalidMove { ply, ref san } => write!(f, "invalid move {} at ply {}", san, ply),
+        }
+    }
+}
+
+impl Error for TrainerError {}
+
+/// When to review the move of a position next, by SM-2.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Card {
+    /// Successful reviews in a row.
+    pub repetitions: u32,
+    /// Days until the next review.
+    pub interval: u32,
+    /// Easiness factor, at least 1.3.
+    pub ease: f64,
+    /// Milliseconds since the Unix epoch when the card is due.
+    pub due: u64,
+}
+
+impl Default for Card {
+    fn default() -> Card {
+        Card {
+            repetitions: 0,
+            interval: 0,
+            ease: 2.5,
+            due: 0,
+        }
+    }
+}
+
+impl Card {
+    pub fn is_due(&self, now: u64) -> bool {
+        self.due <= now
+    }
+
+    /// Schedule the next review after recalling with a quality from 0
+    /// (forgotten) to 5 (perfect).
+    pub fn review(&mut self, quality: u8, now: u64) {
+        let q = f64::from(quality.min(5));
+
+        if quality < 3 {
+            self.repetitions = 0;
+            self.interval = 1;
+        } else {
+            self.repetitions += 1;
+            self.interval = match self.repetitions {
+                1 => 1,
+                2 => 6,
+                _ => (f64::from(self.interval) * self.ease).round() as u32,
+            };
+        }
+
+        self.ease = (self.ease + 0.1 - (5.0 - q) * (0.08 + (5.0 - q) * 0.02)).max(1.3);
+        self.due = now + u64::from(self.interval) * DAY_MS;
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "repetitions": self.repetitions,
+            "interval": self.interval,
+            "ease": self.ease,
+            "due": self.due,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Card> {
+        Some(Card {
+            repetitions: value["repetitions"].as_u64()? as u32,
+            interval: value["interval"].as_u64()? as u32,
+            ease: value["ease"].as_f64()?,
+            due: value["due"].as_u64()?,
+        })
+    }
+}
+
+/// Opening lines to be played with one color, and the review schedule of
+/// their positions.
+#[derive(Debug, Clone)]
+pub struct Repertoire {
+    name: String,
+    color: Color,
+    game: Game,
+    cards: HashMap<String, Card>,
+}
+
+impl Repertoire {
+    /// An empty repertoire. Timestamps given to its methods are
+    /// milliseconds since the Unix epoch.
+    pub fn new(name: &str, color: Color) -> Repertoire {
+        Repertoire {
+            name: name.to_owned(),
+            color,
+            game: Game::new(),
+            cards: HashMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The color the trainee plays.
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// The lines, with variations.
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Add a line of moves in SAN from the starting position. Moves that
+    /// are already known are shared with the other lines.
+    pub fn add_line(&mut self, sans: &[&str]) -> Result<(), TrainerError> {
+        let tree = self.game.tree_mut();
+        let mut node = tree.root();
+        let mut pos = Chess::default();
+
+        for (ply, san) in sans.iter().enumerate() {
+            let invalid = || TrainerError::InvalidMove { ply, san: (*san).to_owned() };
+            let m = San::from_str(san).ok().and_then(|san| san.to_move(&pos).ok()).ok_or_else(invalid)?;
+            node = tree.push(node, m.clone()).map_err(|_| invalid())?;
+            pos.play_unchecked(&m);
+        }
+        Ok(())
+    }
+
+    /// All lines from the starting position to their last move.
+    pub fn lines(&self) -> Vec<Vec<Move>> {
+        let tree = self.game.tree();
+        let mut lines = Vec::new();
+        let mut stack = vec![tree.root()];
+
+        while let Some(node) = stack.pop() {
+            let children = tree.children(node);
+            if children.is_empty() && node != tree.root() {
+                lines.push(self.moves_to(node));
+            }
+            stack.extend(children.iter().rev());
+        }
+        lines
+    }
+
+    fn moves_to(&self, node: NodeId) -> Vec<Move> {
+        let tree = self.game.tree();
+        tree.path(node).into_iter().filter_map(|n| tree.game_move(n).map(|m| m.mv().clone())).collect()
+    }
+
+    /// Positions of a line where the trainee is to move.
+    fn trainee_positions(&self, line: &[Move]) -> Vec<String> {
+        let mut pos = Chess::default();
+        let mut keys = Vec::new();
+        for m in line {
+            if pos.turn() == self.color {
+                keys.push(key(&pos));
+            }
+            pos.play_unchecked(m);
+        }
+        keys
+    }
+
+    /// The card of a position, if it was reviewed before.
+    pub fn card(&self, fen: &str) -> Option<&Card> {
+        self.cards.get(fen)
+    }
+
+    /// Record how well the move in a position was recalled, e.g. from
+    /// `GroundMsg::DrillRecalled`.
+    pub fn review(&mut self, fen: &str, quality: u8, now: u64) {
+        self.cards.entry(fen.to_owned()).or_insert_with(Card::default).review(quality, now);
+    }
+
+    /// Lines with a position that is due, or was never reviewed.
+    pub fn due_lines(&self, now: u64) -> Vec<Vec<Move>> {
+        self.lines().into_iter().filter(|line| {
+            self.trainee_positions(line).iter().any(|fen| self.cards.get(fen).map_or(true, |card| card.is_due(now)))
+        }).collect()
+    }
+
+    /// A drill of a line, for `GroundMsg::SetDrill`.
+    pub fn drill(&self, line: Vec<Move>) -> Drill {
+        Drill { color: self.color, line }
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "color": self.color.fold_wb("white", "black"),
+            "pgn": self.game.to_pgn(),
+            "cards": self.cards.iter().map(|(fen, card)| (fen.clone(), card.to_json())).collect::<serde_json::Map<_, _>>(),
+        })
+    }
+
+    pub(crate) fn from_json(name: &str, value: &Value) -> Option<Repertoire> {
+        let color = match value["color"].as_str()? {
+            "white" => Color::White,
+            "black" => Color::Black,
+            _ => return None,
+        };
+        let game = Game::from_pgn(value["pgn"].as_str()?).ok()?;
+        let cards = value["cards"].as_object()?.iter()
+            .map(|(fen, card)| Some((fen.clone(), Card::from_json(card)?)))
+            .collect::<Option<_>>()?;
+
+        Some(Repertoire { name: name.to_owned(), color, game, cards })
+    }
+}
+
+/// A line to drill, with the color of the trainee.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Drill {
+    color: Color,
+    line: Vec<Move>,
+}
+
+impl Drill {
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    pub fn line(&self) -> &[Move] {
+        &self.line
+    }
+}
+
+/// The answer to a move of the trainee.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Recall {
+    /// The move of the line, with the FEN before it, the quality of the
+    /// recall and the reply of the opponent, if the line goes on.
+    Correct { fen: String, quality: u8, reply: Option<Move> },
+    /// Not the move of the line. It has to be taken back.
+    Wrong(Move),
+}
+
+/// A drill being played.
+#[derive(Debug, Clone)]
+pub(crate) struct DrillRun {
+    drill: Drill,
+    pos: Chess,
+    ply: usize,
+    /// Mistakes at the current ply, and in the whole line.
+    mistakes: (u32, u32),
+}
+
+impl DrillRun {
+    pub fn new(drill: Drill) -> DrillRun {
+        DrillRun { drill, pos: Chess::default(), ply: 0, mistakes: (0, 0) }
+    }
+
+    pub fn color(&self) -> Color {
+        self.drill.color
+    }
+
+    /// Wrong moves in the whole line.
+    pub fn mistakes(&self) -> u32 {
+        self.mistakes.1
+    }
+
+    /// Wrong moves since the last right one.
+    pub fn misses(&self) -> u32 {
+        self.mistakes.0
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.ply >= self.drill.line.len()
+    }
+
+    /// The first move of the opponent, if the line starts with one.
+    pub fn opening_reply(&mut self) -> Option<Move> {
+        if self.ply == 0 && self.pos.turn() != self.drill.color {
+            self.advance()
+        } else {
+            None
+        }
+    }
+
+    fn advance(&mut self) -> Option<Move> {
+        let m = self.drill.line.get(self.ply).cloned()?;
+        self.pos.play_unchecked(&m);
+        self.ply += 1;
+        Some(m)
+    }
+
+    pub fn try_move(&mut self, m: &Move) -> Recall {
+        let expected = match self.drill.line.get(self.ply) {
+            Some(expected) => expected.clone(),
+            None => return Recall::Wrong(m.clone()),
+        };
+
+        if *m != expected {
+            self.mistakes.0 += 1;
+            self.mistakes.1 += 1;
+            return Recall::Wrong(expected);
+        }
+
+        let quality = match self.mistakes.0 {
+            0 => 5,
+            1 => 3,
+            2 => 1,
+            _ => 0,
+        };
+        self.mistakes.0 = 0;
+
+        let fen = key(&self.pos);
+        self.advance();
+        let reply = self.advance();
+        Recall::Correct { fen, quality, reply }
+    }
+}
+
+fn key(pos: &Chess) -> String {
+    Fen::from_position(pos.clone(), EnPassantMode::Legal).to_string()
+}
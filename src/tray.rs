@@ -0,0 +1,246 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! A tray of the pieces captured by one side, with the material advantage.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+use gtk::prelude::*;
+use gtk::DrawingArea;
+use cairo::Context;
+
+use relm::{Relm, Widget, Update};
+
+use shakmaty::{Color, Role};
+
+use pieceset::PieceSet;
+use util::ease;
+
+/// Microseconds a newly captured piece takes to fly into the tray.
+const FLIGHT: f64 = 300_000.0;
+
+/// Captured pieces tray events.
+#[derive(Debug, Msg)]
+pub enum CapturedTrayMsg {
+    /// Show the pieces captured by this side, white by default.
+    SetSide(Color),
+    /// Set the pieces captured by white and black, and the material
+    /// advantage of white, as sent with `GroundMsg::MaterialChanged`.
+    SetMaterial(Vec<Role>, Vec<Role>, i32),
+    /// Draw the pieces with another piece set.
+    SetPieceSet(PieceSet),
+}
+
+/// A captured piece, resting or still on its way into the tray.
+struct Captured {
+    role: Role,
+    /// Frame time when the flight started, once the frame clock ticked.
+    since: Option<i64>,
+    /// Progress of the flight from 0 to 1, where 1 is at rest.
+    progress: f64,
+}
+
+struct Tray {
+    side: Color,
+    white: Vec<Role>,
+    black: Vec<Role>,
+    advantage: i32,
+    captured: Vec<Captured>,
+    piece_set: PieceSet,
+    animating: bool,
+}
+
+impl Tray {
+    fn new() -> Tray {
+        Tray {
+            side: Color::White,
+            white: Vec::new(),
+            black: Vec::new(),
+            advantage: 0,
+            captured: Vec::new(),
+            piece_set: PieceSet::merida(),
+            animating: false,
+        }
+    }
+
+    /// Show the pieces captured by the side. Pieces that are new since
+    /// the last call fly in, unless `animate` is false.
+    fn show(&mut self, animate: bool) {
+        let roles = self.side.fold_wb(&self.white, &self.black).clone();
+
+        let mut captured = Vec::with_capacity(roles.len());
+        for (index, &role) in roles.iter().enumerate() {
+            let nth = roles[..index].iter().filter(|&&r| r == role).count();
+            let resting = self.captured.iter().filter(|c| c.role == role).count() > nth;
+            captured.push(Captured {
+                role,
+                since: None,
+                progress: if resting || !animate { 1.0 } else { 0.0 },
+            });
+        }
+
+        self.captured = captured;
+    }
+
+    fn is_animating(&self) -> bool {
+        self.captured.iter().any(|c| c.progress < 1.0)
+    }
+
+    fn tick(&mut self, frame_time: i64) {
+        for captured in self.captured.iter_mut().filter(|c| c.progress < 1.0) {
+            let since = *captured.since.get_or_insert(frame_time);
+            captured.progress = ((frame_time - since) as f64 / FLIGHT).min(1.0);
+        }
+    }
+
+    fn draw(&self, cr: &Context, width: f64, height: f64) -> Result<(), cairo::Error> {
+        // pieces of a kind overlap, and kinds are set apart a little
+        let mut x = 0.0;
+        let mut previous = None;
+        let mut slots = Vec::with_capacity(self.captured.len());
+        for captured in &self.captured {
+            if previous.is_some() {
+                x += if previous == Some(captured.role) { 0.4 } else { 0.8 };
+            }
+            previous = Some(captured.role);
+            slots.push(x);
+        }
+
+        for (captured, &slot) in self.captured.iter().zip(&slots) {
+            // fly in from above the far end of the tray
+            let start = width / height;
+            let x = ease(start, slot, captured.progress);
+            let y = ease(-1.0, 0.0, captured.progress);
+
+            cr.save()?;
+            cr.scale(height, height);
+            cr.translate(x, y);
+            self.piece_set.render(cr, captured.role.of(!self.side))?;
+            cr.restore()?;
+        }
+
+        let advantage = self.side.fold_wb(self.advantage, -self.advantage);
+        if advantage > 0 {
+            let x = slots.last().map_or(0.0, |x| x + 1.1) * height;
+            cr.set_font_size(height * 0.6);
+            cr.move_to(x, height * 0.7);
+            cr.set_source_rgb(0.5, 0.5, 0.5);
+            cr.show_text(&format!("+{}", advantage))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A row of the pieces captured by one side, with its material advantage
+/// like +2. Newly captured pieces fly into the tray. Forward
+/// `GroundMsg::MaterialChanged` to `CapturedTrayMsg::SetMaterial`.
+pub struct CapturedTray {
+    drawing_area: DrawingArea,
+    tray: Rc<RefCell<Tray>>,
+}
+
+impl fmt::Debug for CapturedTray {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CapturedTray").finish()
+    }
+}
+
+impl Update for CapturedTray {
+    type Model = ();
+    type ModelParam = ();
+    type Msg = CapturedTrayMsg;
+
+    fn model(_: &Relm<Self>, _: ()) {}
+
+    fn update(&mut self, event: CapturedTrayMsg) {
+        let mut tray = self.tray.borrow_mut();
+        match event {
+            CapturedTrayMsg::SetSide(side) => {
+                tray.side = side;
+                tray.show(false);
+            },
+            CapturedTrayMsg::SetMaterial(white, black, advantage) => {
+                tray.white = white;
+                tray.black = black;
+                tray.advantage = advantage;
+                tray.show(true);
+            },
+            CapturedTrayMsg::SetPieceSet(piece_set) => {
+                tray.piece_set = piece_set;
+            },
+        }
+        self.drawing_area.queue_draw();
+    }
+}
+
+impl Widget for CapturedTray {
+    type Root = DrawingArea;
+
+    fn root(&self) -> Self::Root {
+        self.drawing_area.clone()
+    }
+
+    fn view(_: &Relm<Self>, _: ()) -> Self {
+        let drawing_area = DrawingArea::new();
+        drawing_area.set_size_request(-1, 24);
+
+        let tray = Rc::new(RefCell::new(Tray::new()));
+
+        {
+            let weak_tray = Rc::downgrade(&tray);
+            drawing_area.connect_draw(move |widget, cr| {
+                if let Some(tray) = weak_tray.upgrade() {
+                    let mut tray = tray.borrow_mut();
+                    let width = f64::from(widget.allocated_width());
+                    let height = f64::from(widget.allocated_height());
+                    tray.draw(cr, width, height).unwrap();
+
+                    // advance the flights with the frame clock until all
+                    // pieces landed
+                    if !tray.animating && tray.is_animating() {
+                        tray.animating = true;
+
+                        let weak_tray = Weak::clone(&weak_tray);
+                        widget.add_tick_callback(move |widget, clock| {
+                            if let Some(tray) = weak_tray.upgrade() {
+                                let mut tray = tray.borrow_mut();
+                                tray.tick(clock.frame_time());
+                                widget.queue_draw();
+                                if tray.is_animating() {
+                                    return Continue(true);
+                                }
+                                tray.animating = false;
+                            }
+                            Continue(false)
+                        });
+                    }
+                }
+                Inhibit(false)
+            });
+        }
+
+        drawing_area.show();
+
+        CapturedTray {
+            drawing_area,
+            tray,
+        }
+    }
+}
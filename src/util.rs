@@ -14,7 +14,9 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
-use shakmaty::{Square, File, Rank};
+use std::cmp::Ordering;
+
+use shakmaty::{Square, File, Rank, Color, Role, Piece};
 
 pub fn ease(start: f64, end: f64, t: f64) -> f64 {
     // ease in out cubic from https://gist.github.com/gre/1650294
@@ -36,10 +38,40 @@ pub fn pos_to_square((x, y): (f64, f64)) -> Option<Square> {
     }
 }
 
+/// The square a piece dragged to `pos` lands on. If that is not one of
+/// the accepted squares, or off the board, the nearest accepted square
+/// whose edge is within `radius` is taken instead.
+pub fn snap_to_square<F: Fn(Square) -> bool>(pos: (f64, f64), radius: f64, accepts: F) -> Option<Square> {
+    let exact = pos_to_square(pos);
+    if radius <= 0.0 || exact.map_or(false, &accepts) {
+        return exact;
+    }
+
+    let distance = |square: Square| {
+        let (cx, cy) = square_to_pos(square);
+        let dx = ((pos.0 - cx).abs() - 0.5).max(0.0);
+        let dy = ((pos.1 - cy).abs() - 0.5).max(0.0);
+        dx.hypot(dy)
+    };
+
+    Square::ALL.iter().cloned()
+        .filter(|&square| accepts(square))
+        .map(|square| (distance(square), square))
+        .filter(|&(d, _)| d <= radius)
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
+        .map(|(_, square)| square)
+        .or(exact)
+}
+
 pub fn square_to_pos(square: Square) -> (f64, f64) {
     (0.5 + file_to_float(square.file()), 7.5 - rank_to_float(square.rank()))
 }
 
+/// The same point on the screen after the board has been flipped.
+pub fn flip_pos((x, y): (f64, f64)) -> (f64, f64) {
+    (8.0 - x, 8.0 - y)
+}
+
 pub fn rank_to_float(rank: Rank) -> f64 {
     f64::from(i8::from(rank))
 }
@@ -47,3 +79,35 @@ pub fn rank_to_float(rank: Rank) -> f64 {
 pub fn file_to_float(file: File) -> f64 {
     f64::from(i8::from(file))
 }
+
+/// Unicode chess symbol of a piece.
+pub fn figurine(piece: Piece) -> char {
+    match (piece.color, piece.role) {
+        (Color::White, Role::King) => '\u{2654}',
+        (Color::White, Role::Queen) => '\u{2655}',
+        (Color::White, Role::Rook) => '\u{2656}',
+        (Color::White, Role::Bishop) => '\u{2657}',
+        (Color::White, Role::Knight) => '\u{2658}',
+        (Color::White, Role::Pawn) => '\u{2659}',
+        (Color::Black, Role::King) => '\u{265a}',
+        (Color::Black, Role::Queen) => '\u{265b}',
+        (Color::Black, Role::Rook) => '\u{265c}',
+        (Color::Black, Role::Bishop) => '\u{265d}',
+        (Color::Black, Role::Knight) => '\u{265e}',
+        (Color::Black, Role::Pawn) => '\u{265f}',
+    }
+}
+
+#[cfg(feature = "blockchain")]
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(feature = "blockchain")]
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
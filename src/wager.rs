@@ -0,0 +1,323 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Stakes held by an escrow contract and paid out to the winner.
+
+use std::error::Error;
+use std::fmt;
+
+use serde_json::Value;
+
+use shakmaty::{Color, Outcome};
+
+use boardstate::BoardState;
+use chain::{ChainClient, ChainError};
+use commitment::Hash;
+use crypto::{verify, PlayerKey};
+use util::to_hex;
+
+/// Error when handling a wager.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum WagerError {
+    /// Talking to the contract failed.
+    Chain(ChainError),
+    /// The game has no result yet.
+    Unfinished,
+    /// A player has not signed the settlement.
+    MissingSignature(Color),
+    /// The signature of a player does not match their key.
+    InvalidSignature(Color),
+    /// A player has not deposited the full stake.
+    InsufficientDeposit(Color),
+}
+
+impl fmt::Display for WagerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WagerError::Chain(ref err) => write!(f, "escrow error: {}", err),
+            WagerError::Unfinished => f.write_str("game is not finished"),
+            WagerError::MissingSignature(color) => write!(f, "settlement not signed by {}", color.fold_wb("white", "black")),
+            WagerError::InvalidSignature(color) => write!(f, "invalid settlement signature of {}", color.fold_wb("white", "black")),
+            WagerError::InsufficientDeposit(color) => write!(f, "{} did not deposit the stake", color.fold_wb("white", "black")),
+        }
+    }
+}
+
+impl Error for WagerError {}
+
+impl From<ChainError> for WagerError {
+    fn from(err: ChainError) -> WagerError {
+        WagerError::Chain(err)
+    }
+}
+
+/// Result of a wagered game.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GameResult {
+    Winner(Color),
+    Draw,
+}
+
+impl GameResult {
+    pub fn from_outcome(outcome: Outcome) -> GameResult {
+        match outcome {
+            Outcome::Decisive { winner } => GameResult::Winner(winner),
+            Outcome::Draw => GameResult::Draw,
+        }
+    }
+
+    /// The result of the position on the board, if the game is over.
+    pub fn from_board_state(state: &BoardState) -> Option<GameResult> {
+        state.outcome().map(GameResult::from_outcome)
+    }
+
+    /// PGN notation, e.g. `1-0`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GameResult::Winner(Color::White) => "1-0",
+            GameResult::Winner(Color::Black) => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The players and the amount each of them puts at stake.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Stake {
+    pub game_id: String,
+    /// Public keys of the players.
+    pub white: [u8; 32],
+    pub black: [u8; 32],
+    /// Amount deposited by each player, in the smallest unit of the chain.
+    pub amount: u64,
+}
+
+impl Stake {
+    pub fn player(&self, color: Color) -> &[u8; 32] {
+        color.fold_wb(&self.white, &self.black)
+    }
+}
+
+/// The result of a game, together with the hash chain root of its moves,
+/// signed by both players.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Settlement {
+    pub game_id: String,
+    pub result: GameResult,
+    /// `BoardState::state_root()` of the final position.
+    pub state_root: Hash,
+    pub white_signature: Option<Vec<u8>>,
+    pub black_signature: Option<Vec<u8>>,
+}
+
+impl Settlement {
+    pub fn new(game_id: &str, result: GameResult, state_root: Hash) -> Settlement {
+        Settlement {
+            game_id: game_id.to_owned(),
+            result,
+            state_root,
+            white_signature: None,
+            black_signature: None,
+        }
+    }
+
+    /// Settle the game on the board. Fails if it is not over.
+    pub fn from_board_state(game_id: &str, state: &BoardState) -> Result<Settlement, WagerError> {
+        let result = GameResult::from_board_state(state).ok_or(WagerError::Unfinished)?;
+        Ok(Settlement::new(game_id, result, state.state_root()))
+    }
+
+    /// The bytes covered by the signatures.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        format!("settle|{}|{}|{}", self.game_id, self.result, to_hex(&self.state_root)).into_bytes()
+    }
+
+    pub fn signature(&self, color: Color) -> Option<&[u8]> {
+        color.fold_wb(&self.white_signature, &self.black_signature).as_ref().map(Vec::as_slice)
+    }
+
+    /// Add the signature of the player of a color.
+    pub fn sign(&mut self, color: Color, key: &PlayerKey) {
+        let signature = Some(key.sign(&self.signing_bytes()));
+        match color {
+            Color::White => self.white_signature = signature,
+            Color::Black => self.black_signature = signature,
+        }
+    }
+
+    /// Check that both players signed the settlement.
+    pub fn verify(&self, stake: &Stake) -> Result<(), WagerError> {
+        for &color in &[Color::White, Color::Black] {
+            let signature = self.signature(color).ok_or(WagerError::MissingSignature(color))?;
+            if !verify(stake.player(color), &self.signing_bytes(), signature) {
+                return Err(WagerError::InvalidSignature(color));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Transfers from the escrow after a settlement.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Payout {
+    pub game_id: String,
+    /// Public keys of the recipients and the amounts they receive.
+    pub transfers: Vec<([u8; 32], u64)>,
+}
+
+impl Payout {
+    /// The winner receives both stakes. After a draw each player gets
+    /// their stake back.
+    pub fn new(stake: &Stake, result: GameResult) -> Payout {
+        let transfers = match result {
+            GameResult::Winner(color) => vec![(*stake.player(color), stake.amount * 2)],
+            GameResult::Draw => vec![(stake.white, stake.amount), (stake.black, stake.amount)],
+        };
+
+        Payout {
+            game_id: stake.game_id.clone(),
+            transfers,
+        }
+    }
+}
+
+/// An escrow contract. Implement this to support another chain or
+/// contract. Calls block, and return a transaction id where a
+/// transaction is sent.
+pub trait Escrow {
+    /// Deposit the stake of a player.
+    fn deposit(&self, stake: &Stake, color: Color) -> Result<String, WagerError>;
+
+    /// Amount a player has deposited for a game so far.
+    fn deposited(&self, game_id: &str, player: &[u8; 32]) -> Result<u64, WagerError>;
+
+    /// Submit the signed result.
+    fn settle(&self, settlement: &Settlement) -> Result<String, WagerError>;
+
+    /// Broadcast the payout transaction.
+    fn pay_out(&self, payout: &Payout) -> Result<String, WagerError>;
+}
+
+/// The escrow contract behind the JSON-RPC endpoint of a `ChainClient`,
+/// with the `escrow_deposit`, `escrow_getDeposit`, `escrow_settle` and
+/// `escrow_payout` methods.
+#[derive(Debug, Clone)]
+pub struct RpcEscrow {
+    client: ChainClient,
+}
+
+impl RpcEscrow {
+    pub fn new(client: ChainClient) -> RpcEscrow {
+        RpcEscrow { client }
+    }
+
+    fn send(&self, method: &str, params: Value) -> Result<String, WagerError> {
+        self.client.call(method, params)?
+            .as_str().map(str::to_owned).ok_or(WagerError::Chain(ChainError::InvalidResponse))
+    }
+}
+
+impl Escrow for RpcEscrow {
+    fn deposit(&self, stake: &Stake, color: Color) -> Result<String, WagerError> {
+        self.send("escrow_deposit", json!([{
+            "game": stake.game_id,
+            "player": to_hex(stake.player(color)),
+            "amount": stake.amount,
+        }]))
+    }
+
+    fn deposited(&self, game_id: &str, player: &[u8; 32]) -> Result<u64, WagerError> {
+        match self.client.call("escrow_getDeposit", json!([game_id, to_hex(player)]))? {
+            Value::Null => Ok(0),
+            value => value.as_u64().ok_or(WagerError::Chain(ChainError::InvalidResponse)),
+        }
+    }
+
+    fn settle(&self, settlement: &Settlement) -> Result<String, WagerError> {
+        self.send("escrow_settle", json!([{
+            "game": settlement.game_id,
+            "result": settlement.result.as_str(),
+            "stateRoot": to_hex(&settlement.state_root),
+            "whiteSignature": settlement.white_signature.as_ref().map(|s| to_hex(s)),
+            "blackSignature": settlement.black_signature.as_ref().map(|s| to_hex(s)),
+        }]))
+    }
+
+    fn pay_out(&self, payout: &Payout) -> Result<String, WagerError> {
+        self.send("escrow_payout", json!([{
+            "game": payout.game_id,
+            "transfers": payout.transfers.iter().map(|&(ref recipient, amount)| json!({
+                "recipient": to_hex(recipient),
+                "amount": amount,
+            })).collect::<Vec<_>>(),
+        }]))
+    }
+}
+
+/// A game played for stakes held by an escrow contract.
+#[derive(Debug)]
+pub struct Wager<E> {
+    escrow: E,
+    stake: Stake,
+}
+
+impl<E: Escrow> Wager<E> {
+    pub fn new(escrow: E, stake: Stake) -> Wager<E> {
+        Wager { escrow, stake }
+    }
+
+    pub fn stake(&self) -> &Stake {
+        &self.stake
+    }
+
+    pub fn escrow(&self) -> &E {
+        &self.escrow
+    }
+
+    pub fn deposit(&self, color: Color) -> Result<String, WagerError> {
+        self.escrow.deposit(&self.stake, color)
+    }
+
+    /// Check that both players deposited their stake.
+    pub fn check_funded(&self) -> Result<(), WagerError> {
+        for &color in &[Color::White, Color::Black] {
+            if self.escrow.deposited(&self.stake.game_id, self.stake.player(color))? < self.stake.amount {
+                return Err(WagerError::InsufficientDeposit(color));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Submit a settlement signed by both players and broadcast the
+    /// payout, returning the settlement and payout transaction ids.
+    pub fn settle(&self, settlement: &Settlement) -> Result<(String, String), WagerError> {
+        settlement.verify(&self.stake)?;
+        self.check_funded()?;
+
+        let settled = self.escrow.settle(settlement)?;
+        let paid = self.escrow.pay_out(&Payout::new(&self.stake, settlement.result))?;
+        Ok((settled, paid))
+    }
+}
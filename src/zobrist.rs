@@ -0,0 +1,175 @@
+// This file is part of the chessground library.
+// Copyright (C) 2017 Niklas Fiekas <niklas.fiekas@backscattering.de>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Zobrist hashing of positions, to recognize them quickly, e.g. when
+//! searching stored games.
+
+use shakmaty::{attacks, Bitboard, Color, File, Material, Move, Piece, Role, Setup, Square};
+
+/// The key of a feature of a position, from a fixed pseudo random
+/// sequence, so that hashes are the same across runs and machines.
+fn key(index: u64) -> u64 {
+    // splitmix64
+    let mut z = index.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn role_index(role: Role) -> u64 {
+    match role {
+        Role::Pawn => 0,
+        Role::Knight => 1,
+        Role::Bishop => 2,
+        Role::Rook => 3,
+        Role::Queen => 4,
+        Role::King => 5,
+    }
+}
+
+/// Key of a piece on a square.
+pub(crate) fn piece_key(piece: Piece, square: Square) -> u64 {
+    let index = piece.color.fold_wb(0, 6) + role_index(piece.role);
+    key(index * 64 + u64::from(square))
+}
+
+/// Key toggled when black is to move.
+pub(crate) fn turn_key() -> u64 {
+    key(12 * 64)
+}
+
+/// Key of a castling right, by the square of the rook.
+pub(crate) fn castling_key(rook: Square) -> u64 {
+    key(12 * 64 + 1 + u64::from(rook))
+}
+
+/// Key of the file of a legal en passant square.
+pub(crate) fn en_passant_key(file: File) -> u64 {
+    key(13 * 64 + 1 + u64::from(file))
+}
+
+/// Key of the pieces in the Crazyhouse pockets.
+fn pockets_key(pockets: Option<&Material>) -> u64 {
+    let pockets = match pockets {
+        Some(pockets) => pockets,
+        None => return 0,
+    };
+
+    let mut hash = 0;
+    for &color in &[Color::White, Color::Black] {
+        for &role in &[Role::Pawn, Role::Knight, Role::Bishop, Role::Rook, Role::Queen, Role::King] {
+            let count = u64::from(pockets.by_color(color).by_role(role));
+            if count > 0 {
+                // up to 16 pieces of a kind fit in a pocket
+                let index = color.fold_wb(0, 6) + role_index(role);
+                hash ^= key(14 * 64 + index * 17 + count.min(16));
+            }
+        }
+    }
+    hash
+}
+
+/// Hash of the pieces, the side to move, the castling rights, the en
+/// passant square and the pockets of a position. The move counters are ignored, so that
+/// transpositions hash the same.
+pub fn zobrist_hash(setup: &Setup) -> u64 {
+    let mut hash = 0;
+
+    for (square, piece) in setup.board.clone() {
+        hash ^= piece_key(piece, square);
+    }
+
+    if setup.turn == Color::Black {
+        hash ^= turn_key();
+    }
+
+    for rook in setup.castling_rights {
+        hash ^= castling_key(rook);
+    }
+
+    if let Some(ep_square) = setup.ep_square {
+        hash ^= en_passant_key(ep_square.file());
+    }
+
+    hash ^ pockets_key(setup.pockets.as_ref())
+}
+
+/// The change of the hash from `before` to `after`, where `after` is the
+/// position after `m`. Only the squares the move touched are rehashed,
+/// including the neighbours of a capture for explosions in Atomic.
+pub(crate) fn move_delta(before: &Setup, after: &Setup, m: &Move) -> u64 {
+    let mut touched = Bitboard::from_square(m.to());
+
+    match *m {
+        Move::Normal { from, capture, to, .. } => {
+            touched.add(from);
+            if capture.is_some() {
+                touched |= attacks::king_attacks(to);
+            }
+        },
+        Move::EnPassant { from, to } => {
+            touched.add(from);
+            touched.add(Square::from_coords(to.file(), from.rank()));
+            touched |= attacks::king_attacks(to);
+        },
+        Move::Castle { king, rook } => {
+            touched.add(king);
+            touched.add(rook);
+            if let Some(side) = m.castling_side() {
+                touched.add(side.king_to(before.turn));
+                touched.add(side.rook_to(before.turn));
+            }
+        },
+        Move::Put { .. } => (),
+    }
+
+    let mut delta = 0;
+
+    for square in touched {
+        if before.board.piece_at(square) != after.board.piece_at(square) {
+            if let Some(piece) = before.board.piece_at(square) {
+                delta ^= piece_key(piece, square);
+            }
+            if let Some(piece) = after.board.piece_at(square) {
+                delta ^= piece_key(piece, square);
+            }
+        }
+    }
+
+    if before.turn != after.turn {
+        delta ^= turn_key();
+    }
+
+    for rook in before.castling_rights ^ after.castling_rights {
+        delta ^= castling_key(rook);
+    }
+
+    if before.ep_square != after.ep_square {
+        if let Some(ep_square) = before.ep_square {
+            delta ^= en_passant_key(ep_square.file());
+        }
+        if let Some(ep_square) = after.ep_square {
+            delta ^= en_passant_key(ep_square.file());
+        }
+    }
+
+    if before.pockets != after.pockets {
+        delta ^= pockets_key(before.pockets.as_ref()) ^ pockets_key(after.pockets.as_ref());
+    }
+
+    delta
+}